@@ -0,0 +1,312 @@
+//! # Branch Predictor Module
+//!
+//! This module defines the `BranchPredictor` struct, which simulates the branch
+//! prediction unit of the ARC CPU. It observes every conditional jump, keeps
+//! accuracy statistics per branch site and overall, and reports mispredictions
+//! so the CPU can charge a penalty to its cycle count.
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Default number of cycles lost when a conditional jump is mispredicted.
+pub const DEFAULT_MISPREDICTION_PENALTY: u64 = 3;
+/// Default number of global history bits used by the gshare predictor.
+pub const DEFAULT_GSHARE_HISTORY_BITS: u8 = 8;
+/// Largest global history supported by the gshare predictor.
+pub const MAX_GSHARE_HISTORY_BITS: u8 = 16;
+
+/// The prediction strategies the branch predictor can simulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredictorKind {
+    /// Always predicts that the branch is taken.
+    StaticTaken,
+    /// Always predicts that the branch is not taken.
+    StaticNotTaken,
+    /// Predicts the last outcome seen at the branch site.
+    OneBit,
+    /// Uses a 2-bit saturating counter per branch site.
+    TwoBit,
+    /// Indexes a table of 2-bit counters with the branch address XOR the global history.
+    Gshare {
+        /// The number of global history bits (and the log2 of the table size).
+        history_bits: u8,
+    },
+}
+
+impl PredictorKind {
+    /// The predictors offered when cycling through them in the emulator.
+    pub const SELECTABLE: [PredictorKind; 7] = [
+        PredictorKind::StaticNotTaken,
+        PredictorKind::StaticTaken,
+        PredictorKind::OneBit,
+        PredictorKind::TwoBit,
+        PredictorKind::Gshare { history_bits: 4 },
+        PredictorKind::Gshare { history_bits: DEFAULT_GSHARE_HISTORY_BITS },
+        PredictorKind::Gshare { history_bits: 12 },
+    ];
+
+    /// Returns a short human-readable name for the predictor.
+    pub fn name(&self) -> String {
+        match self {
+            PredictorKind::StaticTaken => "static taken".to_string(),
+            PredictorKind::StaticNotTaken => "static not-taken".to_string(),
+            PredictorKind::OneBit => "1-bit".to_string(),
+            PredictorKind::TwoBit => "2-bit saturating".to_string(),
+            PredictorKind::Gshare { history_bits } => format!("gshare ({} history bits)", history_bits),
+        }
+    }
+
+    /// Returns the predictor that follows this one in `SELECTABLE`, wrapping around.
+    pub fn next(&self) -> PredictorKind {
+        let position = Self::SELECTABLE.iter().position(|kind| kind == self).unwrap_or(0);
+        Self::SELECTABLE[(position + 1) % Self::SELECTABLE.len()]
+    }
+}
+
+/// Statistics collected for a single conditional jump site.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BranchSiteStats {
+    /// How many times the branch was executed.
+    pub executed: u64,
+    /// How many times the branch was taken.
+    pub taken: u64,
+    /// How many times the predictor guessed the outcome correctly.
+    pub correct: u64,
+}
+
+impl BranchSiteStats {
+    /// Returns the prediction accuracy for this site as a percentage.
+    pub fn accuracy(&self) -> f64 {
+        if self.executed == 0 {
+            return 0.0;
+        }
+        self.correct as f64 * 100.0 / self.executed as f64
+    }
+}
+
+/// Simulates a branch predictor and collects its statistics.
+#[derive(Debug, Clone)]
+pub struct BranchPredictor {
+    /// The prediction strategy in use.
+    pub kind: PredictorKind,
+    /// Cycles added to the CPU cycle count on every misprediction.
+    pub misprediction_penalty: u64,
+    /// Statistics for each branch site, keyed by the address of the jump instruction.
+    pub sites: BTreeMap<u32, BranchSiteStats>,
+    /// Last outcome per branch site, used by the 1-bit predictor.
+    last_outcome: HashMap<u32, bool>,
+    /// 2-bit saturating counters per branch site, used by the 2-bit predictor.
+    counters: HashMap<u32, u8>,
+    /// Pattern history table of 2-bit counters, used by the gshare predictor.
+    pattern_table: Vec<u8>,
+    /// Global branch history register, most recent outcome in bit 0.
+    global_history: u32,
+}
+
+impl Default for BranchPredictor {
+    /// Creates a `BranchPredictor` using the static not-taken strategy.
+    fn default() -> Self {
+        Self::new(PredictorKind::StaticNotTaken)
+    }
+}
+
+impl BranchPredictor {
+    /// Creates a new `BranchPredictor` using the given strategy.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The prediction strategy to simulate.
+    pub fn new(kind: PredictorKind) -> Self {
+        let mut predictor = Self {
+            kind,
+            misprediction_penalty: DEFAULT_MISPREDICTION_PENALTY,
+            sites: BTreeMap::new(),
+            last_outcome: HashMap::new(),
+            counters: HashMap::new(),
+            pattern_table: Vec::new(),
+            global_history: 0,
+        };
+        predictor.reset();
+        predictor
+    }
+
+    /// Switches to a different prediction strategy, clearing all state and statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The new prediction strategy.
+    pub fn set_kind(&mut self, kind: PredictorKind) {
+        self.kind = kind;
+        self.reset();
+    }
+
+    /// Clears the predictor state and all collected statistics, keeping the strategy.
+    pub fn reset(&mut self) {
+        self.sites.clear();
+        self.last_outcome.clear();
+        self.counters.clear();
+        self.global_history = 0;
+        self.pattern_table = match self.kind {
+            PredictorKind::Gshare { history_bits } => {
+                vec![1; 1 << history_bits.min(MAX_GSHARE_HISTORY_BITS)]
+            }
+            _ => Vec::new(),
+        };
+    }
+
+    /// Predicts whether the branch at `site` will be taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `site` - The address of the conditional jump instruction.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - `true` if the branch is predicted taken.
+    pub fn predict(&self, site: u32) -> bool {
+        match self.kind {
+            PredictorKind::StaticTaken => true,
+            PredictorKind::StaticNotTaken => false,
+            PredictorKind::OneBit => self.last_outcome.get(&site).copied().unwrap_or(false),
+            PredictorKind::TwoBit => self.counters.get(&site).copied().unwrap_or(1) >= 2,
+            PredictorKind::Gshare { .. } => self.pattern_table[self.gshare_index(site)] >= 2,
+        }
+    }
+
+    /// Records the actual outcome of the branch at `site` and updates the predictor.
+    ///
+    /// # Arguments
+    ///
+    /// * `site` - The address of the conditional jump instruction.
+    /// * `taken` - Whether the branch was actually taken.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - `true` if the branch was predicted correctly.
+    pub fn observe(&mut self, site: u32, taken: bool) -> bool {
+        let correct = self.predict(site) == taken;
+
+        let stats = self.sites.entry(site).or_default();
+        stats.executed += 1;
+        if taken {
+            stats.taken += 1;
+        }
+        if correct {
+            stats.correct += 1;
+        }
+
+        match self.kind {
+            PredictorKind::StaticTaken | PredictorKind::StaticNotTaken => {}
+            PredictorKind::OneBit => {
+                self.last_outcome.insert(site, taken);
+            }
+            PredictorKind::TwoBit => {
+                let counter = self.counters.entry(site).or_insert(1);
+                *counter = saturating_update(*counter, taken);
+            }
+            PredictorKind::Gshare { history_bits } => {
+                let index = self.gshare_index(site);
+                self.pattern_table[index] = saturating_update(self.pattern_table[index], taken);
+                let mask = (1u32 << history_bits.min(MAX_GSHARE_HISTORY_BITS)) - 1;
+                self.global_history = ((self.global_history << 1) | taken as u32) & mask;
+            }
+        }
+
+        correct
+    }
+
+    /// Returns the total number of conditional jumps observed.
+    pub fn total_executed(&self) -> u64 {
+        self.sites.values().map(|s| s.executed).sum()
+    }
+
+    /// Returns the total number of mispredicted conditional jumps.
+    pub fn total_mispredicted(&self) -> u64 {
+        self.sites.values().map(|s| s.executed - s.correct).sum()
+    }
+
+    /// Returns the overall prediction accuracy as a percentage.
+    pub fn accuracy(&self) -> f64 {
+        let executed = self.total_executed();
+        if executed == 0 {
+            return 0.0;
+        }
+        (executed - self.total_mispredicted()) as f64 * 100.0 / executed as f64
+    }
+
+    /// Computes the pattern table index for the gshare predictor.
+    fn gshare_index(&self, site: u32) -> usize {
+        (((site >> 2) ^ self.global_history) as usize) & (self.pattern_table.len() - 1)
+    }
+}
+
+/// Moves a 2-bit saturating counter towards taken (3) or not-taken (0).
+fn saturating_update(counter: u8, taken: bool) -> u8 {
+    if taken {
+        (counter + 1).min(3)
+    } else {
+        counter.saturating_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod branch_predictor_test {
+    use super::*;
+
+    #[test]
+    fn static_predictors() {
+        let mut taken = BranchPredictor::new(PredictorKind::StaticTaken);
+        assert!(taken.observe(0x10, true));
+        assert!(!taken.observe(0x10, false));
+
+        let mut not_taken = BranchPredictor::new(PredictorKind::StaticNotTaken);
+        assert!(not_taken.observe(0x10, false));
+        assert!(!not_taken.observe(0x10, true));
+        assert_eq!(not_taken.total_executed(), 2);
+        assert_eq!(not_taken.total_mispredicted(), 1);
+        assert_eq!(not_taken.accuracy(), 50.0);
+    }
+
+    #[test]
+    fn one_bit_follows_last_outcome() {
+        let mut predictor = BranchPredictor::new(PredictorKind::OneBit);
+        assert!(!predictor.observe(0x20, true));
+        assert!(predictor.observe(0x20, true));
+        assert!(!predictor.observe(0x20, false));
+        assert!(predictor.observe(0x20, false));
+    }
+
+    #[test]
+    fn two_bit_tolerates_single_anomaly() {
+        let mut predictor = BranchPredictor::new(PredictorKind::TwoBit);
+        predictor.observe(0x30, true);
+        predictor.observe(0x30, true);
+        predictor.observe(0x30, true);
+        // A single not-taken outcome does not flip the prediction.
+        assert!(!predictor.observe(0x30, false));
+        assert!(predictor.observe(0x30, true));
+        assert_eq!(predictor.sites[&0x30].executed, 5);
+        assert_eq!(predictor.sites[&0x30].taken, 4);
+    }
+
+    #[test]
+    fn gshare_learns_alternating_pattern() {
+        let mut predictor = BranchPredictor::new(PredictorKind::Gshare { history_bits: 4 });
+        for i in 0..64 {
+            predictor.observe(0x40, i % 2 == 0);
+        }
+        predictor.sites.clear();
+        for i in 0..16 {
+            predictor.observe(0x40, i % 2 == 0);
+        }
+        assert_eq!(predictor.accuracy(), 100.0);
+    }
+
+    #[test]
+    fn next_cycles_through_selectable_kinds() {
+        let mut kind = PredictorKind::SELECTABLE[0];
+        for _ in 0..PredictorKind::SELECTABLE.len() {
+            kind = kind.next();
+        }
+        assert_eq!(kind, PredictorKind::SELECTABLE[0]);
+    }
+}
@@ -2,16 +2,19 @@
 //!
 //! This module defines the `CallStack` struct, which simulates a call stack
 //! for the ARC CPU. It provides basic stack operations like push, pop, and peek,
-//! with checks for overflow and underflow.
+//! with checks for underflow. The stack keeps a bounded number of frames; when
+//! it is full the oldest frame is dropped, so deep recursion never overflows it.
 
 use std::vec::Vec; 
-/// Represents a fixed-size call stack for storing return addresses.
+/// Represents a bounded call stack for storing return addresses.
 #[derive(Debug, Clone)]
 pub struct CallStack {
-    /// The underlying vector used to store stack elements.
+    /// The underlying vector used to store stack elements, oldest first.
     pub stack: Vec<u32>,
-    /// The maximum capacity of the call stack.
+    /// The maximum number of frames kept.
     max_size: usize,
+    /// The number of oldest frames dropped to stay within `max_size`.
+    dropped: usize,
 }
 
 impl Default for CallStack {
@@ -27,6 +30,7 @@ impl CallStack {
         Self {
             stack: Vec::new(),
             max_size: 256, // Reasonable default
+            dropped: 0,
         }
     }
     
@@ -34,29 +38,56 @@ impl CallStack {
     ///
     /// # Arguments
     ///
-    /// * `max_size` - The maximum number of elements the stack can hold.
+    /// * `max_size` - The maximum number of elements the stack keeps.
     pub fn with_capacity(max_size: usize) -> Self {
         Self {
             stack: Vec::with_capacity(max_size),
             max_size,
+            dropped: 0,
         }
     }
     
-    /// Pushes an address onto the call stack.
+    /// Pushes an address onto the call stack, dropping the oldest frame if the stack is full.
     ///
     /// # Arguments
     ///
     /// * `address` - The `u32` address to push.
+    pub fn push(&mut self, address: u32) {
+        if self.max_size == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.stack.len() >= self.max_size {
+            self.stack.remove(0);
+            self.dropped += 1;
+        }
+        self.stack.push(address);
+    }
+
+    /// Unwinds the call stack for a return to `address`.
+    ///
+    /// The frames above the one returning to `address` are popped with it. A return to an
+    /// address that is not on the stack is accepted as a return into a dropped frame if any
+    /// were dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The return address read from memory.
     ///
     /// # Returns
     ///
-    /// * `Result<(), String>` - `Ok(())` on success, or an error message if the stack overflows.
-    pub fn push(&mut self, address: u32) -> Result<(), String> {
-        if self.stack.len() >= self.max_size {
-            return Err("Call stack overflow".to_string());
+    /// * `Result<(), String>` - An error message if no `CALL` pushed the address.
+    pub fn unwind_to(&mut self, address: u32) -> Result<(), String> {
+        if self.stack.contains(&address) {
+            while self.pop()? != address {}
+            return Ok(());
         }
-        self.stack.push(address);
-        Ok(())
+        if self.dropped > 0 {
+            self.stack.clear();
+            self.dropped -= 1;
+            return Ok(());
+        }
+        Err(format!("Mismatched return: RET to 0x{:04X}, which was never pushed by CALL", address))
     }
     
     /// Pops an address from the call stack.
@@ -80,6 +111,12 @@ impl CallStack {
     /// Clears all elements from the call stack.
     pub fn clear(&mut self) {
         self.stack.clear();
+        self.dropped = 0;
+    }
+
+    /// Returns the number of oldest frames dropped because the stack was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped
     }
     
     /// Returns the current number of elements in the call stack.
@@ -5,10 +5,16 @@
 //! decoding, and execution.
 
 use crate::memory::main_memory::WorkMemory;
+use crate::memory::mmu::PageFault;
 use crate::memory::registers::{Registers, Reg};
+use crate::chips::branch_predictor::BranchPredictor;
+use crate::chips::call_stack::CallStack;
 use crate::instructions::{moves, aritmethic, bitwise, compare, system, control, io};
 use crate::utils::assembler::operands::Operand;
 
+/// The number of cycles charged for executing a single instruction.
+pub const CYCLES_PER_INSTRUCTION: u64 = 1;
+
 /// Converts a numeric representation to a `Reg` enum variant.
 ///
 /// This helper function is used during instruction decoding to map
@@ -22,7 +28,7 @@ use crate::utils::assembler::operands::Operand;
 ///
 /// * `Result<Reg, String>` - `Ok(Reg)` if the number maps to a valid register,
 ///   or `Err(String)` if the number is invalid.
-fn number_to_register(num: u8) -> Result<Reg, String> {
+pub(crate) fn number_to_register(num: u8) -> Result<Reg, String> {
     match num {
         0 => Ok(Reg::AX), 1 => Ok(Reg::BX), 2 => Ok(Reg::CX), 3 => Ok(Reg::DX),
         4 => Ok(Reg::EX), 5 => Ok(Reg::FX), 6 => Ok(Reg::GX), 7 => Ok(Reg::HX),
@@ -89,6 +95,12 @@ pub struct CPU {
     pub registers: Registers,
     /// A flag indicating whether the CPU is halted.
     pub halted: bool,
+    /// The number of cycles spent executing instructions, including misprediction penalties.
+    pub cycles: u64,
+    /// The branch predictor observing every conditional jump.
+    pub branch_predictor: BranchPredictor,
+    /// Hardware shadow of the return addresses pushed by `CALL` and popped by `RET`.
+    pub call_stack: CallStack,
 }
 
 impl Default for CPU {
@@ -103,15 +115,22 @@ impl CPU {
         CPU {
             registers: Registers::new(),
             halted: false,
+            cycles: 0,
+            branch_predictor: BranchPredictor::default(),
+            call_stack: CallStack::new(),
         }
     }
 
     /// Resets the CPU to its initial state.
     ///
-    /// This includes resetting all registers and unhalting the CPU.
+    /// This includes resetting all registers, unhalting the CPU, clearing the
+    /// cycle count, the call stack and the branch predictor (its strategy is kept).
     pub fn reset(&mut self) {
         self.registers.reset();
         self.halted = false;
+        self.cycles = 0;
+        self.branch_predictor.reset();
+        self.call_stack.clear();
     }
 
     /// Loads a program into the CPU (currently a stub, actual loading is external).
@@ -147,7 +166,7 @@ impl CPU {
         match opcode {
             0x01 | 0x03 => { // MOVI, LODI
                 let reg = number_to_register(((instruction >> 16) & 0xFF) as u8)?;
-                let imm = (instruction & 0xFFFF) as u32;
+                let imm = instruction & 0xFFFF;
                 let op1 = Operand::Register(reg);
                 let op2 = Operand::Immediate(imm);
                 let result = match opcode {
@@ -155,9 +174,7 @@ impl CPU {
                     0x03 => moves::execute_lodi(self, &op1, &op2, memory),
                     _ => unreachable!(),
                 };
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x02 | 0x10 | 0x11 | 0x12 | 0x21 | 0x22 | 0x23 | 0x24 | 0x25 | 0x30 => { // MOVW, ADDW, SUBW, MUL, AND, OR, XOR, SHL, SHR, CMPW
@@ -167,7 +184,7 @@ impl CPU {
                     let reg2 = number_to_register(((instruction >> 8) & 0xFF) as u8)?;
                     Operand::Register(reg2)
                 } else {
-                    let imm = (instruction & 0xFFFF) as u32; // Changed to u32
+                    let imm = instruction & 0xFFFF;
                     Operand::Immediate(imm)
                 };
                 let result = match opcode {
@@ -183,9 +200,7 @@ impl CPU {
                     0x30 => compare::execute_cmpw(self, &op1, &op2, memory),
                     _ => unreachable!(),
                 };
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x04 => { // LODW
@@ -194,20 +209,16 @@ impl CPU {
                 let op1 = Operand::Register(reg);
                 let op2 = Operand::Address(addr);
                 let result = moves::execute_lodw(self, &op1, &op2, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x05 => { // STRI
                 let addr = (instruction >> 16) & 0xFF;
-                let imm = (instruction & 0xFFFF) as u32;
+                let imm = instruction & 0xFFFF;
                 let op1 = Operand::Address(addr);
                 let op2 = Operand::Immediate(imm);
                 let result = moves::execute_stri(self, &op1, &op2, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x06 => { // STRW
@@ -217,24 +228,20 @@ impl CPU {
                     let reg = number_to_register(((instruction >> 8) & 0xFF) as u8)?;
                     Operand::Register(reg)
                 } else {
-                    let imm = (instruction & 0xFFFF) as u32;
+                    let imm = instruction & 0xFFFF;
                     Operand::Immediate(imm)
                 };
                 let result = moves::execute_strw(self, &op1, &op2, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x54 => { // INSW
                 let addr_field = (instruction >> 16) & 0xFF; // 8-bit address field
                 let op1 = decode_8bit_address_operand(self, addr_field)?; // Use new helper
-                let imm = (instruction & 0xFFFF) as u32; // I/O slot, if applicable
+                let imm = instruction & 0xFFFF; // I/O slot, if applicable
                 let op2 = Operand::Immediate(imm);
                 let result = io::execute_insw(self, &op1, &op2, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x55 => { // OUTW
@@ -244,13 +251,11 @@ impl CPU {
                     let reg = number_to_register(((instruction >> 8) & 0xFF) as u8)?;
                     Operand::Register(reg)
                 } else {
-                    let imm = (instruction & 0xFFFF) as u32; // I/O slot, if applicable
+                    let imm = instruction & 0xFFFF; // I/O slot, if applicable
                     Operand::Immediate(imm)
                 };
                 let result = io::execute_outw(self, &op1, &op2, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x07 => { // PUSH
@@ -258,22 +263,18 @@ impl CPU {
                     let reg = number_to_register(((instruction >> 16) & 0xFF) as u8)?;
                     Operand::Register(reg)
                 } else {
-                    let imm = (instruction & 0xFFFF) as u32;
+                    let imm = instruction & 0xFFFF;
                     Operand::Immediate(imm)
                 };
                 let result = moves::execute_push(self, &op1, &Operand::None, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x51 => { // OUT
                 let addr_field = instruction & 0xFFFFFF;
                 let op1 = decode_address_operand(self, addr_field)?; // Use helper
                 let result = io::execute_out(self, &op1, &Operand::None, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x08 | 0x13 | 0x14 | 0x15 | 0x20 => { // POP, INC, DEC, NEG, NOT
@@ -287,9 +288,7 @@ impl CPU {
                     0x20 => bitwise::execute_not_instruction(self, &op1, &Operand::None, memory),
                     _ => unreachable!(),
                 };
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x09 => { // XCGH
@@ -298,9 +297,7 @@ impl CPU {
                 let op1 = Operand::Register(reg1);
                 let op2 = Operand::Register(reg2);
                 let result = moves::execute_xcgh(self, &op1, &op2, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x40 | 0x41 | 0x43 | 0x44 | 0x45 | 0x46 | 0x47 | 0x48 | 0x49 | 0x4A => { // JMP, CALL, JE, JNE, JGT, JGE, JLT, JLE, JS, JCO
@@ -319,34 +316,26 @@ impl CPU {
                     0x4A => compare::execute_jco(self, &op1, &Operand::None, memory),
                     _ => unreachable!(),
                 };
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x50 => { // IN
                 let addr_field = instruction & 0xFFFFFF;
                 let op1 = decode_address_operand(self, addr_field)?;
                 let result = io::execute_in(self, &op1, &Operand::None, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x52 => { // INSI
                 let addr_field = instruction & 0xFFFFFF;
                 let op1 = decode_address_operand(self, addr_field)?;
                 let result = io::execute_insi(self, &op1, &Operand::None, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x42 => { // RET
                 let result = compare::execute_ret(self, &Operand::None, &Operand::None, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0x60 | 0x61 => { // SETF, CLRF
@@ -357,16 +346,12 @@ impl CPU {
                     0x61 => control::execute_clrf(self, &op1, &Operand::None, memory),
                     _ => unreachable!(),
                 };
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             0xFF => { // HALT
                 let result = system::execute_halt(self, &Operand::None, &Operand::None, memory);
-                if let Err(e) = result {
-                    return Err(e);
-                }
+                result?;
                 Ok(())
             },
             _ => Err(format!("Unknown opcode: {:#04x}", opcode)),
@@ -375,6 +360,9 @@ impl CPU {
 
     /// Fetches the next instruction from memory, increments the program counter, and executes it.
     ///
+    /// If the instruction causes a page fault and the MMU has a fault handler,
+    /// the fault is delivered to the handler instead of stopping the CPU.
+    ///
     /// # Arguments
     ///
     /// * `memory` - A mutable reference to the `WorkMemory`.
@@ -383,11 +371,44 @@ impl CPU {
     ///
     /// * `Result<(), String>` - `Ok(())` on successful step, or `Err(String)` if an error occurs.
     pub fn step(&mut self, memory: &mut WorkMemory) -> Result<(), String> {
+        let instruction_pc = self.registers.pc;
+        memory.mmu.take_pending_fault();
+        let result = self.fetch_and_execute(memory);
+        if let Err(error) = result {
+            return match memory.mmu.take_pending_fault() {
+                Some(fault) if memory.mmu.fault_handler != 0 => {
+                    self.deliver_page_fault(memory, &fault, instruction_pc)
+                        .map_err(|e| format!("Double fault: {} while handling '{}'", e, error))
+                }
+                _ => Err(error),
+            };
+        }
+        Ok(())
+    }
+
+    /// Fetches the instruction at the program counter, advances past it and executes it.
+    fn fetch_and_execute(&mut self, memory: &mut WorkMemory) -> Result<(), String> {
         let instruction = memory.read_instruction(self.registers.pc)?;
         self.registers.pc += 4;
+        self.cycles += CYCLES_PER_INSTRUCTION;
         self.execute_instruction(memory, instruction)
     }
 
+    /// Enters the page-fault handler as if the faulting instruction had called it.
+    ///
+    /// The fault is stored in the MMU fault registers and the address of the
+    /// faulting instruction is pushed as the return address, so a `RET` from the
+    /// handler retries the instruction once the page has been mapped.
+    fn deliver_page_fault(&mut self, memory: &mut WorkMemory, fault: &PageFault, instruction_pc: u32) -> Result<(), String> {
+        memory.mmu.record_fault(fault, instruction_pc);
+        let sp = self.registers.get(&Reg::SP)?.wrapping_sub(4);
+        memory.write_u32(sp, instruction_pc)?;
+        self.registers.set(&Reg::SP, sp)?;
+        self.call_stack.push(instruction_pc);
+        self.registers.pc = memory.mmu.fault_handler;
+        Ok(())
+    }
+
     /// Runs the CPU continuously until a HALT instruction is encountered or an error occurs.
     ///
     /// # Arguments
@@ -398,7 +419,7 @@ impl CPU {
     ///
     /// * `Result<(), String>` - `Ok(())` on successful program completion, or `Err(String)` if an error occurs.
     pub fn run(&mut self, memory: &mut WorkMemory) -> Result<(), String> {
-        while !self.halted && self.registers.pc < memory.size as u32 {
+        while !self.halted && (self.registers.pc as usize) < memory.size {
             self.step(memory)?;
         }
         Ok(())
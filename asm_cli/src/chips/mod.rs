@@ -1,9 +1,11 @@
 //! # Chips Module
 //!
 //! This module contains the core hardware components (chips) of the simulated
-//! ARC computer. It includes the CPU, instruction queue, call stack, and I/O device.
+//! ARC computer. It includes the CPU, instruction queue, call stack, I/O device,
+//! and branch predictor.
 
 pub mod cpu; 
 pub mod instruction_queue;
 pub mod call_stack;
-pub mod io_device;
\ No newline at end of file
+pub mod io_device;
+pub mod branch_predictor;
\ No newline at end of file
@@ -48,7 +48,7 @@ pub fn execute_and_instruction(cpu: &mut CPU, op1: &Operand, op2: &Operand, _mem
         let val1 = cpu.registers.get(reg)?;
         let val2 = match op2 {
             Operand::Register(reg) => cpu.registers.get(reg)?,
-            Operand::Immediate(imm) => *imm as u32,
+            Operand::Immediate(imm) => *imm,
             _ => return Err("Invalid second operand for AND".to_string()),
         };
         cpu.registers.set(reg, val1 & val2)
@@ -62,7 +62,7 @@ pub fn execute_or_instruction(cpu: &mut CPU, op1: &Operand, op2: &Operand, _memo
         let val1 = cpu.registers.get(reg)?;
         let val2 = match op2 {
             Operand::Register(reg) => cpu.registers.get(reg)?,
-            Operand::Immediate(imm) => *imm as u32,
+            Operand::Immediate(imm) => *imm,
             _ => return Err("Invalid second operand for OR".to_string()),
         };
         cpu.registers.set(reg, val1 | val2)
@@ -76,7 +76,7 @@ pub fn execute_xor_instruction(cpu: &mut CPU, op1: &Operand, op2: &Operand, _mem
         let val1 = cpu.registers.get(reg)?;
         let val2 = match op2 {
             Operand::Register(ref src_reg) => cpu.registers.get(src_reg)?,
-            &Operand::Immediate(imm) => (imm).into(),
+            &Operand::Immediate(imm) => imm,
             _ => return Err("XOR requires register or immediate second operand".to_string()),
         };
         cpu.registers.set(reg, val1 ^ val2)
@@ -99,7 +99,7 @@ pub fn execute_shl_instruction(cpu: &mut CPU, op1: &Operand, op2: &Operand, _mem
         let val = cpu.registers.get(reg)?;
         let shift_amount = match op2 {
             Operand::Register(ref src_reg) => cpu.registers.get(src_reg)?,
-            &Operand::Immediate(imm) => (imm).into(),
+            &Operand::Immediate(imm) => imm,
             _ => return Err("SHL requires register or immediate second operand".to_string()),
         };
         cpu.registers.set(reg, val.wrapping_shl(shift_amount))
@@ -113,7 +113,7 @@ pub fn execute_shr_instruction(cpu: &mut CPU, op1: &Operand, op2: &Operand, _mem
         let val = cpu.registers.get(reg)?;
         let shift_amount = match op2 {
             Operand::Register(ref src_reg) => cpu.registers.get(src_reg)?,
-            &Operand::Immediate(imm) => (imm).into(),
+            &Operand::Immediate(imm) => imm,
             _ => return Err("SHR requires register or immediate second operand".to_string()),
         };
         cpu.registers.set(reg, val.wrapping_shr(shift_amount))
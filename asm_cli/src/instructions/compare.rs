@@ -9,22 +9,6 @@ use crate::memory::main_memory::WorkMemory;
 use crate::utils::assembler::operands::Operand;
 use crate::memory::registers::Reg;
 
-/// Executes the `CMPW` instruction, performing a floating-point comparison.
-///
-/// This instruction compares the values of `op1` and `op2` by effectively
-/// subtracting `op2` from `op1` and updating the CPU's flags (zero, sign)
-/// based on the result, without storing the result itself.
-///
-/// # Arguments
-///
-/// * `cpu` - A mutable reference to the `CPU` state.
-/// * `op1` - The first operand, which can be a register, immediate value, or memory address.
-/// * `op2` - The second operand, which can be a register, immediate value, or memory address.
-/// * `memory` - A mutable reference to the `WorkMemory`.
-///
-/// # Returns
-///
-/// * `Result<(), String>` - `Ok(())` on successful execution, or an error message on failure.
 /// Executes the `CMPW` instruction, performing a floating-point comparison.
 ///
 /// This instruction compares the values of `op1` and `op2` by effectively
@@ -104,7 +88,8 @@ pub fn execute_cmpw(cpu: &mut CPU, op1: &Operand, op2: &Operand, memory: &mut Wo
 /// # Arguments
 ///
 /// * `cpu` - A mutable reference to the `CPU` state.
-/// * `op1` - The destination operand, which can be a label, immediate value, address, or register.
+/// * `op1` - The destination operand, which can be a label, immediate value, address, or register,
+///   either directly or as `[REG]`.
 /// * `_op2` - The second operand (unused in this instruction).
 /// * `_memory` - A mutable reference to the `WorkMemory` (unused in this instruction).
 ///
@@ -116,9 +101,9 @@ pub fn execute_jmp(cpu: &mut CPU, op1: &Operand, _op2: &Operand, _memory: &mut W
         Operand::Label(label) => {
             label.parse::<u32>().map_err(|_| format!("Invalid address: {}", label))?
         },
-        Operand::Immediate(imm) => *imm as u32,
+        Operand::Immediate(imm) => *imm,
         Operand::Address(addr) => *addr,
-        Operand::Register(reg) => cpu.registers.get(reg)?,
+        Operand::Register(reg) | Operand::AddressRegister(reg) => cpu.registers.get(reg)?,
         _ => return Err("JMP requires a label, immediate, address, or register operand".to_string()),
     };
     
@@ -129,11 +114,14 @@ pub fn execute_jmp(cpu: &mut CPU, op1: &Operand, _op2: &Operand, _memory: &mut W
 ///
 /// The current program counter (return address) is pushed onto the stack,
 /// and then the program counter is set to the address specified by `op1`.
+/// The return address is also pushed onto the CPU's hardware call stack so
+/// that `RET` can be checked and backtraces can be produced.
 ///
 /// # Arguments
 ///
 /// * `cpu` - A mutable reference to the `CPU` state.
-/// * `op1` - The destination operand, which can be a label, immediate value, address, or register.
+/// * `op1` - The destination operand, which can be a label, immediate value, address, or register,
+///   either directly or as `[REG]`.
 /// * `_op2` - The second operand (unused in this instruction).
 /// * `memory` - A mutable reference to the `WorkMemory` for stack operations.
 ///
@@ -145,9 +133,9 @@ pub fn execute_call(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut W
         Operand::Label(label) => {
             label.parse::<u32>().map_err(|_| format!("Invalid address: {}", label))?
         },
-        Operand::Immediate(imm) => *imm as u32,
+        Operand::Immediate(imm) => *imm,
         Operand::Address(addr) => *addr,
-        Operand::Register(reg) => cpu.registers.get(reg)?,
+        Operand::Register(reg) | Operand::AddressRegister(reg) => cpu.registers.get(reg)?,
         _ => return Err("CALL requires a label, immediate, address, or register operand".to_string()),
     };
     
@@ -158,6 +146,7 @@ pub fn execute_call(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut W
     
     // Update stack pointer
     cpu.registers.set(&Reg::SP, sp)?;
+    cpu.call_stack.push(return_addr);
     
     // Jump to subroutine
     cpu.registers.set(&Reg::PC, address)
@@ -168,6 +157,11 @@ pub fn execute_call(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut W
 /// The return address is popped from the stack, and the program counter (`PC`)
 /// is set to this address.
 ///
+/// The address is checked against the CPU's hardware call stack. Returning to
+/// an address deeper in the call stack unwinds the frames above it; returning
+/// to an address that no `CALL` ever pushed is reported as a mismatched return,
+/// unless the call stack has dropped old frames the return could belong to.
+///
 /// # Arguments
 ///
 /// * `cpu` - A mutable reference to the `CPU` state.
@@ -183,6 +177,9 @@ pub fn execute_ret(cpu: &mut CPU, _op1: &Operand, _op2: &Operand, memory: &mut W
     let sp = cpu.registers.get(&Reg::SP)?;
     let return_addr = memory.read_u32(sp)?;
     
+    // Check the return address against the hardware call stack
+    cpu.call_stack.unwind_to(return_addr)?;
+    
     // Update stack pointer
     cpu.registers.set(&Reg::SP, sp.wrapping_add(4))?;
     
@@ -190,6 +187,36 @@ pub fn execute_ret(cpu: &mut CPU, _op1: &Operand, _op2: &Operand, memory: &mut W
     cpu.registers.set(&Reg::PC, return_addr)
 }
 
+/// Resolves a conditional jump once its condition has been evaluated.
+///
+/// The outcome is reported to the CPU's branch predictor, keyed by the address
+/// of the jump instruction (the program counter has already advanced past it).
+/// A misprediction adds the predictor's penalty to the CPU cycle count. If the
+/// branch is taken, the jump is performed as with `JMP`.
+///
+/// # Arguments
+///
+/// * `cpu` - A mutable reference to the `CPU` state.
+/// * `taken` - Whether the jump condition holds.
+/// * `op1` - The destination operand for the jump.
+/// * `memory` - A mutable reference to the `WorkMemory`.
+///
+/// # Returns
+///
+/// * `Result<(), String>` - `Ok(())` on successful execution, or an error message on failure.
+fn execute_conditional_jump(cpu: &mut CPU, taken: bool, op1: &Operand, memory: &mut WorkMemory) -> Result<(), String> {
+    let site = cpu.registers.pc.wrapping_sub(4);
+    if !cpu.branch_predictor.observe(site, taken) {
+        cpu.cycles += cpu.branch_predictor.misprediction_penalty;
+    }
+
+    if taken {
+        execute_jmp(cpu, op1, &Operand::None, memory)
+    } else {
+        Ok(())
+    }
+}
+
 // Conditional jump implementations
 /// Executes the `JE` (Jump if Equal) instruction.
 ///
@@ -207,11 +234,8 @@ pub fn execute_ret(cpu: &mut CPU, _op1: &Operand, _op2: &Operand, memory: &mut W
 ///
 /// * `Result<(), String>` - `Ok(())` on successful execution, or an error message on failure.
 pub fn execute_je(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut WorkMemory) -> Result<(), String> {
-    if cpu.registers.get_flag("zero")? {
-        execute_jmp(cpu, op1, &Operand::None, memory)
-    } else {
-        Ok(())
-    }
+    let taken = cpu.registers.get_flag("zero")?;
+    execute_conditional_jump(cpu, taken, op1, memory)
 }
 
 /// Executes the `JNE` (Jump if Not Equal) instruction.
@@ -230,11 +254,8 @@ pub fn execute_je(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wor
 ///
 /// * `Result<(), String>` - `Ok(())` on successful execution, or an error message on failure.
 pub fn execute_jne(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut WorkMemory) -> Result<(), String> {
-    if !cpu.registers.get_flag("zero")? {
-        execute_jmp(cpu, op1, &Operand::None, memory)
-    } else {
-        Ok(())
-    }
+    let taken = !cpu.registers.get_flag("zero")?;
+    execute_conditional_jump(cpu, taken, op1, memory)
 }
 
 /// Executes the `JGT` (Jump if Greater Than) instruction.
@@ -257,11 +278,8 @@ pub fn execute_jgt(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wo
     // Jump if greater than (for floats: not zero and not sign)
     let zero = cpu.registers.get_flag("zero")?;
     let sign = cpu.registers.get_flag("sign")?;
-    if !zero && !sign {
-        execute_jmp(cpu, op1, &Operand::None, memory)
-    } else {
-        Ok(())
-    }
+    let taken = !zero && !sign;
+    execute_conditional_jump(cpu, taken, op1, memory)
 }
 
 /// Executes the `JGE` (Jump if Greater Than or Equal) instruction.
@@ -283,11 +301,8 @@ pub fn execute_jgt(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wo
 pub fn execute_jge(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut WorkMemory) -> Result<(), String> {
     // Jump if greater than or equal (for floats: not sign)
     let sign = cpu.registers.get_flag("sign")?;
-    if !sign {
-        execute_jmp(cpu, op1, &Operand::None, memory)
-    } else {
-        Ok(())
-    }
+    let taken = !sign;
+    execute_conditional_jump(cpu, taken, op1, memory)
 }
 
 /// Executes the `JLT` (Jump if Less Than) instruction.
@@ -310,11 +325,8 @@ pub fn execute_jlt(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wo
     // Jump if less than (for floats: not zero and sign)
     let zero = cpu.registers.get_flag("zero")?;
     let sign = cpu.registers.get_flag("sign")?;
-    if !zero && sign {
-        execute_jmp(cpu, op1, &Operand::None, memory)
-    } else {
-        Ok(())
-    }
+    let taken = !zero && sign;
+    execute_conditional_jump(cpu, taken, op1, memory)
 }
 
 /// Executes the `JLE` (Jump if Less Than or Equal) instruction.
@@ -337,11 +349,8 @@ pub fn execute_jle(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wo
     // Jump if less than or equal (for floats: zero or sign)
     let zero = cpu.registers.get_flag("zero")?;
     let sign = cpu.registers.get_flag("sign")?;
-    if zero || sign {
-        execute_jmp(cpu, op1, &Operand::None, memory)
-    } else {
-        Ok(())
-    }
+    let taken = zero || sign;
+    execute_conditional_jump(cpu, taken, op1, memory)
 }
 
 /// Executes the `JS` (Jump if Sign) instruction.
@@ -361,11 +370,8 @@ pub fn execute_jle(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wo
 /// * `Result<(), String>` - `Ok(())` on successful execution, or an error message on failure.
 pub fn execute_js(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut WorkMemory) -> Result<(), String> {
     // Jump if sign (negative)
-    if cpu.registers.get_flag("sign")? {
-        execute_jmp(cpu, op1, &Operand::None, memory)
-    } else {
-        Ok(())
-    }
+    let taken = cpu.registers.get_flag("sign")?;
+    execute_conditional_jump(cpu, taken, op1, memory)
 }
 
 /// Executes the `JCO` (Jump if Carry or Overflow) instruction.
@@ -387,12 +393,8 @@ pub fn execute_jco(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wo
     // Jump if carry or overflow (no longer standard for floats, but kept for compatibility if needed)
     let carry = cpu.registers.get_flag("carry")?;
     let overflow = cpu.registers.get_flag("overflow")?;
-    
-    if carry || overflow {
-        execute_jmp(cpu, op1, &Operand::None, memory)
-    } else {
-        Ok(())
-    }
+    let taken = carry || overflow;
+    execute_conditional_jump(cpu, taken, op1, memory)
 }
 
 #[cfg(test)]
@@ -436,6 +438,11 @@ mod compare_test {
 
         execute_jmp(&mut cpu, &Operand::Immediate(123), &Operand::None, &mut memory).unwrap();
         assert_eq!(cpu.registers.get(&Reg::PC).unwrap(), 123);
+
+        // JMP [BX]
+        cpu.registers.set(&Reg::BX, 0x40).unwrap();
+        execute_jmp(&mut cpu, &Operand::AddressRegister(Reg::BX), &Operand::None, &mut memory).unwrap();
+        assert_eq!(cpu.registers.get(&Reg::PC).unwrap(), 0x40);
     }
 
     #[test]
@@ -455,6 +462,11 @@ mod compare_test {
         execute_ret(&mut cpu, &Operand::None, &Operand::None, &mut memory).unwrap();
         assert_eq!(cpu.registers.get(&Reg::PC).unwrap(), 104);
         assert_eq!(cpu.registers.get(&Reg::SP).unwrap(), 1020);
+
+        // CALL [DX], as `CALLR DX` assembles
+        cpu.registers.set(&Reg::DX, 300).unwrap();
+        execute_call(&mut cpu, &Operand::AddressRegister(Reg::DX), &Operand::None, &mut memory).unwrap();
+        assert_eq!(cpu.registers.get(&Reg::PC).unwrap(), 300);
     }
 
     #[test]
@@ -473,4 +485,67 @@ mod compare_test {
         execute_jne(&mut cpu, &Operand::Immediate(400), &Operand::None, &mut memory).unwrap();
         assert_eq!(cpu.registers.get(&Reg::PC).unwrap(), 400);
     }
+
+    #[test]
+    fn conditional_jump_feeds_branch_predictor() {
+        let mut cpu = CPU::new();
+        let mut memory = WorkMemory::new(1024);
+
+        // JE at 0x20 taken, predicted not-taken by default
+        cpu.registers.set(&Reg::PC, 0x24).unwrap();
+        cpu.registers.set_flag("zero", true);
+        execute_je(&mut cpu, &Operand::Immediate(0x80), &Operand::None, &mut memory).unwrap();
+        assert_eq!(cpu.cycles, cpu.branch_predictor.misprediction_penalty);
+
+        // JNE at 0x20 not taken, predicted correctly
+        cpu.registers.set(&Reg::PC, 0x24).unwrap();
+        execute_jne(&mut cpu, &Operand::Immediate(0x80), &Operand::None, &mut memory).unwrap();
+        assert_eq!(cpu.registers.get(&Reg::PC).unwrap(), 0x24);
+        assert_eq!(cpu.cycles, cpu.branch_predictor.misprediction_penalty);
+
+        let site = &cpu.branch_predictor.sites[&0x20];
+        assert_eq!(site.executed, 2);
+        assert_eq!(site.taken, 1);
+        assert_eq!(site.correct, 1);
+    }
+
+    #[test]
+    fn call_ret_shadow_call_stack() {
+        let mut cpu = CPU::new();
+        let mut memory = WorkMemory::new(1024);
+        cpu.registers.set(&Reg::SP, 1020).unwrap();
+        cpu.registers.set(&Reg::PC, 100).unwrap();
+
+        execute_call(&mut cpu, &Operand::Immediate(200), &Operand::None, &mut memory).unwrap();
+        assert_eq!(cpu.call_stack.peek().unwrap(), 104);
+        execute_ret(&mut cpu, &Operand::None, &Operand::None, &mut memory).unwrap();
+        assert!(cpu.call_stack.is_empty());
+
+        // Clobber the return address on the stack before returning
+        execute_call(&mut cpu, &Operand::Immediate(200), &Operand::None, &mut memory).unwrap();
+        memory.write_u32(cpu.registers.get(&Reg::SP).unwrap(), 0x300).unwrap();
+        let err = execute_ret(&mut cpu, &Operand::None, &Operand::None, &mut memory).unwrap_err();
+        assert!(err.contains("Mismatched return"));
+    }
+
+    #[test]
+    fn deep_recursion_drops_the_oldest_shadow_frames() {
+        let mut cpu = CPU::new();
+        let mut memory = WorkMemory::new(8192);
+        cpu.registers.set(&Reg::SP, 8192).unwrap();
+        cpu.registers.set(&Reg::PC, 100).unwrap();
+
+        // A routine at 200 that calls itself 300 times
+        execute_call(&mut cpu, &Operand::Immediate(200), &Operand::None, &mut memory).unwrap();
+        for _ in 0..300 {
+            execute_call(&mut cpu, &Operand::Immediate(200), &Operand::None, &mut memory).unwrap();
+        }
+        assert_eq!(cpu.call_stack.depth(), 256);
+        assert_eq!(cpu.call_stack.dropped(), 45);
+        for _ in 0..301 {
+            execute_ret(&mut cpu, &Operand::None, &Operand::None, &mut memory).unwrap();
+        }
+        assert_eq!(cpu.registers.get(&Reg::PC).unwrap(), 104);
+        assert_eq!(cpu.registers.get(&Reg::SP).unwrap(), 8192);
+    }
 }
\ No newline at end of file
@@ -34,18 +34,6 @@ fn flag_id_to_name(id: u8) -> Result<&'static str, String> {
     }
 }
 
-/// Executes the `SETF` instruction, setting a specified CPU flag to true.
-///
-/// # Arguments
-///
-/// * `cpu` - A mutable reference to the `CPU` state.
-/// * `op1` - The operand, which must be an `Operand::Flag` containing the flag ID.
-/// * `_op2` - The second operand (unused in this instruction).
-/// * `_memory` - A mutable reference to the `WorkMemory` (unused in this instruction).
-///
-/// # Returns
-///
-/// * `Result<(), String>` - `Ok(())` on successful execution, or an error message on failure.
 /// Executes the `SETF` instruction, setting a specified CPU flag to true.
 ///
 /// # Arguments
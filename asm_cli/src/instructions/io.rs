@@ -6,16 +6,12 @@
 
 use crate::chips::cpu::CPU;
 use crate::memory::main_memory::WorkMemory;
-
-// Fixed I/O segment addresses
-const IO_START: u32 = 0xE000;
-const IO_SIZE: u32 = 0x1000;
 use crate::utils::assembler::operands::Operand;
 
 /// Executes the `IN` instruction, which reads data from an I/O port into memory.
 ///
-/// The `IN` instruction reads a sequence of bytes from the I/O region
-/// (starting at `IO_START`) until a null byte (0x00) is encountered,
+/// The `IN` instruction reads a sequence of bytes from the console input buffer
+/// (the start of the console's bus window) until a null byte (0x00) is encountered,
 /// and then loads this data into the memory address specified by `op1`.
 ///
 /// # Arguments
@@ -36,9 +32,10 @@ pub fn execute_in(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wor
         _ => return Err("IN requires an address or address register operand".to_string()),
     };
 
+    let (io_start, io_size) = memory.console_window();
     let mut buffer = Vec::new();
-    for i in 0..IO_SIZE {
-        let byte = memory.read_u8(IO_START + i)?;
+    for i in 0..io_size {
+        let byte = memory.read_u8(io_start + i)?;
         if byte == 0 {
             break;
         }
@@ -107,7 +104,7 @@ pub fn execute_outi(_cpu: &mut CPU, op1: &Operand, _op2: &Operand, _memory: &mut
 /// Executes the `INSW` instruction, reading a word (32-bit) from an I/O port into memory.
 ///
 /// The `INSW` instruction reads a 32-bit word from the I/O region
-/// (starting at `IO_START`) and stores it into the memory address specified by `op1`.
+/// (the console's bus window) and stores it into the memory address specified by `op1`.
 ///
 /// # Arguments
 ///
@@ -207,19 +204,29 @@ pub fn execute_out(cpu: &mut CPU, op1: &Operand, _op2: &Operand, memory: &mut Wo
 mod io_test {
     use super::*;
     use crate::chips::cpu::CPU;
-    use crate::memory::main_memory::{WorkMemory, IO_START, MEMORY_MAX_SIZE};
+    use crate::memory::bus::ConsoleDevice;
+    use crate::memory::main_memory::{default_console_base, WorkMemory, DEFAULT_MEMORY_SIZE, IO_SIZE};
     use crate::utils::assembler::operands::Operand;
 
+    /// Creates a memory with the console mapped where the emulator maps it.
+    fn memory() -> WorkMemory {
+        let mut memory = WorkMemory::new(DEFAULT_MEMORY_SIZE);
+        memory.bus.map(default_console_base(DEFAULT_MEMORY_SIZE), IO_SIZE, Box::new(ConsoleDevice::new(IO_SIZE))).unwrap();
+        memory
+    }
+
     #[test]
     fn in_behavior() {
         let mut cpu = CPU::new();
-        let mut memory = WorkMemory::new(MEMORY_MAX_SIZE);
+        let mut memory = memory();
         let dest_addr = 0x100;
 
+        let io_start = memory.console_window().0;
+
         // Prepare some data in the I/O region
-        memory.write_u8(IO_START, 0x41).unwrap(); // 'A'
-        memory.write_u8(IO_START + 1, 0x42).unwrap(); // 'B'
-        memory.write_u8(IO_START + 2, 0x00).unwrap(); // Null terminator
+        memory.write_u8(io_start, 0x41).unwrap(); // 'A'
+        memory.write_u8(io_start + 1, 0x42).unwrap(); // 'B'
+        memory.write_u8(io_start + 2, 0x00).unwrap(); // Null terminator
 
         execute_in(&mut cpu, &Operand::Address(dest_addr), &Operand::None, &mut memory).unwrap();
 
@@ -231,7 +238,7 @@ mod io_test {
     #[test]
     fn out_behavior() {
         let mut cpu = CPU::new();
-        let mut memory = WorkMemory::new(MEMORY_MAX_SIZE);
+        let mut memory = memory();
         let src_addr = 0x100;
 
         // Prepare some data in memory to be "outputted"
@@ -249,7 +256,7 @@ mod io_test {
     #[test]
     fn insi_behavior() {
         let mut cpu = CPU::new();
-        let mut memory = WorkMemory::new(MEMORY_MAX_SIZE);
+        let mut memory = memory();
         let dest_addr = 0x200;
 
         execute_insi(&mut cpu, &Operand::Address(dest_addr), &Operand::None, &mut memory).unwrap();
@@ -259,7 +266,7 @@ mod io_test {
     #[test]
     fn outi_behavior() {
         let mut cpu = CPU::new();
-        let mut memory = WorkMemory::new(MEMORY_MAX_SIZE);
+        let mut memory = memory();
         let immediate_val = 12345;
 
         // This test primarily checks for no errors during execution
@@ -272,7 +279,7 @@ mod io_test {
     #[test]
     fn insw_behavior() {
         let mut cpu = CPU::new();
-        let mut memory = WorkMemory::new(MEMORY_MAX_SIZE);
+        let mut memory = memory();
         let dest_addr = 0x300;
 
         execute_insw(&mut cpu, &Operand::Address(dest_addr), &Operand::None, &mut memory).unwrap();
@@ -282,7 +289,7 @@ mod io_test {
     #[test]
     fn outw_behavior() {
         let mut cpu = CPU::new();
-        let mut memory = WorkMemory::new(MEMORY_MAX_SIZE);
+        let mut memory = memory();
         let src_addr = 0x300;
         let word_val = 0x12345678;
 
@@ -1,18 +1,220 @@
+use arc_emulator::utils::runner::{assemble_headless, assemble_object_headless, disassemble_file, run_headless, DumpRequest, RunOptions, DEFAULT_MAX_STEPS};
+use arc_emulator::utils::assembler::conditionals::parse_define;
+use arc_emulator::utils::assembler::lint::lint_program;
 use arc_emulator::utils::tui::TuiApp;
 use arc_emulator::utils::workspaces::Workspace;
 use arc_emulator::utils::config::config_manager::ConfigManager;
-use arc_emulator::memory::main_memory::DEFAULT_MEMORY_SIZE;
+use arc_emulator::utils::linker::link;
+use arc_emulator::utils::linker::executable::Executable;
+use arc_emulator::utils::linker::object::{Archive, ObjectFile, OBJECT_VERSION};
+use arc_emulator::utils::linker::script::parse_linker_script;
+use arc_emulator::utils::assembler::command_processor::SegmentLayout;
+use arc_emulator::memory::dump::{format_segments, DumpFormat};
+use arc_emulator::memory::main_memory::{ImageFormat, DEFAULT_MEMORY_SIZE, MEMORY_MAX_SIZE};
 use std::env;
-use clap::Parser;
+use std::path::{Path, PathBuf};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
 
 /// ARC CPU Emulator and Assembler CLI
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// Set the total memory size for the emulator (e.g., 64KB, 1MB, 8MB).
-    /// Minimum: 64KB, Maximum: 8MB.
-    #[arg(long, short, value_parser = parse_memory_size, help = "Set the total memory size (e.g., 64KB, 1MB, 8MB). Min: 64KB, Max: 8MB)")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Set the total memory size for the emulator (e.g., 64KB, 1MB, 16MB, 4GB).
+    /// Minimum: 64KB, Maximum: 4GB. Memory is allocated lazily, so large sizes are cheap.
+    #[arg(long, short, global = true, value_parser = parse_memory_size, help = "Set the total memory size (e.g., 64KB, 1MB, 16MB, 4GB). Min: 64KB, Max: 4GB")]
     memory_size: Option<usize>,
+
+    #[command(flatten)]
+    machine: MachineArgs,
+
+    #[command(flatten)]
+    assembler: AssemblerArgs,
+
+    /// Load a memory image (raw binary, Intel HEX or S-record) or an `.arx` executable and open it in the emulator.
+    #[arg(long, value_name = "FILE", help = "Load an .arx executable, or a raw binary, Intel HEX or S-record image into memory")]
+    load: Option<PathBuf>,
+
+    /// The format of the image; guessed from the extension (.hex/.ihex, .srec/.s19/.s28/.s37/.mot, otherwise raw).
+    #[arg(long, value_parser = ImageFormat::from_name, requires = "load", help = "Image format: raw, ihex or srec (default: from the file extension)")]
+    format: Option<ImageFormat>,
+
+    /// The address raw images are loaded at.
+    #[arg(long, value_parser = parse_address, default_value = "0", requires = "load", help = "Load address of raw images (default: 0)")]
+    load_address: u32,
+
+    /// The entry point, overriding the start address recorded in the image.
+    #[arg(long, value_parser = parse_address, requires = "load", help = "Entry point (default: from the image, else its lowest address)")]
+    entry: Option<u32>,
+}
+
+/// Options of the emulated machine, taken by the emulator and `run`.
+#[derive(Args, Debug, Clone, Default, PartialEq)]
+struct MachineArgs {
+    /// Install the MMU (page tables, TLB and page faults). Programs enable translation
+    /// through the MMU control registers at 0xD000.
+    #[arg(long, help = "Install the MMU; programs enable paging through its registers at 0xD000")]
+    mmu: bool,
+
+    /// Report reads of memory that was never initialized by the program load or a write.
+    #[arg(long, help = "Report reads of uninitialized memory (like a memcheck tool)")]
+    memcheck: bool,
+
+    /// Devices moved to another base address, as `NAME=ADDRESS` (e.g. `console=0x20000`).
+    #[arg(long = "device", value_name = "NAME=ADDRESS", value_parser = parse_device, help = "Map a device at another base address, e.g. console=0x20000 (repeatable)")]
+    devices: Vec<(String, u32)>,
+}
+
+/// Options of the assembler, taken by the emulator and every subcommand that assembles source.
+#[derive(Args, Debug, Clone, Default, PartialEq)]
+struct AssemblerArgs {
+    /// Directories searched for `.include`d files that are not next to the including file.
+    #[arg(long = "include-path", short = 'I', value_name = "DIR", help = "Add a directory to the include path (repeatable)")]
+    include_paths: Vec<PathBuf>,
+
+    /// Constants for conditional assembly, as `NAME` (defined as 1) or `NAME=VALUE`.
+    #[arg(long = "define", short = 'D', value_name = "NAME[=VALUE]", value_parser = parse_define, help = "Define an assembler constant (repeatable)")]
+    defines: Vec<(String, u32)>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Assemble and run a program without the TUI, writing memory dumps when it halts.
+    Run {
+        /// The assembly source file to run.
+        program: PathBuf,
+
+        /// Stop with an error if the program has not halted after this many instructions.
+        #[arg(long, default_value_t = DEFAULT_MAX_STEPS, help = "Maximum number of instructions to execute")]
+        max_steps: u64,
+
+        /// Write a memory range when the program halts, e.g. `data=data.hex` or `0x8000:64=out.rs,rust`.
+        #[arg(long, value_parser = DumpRequest::parse, value_name = "RANGE=FILE[,FORMAT]", help = "Dump a range (START:LEN, START..END, text, data or stack) at HALT; FILE '-' is stdout")]
+        dump: Vec<DumpRequest>,
+
+        #[command(flatten)]
+        machine: MachineArgs,
+
+        #[command(flatten)]
+        assembler: AssemblerArgs,
+    },
+    /// Assemble a program into an `.arx` executable.
+    Assemble {
+        /// The assembly source file.
+        program: PathBuf,
+
+        /// The executable file; defaults to the program with a `.arx` extension.
+        #[arg(long, short, value_name = "FILE", help = "Executable file (default: PROGRAM.arx)")]
+        output: Option<PathBuf>,
+
+        /// Leave out the symbol and line tables.
+        #[arg(long, help = "Leave out the symbol and line tables")]
+        strip: bool,
+
+        #[command(flatten)]
+        assembler: AssemblerArgs,
+    },
+    /// Disassemble an `.arx` executable or a memory image.
+    Disasm {
+        /// The executable, or a raw binary, Intel HEX or S-record image.
+        image: PathBuf,
+
+        /// The format of an image; guessed from the extension (.arx executables are recognized by theirs).
+        #[arg(long, value_parser = ImageFormat::from_name, help = "Image format: raw, ihex or srec (default: from the file extension)")]
+        format: Option<ImageFormat>,
+
+        /// The address raw images start at.
+        #[arg(long, value_parser = parse_address, default_value = "0", help = "Start address of raw images (default: 0)")]
+        base: u32,
+
+        /// The file to write; standard output by default.
+        #[arg(long, short, value_name = "FILE", help = "Output file (default: standard output)")]
+        output: Option<PathBuf>,
+
+        /// Name the pseudo-instructions that instruction sequences match; hand-written code may match too.
+        #[arg(long, help = "Mark sequences matching a pseudo-instruction expansion (e.g. XOR AX, AX as CLR AX)")]
+        pseudo: bool,
+    },
+    /// Assemble a program and print its symbol table (address, size, section and name).
+    Symbols {
+        /// The assembly source file.
+        program: PathBuf,
+
+        #[command(flatten)]
+        assembler: AssemblerArgs,
+    },
+    /// Assemble a program and write its listing, symbol table and cross-reference table.
+    Listing {
+        /// The assembly source file.
+        program: PathBuf,
+
+        /// The listing file; defaults to the program with a `.lst` extension.
+        #[arg(long, short, value_name = "FILE", help = "Listing file (default: PROGRAM.lst); '-' is stdout")]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        assembler: AssemblerArgs,
+    },
+    /// Assemble a program and print the warnings of the lint pass.
+    Lint {
+        /// The assembly source file.
+        program: PathBuf,
+
+        #[command(flatten)]
+        assembler: AssemblerArgs,
+    },
+    /// Assemble a program into a relocatable object file for the linker.
+    Object {
+        /// The assembly source file.
+        program: PathBuf,
+
+        /// The object file; defaults to the program with a `.o` extension.
+        #[arg(long, short, value_name = "FILE", help = "Object file (default: PROGRAM.o)")]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        assembler: AssemblerArgs,
+    },
+    /// Combine object files into a static archive.
+    Archive {
+        /// The archive file to write.
+        #[arg(long, short, value_name = "FILE", help = "Archive file to write (usually .a)")]
+        output: PathBuf,
+
+        /// The object files to add, in order.
+        #[arg(required = true)]
+        objects: Vec<PathBuf>,
+    },
+    /// Link object files and archives into a program image.
+    Link {
+        /// The object files (`.o`) and archives (`.a`) to link; every object is included,
+        /// archive members only when they define a symbol that is still undefined.
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// A linker script setting text_start, data_start, stack_start and stack_size.
+        #[arg(long, short = 'T', value_name = "FILE", help = "Linker script with the segment layout")]
+        script: Option<PathBuf>,
+
+        /// The image to write: an executable for `.arx`, Intel HEX for `.hex`/`.ihex`, raw binary from the text start otherwise.
+        #[arg(long, short, value_name = "FILE", default_value = "a.arx", help = "Output file (default: a.arx); .arx executable, .hex Intel HEX, raw binary otherwise")]
+        output: PathBuf,
+    },
+}
+
+fn parse_address(s: &str) -> Result<u32, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse::<u32>(),
+    }.map_err(|_| format!("Invalid address: {} (use decimal or 0x-prefixed hex)", s))
+}
+
+fn parse_device(s: &str) -> Result<(String, u32), String> {
+    let (name, base) = s.split_once('=').ok_or(format!("Invalid device mapping: {} (expected NAME=ADDRESS)", s))?;
+    Ok((name.trim().to_string(), parse_address(base.trim())?))
 }
 
 fn parse_memory_size(s: &str) -> Result<usize, String> {
@@ -21,41 +223,240 @@ fn parse_memory_size(s: &str) -> Result<usize, String> {
         (&s_upper[0..s_upper.len() - 2], "KB")
     } else if s_upper.ends_with("MB") {
         (&s_upper[0..s_upper.len() - 2], "MB")
+    } else if s_upper.ends_with("GB") {
+        (&s_upper[0..s_upper.len() - 2], "GB")
     } else {
-        return Err(format!("Invalid memory size format: {}. Use KB or MB suffix (e.g., 64KB, 1MB).", s));
+        return Err(format!("Invalid memory size format: {}. Use KB, MB or GB suffix (e.g., 64KB, 1MB, 4GB).", s));
     };
 
     let value = value_str.parse::<f64>()
         .map_err(|_| format!("Invalid number in memory size: {}", value_str))?;
 
     let bytes = match unit {
-        "KB" => (value * 1024.0) as usize,
-        "MB" => (value * 1024.0 * 1024.0) as usize,
+        "KB" => (value * 1024.0) as u64,
+        "MB" => (value * 1024.0 * 1024.0) as u64,
+        "GB" => (value * 1024.0 * 1024.0 * 1024.0) as u64,
         _ => unreachable!(), // Should be caught by earlier checks
     };
 
-    const MIN_MEMORY_SIZE: usize = 64 * 1024; // 64KB
-    const MAX_MEMORY_SIZE: usize = 8 * 1024 * 1024; // 8MB
+    const MIN_MEMORY_SIZE: u64 = 64 * 1024; // 64KB
 
-    if bytes < MIN_MEMORY_SIZE || bytes > MAX_MEMORY_SIZE {
+    if !(MIN_MEMORY_SIZE..=MEMORY_MAX_SIZE).contains(&bytes) {
         return Err(format!(
-            "Memory size {} is out of range. Minimum: {}KB, Maximum: {}MB.",
-            s, MIN_MEMORY_SIZE / 1024, MAX_MEMORY_SIZE / (1024 * 1024)
+            "Memory size {} is out of range. Minimum: {}KB, Maximum: {}GB.",
+            s, MIN_MEMORY_SIZE / 1024, MEMORY_MAX_SIZE / (1024 * 1024 * 1024)
         ));
     }
 
-    Ok(bytes)
+    usize::try_from(bytes).map_err(|_| format!("Memory size {} does not fit in the address space of this platform.", s))
 }
 
 fn main() {
     let cli = Cli::parse();
+    // Subcommands take only the options that apply to them, after their name
+    if cli.command.is_some() && (cli.machine != MachineArgs::default() || cli.assembler != AssemblerArgs::default()) {
+        Cli::command()
+            .bin_name("arcs")
+            .error(ErrorKind::ArgumentConflict, "emulator and assembler options go after the subcommand, e.g. `arcs run PROGRAM --mmu -D DEBUG`")
+            .exit();
+    }
     let memory_size = cli.memory_size.unwrap_or(DEFAULT_MEMORY_SIZE);
 
+    if let Some(Command::Run { program, max_steps, dump, machine, assembler }) = cli.command {
+        let options = RunOptions {
+            program, memory_size, max_steps, mmu: machine.mmu, memcheck: machine.memcheck, devices: machine.devices,
+            include_paths: assembler.include_paths, defines: assembler.defines, dumps: dump,
+        };
+        match run_headless(&options) {
+            Ok(summary) => {
+                for report in &summary.memcheck {
+                    eprintln!("Memcheck: {}", report);
+                }
+                eprintln!("Halted after {} instructions ({} cycles)", summary.steps, summary.cycles);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Assemble { program, output, strip, assembler }) = cli.command {
+        let output = output.unwrap_or_else(|| program.with_extension("arx"));
+        let options = assembler_options(program, memory_size, assembler);
+        let result = assemble_headless(&options).and_then(|assembled| {
+            let mut executable = Executable::new(assembled);
+            if strip {
+                executable.strip();
+            }
+            executable.save(&output)
+        });
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Disasm { image, format, base, output, pseudo }) = cli.command {
+        let result = disassemble_file(&image, format, base, pseudo).and_then(|text| match &output {
+            Some(output) => std::fs::write(output, text).map_err(|e| format!("Error writing {}: {}", output.display(), e)),
+            None => {
+                print!("{}", text);
+                Ok(())
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Symbols { program, assembler }) = cli.command {
+        let options = assembler_options(program, memory_size, assembler);
+        match assemble_headless(&options) {
+            Ok(assembled) => print!("{}", assembled.symbols.dump()),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Listing { program, output, assembler }) = cli.command {
+        let output = output.unwrap_or_else(|| program.with_extension("lst"));
+        let options = assembler_options(program, memory_size, assembler);
+        let result = assemble_headless(&options).and_then(|assembled| {
+            let listing = assembled.listing.render(&assembled.symbols);
+            if output == Path::new("-") {
+                print!("{}", listing);
+                Ok(())
+            } else {
+                std::fs::write(&output, listing).map_err(|e| format!("Error writing {}: {}", output.display(), e))
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Lint { program, assembler }) = cli.command {
+        let options = assembler_options(program, memory_size, assembler);
+        match assemble_headless(&options) {
+            Ok(assembled) => {
+                for warning in lint_program(&assembled) {
+                    println!("{}", warning);
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Object { program, output, assembler }) = cli.command {
+        let output = output.unwrap_or_else(|| program.with_extension("o"));
+        let options = assembler_options(program, memory_size, assembler);
+        if let Err(e) = assemble_object_headless(&options).and_then(|object| object.save(&output)) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Archive { output, objects }) = cli.command {
+        let result = objects.iter()
+            .map(|path| ObjectFile::load(path))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|members| Archive { version: OBJECT_VERSION, members }.save(&output));
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Link { inputs, script, output }) = cli.command {
+        if let Err(e) = link_files(&inputs, script.as_deref(), &output, memory_size) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let workspace = Workspace::new(env::current_dir().expect("Failed to get current directory"), memory_size);
     let config_manager = ConfigManager::new().expect("Failed to create ConfigManager");
     let mut app = TuiApp::new(workspace, config_manager, memory_size);
+    if cli.machine.mmu {
+        app.enable_mmu();
+    }
+    if cli.machine.memcheck {
+        app.enable_memcheck();
+    }
+    for (name, base) in &cli.machine.devices {
+        if let Err(e) = app.move_device(name, *base) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+    app.set_include_paths(cli.assembler.include_paths);
+    app.set_defines(cli.assembler.defines);
+    if let Some(path) = &cli.load {
+        let loaded = if path.extension().is_some_and(|extension| extension == "arx") {
+            app.load_executable(path, cli.entry)
+        } else {
+            app.load_image(path, cli.format, cli.load_address, cli.entry)
+        };
+        if let Err(e) = loaded {
+            eprintln!("Error loading image: {}", e);
+            std::process::exit(1);
+        }
+    }
 
     if let Err(e) = app.run() {
         eprintln!("Error running TUI: {}", e);
     }
-}
\ No newline at end of file
+}
+
+/// Returns the settings for assembling a program without running it.
+fn assembler_options(program: PathBuf, memory_size: usize, assembler: AssemblerArgs) -> RunOptions {
+    RunOptions {
+        program, memory_size, max_steps: DEFAULT_MAX_STEPS, mmu: false, memcheck: false, devices: Vec::new(),
+        include_paths: assembler.include_paths, defines: assembler.defines, dumps: Vec::new(),
+    }
+}
+
+/// Loads the objects, archives and linker script, links them and writes the program as an
+/// executable or an image, with its entry point at the start of the text segment.
+fn link_files(inputs: &[PathBuf], script: Option<&Path>, output: &Path, memory_size: usize) -> Result<(), String> {
+    let (mut objects, mut archives) = (Vec::new(), Vec::new());
+    for input in inputs {
+        if input.extension().is_some_and(|extension| extension == "a") {
+            archives.push(Archive::load(input)?);
+        } else {
+            objects.push(ObjectFile::load(input)?);
+        }
+    }
+    let layout = match script {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+            parse_linker_script(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => SegmentLayout::default(),
+    };
+    let program = link(&objects, &archives, &layout, memory_size)?;
+    if output.extension().is_some_and(|extension| extension == "arx") {
+        return Executable::new(program).save(output);
+    }
+    let text: Vec<u8> = program.text.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let segments = [(program.actual_text_start, text.as_slice()), (program.actual_data_start, program.data.as_slice())];
+    let image = format_segments(DumpFormat::from_path(output), &segments, Some(program.actual_text_start))?;
+    std::fs::write(output, image).map_err(|e| format!("Error writing {}: {}", output.display(), e))
+}
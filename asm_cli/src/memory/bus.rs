@@ -0,0 +1,313 @@
+//! # Bus Module
+//!
+//! This module defines the `Bus` struct, which connects memory-mapped devices
+//! to the ARC CPU address space. Devices register an address range on the bus
+//! and receive every read and write that `WorkMemory` performs inside it.
+
+use std::fmt;
+
+/// The name under which the console device is registered on the bus.
+pub const CONSOLE_DEVICE_NAME: &str = "console";
+
+/// A device that can be mapped into the address space through the `Bus`.
+///
+/// Offsets passed to `read` and `write` are relative to the base address
+/// the device was mapped at.
+pub trait BusDevice: fmt::Debug {
+    /// Returns the name used to identify the device on the bus.
+    fn name(&self) -> &str;
+
+    /// Reads a byte from the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset inside the device's address range.
+    fn read(&self, offset: u32) -> u8;
+
+    /// Writes a byte to the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset inside the device's address range.
+    /// * `value` - The byte value to write.
+    fn write(&mut self, offset: u32, value: u8);
+
+    /// Restores the device to its power-on state.
+    fn reset(&mut self) {}
+
+    /// Returns a boxed copy of the device, so that memories holding devices can be cloned.
+    fn clone_box(&self) -> Box<dyn BusDevice>;
+}
+
+impl Clone for Box<dyn BusDevice> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// A device mapped at a fixed address range.
+#[derive(Debug, Clone)]
+pub struct DeviceMapping {
+    /// The first address of the range.
+    pub base: u32,
+    /// The size of the range in bytes.
+    pub size: u32,
+    /// The device receiving accesses to the range.
+    pub device: Box<dyn BusDevice>,
+}
+
+impl DeviceMapping {
+    /// Returns `true` if `address` falls inside the mapped range.
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.base && (address - self.base) < self.size
+    }
+
+    /// Returns the last address of the mapped range.
+    pub fn last_address(&self) -> u32 {
+        self.base + (self.size - 1)
+    }
+}
+
+/// Routes accesses to the devices mapped into the address space.
+#[derive(Debug, Clone, Default)]
+pub struct Bus {
+    /// The mapped devices, in the order they were registered.
+    pub mappings: Vec<DeviceMapping>,
+}
+
+impl Bus {
+    /// Creates a new `Bus` with no devices mapped.
+    pub fn new() -> Self {
+        Self { mappings: Vec::new() }
+    }
+
+    /// Maps a device at the given address range.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The first address of the range.
+    /// * `size` - The size of the range in bytes.
+    /// * `device` - The device receiving accesses to the range.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - `Ok(())` on success, or an error message if the range is empty,
+    ///   wraps past the end of the address space, overlaps another device or the name is taken.
+    pub fn map(&mut self, base: u32, size: u32, device: Box<dyn BusDevice>) -> Result<(), String> {
+        if size == 0 {
+            return Err(format!("Cannot map device '{}' with an empty address range", device.name()));
+        }
+        if base.checked_add(size - 1).is_none() {
+            return Err(format!("Device '{}' at {:#010x} extends past the end of the address space", device.name(), base));
+        }
+        if self.find(device.name()).is_some() {
+            return Err(format!("A device named '{}' is already mapped", device.name()));
+        }
+        let last = base + (size - 1);
+        if let Some(other) = self.mappings.iter().find(|m| base <= m.last_address() && m.base <= last) {
+            return Err(format!(
+                "Device '{}' ({:#010x} - {:#010x}) overlaps device '{}' ({:#010x} - {:#010x})",
+                device.name(), base, last, other.device.name(), other.base, other.last_address()
+            ));
+        }
+        self.mappings.push(DeviceMapping { base, size, device });
+        Ok(())
+    }
+
+    /// Removes a device from the bus.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the device to remove.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Box<dyn BusDevice>, String>` - The removed device, or an error message if no device has that name.
+    pub fn unmap(&mut self, name: &str) -> Result<Box<dyn BusDevice>, String> {
+        let index = self.mappings.iter()
+            .position(|m| m.device.name() == name)
+            .ok_or(format!("No device named '{}' is mapped", name))?;
+        Ok(self.mappings.remove(index).device)
+    }
+
+    /// Moves a mapped device to another base address, keeping the size of its range.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the device.
+    /// * `base` - The new first address of its range.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error message if no device has that name or the new range does
+    ///   not fit; the device then stays where it was.
+    pub fn move_device(&mut self, name: &str, base: u32) -> Result<(), String> {
+        let size = self.find(name).ok_or(format!("No device named '{}' is mapped", name))?.size;
+        let index = self.mappings.iter().position(|m| m.device.name() == name).expect("the device was just found");
+        let mapping = self.mappings.remove(index);
+        let old_base = mapping.base;
+        match self.map(base, size, mapping.device.clone_box()) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.mappings.insert(index, DeviceMapping { base: old_base, ..mapping });
+                Err(e)
+            }
+        }
+    }
+
+    /// Finds the mapping of the device with the given name.
+    pub fn find(&self, name: &str) -> Option<&DeviceMapping> {
+        self.mappings.iter().find(|m| m.device.name() == name)
+    }
+
+    /// Finds the mapping that contains `address`, if any.
+    pub fn mapping_at(&self, address: u32) -> Option<&DeviceMapping> {
+        self.mappings.iter().find(|m| m.contains(address))
+    }
+
+    /// Reads a byte from the device mapped at `address`.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u8>` - The byte read, or `None` if no device is mapped at `address`.
+    pub fn read(&self, address: u32) -> Option<u8> {
+        self.mapping_at(address).map(|m| m.device.read(address - m.base))
+    }
+
+    /// Writes a byte to the device mapped at `address`.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - `true` if a device received the write, `false` if no device is mapped at `address`.
+    pub fn write(&mut self, address: u32, value: u8) -> bool {
+        match self.mappings.iter_mut().find(|m| m.contains(address)) {
+            Some(mapping) => {
+                mapping.device.write(address - mapping.base, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resets every mapped device.
+    pub fn reset(&mut self) {
+        for mapping in &mut self.mappings {
+            mapping.device.reset();
+        }
+    }
+}
+
+/// A text console: the first half of its range is the input buffer filled by
+/// the user, the second half is the output buffer written by the program.
+/// Both buffers hold null-terminated strings.
+#[derive(Debug, Clone)]
+pub struct ConsoleDevice {
+    /// The backing bytes of the input and output buffers.
+    pub buffer: Vec<u8>,
+}
+
+impl ConsoleDevice {
+    /// Creates a new `ConsoleDevice` covering `size` bytes.
+    pub fn new(size: u32) -> Self {
+        Self { buffer: vec![0; size as usize] }
+    }
+}
+
+impl BusDevice for ConsoleDevice {
+    fn name(&self) -> &str {
+        CONSOLE_DEVICE_NAME
+    }
+
+    fn read(&self, offset: u32) -> u8 {
+        self.buffer.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, offset: u32, value: u8) {
+        if let Some(byte) = self.buffer.get_mut(offset as usize) {
+            *byte = value;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0);
+    }
+
+    fn clone_box(&self) -> Box<dyn BusDevice> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod bus_test {
+    use super::*;
+
+    /// A device that returns a fixed value and counts writes.
+    #[derive(Debug, Clone)]
+    struct ConstantDevice {
+        value: u8,
+        writes: u32,
+    }
+
+    impl BusDevice for ConstantDevice {
+        fn name(&self) -> &str {
+            "constant"
+        }
+
+        fn read(&self, _offset: u32) -> u8 {
+            self.value
+        }
+
+        fn write(&mut self, _offset: u32, _value: u8) {
+            self.writes += 1;
+        }
+
+        fn clone_box(&self) -> Box<dyn BusDevice> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn map_rejects_overlaps_and_empty_ranges() {
+        let mut bus = Bus::new();
+        bus.map(0x1000, 0x100, Box::new(ConsoleDevice::new(0x100))).unwrap();
+        assert!(bus.map(0x10FF, 0x10, Box::new(ConstantDevice { value: 0, writes: 0 })).is_err());
+        assert!(bus.map(0x2000, 0, Box::new(ConstantDevice { value: 0, writes: 0 })).is_err());
+        assert!(bus.map(0xFFFF_FFF0, 0x20, Box::new(ConstantDevice { value: 0, writes: 0 })).is_err());
+        assert!(bus.map(0x1100, 0x10, Box::new(ConsoleDevice::new(0x10))).is_err());
+        bus.map(0x1100, 0x10, Box::new(ConstantDevice { value: 0, writes: 0 })).unwrap();
+        assert_eq!(bus.mappings.len(), 2);
+    }
+
+    #[test]
+    fn accesses_reach_the_mapped_device() {
+        let mut bus = Bus::new();
+        bus.map(0x1000, 0x100, Box::new(ConsoleDevice::new(0x100))).unwrap();
+        bus.map(0x2000, 0x4, Box::new(ConstantDevice { value: 0x5A, writes: 0 })).unwrap();
+
+        assert!(bus.write(0x1010, 0x41));
+        assert_eq!(bus.read(0x1010), Some(0x41));
+        assert_eq!(bus.read(0x2003), Some(0x5A));
+        assert!(bus.write(0x2000, 1));
+        assert_eq!(bus.read(0x3000), None);
+        assert!(!bus.write(0x3000, 1));
+
+        bus.reset();
+        assert_eq!(bus.read(0x1010), Some(0));
+        assert!(bus.unmap(CONSOLE_DEVICE_NAME).is_ok());
+        assert_eq!(bus.read(0x1010), None);
+    }
+    #[test]
+    fn devices_move_with_their_contents() {
+        let mut bus = Bus::new();
+        bus.map(0x1000, 0x100, Box::new(ConsoleDevice::new(0x100))).unwrap();
+        bus.map(0x2000, 0x4, Box::new(ConstantDevice { value: 0x5A, writes: 0 })).unwrap();
+        bus.write(0x1010, 0x41);
+
+        assert!(bus.move_device(CONSOLE_DEVICE_NAME, 0x1F80).is_err());
+        assert_eq!(bus.find(CONSOLE_DEVICE_NAME).map(|m| m.base), Some(0x1000));
+        assert!(bus.move_device("missing", 0x4000).is_err());
+        bus.move_device(CONSOLE_DEVICE_NAME, 0x4000).unwrap();
+        assert_eq!(bus.read(0x4010), Some(0x41));
+        assert_eq!(bus.read(0x1010), None);
+    }
+}
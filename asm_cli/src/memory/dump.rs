@@ -0,0 +1,271 @@
+//! # Dump Module
+//!
+//! This module exports ranges of memory to files that can be checked into
+//! tests or compared with tools outside the emulator: raw binary, Intel HEX,
+//! a hexdump listing, or an array literal for Rust or C sources. Intel HEX
+//! dumps can be loaded back with the image loaders of `WorkMemory`.
+
+use std::path::Path;
+
+use crate::memory::watchpoints::parse_number;
+
+/// Number of bytes in each Intel HEX data record and hexdump row.
+const BYTES_PER_LINE: usize = 16;
+
+/// Number of bytes on each line of an array literal.
+const BYTES_PER_ARRAY_LINE: usize = 12;
+
+/// The formats memory can be exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// The bytes themselves.
+    Raw,
+    /// Intel HEX records, with the entry point as a start address record.
+    IntelHex,
+    /// Rows of hexadecimal bytes with their addresses and ASCII characters.
+    HexDump,
+    /// A Rust `[u8; N]` constant.
+    RustArray,
+    /// A C `unsigned char` array.
+    CArray,
+}
+
+impl DumpFormat {
+    /// Guesses the format of a dump from the extension of the file it is written to.
+    ///
+    /// `.hex`/`.ihex` is Intel HEX, `.txt` a hexdump, `.rs` a Rust array,
+    /// `.c`/`.h` a C array and anything else raw binary.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        match extension.as_str() {
+            "hex" | "ihex" | "ihx" => DumpFormat::IntelHex,
+            "txt" | "dump" => DumpFormat::HexDump,
+            "rs" => DumpFormat::RustArray,
+            "c" | "h" => DumpFormat::CArray,
+            _ => DumpFormat::Raw,
+        }
+    }
+
+    /// Parses a format name as typed in the emulator or given on the command line.
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "raw" | "bin" | "binary" => Ok(DumpFormat::Raw),
+            "ihex" | "hex" | "intel-hex" => Ok(DumpFormat::IntelHex),
+            "hexdump" | "txt" | "text" => Ok(DumpFormat::HexDump),
+            "rust" | "rs" => Ok(DumpFormat::RustArray),
+            "c" => Ok(DumpFormat::CArray),
+            _ => Err(format!("Unknown dump format: {} (expected raw, ihex, hexdump, rust or c)", name)),
+        }
+    }
+}
+
+/// Parses a memory range.
+///
+/// A range is `START:LENGTH`, `START..END` (end exclusive) or the name of a
+/// segment such as `text`, `data` or `stack`. `START` and `END` are decimal or
+/// `0x`-prefixed addresses or labels.
+///
+/// # Arguments
+///
+/// * `spec` - The range typed by the user.
+/// * `segment` - Resolves a segment name to its start address and size.
+/// * `resolve` - Resolves a label to its address.
+///
+/// # Returns
+///
+/// * `Result<(u32, u32), String>` - The start address and length, or an error message.
+pub fn parse_dump_range(
+    spec: &str,
+    segment: impl Fn(&str) -> Option<(u32, u32)>,
+    resolve: impl Fn(&str) -> Option<u32>,
+) -> Result<(u32, u32), String> {
+    let spec = spec.trim();
+    let address = |text: &str| parse_number(text.trim())
+        .or_else(|e| resolve(text.trim()).ok_or(e))
+        .map_err(|_| format!("Unknown address or label: {}", text.trim()));
+    if let Some((start, end)) = spec.split_once("..") {
+        let (start, end) = (address(start)?, address(end)?);
+        if end < start {
+            return Err(format!("Range end 0x{:04X} is before its start 0x{:04X}", end, start));
+        }
+        return Ok((start, end - start));
+    }
+    if let Some((start, length)) = spec.split_once(':') {
+        return Ok((address(start)?, parse_number(length.trim())?));
+    }
+    segment(spec).ok_or(format!("Expected START:LENGTH, START..END or a segment name, got: {}", spec))
+}
+
+/// Formats a range of memory as a dump.
+///
+/// # Arguments
+///
+/// * `format` - The format of the dump.
+/// * `start` - The address of the first byte.
+/// * `bytes` - The contents of the range.
+/// * `name` - The name of the array in array formats.
+/// * `entry` - The entry point recorded in Intel HEX dumps, if any.
+///
+/// # Returns
+///
+/// * `Vec<u8>` - The contents of the dump file.
+pub fn format_dump(format: DumpFormat, start: u32, bytes: &[u8], name: &str, entry: Option<u32>) -> Vec<u8> {
+    match format {
+        DumpFormat::Raw => bytes.to_vec(),
+        DumpFormat::IntelHex => intel_hex(&[(start, bytes)], entry).into_bytes(),
+        DumpFormat::HexDump => hexdump(start, bytes).into_bytes(),
+        DumpFormat::RustArray => {
+            let name = array_name(name).to_uppercase();
+            format!("pub const {}: [u8; {}] = [\n{}];\n", name, bytes.len(), array_body(bytes)).into_bytes()
+        }
+        DumpFormat::CArray => {
+            let name = array_name(name);
+            format!("const unsigned char {}[{}] = {{\n{}}};\n", name, bytes.len(), array_body(bytes)).into_bytes()
+        }
+    }
+}
+
+/// Formats several separate ranges of memory as one image, for programs whose segments are far apart.
+///
+/// # Arguments
+///
+/// * `format` - The format of the image; only Intel HEX and raw binary can hold a program.
+/// * `segments` - The address and contents of every range.
+/// * `entry` - The entry point recorded in Intel HEX images, if any.
+///
+/// # Returns
+///
+/// * `Result<Vec<u8>, String>` - The contents of the image file. Raw images cover everything from
+///   the lowest to the highest address, with the gaps filled with zeros.
+pub fn format_segments(format: DumpFormat, segments: &[(u32, &[u8])], entry: Option<u32>) -> Result<Vec<u8>, String> {
+    match format {
+        DumpFormat::IntelHex => Ok(intel_hex(segments, entry).into_bytes()),
+        DumpFormat::Raw => {
+            let segments: Vec<&(u32, &[u8])> = segments.iter().filter(|(_, bytes)| !bytes.is_empty()).collect();
+            let start = segments.iter().map(|(start, _)| *start).min().unwrap_or(0);
+            let end = segments.iter().map(|(start, bytes)| *start as usize + bytes.len()).max().unwrap_or(0);
+            let mut image = vec![0; end.saturating_sub(start as usize)];
+            for (address, bytes) in segments {
+                let offset = (address - start) as usize;
+                image[offset..offset + bytes.len()].copy_from_slice(bytes);
+            }
+            Ok(image)
+        }
+        _ => Err("Programs can only be written as Intel HEX or raw binary images".to_string()),
+    }
+}
+
+/// Writes Intel HEX data records, switching the upper address with extended linear address records.
+fn intel_hex(segments: &[(u32, &[u8])], entry: Option<u32>) -> String {
+    let mut out = String::new();
+    let mut upper = 0u16;
+    for &(start, bytes) in segments {
+        let mut offset = 0usize;
+        while offset < bytes.len() {
+            let address = start.wrapping_add(offset as u32);
+            if (address >> 16) as u16 != upper {
+                upper = (address >> 16) as u16;
+                out.push_str(&hex_record(0x0000, 0x04, &upper.to_be_bytes()));
+            }
+            // Records may not cross a 64KB boundary
+            let to_boundary = 0x10000 - (address & 0xFFFF) as usize;
+            let length = BYTES_PER_LINE.min(bytes.len() - offset).min(to_boundary);
+            out.push_str(&hex_record(address as u16, 0x00, &bytes[offset..offset + length]));
+            offset += length;
+        }
+    }
+    if let Some(entry) = entry {
+        out.push_str(&hex_record(0x0000, 0x05, &entry.to_be_bytes()));
+    }
+    out.push_str(&hex_record(0x0000, 0x01, &[]));
+    out
+}
+
+/// Formats one Intel HEX record with its checksum.
+fn hex_record(address: u16, kind: u8, data: &[u8]) -> String {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&address.to_be_bytes());
+    record.push(kind);
+    record.extend_from_slice(data);
+    let checksum = record.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)).wrapping_neg();
+    record.push(checksum);
+    let digits: String = record.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(":{}\n", digits)
+}
+
+/// Formats rows of bytes with their addresses and ASCII characters.
+fn hexdump(start: u32, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (row, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let ascii: String = chunk.iter()
+            .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+            .collect();
+        let address = start.wrapping_add((row * BYTES_PER_LINE) as u32);
+        out.push_str(&format!("0x{:04X}: {:<width$}  {}\n", address, hex.join(" "), ascii, width = BYTES_PER_LINE * 3 - 1));
+    }
+    out
+}
+
+/// Formats the elements of an array literal, indented and followed by commas.
+fn array_body(bytes: &[u8]) -> String {
+    bytes.chunks(BYTES_PER_ARRAY_LINE)
+        .map(|chunk| {
+            let line: Vec<String> = chunk.iter().map(|byte| format!("0x{:02X},", byte)).collect();
+            format!("    {}\n", line.join(" "))
+        })
+        .collect()
+}
+
+/// Turns a name into a valid identifier for an array literal.
+fn array_name(name: &str) -> String {
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() => name,
+        _ => format!("dump_{}", name),
+    }
+}
+
+#[cfg(test)]
+mod dump_test {
+    use super::*;
+    use crate::memory::main_memory::{ImageFormat, MemoryImage};
+
+    #[test]
+    fn ranges_accept_lengths_ends_segments_and_labels() {
+        let segment = |name: &str| (name == "data").then_some((0x8000, 0x20));
+        let resolve = |name: &str| (name == "buffer").then_some(0x8010);
+        assert_eq!(parse_dump_range("0x100:16", segment, resolve), Ok((0x100, 16)));
+        assert_eq!(parse_dump_range("buffer..0x8018", segment, resolve), Ok((0x8010, 8)));
+        assert_eq!(parse_dump_range("data", segment, resolve), Ok((0x8000, 0x20)));
+        assert!(parse_dump_range("0x20..0x10", segment, resolve).is_err());
+        assert!(parse_dump_range("nowhere:4", segment, resolve).is_err());
+    }
+
+    #[test]
+    fn intel_hex_dumps_load_back() {
+        let bytes: Vec<u8> = (0..40).collect();
+        let dump = format_dump(DumpFormat::IntelHex, 0xFFF0, &bytes, "", Some(0x100));
+        let image = MemoryImage::parse(ImageFormat::IntelHex, &dump, 0).unwrap();
+        assert_eq!(image.entry, Some(0x100));
+        let loaded: Vec<u8> = image.blocks.iter().flat_map(|(_, block)| block.clone()).collect();
+        assert_eq!(loaded, bytes);
+        assert_eq!(image.blocks[1].0, 0x10000);
+    }
+
+    #[test]
+    fn text_formats() {
+        assert_eq!(
+            String::from_utf8(format_dump(DumpFormat::HexDump, 0x10, b"AB\0", "", None)).unwrap(),
+            format!("0x0010: 41 42 00{}  AB.\n", " ".repeat(39))
+        );
+        assert_eq!(
+            String::from_utf8(format_dump(DumpFormat::RustArray, 0, &[1, 2], "data", None)).unwrap(),
+            "pub const DATA: [u8; 2] = [\n    0x01, 0x02,\n];\n"
+        );
+        assert_eq!(
+            String::from_utf8(format_dump(DumpFormat::CArray, 0, &[0xFF], "0x8000:1", None)).unwrap(),
+            "const unsigned char dump_0x8000_1[1] = {\n    0xFF,\n};\n"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! # Journal Module
+//!
+//! This module defines the `WriteJournal`, which records every byte of memory
+//! that changes value, and the `Snapshot` and `StateDiff` types built on top
+//! of it. A snapshot only stores the registers and a position in the journal,
+//! so taking one after every step is cheap; the memory changes between two
+//! snapshots are replayed from the journal entries recorded in between.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::memory::registers::Registers;
+
+/// Number of byte changes kept before the oldest ones are discarded.
+pub const JOURNAL_CAPACITY: usize = 0x10000;
+
+/// A byte of memory whose value changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryChange {
+    /// The physical address of the byte.
+    pub address: u32,
+    /// The value before the change.
+    pub old: u8,
+    /// The value after the change.
+    pub new: u8,
+}
+
+/// A bounded log of the byte changes made to memory.
+#[derive(Debug, Clone)]
+pub struct WriteJournal {
+    /// The most recent changes, oldest first.
+    entries: VecDeque<MemoryChange>,
+    /// The position of the oldest retained entry.
+    first_position: u64,
+}
+
+impl Default for WriteJournal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WriteJournal {
+    /// Creates an empty journal.
+    pub fn new() -> Self {
+        Self { entries: VecDeque::new(), first_position: 0 }
+    }
+
+    /// Returns the position the next change will be recorded at.
+    pub fn position(&self) -> u64 {
+        self.first_position + self.entries.len() as u64
+    }
+
+    /// Records a byte change; writes that keep the old value are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The physical address of the byte.
+    /// * `old` - The value before the write.
+    /// * `new` - The value written.
+    pub fn record(&mut self, address: u32, old: u8, new: u8) {
+        if old == new {
+            return;
+        }
+        if self.entries.len() == JOURNAL_CAPACITY {
+            self.entries.pop_front();
+            self.first_position += 1;
+        }
+        self.entries.push_back(MemoryChange { address, old, new });
+    }
+
+    /// Collects the net changes recorded between two positions.
+    ///
+    /// A byte written several times appears once, with its first old value and
+    /// last new value; bytes that ended up with their original value are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The earlier position.
+    /// * `to` - The later position.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<MemoryChange>, String>` - The changes in address order, or an error message
+    ///   if the journal no longer holds the changes since `from`.
+    pub fn changes_between(&self, from: u64, to: u64) -> Result<Vec<MemoryChange>, String> {
+        if from < self.first_position {
+            return Err(format!("The journal only keeps the last {} memory changes", JOURNAL_CAPACITY));
+        }
+        let (from, to) = (from.min(to), to.max(from).min(self.position()));
+        let mut net: BTreeMap<u32, MemoryChange> = BTreeMap::new();
+        for change in self.entries.range((from - self.first_position) as usize..(to - self.first_position) as usize) {
+            net.entry(change.address)
+                .and_modify(|existing| existing.new = change.new)
+                .or_insert(*change);
+        }
+        Ok(net.into_values().filter(|change| change.old != change.new).collect())
+    }
+}
+
+/// The state of the machine at one point in time.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The register values.
+    pub registers: Registers,
+    /// The number of executed cycles.
+    pub cycles: u64,
+    /// The journal position when the snapshot was taken.
+    pub journal_position: u64,
+}
+
+/// The differences between two snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct StateDiff {
+    /// The name, old value and new value of each changed register.
+    pub registers: Vec<(&'static str, u32, u32)>,
+    /// The bytes of memory that changed.
+    pub memory: Vec<MemoryChange>,
+}
+
+impl StateDiff {
+    /// Compares two snapshots.
+    ///
+    /// # Arguments
+    ///
+    /// * `before` - The earlier snapshot.
+    /// * `after` - The later snapshot.
+    /// * `journal` - The journal both snapshots were taken from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<StateDiff, String>` - The changes, or an error message if they are no longer in the journal.
+    pub fn between(before: &Snapshot, after: &Snapshot, journal: &WriteJournal) -> Result<StateDiff, String> {
+        Ok(StateDiff {
+            registers: after.registers.changes_since(&before.registers),
+            memory: journal.changes_between(before.journal_position, after.journal_position)?,
+        })
+    }
+
+    /// Returns `true` if the register with the given name changed.
+    pub fn register_changed(&self, name: &str) -> bool {
+        self.registers.iter().any(|(changed, _, _)| *changed == name)
+    }
+
+    /// Returns `true` if the byte at `address` changed.
+    pub fn byte_changed(&self, address: u32) -> bool {
+        self.memory.binary_search_by_key(&address, |change| change.address).is_ok()
+    }
+
+    /// Returns `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod journal_test {
+    use super::*;
+
+    #[test]
+    fn changes_are_coalesced_per_address() {
+        let mut journal = WriteJournal::new();
+        journal.record(0x10, 0, 1);
+        let start = journal.position();
+        journal.record(0x20, 0, 5);
+        journal.record(0x10, 1, 2);
+        journal.record(0x20, 5, 0);
+        journal.record(0x30, 7, 7);
+
+        let changes = journal.changes_between(start, journal.position()).unwrap();
+        assert_eq!(changes, vec![MemoryChange { address: 0x10, old: 1, new: 2 }]);
+        assert_eq!(journal.changes_between(0, start).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn old_positions_expire() {
+        let mut journal = WriteJournal::new();
+        for i in 0..JOURNAL_CAPACITY as u32 + 1 {
+            journal.record(i, 0, 1);
+        }
+        assert!(journal.changes_between(0, journal.position()).is_err());
+        assert_eq!(journal.changes_between(1, journal.position()).unwrap().len(), JOURNAL_CAPACITY);
+    }
+
+    #[test]
+    fn snapshots_diff_registers_and_memory() {
+        let mut journal = WriteJournal::new();
+        let mut registers = Registers::new();
+        let before = Snapshot { registers: registers.clone(), cycles: 0, journal_position: journal.position() };
+        registers.ax = 3;
+        journal.record(0x8000, 0, 3);
+        let after = Snapshot { registers, cycles: 1, journal_position: journal.position() };
+
+        let diff = StateDiff::between(&before, &after, &journal).unwrap();
+        assert_eq!(diff.registers, vec![("AX", 0, 3)]);
+        assert!(diff.register_changed("AX") && !diff.register_changed("BX"));
+        assert!(diff.byte_changed(0x8000) && !diff.byte_changed(0x8001));
+    }
+}
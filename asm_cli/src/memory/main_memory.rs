@@ -2,36 +2,170 @@
 //!
 //! This module defines the `WorkMemory` struct, which simulates the main memory
 //! of the ARC CPU. It includes memory layout constants and methods for reading,
-//! writing, and managing memory contents.
+//! writing, and managing memory contents. Accesses to address ranges claimed by
+//! a device on the `Bus` are forwarded to that device instead of RAM, and every
+//! access is checked against the `MemoryProtection` permission map. When the
+//! optional `Mmu` is enabled, program addresses are virtual and are translated
+//! to physical addresses first. RAM is backed by a `SparseMemory`, so only the
+//! pages a program touches are allocated. Data reads and writes are also
+//! checked against the user's `Watchpoints`, and every byte that changes value
+//! is recorded in a `WriteJournal`. A `ShadowMemory` tracks which bytes have
+//! been initialized, so that reads of uninitialized memory can be reported.
+//! Besides assembled programs, memory can be loaded from raw binaries, Intel
+//! HEX and Motorola S-record images.
+
+use crate::memory::bus::{Bus, CONSOLE_DEVICE_NAME};
+use crate::memory::journal::WriteJournal;
+use crate::memory::mmu::Mmu;
+use crate::memory::protection::{AccessKind, MemoryProtection};
+use crate::memory::shadow::{ShadowMemory, UninitializedRead};
+use crate::memory::sparse::SparseMemory;
+use crate::memory::watchpoints::Watchpoints;
 
 /// Default memory size if not specified (64KB).
 pub const DEFAULT_MEMORY_SIZE: usize = 0x10000; // 64KB
+/// Largest memory size the emulator accepts: the full 32-bit address space (4GB).
+///
+/// This is a `u64` because it does not fit in `usize` on 32-bit targets, where the
+/// largest size that can actually be allocated is `usize::MAX`.
+pub const MEMORY_MAX_SIZE: u64 = 0x1_0000_0000; // 4GB
+/// Default size of the console device's address range.
+pub const IO_SIZE: u32 = 0x1000;
+/// Where the console goes when RAM fills the address space: halfway up, away from the text
+/// segment at the bottom and the data and stack segments at the top.
+const IO_FALLBACK_START: u32 = 0x8000_0000;
+
+/// Returns the default base address of the console device: the first address past the end of
+/// RAM, so it never overlaps the segments of a program.
+///
+/// # Arguments
+///
+/// * `memory_size` - The size of RAM.
+///
+/// # Returns
+///
+/// * `u32` - The base address, or `IO_FALLBACK_START` if the console would not end by
+///   `MEMORY_MAX_SIZE`.
+pub fn default_console_base(memory_size: usize) -> u32 {
+    let base = memory_size as u64;
+    if base + IO_SIZE as u64 <= MEMORY_MAX_SIZE {
+        base as u32
+    } else {
+        IO_FALLBACK_START
+    }
+}
+
+/// The file formats memory images can be loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Raw bytes, loaded at an address given by the user.
+    Raw,
+    /// Intel HEX records.
+    IntelHex,
+    /// Motorola S-records.
+    SRecord,
+}
+
+impl ImageFormat {
+    /// Guesses the format of an image from its file extension; unknown extensions are raw.
+    pub fn from_path(path: &std::path::Path) -> Self {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        match extension.as_str() {
+            "hex" | "ihex" | "ihx" => ImageFormat::IntelHex,
+            "srec" | "s19" | "s28" | "s37" | "mot" => ImageFormat::SRecord,
+            _ => ImageFormat::Raw,
+        }
+    }
+
+    /// Parses a format name as given on the command line.
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "raw" | "bin" | "binary" => Ok(ImageFormat::Raw),
+            "ihex" | "hex" | "intel-hex" => Ok(ImageFormat::IntelHex),
+            "srec" | "s-record" | "srecord" => Ok(ImageFormat::SRecord),
+            _ => Err(format!("Unknown image format: {} (expected raw, ihex or srec)", name)),
+        }
+    }
+}
+
+/// The contents of a memory image: blocks of bytes and their addresses.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryImage {
+    /// The address and bytes of each block, in file order.
+    pub blocks: Vec<(u32, Vec<u8>)>,
+    /// The entry point recorded in the image, if any.
+    pub entry: Option<u32>,
+}
+
+impl MemoryImage {
+    /// Parses an image file.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format of the file.
+    /// * `contents` - The contents of the file.
+    /// * `base` - The load address of raw images; ignored by the other formats.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<MemoryImage, String>` - The parsed image, or an error message naming the offending line.
+    pub fn parse(format: ImageFormat, contents: &[u8], base: u32) -> Result<Self, String> {
+        match format {
+            ImageFormat::Raw => Ok(Self { blocks: vec![(base, contents.to_vec())], entry: None }),
+            ImageFormat::IntelHex => parse_intel_hex(&String::from_utf8_lossy(contents)),
+            ImageFormat::SRecord => parse_srecord(&String::from_utf8_lossy(contents)),
+        }
+    }
+
+    /// Returns the lowest address of the image.
+    pub fn lowest_address(&self) -> Option<u32> {
+        self.blocks.iter().filter(|(_, bytes)| !bytes.is_empty()).map(|(address, _)| *address).min()
+    }
+}
 
 /// Represents the simulated main memory of the ARC CPU.
 #[derive(Debug, Clone)]
 pub struct WorkMemory {
-    /// The underlying vector of bytes representing the memory.
-    pub memory: Vec<u8>,
+    /// The sparse backing store of RAM.
+    pub memory: SparseMemory,
     /// The total size of the memory in bytes.
     pub size: usize,
     /// The current value of the stack pointer.
     pub stack_pointer: u32,
+    /// The memory-mapped devices sharing the address space with RAM.
+    pub bus: Bus,
+    /// The permissions enforced on every access.
+    pub protection: MemoryProtection,
+    /// The memory management unit translating virtual addresses.
+    pub mmu: Mmu,
+    /// The watchpoints checked on every data read and write.
+    pub watchpoints: Watchpoints,
+    /// The log of byte changes, used to show what a step changed.
+    pub journal: WriteJournal,
+    /// The initialization state of every byte, checked on data reads in memcheck mode.
+    pub shadow: ShadowMemory,
 }
 
 impl WorkMemory {
     /// Creates a new `WorkMemory` instance with a specified size.
     ///
-    /// All memory locations are initialized to 0, and the stack pointer
-    /// is set to the top of the stack segment.
+    /// All memory locations read as 0, but no RAM is allocated until it is
+    /// written. The stack pointer is set to the top of the stack segment.
     ///
     /// # Arguments
     ///
     /// * `size` - The total size of the memory in bytes.
     pub fn new(size: usize) -> Self {
         WorkMemory {
-            memory: vec![0; size],
+            memory: SparseMemory::new(size),
             size,
             stack_pointer: (size - 1) as u32, // Initialize SP to the top of the allocated memory
+            bus: Bus::new(),
+            protection: MemoryProtection::default(),
+            mmu: Mmu::default(),
+            watchpoints: Watchpoints::new(),
+            journal: WriteJournal::new(),
+            shadow: ShadowMemory::new(),
         }
     }
 
@@ -45,10 +179,9 @@ impl WorkMemory {
     ///
     /// * `Result<u8, String>` - The byte value on success, or an error message if the address is out of bounds.
     pub fn read_u8(&self, address: u32) -> Result<u8, String> {
-        if address as usize >= self.size {
-            return Err(format!("Memory read error: Address {:#010x} out of bounds", address));
-        }
-        Ok(self.memory[address as usize])
+        let mut bytes = [0u8; 1];
+        self.read_bytes(address, &mut bytes, AccessKind::Read)?;
+        Ok(bytes[0])
     }
 
     /// Writes a single byte (u8) to the specified memory address.
@@ -62,11 +195,7 @@ impl WorkMemory {
     ///
     /// * `Result<(), String>` - `Ok(())` on success, or an error message if the address is out of bounds.
     pub fn write_u8(&mut self, address: u32, value: u8) -> Result<(), String> {
-        if address as usize >= self.size {
-            return Err(format!("Memory write error: Address {:#010x} out of bounds", address));
-        }
-        self.memory[address as usize] = value;
-        Ok(())
+        self.write_bytes(address, &[value])
     }
 
     /// Reads a 16-bit unsigned integer (u16) from the specified memory address.
@@ -81,11 +210,8 @@ impl WorkMemory {
     ///
     /// * `Result<u16, String>` - The u16 value on success, or an error message if the address is out of bounds.
     pub fn read_u16(&self, address: u32) -> Result<u16, String> {
-        if address as usize + 1 >= self.size {
-            return Err(format!("Memory read error: Address {:#010x} out of bounds", address));
-        }
         let mut bytes = [0u8; 2];
-        bytes.copy_from_slice(&self.memory[address as usize..address as usize + 2]);
+        self.read_bytes(address, &mut bytes, AccessKind::Read)?;
         Ok(u16::from_le_bytes(bytes))
     }
 
@@ -102,12 +228,7 @@ impl WorkMemory {
     ///
     /// * `Result<(), String>` - `Ok(())` on success, or an error message if the address is out of bounds.
     pub fn write_u16(&mut self, address: u32, value: u16) -> Result<(), String> {
-        if address as usize + 1 >= self.size {
-            return Err(format!("Memory write error: Address {:#010x} out of bounds", address));
-        }
-        let bytes = value.to_le_bytes();
-        self.memory[address as usize..address as usize + 2].copy_from_slice(&bytes);
-        Ok(())
+        self.write_bytes(address, &value.to_le_bytes())
     }
 
     /// Reads a 32-bit unsigned integer (u32) from the specified memory address.
@@ -122,11 +243,8 @@ impl WorkMemory {
     ///
     /// * `Result<u32, String>` - The u32 value on success, or an error message if the address is out of bounds.
     pub fn read_u32(&self, address: u32) -> Result<u32, String> {
-        if address as usize + 3 >= self.size {
-            return Err(format!("Memory read error: Address {:#010x} out of bounds", address));
-        }
         let mut bytes = [0u8; 4];
-        bytes.copy_from_slice(&self.memory[address as usize..address as usize + 4]);
+        self.read_bytes(address, &mut bytes, AccessKind::Read)?;
         Ok(u32::from_le_bytes(bytes))
     }
 
@@ -143,12 +261,7 @@ impl WorkMemory {
     ///
     /// * `Result<(), String>` - `Ok(())` on success, or an error message if the address is out of bounds.
     pub fn write_u32(&mut self, address: u32, value: u32) -> Result<(), String> {
-        if address as usize + 3 >= self.size {
-            return Err(format!("Memory write error: Address {:#010x} out of bounds", address));
-        }
-        let bytes = value.to_le_bytes();
-        self.memory[address as usize..address as usize + 4].copy_from_slice(&bytes);
-        Ok(())
+        self.write_bytes(address, &value.to_le_bytes())
     }
 
     /// Reads a 32-bit floating-point number (f32) from the specified memory address.
@@ -163,11 +276,8 @@ impl WorkMemory {
     ///
     /// * `Result<f32, String>` - The f32 value on success, or an error message if the address is out of bounds.
     pub fn read_f32(&self, address: u32) -> Result<f32, String> {
-        if address as usize + 3 >= self.size {
-            return Err(format!("Memory read error: Address {:#010x} out of bounds", address));
-        }
         let mut bytes = [0u8; 4];
-        bytes.copy_from_slice(&self.memory[address as usize..address as usize + 4]);
+        self.read_bytes(address, &mut bytes, AccessKind::Read)?;
         Ok(f32::from_le_bytes(bytes))
     }
 
@@ -184,12 +294,20 @@ impl WorkMemory {
     ///
     /// * `Result<(), String>` - `Ok(())` on success, or an error message if the address is out of bounds.
     pub fn write_f32(&mut self, address: u32, value: f32) -> Result<(), String> {
-        if address as usize + 3 >= self.size {
-            return Err(format!("Memory write error: Address {:#010x} out of bounds", address));
+        self.write_bytes(address, &value.to_le_bytes())
+    }
+
+    /// Returns the address range of the console device.
+    ///
+    /// # Returns
+    ///
+    /// * `(u32, u32)` - The base address and size of the console, or the default
+    ///   window (see `default_console_base`) if no console is mapped on the bus.
+    pub fn console_window(&self) -> (u32, u32) {
+        match self.bus.find(CONSOLE_DEVICE_NAME) {
+            Some(mapping) => (mapping.base, mapping.size),
+            None => (default_console_base(self.size), IO_SIZE),
         }
-        let bytes = value.to_le_bytes();
-        self.memory[address as usize..address as usize + 4].copy_from_slice(&bytes);
-        Ok(())
     }
 
     /// Returns the current value of the stack pointer.
@@ -225,8 +343,9 @@ impl WorkMemory {
             return Err("Program too large for memory".to_string());
         }
         
+        // Loading bypasses memory protection, so code can be placed in read-only text
         for (i, &instruction) in program.iter().enumerate() {
-            self.write_u32(start_address + (i * 4) as u32, instruction)?;
+            self.store_bytes(start_address + (i * 4) as u32, &instruction.to_le_bytes())?;
         }
         
         Ok(())
@@ -247,16 +366,56 @@ impl WorkMemory {
             return Err("Data too large for memory".to_string());
         }
         
-        for (i, &byte) in data.iter().enumerate() {
-            self.write_u8(start_address + i as u32, byte)?;
-        }
+        self.store_bytes(start_address, data)?;
         
         Ok(())
     }
 
+    /// Loads a memory image, bypassing memory protection like the other loaders.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The parsed image.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - `Ok(())` on success, or an error message if a block does not fit in memory.
+    ///   Blocks are checked before any of them is loaded.
+    pub fn load_image(&mut self, image: &MemoryImage) -> Result<(), String> {
+        for (address, bytes) in &image.blocks {
+            if *address as u64 + bytes.len() as u64 > self.size as u64 {
+                return Err(format!(
+                    "Image block at 0x{:04X} ({} bytes) does not fit in memory (0x{:X} bytes)",
+                    address, bytes.len(), self.size
+                ));
+            }
+        }
+        for (address, bytes) in &image.blocks {
+            self.store_bytes(*address, bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Copies a range of physical memory for a dump, without checking protection or watchpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The address of the first byte.
+    /// * `length` - The number of bytes.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u8>, String>` - The bytes, or an error message if the range leaves memory.
+    pub fn dump_range(&self, start: u32, length: u32) -> Result<Vec<u8>, String> {
+        if start as u64 + length as u64 > self.size as u64 {
+            return Err(format!("Range 0x{:04X}:{} is outside memory (0x{:X} bytes)", start, length, self.size));
+        }
+        Ok((0..length).map(|offset| self.peek_physical_byte(start + offset)).collect())
+    }
+
     /// Reads a 32-bit instruction from the specified memory address.
     ///
-    /// This is a convenience function that calls `read_u32`.
+    /// This is `read_u32` preceded by a check that the address is executable.
     ///
     /// # Arguments
     ///
@@ -266,7 +425,27 @@ impl WorkMemory {
     ///
     /// * `Result<u32, String>` - The instruction as a `u32` on success, or an error message on failure.
     pub fn read_instruction(&self, address: u32) -> Result<u32, String> {
-        self.read_u32(address)
+        let mut bytes = [0u8; 4];
+        self.read_bytes(address, &mut bytes, AccessKind::Execute)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Reads the entries of the MMU page table from physical memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of entries to read.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(u32, u32)>` - The virtual page number and page-table entry of each readable entry.
+    pub fn page_table(&self, limit: usize) -> Vec<(u32, u32)> {
+        (0..self.mmu.ptlr.min(limit as u32))
+            .filter_map(|vpn| {
+                let pte_address = self.mmu.ptbr.checked_add(vpn * 4)?;
+                self.read_physical_u32(pte_address).map(|pte| (vpn, pte))
+            })
+            .collect()
     }
 
     /// Calculates the number of pages required to display the entire memory.
@@ -285,29 +464,357 @@ impl WorkMemory {
         self.size.div_ceil(page_size)
     }
 
-    /// Reads a block of memory as 32-bit words for display purposes.
-    ///
-    /// # Arguments
-    ///
-    /// * `start_address` - The starting memory address of the block.
-    /// * `count` - The number of 32-bit words to read.
-    ///
-    /// # Returns
-    ///
-    /// * `Vec<(u32, u32)>` - A vector of tuples, where each tuple contains
-    ///   the address and the 32-bit value at that address.
-    pub fn read_memory_block(&self, start_address: u32, count: usize) -> Vec<(u32, u32)> {
-        let mut result = Vec::new();
-        
-        for i in 0..count {
-            let addr = start_address + (i * 4) as u32;
-            if addr as usize + 3 < self.size {
-                if let Ok(value) = self.read_u32(addr) {
-                    result.push((addr, value));
+    /// Reads consecutive bytes, forwarding each one to the MMU registers, the bus
+    /// device mapped at its physical address, or RAM. Data reads are checked against the watchpoints.
+    fn read_bytes(&self, address: u32, bytes: &mut [u8], kind: AccessKind) -> Result<(), String> {
+        let mut page = None;
+        let mut first_uninitialized = None;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let current = address.checked_add(i as u32)
+                .ok_or(format!("Memory read error: Address {:#010x} out of bounds", address))?;
+            if self.mmu.in_register_window(current) {
+                *byte = self.mmu.read_register_byte(current - self.mmu.base);
+                continue;
+            }
+            let physical = self.physical_address(current, kind, &mut page)?;
+            self.protection.check(physical, kind, self.bus.mapping_at(physical).is_some())?;
+            *byte = match self.bus.read(physical) {
+                Some(value) => value,
+                None if (physical as usize) < self.size => {
+                    if first_uninitialized.is_none() && !self.shadow.is_initialized(physical) {
+                        first_uninitialized = Some(current);
+                    }
+                    self.memory[physical as usize]
+                }
+                None => return Err(format!("Memory read error: Address {:#010x} out of bounds", address)),
+            };
+        }
+        if kind == AccessKind::Read {
+            self.watchpoints.check(address, kind, bytes, bytes);
+            if let (true, Some(first_uninitialized)) = (self.shadow.enabled, first_uninitialized) {
+                self.shadow.report(UninitializedRead { address, size: bytes.len() as u32, first_uninitialized });
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes consecutive bytes after translating them and checking that every one of them is writable.
+    ///
+    /// The whole range is checked before anything is written, so a failed write leaves memory untouched.
+    fn write_bytes(&mut self, address: u32, bytes: &[u8]) -> Result<(), String> {
+        let mut page = None;
+        // `None` marks bytes that land in the MMU register window
+        let mut targets = Vec::with_capacity(bytes.len());
+        for i in 0..bytes.len() {
+            let current = address.checked_add(i as u32)
+                .ok_or(format!("Memory write error: Address {:#010x} out of bounds", address))?;
+            if self.mmu.in_register_window(current) {
+                targets.push(None);
+                continue;
+            }
+            let physical = self.physical_address(current, AccessKind::Write, &mut page)?;
+            let on_bus = self.bus.mapping_at(physical).is_some();
+            self.protection.check(physical, AccessKind::Write, on_bus)?;
+            if (physical as usize) >= self.size && !on_bus {
+                return Err(format!("Memory write error: Address {:#010x} out of bounds", address));
+            }
+            targets.push(Some(physical));
+        }
+        if !self.watchpoints.list.is_empty() {
+            let old: Vec<u8> = targets.iter().enumerate().map(|(i, target)| match target {
+                Some(physical) => self.peek_physical_byte(*physical),
+                None => self.mmu.read_register_byte(address + i as u32 - self.mmu.base),
+            }).collect();
+            self.watchpoints.check(address, AccessKind::Write, &old, bytes);
+        }
+        for (i, (target, &byte)) in targets.iter().zip(bytes).enumerate() {
+            match target {
+                Some(physical) => self.store_physical_byte(*physical, byte),
+                None => {
+                    let offset = address + i as u32 - self.mmu.base;
+                    self.mmu.write_register_byte(offset, byte);
                 }
             }
         }
-        
-        result
+        Ok(())
+    }
+
+    /// Stores consecutive bytes at physical addresses without checking permissions,
+    /// forwarding each one to the bus device mapped at its address or to RAM.
+    ///
+    /// The whole range is checked before anything is written, so a failed write leaves memory untouched.
+    fn store_bytes(&mut self, address: u32, bytes: &[u8]) -> Result<(), String> {
+        for i in 0..bytes.len() {
+            let in_range = address.checked_add(i as u32)
+                .is_some_and(|current| (current as usize) < self.size || self.bus.mapping_at(current).is_some());
+            if !in_range {
+                return Err(format!("Memory write error: Address {:#010x} out of bounds", address));
+            }
+        }
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.store_physical_byte(address + i as u32, byte);
+        }
+        Ok(())
+    }
+
+    /// Reads a byte at a physical address that is known to be in range, without side effects on the
+    /// watchpoints, the uninitialized-read checker or the memory protection, so display code can use it.
+    pub fn peek_physical_byte(&self, address: u32) -> u8 {
+        self.bus.read(address).unwrap_or_else(|| self.memory.get(address as usize))
+    }
+
+    /// Stores a byte at a physical address that is known to be in range, recording the change in the journal.
+    fn store_physical_byte(&mut self, address: u32, value: u8) {
+        let old = self.peek_physical_byte(address);
+        if !self.bus.write(address, value) {
+            self.memory.set(address as usize, value);
+            self.shadow.mark_initialized(address);
+        }
+        self.journal.record(address, old, value);
+    }
+
+    /// Resolves the physical address of one byte of an access.
+    ///
+    /// `page` caches the virtual page number and frame of the last translation,
+    /// so a multi-byte access consults the MMU once per page it touches.
+    fn physical_address(&self, address: u32, kind: AccessKind, page: &mut Option<(u32, u32)>) -> Result<u32, String> {
+        if !self.mmu.enabled() {
+            return Ok(address);
+        }
+        let page_size = self.mmu.effective_page_size();
+        let vpn = address / page_size;
+        if let Some((cached_vpn, frame)) = *page {
+            if cached_vpn == vpn {
+                return Ok(frame | (address % page_size));
+            }
+        }
+        let physical = self.mmu.translate(address, kind, |pte_address| self.read_physical_u32(pte_address))?;
+        *page = Some((vpn, physical & !(page_size - 1)));
+        Ok(physical)
+    }
+
+    /// Reads a 32-bit word of RAM directly, bypassing the MMU, devices and protection.
+    fn read_physical_u32(&self, address: u32) -> Option<u32> {
+        if address as usize + 4 > self.size {
+            return None;
+        }
+        let mut bytes = [0u8; 4];
+        self.memory.read_slice(address as usize, &mut bytes);
+        Some(u32::from_le_bytes(bytes))
+    }
+}
+
+/// Parses a hexadecimal record into its bytes, checking that the checksum makes the sum of every byte zero
+/// (Intel HEX) or all ones (S-record, where `ones_complement` is set).
+fn decode_record(digits: &str, line_number: usize, ones_complement: bool) -> Result<Vec<u8>, String> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return Err(format!("Line {}: malformed record", line_number));
+    }
+    let bytes = (0..digits.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| format!("Line {}: invalid hex digits", line_number))?;
+    let sum = bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    let expected = if ones_complement { 0xFF } else { 0x00 };
+    if sum != expected {
+        return Err(format!("Line {}: checksum mismatch", line_number));
+    }
+    Ok(bytes)
+}
+
+/// Parses Intel HEX records (types 00 to 05).
+fn parse_intel_hex(text: &str) -> Result<MemoryImage, String> {
+    let mut image = MemoryImage::default();
+    let mut upper_address = 0u32;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let digits = line.strip_prefix(':').ok_or(format!("Line {}: Intel HEX records start with ':'", line_number))?;
+        let record = decode_record(digits, line_number, false)?;
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(format!("Line {}: record length does not match its byte count", line_number));
+        }
+        let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            0x00 => image.blocks.push((upper_address.wrapping_add(offset), data.to_vec())),
+            0x01 => break,
+            0x02 if data.len() == 2 => upper_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            0x03 if data.len() == 4 => {
+                let segment = u16::from_be_bytes([data[0], data[1]]) as u32;
+                let pointer = u16::from_be_bytes([data[2], data[3]]) as u32;
+                image.entry = Some((segment << 4) + pointer);
+            }
+            0x04 if data.len() == 2 => upper_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            0x05 if data.len() == 4 => image.entry = Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]])),
+            kind => return Err(format!("Line {}: unsupported or malformed record type {:02X}", line_number, kind)),
+        }
+    }
+    Ok(image)
+}
+
+/// Parses Motorola S-records (S0 to S9).
+fn parse_srecord(text: &str) -> Result<MemoryImage, String> {
+    let mut image = MemoryImage::default();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut chars = line.chars();
+        if chars.next() != Some('S') {
+            return Err(format!("Line {}: S-records start with 'S'", line_number));
+        }
+        let kind = chars.next().and_then(|c| c.to_digit(10)).ok_or(format!("Line {}: missing record type", line_number))?;
+        let record = decode_record(&line[2..], line_number, true)?;
+        if record.is_empty() || record.len() != record[0] as usize + 1 {
+            return Err(format!("Line {}: record length does not match its byte count", line_number));
+        }
+        let address_size = match kind {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            3 | 7 => 4,
+            _ => return Err(format!("Line {}: unsupported record type S{}", line_number, kind)),
+        };
+        if record.len() < address_size + 2 {
+            return Err(format!("Line {}: record too short", line_number));
+        }
+        let address = record[1..=address_size].iter().fold(0u32, |address, &byte| (address << 8) | byte as u32);
+        let data = &record[address_size + 1..record.len() - 1];
+        match kind {
+            1..=3 => image.blocks.push((address, data.to_vec())),
+            7..=9 => image.entry = Some(address),
+            _ => {} // Headers (S0) and record counts (S5, S6) carry no memory contents
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod main_memory_test {
+    use super::*;
+    use crate::memory::bus::ConsoleDevice;
+
+    #[test]
+    fn device_ranges_bypass_ram() {
+        let mut memory = WorkMemory::new(0x1000);
+        memory.bus.map(0x800, 0x10, Box::new(ConsoleDevice::new(0x10))).unwrap();
+
+        memory.write_u32(0x7FE, 0xAABBCCDD).unwrap();
+        assert_eq!(memory.memory[0x7FE], 0xDD);
+        assert_eq!(memory.memory[0x7FF], 0xCC);
+        assert_eq!(memory.memory[0x800], 0x00);
+        assert_eq!(memory.read_u32(0x7FE).unwrap(), 0xAABBCCDD);
+        assert_eq!(memory.bus.read(0x801), Some(0xAA));
+        assert_eq!(memory.console_window(), (0x800, 0x10));
+    }
+
+    #[test]
+    fn the_console_goes_past_ram_within_the_address_space() {
+        assert_eq!(default_console_base(DEFAULT_MEMORY_SIZE), 0x10000);
+        assert_eq!(default_console_base(0xFFFF_F000), 0xFFFF_F000);
+        assert_eq!(default_console_base(0xFFFF_F004), IO_FALLBACK_START);
+    }
+
+    #[test]
+    fn devices_above_ram_are_reachable() {
+        let mut memory = WorkMemory::new(0x1000);
+        assert!(memory.write_u8(0x2000, 1).is_err());
+        memory.bus.map(0x2000, 0x10, Box::new(ConsoleDevice::new(0x10))).unwrap();
+        memory.write_u16(0x200E, 0x1234).unwrap();
+        assert_eq!(memory.read_u16(0x200E).unwrap(), 0x1234);
+        assert!(memory.write_u32(0x200E, 0).is_err());
+        assert_eq!(memory.read_u16(0x200E).unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn protection_is_enforced_except_when_loading() {
+        use crate::memory::protection::Permissions;
+
+        let mut memory = WorkMemory::new(0x1000);
+        memory.protection.enabled = true;
+        memory.protection.add_region("text", 0x0, 0x10, Permissions::READ_EXECUTE);
+        memory.protection.add_region("data", 0x800, 0x10, Permissions::READ_WRITE);
+
+        memory.load_program(0x0, &[0xFF000000]).unwrap();
+        assert_eq!(memory.read_instruction(0x0).unwrap(), 0xFF000000);
+        assert!(memory.write_u32(0x0, 0).unwrap_err().starts_with("Protection fault: write"));
+        assert_eq!(memory.read_u32(0x0).unwrap(), 0xFF000000);
+
+        memory.load_data(0x800, &[1, 2, 3, 4]).unwrap();
+        memory.write_u8(0x800, 9).unwrap();
+        assert!(memory.read_instruction(0x800).unwrap_err().starts_with("Protection fault: execute"));
+    }
+
+    #[test]
+    fn watchpoints_see_data_accesses_only() {
+        use crate::memory::watchpoints::parse_watchpoint;
+
+        let mut memory = WorkMemory::new(0x1000);
+        memory.watchpoints.add(parse_watchpoint("0x100 a", |_| None).unwrap());
+
+        memory.load_data(0x100, &[5, 0, 0, 0]).unwrap();
+        memory.read_instruction(0x100).unwrap();
+        assert_eq!(memory.watchpoints.take_hit(), None);
+
+        memory.write_u16(0x102, 0x0001).unwrap();
+        let hit = memory.watchpoints.take_hit().unwrap();
+        assert_eq!((hit.access, hit.address, hit.old_value, hit.new_value), (AccessKind::Write, 0x102, 0, 1));
+
+        assert_eq!(memory.read_u32(0x100).unwrap(), 0x00010005);
+        assert_eq!(memory.watchpoints.take_hit().unwrap().access, AccessKind::Read);
+    }
+
+    #[test]
+    fn memcheck_reports_uninitialized_data_reads() {
+        let mut memory = WorkMemory::new(0x1000);
+        memory.load_data(0x100, &[1, 2]).unwrap();
+        memory.write_u8(0x102, 3).unwrap();
+
+        memory.read_u32(0x100).unwrap();
+        assert_eq!(memory.shadow.take_pending(), None, "Nothing is reported while memcheck is disabled");
+
+        memory.shadow.enabled = true;
+        memory.read_u16(0x101).unwrap();
+        memory.read_instruction(0x200).unwrap();
+        assert_eq!(memory.shadow.take_pending(), None);
+
+        memory.read_u32(0x100).unwrap();
+        let read = memory.shadow.take_pending().unwrap();
+        assert_eq!((read.address, read.size, read.first_uninitialized), (0x100, 4, 0x103));
+    }
+
+    #[test]
+    fn intel_hex_images_load_with_their_entry_point() {
+        let text = ":020000040000FA\n:0401000001020304F1\n:0400000500000100F6\n:00000001FF\n";
+        let image = MemoryImage::parse(ImageFormat::IntelHex, text.as_bytes(), 0).unwrap();
+        assert_eq!(image.blocks, vec![(0x100, vec![1, 2, 3, 4])]);
+        assert_eq!(image.entry, Some(0x100));
+
+        let mut memory = WorkMemory::new(0x1000);
+        memory.load_image(&image).unwrap();
+        assert_eq!(memory.read_u32(0x100).unwrap(), 0x04030201);
+
+        assert!(MemoryImage::parse(ImageFormat::IntelHex, b":0401000001020304F2", 0).unwrap_err().contains("checksum"));
+    }
+
+    #[test]
+    fn srecord_and_raw_images() {
+        let text = "S00600004844521B\nS1070200AABBCCDDE8\nS9030200FA\n";
+        let image = MemoryImage::parse(ImageFormat::SRecord, text.as_bytes(), 0).unwrap();
+        assert_eq!(image.blocks, vec![(0x200, vec![0xAA, 0xBB, 0xCC, 0xDD])]);
+        assert_eq!(image.entry, Some(0x200));
+
+        let raw = MemoryImage::parse(ImageFormat::Raw, &[9, 8], 0x300).unwrap();
+        assert_eq!(raw.lowest_address(), Some(0x300));
+        let mut memory = WorkMemory::new(0x1000);
+        assert!(memory.load_image(&MemoryImage { blocks: vec![(0xFFF, vec![1, 2])], entry: None }).is_err());
+        memory.load_image(&raw).unwrap();
+        assert_eq!(memory.read_u16(0x300).unwrap(), 0x0809);
+        assert_eq!(ImageFormat::from_path(std::path::Path::new("dump.S19")), ImageFormat::SRecord);
     }
 }
\ No newline at end of file
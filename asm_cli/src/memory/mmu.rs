@@ -0,0 +1,431 @@
+//! # MMU Module
+//!
+//! This module defines the `Mmu` struct, an optional memory management unit
+//! for the ARC CPU. When enabled, every address used by a program is virtual:
+//! it is translated through a single-level page table in physical memory,
+//! with a small TLB caching recent translations.
+//!
+//! The MMU is programmed through a window of 32-bit registers that is always
+//! visible at `MMU_REGISTERS_BASE`, whether translation is enabled or not.
+//! A page-table entry holds the physical base address of its frame in the bits
+//! above the page offset and the `PTE_*` flags in the low bits. Page faults are
+//! delivered by the CPU to the handler in `MMU_FAULT_HANDLER`.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::memory::protection::AccessKind;
+
+/// Default physical address of the MMU register window.
+pub const MMU_REGISTERS_BASE: u32 = 0xD000;
+/// Size of the MMU register window in bytes.
+pub const MMU_REGISTERS_SIZE: u32 = 0x24;
+
+/// Control register; bit 0 enables translation.
+pub const MMU_CONTROL: u32 = 0x00;
+/// Page-table base register: physical address of the page table.
+pub const MMU_PTBR: u32 = 0x04;
+/// Page-table length register: number of entries in the page table.
+pub const MMU_PTLR: u32 = 0x08;
+/// Page size in bytes; must be a power of two between `MIN_PAGE_SIZE` and `MAX_PAGE_SIZE`.
+pub const MMU_PAGE_SIZE: u32 = 0x0C;
+/// Virtual address of the last page fault (read-only).
+pub const MMU_FAULT_ADDRESS: u32 = 0x10;
+/// Address of the instruction that caused the last page fault (read-only).
+pub const MMU_FAULT_PC: u32 = 0x14;
+/// Cause of the last page fault, a `FaultCause` value (read-only).
+pub const MMU_FAULT_CAUSE: u32 = 0x18;
+/// Address of the page-fault handler; 0 means faults stop the CPU.
+pub const MMU_FAULT_HANDLER: u32 = 0x1C;
+/// Writing any value to this register flushes the TLB.
+pub const MMU_TLB_FLUSH: u32 = 0x20;
+
+/// The page is mapped.
+pub const PTE_PRESENT: u32 = 1 << 0;
+/// The page can be written.
+pub const PTE_WRITABLE: u32 = 1 << 1;
+/// Instructions can be fetched from the page.
+pub const PTE_EXECUTABLE: u32 = 1 << 2;
+
+/// Page size used after reset and whenever `MMU_PAGE_SIZE` holds an invalid value.
+pub const DEFAULT_PAGE_SIZE: u32 = 0x1000;
+/// Smallest supported page size.
+pub const MIN_PAGE_SIZE: u32 = 0x100;
+/// Largest supported page size.
+pub const MAX_PAGE_SIZE: u32 = 0x10000;
+/// Number of translations the TLB can hold.
+pub const TLB_ENTRIES: usize = 16;
+
+/// Why a translation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultCause {
+    /// The page is outside the page table or its entry is not present.
+    NotPresent = 1,
+    /// A write to a page without `PTE_WRITABLE`.
+    WriteProtected = 2,
+    /// An instruction fetch from a page without `PTE_EXECUTABLE`.
+    NotExecutable = 3,
+}
+
+impl fmt::Display for FaultCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultCause::NotPresent => write!(f, "page not present"),
+            FaultCause::WriteProtected => write!(f, "page not writable"),
+            FaultCause::NotExecutable => write!(f, "page not executable"),
+        }
+    }
+}
+
+/// A failed translation waiting to be delivered by the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageFault {
+    /// The virtual address that could not be translated.
+    pub address: u32,
+    /// Why the translation failed.
+    pub cause: FaultCause,
+}
+
+/// A cached translation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlbEntry {
+    /// The virtual page number.
+    pub vpn: u32,
+    /// The page-table entry of the page.
+    pub pte: u32,
+}
+
+/// TLB usage statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TlbStats {
+    /// Translations found in the TLB.
+    pub hits: u64,
+    /// Translations that required a page-table walk.
+    pub misses: u64,
+    /// Times the TLB was flushed.
+    pub flushes: u64,
+}
+
+impl TlbStats {
+    /// Returns the TLB hit rate as a percentage.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 * 100.0 / lookups as f64
+    }
+}
+
+/// The memory management unit.
+///
+/// The TLB, its statistics and the pending fault use interior mutability,
+/// because translations happen during reads, which only borrow memory immutably.
+#[derive(Debug, Clone)]
+pub struct Mmu {
+    /// Whether the MMU hardware is installed; when `false` the register window is ordinary memory.
+    pub present: bool,
+    /// Physical address of the register window.
+    pub base: u32,
+    /// The control register.
+    pub control: u32,
+    /// The page-table base register.
+    pub ptbr: u32,
+    /// The page-table length register.
+    pub ptlr: u32,
+    /// The page size register.
+    pub page_size: u32,
+    /// Virtual address of the last page fault.
+    pub fault_address: u32,
+    /// Address of the instruction that caused the last page fault.
+    pub fault_pc: u32,
+    /// Cause of the last page fault (0 if none happened yet).
+    pub fault_cause: u32,
+    /// Address of the page-fault handler.
+    pub fault_handler: u32,
+    /// Cached translations, oldest first.
+    tlb: RefCell<VecDeque<TlbEntry>>,
+    /// TLB usage statistics.
+    stats: Cell<TlbStats>,
+    /// The last translation failure, until the CPU takes it.
+    pending_fault: Cell<Option<PageFault>>,
+}
+
+impl Default for Mmu {
+    /// Creates an MMU that is not installed.
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl Mmu {
+    /// Creates a new `Mmu` with translation disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `present` - Whether the MMU hardware is installed.
+    pub fn new(present: bool) -> Self {
+        Self {
+            present,
+            base: MMU_REGISTERS_BASE,
+            control: 0,
+            ptbr: 0,
+            ptlr: 0,
+            page_size: DEFAULT_PAGE_SIZE,
+            fault_address: 0,
+            fault_pc: 0,
+            fault_cause: 0,
+            fault_handler: 0,
+            tlb: RefCell::new(VecDeque::with_capacity(TLB_ENTRIES)),
+            stats: Cell::new(TlbStats::default()),
+            pending_fault: Cell::new(None),
+        }
+    }
+
+    /// Returns `true` if the MMU is installed and translation is enabled.
+    pub fn enabled(&self) -> bool {
+        self.present && self.control & 1 != 0
+    }
+
+    /// Returns the page size in use, falling back to `DEFAULT_PAGE_SIZE` if the register is invalid.
+    pub fn effective_page_size(&self) -> u32 {
+        if self.page_size.is_power_of_two() && (MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&self.page_size) {
+            self.page_size
+        } else {
+            DEFAULT_PAGE_SIZE
+        }
+    }
+
+    /// Returns `true` if `address` falls inside the register window of an installed MMU.
+    pub fn in_register_window(&self, address: u32) -> bool {
+        self.present && address >= self.base && (address - self.base) < MMU_REGISTERS_SIZE
+    }
+
+    /// Reads a byte of the register window.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset inside the register window.
+    pub fn read_register_byte(&self, offset: u32) -> u8 {
+        let register = match offset & !3 {
+            MMU_CONTROL => self.control,
+            MMU_PTBR => self.ptbr,
+            MMU_PTLR => self.ptlr,
+            MMU_PAGE_SIZE => self.page_size,
+            MMU_FAULT_ADDRESS => self.fault_address,
+            MMU_FAULT_PC => self.fault_pc,
+            MMU_FAULT_CAUSE => self.fault_cause,
+            MMU_FAULT_HANDLER => self.fault_handler,
+            _ => 0,
+        };
+        register.to_le_bytes()[(offset & 3) as usize]
+    }
+
+    /// Writes a byte of the register window.
+    ///
+    /// Writes to the read-only fault registers are ignored. Changing the control,
+    /// page-table or page size registers flushes the TLB, as does any write to
+    /// `MMU_TLB_FLUSH`.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset inside the register window.
+    /// * `value` - The byte value to write.
+    pub fn write_register_byte(&mut self, offset: u32, value: u8) {
+        let register = match offset & !3 {
+            MMU_CONTROL => &mut self.control,
+            MMU_PTBR => &mut self.ptbr,
+            MMU_PTLR => &mut self.ptlr,
+            MMU_PAGE_SIZE => &mut self.page_size,
+            MMU_FAULT_HANDLER => &mut self.fault_handler,
+            MMU_TLB_FLUSH => {
+                self.flush_tlb();
+                return;
+            }
+            _ => return,
+        };
+        let mut bytes = register.to_le_bytes();
+        bytes[(offset & 3) as usize] = value;
+        *register = u32::from_le_bytes(bytes);
+        if offset & !3 != MMU_FAULT_HANDLER {
+            self.flush_tlb();
+        }
+    }
+
+    /// Translates a virtual address into a physical address.
+    ///
+    /// On a failed translation the fault is recorded as pending, so the CPU can
+    /// deliver it to the page-fault handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The virtual address.
+    /// * `kind` - The kind of access, checked against the page flags.
+    /// * `read_physical` - Reads a 32-bit word of physical memory, used for page-table walks.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u32, String>` - The physical address, or an error message describing the page fault.
+    pub fn translate(&self, address: u32, kind: AccessKind, read_physical: impl Fn(u32) -> Option<u32>) -> Result<u32, String> {
+        if !self.enabled() {
+            return Ok(address);
+        }
+        let page_size = self.effective_page_size();
+        let vpn = address / page_size;
+        let offset = address % page_size;
+
+        let pte = match self.lookup_tlb(vpn) {
+            Some(pte) => pte,
+            None => {
+                let pte = if vpn < self.ptlr {
+                    let pte_address = self.ptbr.wrapping_add(vpn.wrapping_mul(4));
+                    read_physical(pte_address)
+                        .ok_or(format!("MMU error: page table entry at {:#010x} is outside memory", pte_address))?
+                } else {
+                    0
+                };
+                if pte & PTE_PRESENT == 0 {
+                    return Err(self.raise(address, FaultCause::NotPresent, kind));
+                }
+                self.insert_tlb(TlbEntry { vpn, pte });
+                pte
+            }
+        };
+
+        if kind == AccessKind::Write && pte & PTE_WRITABLE == 0 {
+            return Err(self.raise(address, FaultCause::WriteProtected, kind));
+        }
+        if kind == AccessKind::Execute && pte & PTE_EXECUTABLE == 0 {
+            return Err(self.raise(address, FaultCause::NotExecutable, kind));
+        }
+        Ok((pte & !(page_size - 1)) | offset)
+    }
+
+    /// Takes the pending page fault, if any.
+    pub fn take_pending_fault(&self) -> Option<PageFault> {
+        self.pending_fault.take()
+    }
+
+    /// Stores a fault in the fault registers before it is delivered to the handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `fault` - The fault being delivered.
+    /// * `pc` - The address of the faulting instruction.
+    pub fn record_fault(&mut self, fault: &PageFault, pc: u32) {
+        self.fault_address = fault.address;
+        self.fault_cause = fault.cause as u32;
+        self.fault_pc = pc;
+    }
+
+    /// Empties the TLB.
+    pub fn flush_tlb(&self) {
+        self.tlb.borrow_mut().clear();
+        let mut stats = self.stats.get();
+        stats.flushes += 1;
+        self.stats.set(stats);
+    }
+
+    /// Returns the cached translations, oldest first.
+    pub fn tlb_entries(&self) -> Vec<TlbEntry> {
+        self.tlb.borrow().iter().copied().collect()
+    }
+
+    /// Returns the TLB usage statistics.
+    pub fn tlb_stats(&self) -> TlbStats {
+        self.stats.get()
+    }
+
+    /// Looks up a virtual page in the TLB, counting the hit or miss.
+    fn lookup_tlb(&self, vpn: u32) -> Option<u32> {
+        let pte = self.tlb.borrow().iter().find(|entry| entry.vpn == vpn).map(|entry| entry.pte);
+        let mut stats = self.stats.get();
+        if pte.is_some() {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+        self.stats.set(stats);
+        pte
+    }
+
+    /// Caches a translation, evicting the oldest one when the TLB is full.
+    fn insert_tlb(&self, entry: TlbEntry) {
+        let mut tlb = self.tlb.borrow_mut();
+        if tlb.len() == TLB_ENTRIES {
+            tlb.pop_front();
+        }
+        tlb.push_back(entry);
+    }
+
+    /// Records a pending fault and returns its error message.
+    fn raise(&self, address: u32, cause: FaultCause, kind: AccessKind) -> String {
+        self.pending_fault.set(Some(PageFault { address, cause }));
+        format!("Page fault: {} at {:#010x} ({})", kind, address, cause)
+    }
+}
+
+#[cfg(test)]
+mod mmu_test {
+    use super::*;
+    use crate::chips::cpu::CPU;
+    use crate::memory::main_memory::WorkMemory;
+    use crate::memory::registers::Reg;
+
+    /// Builds a memory with the MMU installed and a page table at 0x8000.
+    fn paged_memory() -> WorkMemory {
+        let mut memory = WorkMemory::new(0x10000);
+        memory.mmu = Mmu::new(true);
+        memory.write_u32(MMU_REGISTERS_BASE + MMU_PTBR, 0x8000).unwrap();
+        memory.write_u32(MMU_REGISTERS_BASE + MMU_PTLR, 16).unwrap();
+        memory.write_u32(MMU_REGISTERS_BASE + MMU_PAGE_SIZE, 0x1000).unwrap();
+        memory
+    }
+
+    fn map(memory: &mut WorkMemory, vpn: u32, frame: u32, flags: u32) {
+        memory.write_u32(0x8000 + vpn * 4, frame | flags).unwrap();
+    }
+
+    #[test]
+    fn translates_through_the_page_table_and_tlb() {
+        let mut memory = paged_memory();
+        map(&mut memory, 1, 0x5000, PTE_PRESENT | PTE_WRITABLE);
+        memory.write_u32(MMU_REGISTERS_BASE + MMU_CONTROL, 1).unwrap();
+
+        memory.write_u32(0x1010, 0xCAFE).unwrap();
+        assert_eq!(memory.read_u32(0x1010).unwrap(), 0xCAFE);
+        assert_eq!(memory.memory[0x5010], 0xFE);
+        let stats = memory.mmu.tlb_stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+
+        let fault = memory.read_u8(0x2000).unwrap_err();
+        assert!(fault.starts_with("Page fault: read at 0x00002000"));
+        assert_eq!(memory.mmu.take_pending_fault(), Some(PageFault { address: 0x2000, cause: FaultCause::NotPresent }));
+        assert!(memory.read_instruction(0x1000).is_err());
+        assert_eq!(memory.mmu.take_pending_fault().unwrap().cause, FaultCause::NotExecutable);
+    }
+
+    #[test]
+    fn page_faults_are_delivered_to_the_handler() {
+        let mut memory = paged_memory();
+        // Identity-map the code page; the data page 2 is missing
+        map(&mut memory, 0, 0x0000, PTE_PRESENT | PTE_EXECUTABLE | PTE_WRITABLE);
+        map(&mut memory, 0xF, 0xF000, PTE_PRESENT | PTE_WRITABLE);
+        memory.load_program(0x0, &[0x04002000, 0xFF000000]).unwrap(); // LODW AX, [0x2000]; HALT
+        memory.load_program(0x100, &[0xFF000000]).unwrap(); // handler: HALT
+        memory.write_u32(MMU_REGISTERS_BASE + MMU_FAULT_HANDLER, 0x100).unwrap();
+        memory.write_u32(MMU_REGISTERS_BASE + MMU_CONTROL, 1).unwrap();
+
+        let mut cpu = CPU::new();
+        cpu.registers.set(&Reg::SP, 0xFFF0).unwrap();
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.registers.pc, 0x100);
+        assert_eq!(memory.mmu.fault_address, 0x2000);
+        assert_eq!(memory.mmu.fault_pc, 0x0);
+        assert_eq!(memory.mmu.fault_cause, FaultCause::NotPresent as u32);
+        // The faulting instruction is the handler's return address
+        assert_eq!(memory.read_u32(0xFFEC).unwrap(), 0x0);
+        assert_eq!(cpu.call_stack.peek().unwrap(), 0x0);
+    }
+}
@@ -1,10 +1,20 @@
 //! # Memory Module
 //!
 //! This module defines the memory-related components of the ARC CPU,
-//! including the main working memory and CPU registers.
+//! including the main working memory and its sparse backing store, the device
+//! bus, memory protection, the MMU, watchpoints, the write journal, the shadow
+//! memory used by memcheck, memory dumps and CPU registers.
 
+pub mod bus;
+pub mod dump;
+pub mod journal;
 pub mod main_memory; 
+pub mod mmu;
+pub mod protection;
+pub mod sparse;
 pub mod registers; 
+pub mod shadow;
+pub mod watchpoints;
 use crate::memory::main_memory::WorkMemory;
 fn _generate_work_memory() -> WorkMemory {
     let work_memory:WorkMemory = WorkMemory::new(1024);
@@ -0,0 +1,339 @@
+//! # Memory Protection Module
+//!
+//! This module defines the `MemoryProtection` struct, which assigns read, write
+//! and execute permissions to regions of the address space. `WorkMemory`
+//! consults it on every access and reports a protection fault when an access
+//! is not allowed, e.g. a program overwriting its own code or jumping into data.
+
+use std::fmt;
+
+use crate::memory::bus::Bus;
+use crate::utils::assembler::command_processor::AssembledProgram;
+
+/// The size in bytes of the guards placed on the free sides of the stack.
+pub const GUARD_SIZE: u32 = 0x100;
+
+/// The kinds of memory access that are checked against region permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// A data read.
+    Read,
+    /// A data write.
+    Write,
+    /// An instruction fetch.
+    Execute,
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessKind::Read => write!(f, "read"),
+            AccessKind::Write => write!(f, "write"),
+            AccessKind::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+/// The accesses allowed in a memory region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    /// Whether data can be read.
+    pub read: bool,
+    /// Whether data can be written.
+    pub write: bool,
+    /// Whether instructions can be fetched.
+    pub execute: bool,
+    /// Whether the region is served by a bus device; only bus accesses are allowed in it,
+    /// and bus accesses are allowed nowhere else.
+    pub device: bool,
+}
+
+impl Permissions {
+    /// No access at all, used for guard regions.
+    pub const NONE: Permissions = Permissions { read: false, write: false, execute: false, device: false };
+    /// Read-only data.
+    pub const READ: Permissions = Permissions { read: true, write: false, execute: false, device: false };
+    /// Read-write data.
+    pub const READ_WRITE: Permissions = Permissions { read: true, write: true, execute: false, device: false };
+    /// Read-only code.
+    pub const READ_EXECUTE: Permissions = Permissions { read: true, write: false, execute: true, device: false };
+    /// Unrestricted access, used for self-modifying code.
+    pub const ALL: Permissions = Permissions { read: true, write: true, execute: true, device: false };
+    /// Read-write access to a bus device; RAM accesses fault.
+    pub const DEVICE: Permissions = Permissions { read: true, write: true, execute: false, device: true };
+
+    /// Returns `true` if the given kind of access is allowed.
+    pub fn allows(&self, kind: AccessKind) -> bool {
+        match kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+            AccessKind::Execute => self.execute,
+        }
+    }
+}
+
+impl fmt::Display for Permissions {
+    /// Formats the permissions as `RWX`, with `-` for each denied access and a `D` suffix for devices.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}{}",
+            if self.read { 'R' } else { '-' },
+            if self.write { 'W' } else { '-' },
+            if self.execute { 'X' } else { '-' },
+            if self.device { "D" } else { "" },
+        )
+    }
+}
+
+/// A named address range with its permissions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtectedRegion {
+    /// The name shown in protection faults (e.g. "text", "stack").
+    pub name: String,
+    /// The first address of the region.
+    pub start: u32,
+    /// The size of the region in bytes.
+    pub size: u32,
+    /// The accesses allowed in the region.
+    pub permissions: Permissions,
+}
+
+impl ProtectedRegion {
+    /// Returns `true` if `address` falls inside the region.
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.start && (address - self.start) < self.size
+    }
+}
+
+/// The permission map of the address space.
+///
+/// Regions are searched from the most recently added, so guards added on top of
+/// a segment take precedence over it. Addresses outside every region use
+/// `default_permissions`, which deny every access unless changed. While
+/// disabled, every access is allowed.
+#[derive(Debug, Clone)]
+pub struct MemoryProtection {
+    /// Whether permissions are enforced.
+    pub enabled: bool,
+    /// The protected regions.
+    pub regions: Vec<ProtectedRegion>,
+    /// The permissions of addresses outside every region.
+    pub default_permissions: Permissions,
+}
+
+impl Default for MemoryProtection {
+    /// Creates a disabled `MemoryProtection` with no regions, under which unmapped memory faults once enabled.
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            regions: Vec::new(),
+            default_permissions: Permissions::NONE,
+        }
+    }
+}
+
+impl MemoryProtection {
+    /// Builds the permission map for a loaded program.
+    ///
+    /// The text segment is `R-X` (or `RWX` when self-modifying code is allowed),
+    /// the data and stack segments are `RW-` and every device on the bus is `RW-D`.
+    /// The gaps between segments and `GUARD_SIZE` bytes on each free side of the
+    /// stack are guards, and any other memory outside these regions faults.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The assembled program whose segments define the regions.
+    /// * `bus` - The bus whose device ranges are added as I/O regions.
+    /// * `allow_self_modifying_code` - Whether the text segment stays writable.
+    pub fn for_program(program: &AssembledProgram, bus: &Bus, allow_self_modifying_code: bool) -> Self {
+        let mut protection = Self { enabled: true, ..Self::default() };
+        protection.add_guards(program);
+        protection.add_region("stack", program.actual_stack_start, program.actual_stack_size, Permissions::READ_WRITE);
+        protection.add_region("data", program.actual_data_start, program.data.len() as u32, Permissions::READ_WRITE);
+        let text_permissions = if allow_self_modifying_code { Permissions::ALL } else { Permissions::READ_EXECUTE };
+        protection.add_region("text", program.actual_text_start, program.text.len() as u32 * 4, text_permissions);
+        for mapping in &bus.mappings {
+            protection.add_region(&format!("I/O {}", mapping.device.name()), mapping.base, mapping.size, Permissions::DEVICE);
+        }
+        protection
+    }
+
+    /// Adds guards over the gaps between the segments of a program and on each side of its
+    /// stack that no other segment touches, so that overflowing a segment faults at once.
+    fn add_guards(&mut self, program: &AssembledProgram) {
+        let mut segments: Vec<(u64, u64)> = ["text", "data", "stack"].iter()
+            .filter_map(|name| program.segment(name))
+            .filter(|&(_, size)| size > 0)
+            .map(|(start, size)| (start as u64, start as u64 + size as u64))
+            .collect();
+        segments.sort();
+        for pair in segments.windows(2) {
+            let (gap_start, gap_end) = (pair[0].1, pair[1].0);
+            if gap_end > gap_start {
+                self.add_guard(gap_start as u32, (gap_end - gap_start) as u32);
+            }
+        }
+
+        // The gaps above already guard the sides of the stack that face another segment
+        if program.actual_stack_size == 0 {
+            return;
+        }
+        let stack_start = program.actual_stack_start as u64;
+        let stack_end = stack_start + program.actual_stack_size as u64;
+        if segments.iter().all(|&(start, _)| start >= stack_start) {
+            let below = stack_start.saturating_sub(GUARD_SIZE as u64);
+            self.add_guard(below as u32, (stack_start - below) as u32);
+        }
+        if segments.iter().all(|&(_, end)| end <= stack_end) {
+            let above = (stack_end + GUARD_SIZE as u64).min(1 << 32);
+            self.add_guard(stack_end as u32, (above - stack_end) as u32);
+        }
+    }
+
+    /// Adds a region; empty regions are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name shown in protection faults.
+    /// * `start` - The first address of the region.
+    /// * `size` - The size of the region in bytes.
+    /// * `permissions` - The accesses allowed in the region.
+    pub fn add_region(&mut self, name: &str, start: u32, size: u32, permissions: Permissions) {
+        if size == 0 {
+            return;
+        }
+        self.regions.push(ProtectedRegion { name: name.to_string(), start, size, permissions });
+    }
+
+    /// Adds a guard region that faults on every access.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The first address of the guard.
+    /// * `size` - The size of the guard in bytes.
+    pub fn add_guard(&mut self, start: u32, size: u32) {
+        self.add_region("guard", start, size, Permissions::NONE);
+    }
+
+    /// Sets the permissions of every region with the given name.
+    pub fn set_permissions(&mut self, name: &str, permissions: Permissions) {
+        for region in self.regions.iter_mut().filter(|r| r.name == name) {
+            region.permissions = permissions;
+        }
+    }
+
+    /// Finds the region governing `address`, if any.
+    pub fn region_at(&self, address: u32) -> Option<&ProtectedRegion> {
+        self.regions.iter().rev().find(|r| r.contains(address))
+    }
+
+    /// Checks whether an access to `address` is allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address being accessed.
+    /// * `kind` - The kind of access.
+    /// * `on_bus` - Whether a bus device serves the address; device regions only accept
+    ///   such accesses, and every other region rejects them.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - `Ok(())` if the access is allowed, or a protection fault message.
+    pub fn check(&self, address: u32, kind: AccessKind, on_bus: bool) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let (location, permissions) = match self.region_at(address) {
+            Some(region) => (format!("in {}", region.name), region.permissions),
+            None => ("outside any segment".to_string(), self.default_permissions),
+        };
+        if !permissions.allows(kind) {
+            return Err(format!("Protection fault: {} at {:#010x} {} ({})", kind, address, location, permissions));
+        }
+        match (permissions.device, on_bus) {
+            (true, false) => Err(format!(
+                "Protection fault: {} at {:#010x} {} ({}) but no device is mapped there",
+                kind, address, location, permissions
+            )),
+            (false, true) => Err(format!(
+                "Protection fault: device {} at {:#010x} {} ({})",
+                kind, address, location, permissions
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod protection_test {
+    use super::*;
+
+    fn program() -> AssembledProgram {
+        AssembledProgram {
+            text: vec![0; 4],
+            data: vec![0; 8],
+            actual_text_start: 0x0,
+            actual_data_start: 0xEFF8,
+            actual_stack_start: 0xF000,
+            actual_stack_size: 0x1000,
+            ..AssembledProgram::default()
+        }
+    }
+
+    #[test]
+    fn segments_get_their_permissions() {
+        let protection = MemoryProtection::for_program(&program(), &Bus::new(), false);
+        assert!(protection.check(0x4, AccessKind::Execute, false).is_ok());
+        assert!(protection.check(0x4, AccessKind::Read, false).is_ok());
+        let fault = protection.check(0x4, AccessKind::Write, false).unwrap_err();
+        assert_eq!(fault, "Protection fault: write at 0x00000004 in text (R-X)");
+        assert!(protection.check(0xEFF8, AccessKind::Write, false).is_ok());
+        assert!(protection.check(0xEFF8, AccessKind::Execute, false).is_err());
+        assert!(protection.check(0xFFFC, AccessKind::Write, false).is_ok());
+        assert!(protection.check(0xF800, AccessKind::Execute, false).is_err());
+        // Unmapped memory faults
+        let fault = protection.check(0x20000, AccessKind::Read, false).unwrap_err();
+        assert_eq!(fault, "Protection fault: read at 0x00020000 outside any segment (---)");
+    }
+
+    #[test]
+    fn guards_surround_the_stack_and_fill_the_gaps() {
+        let protection = MemoryProtection::for_program(&program(), &Bus::new(), false);
+        assert!(protection.check(0x10, AccessKind::Read, false).unwrap_err().contains("in guard"));
+        assert!(protection.check(0xEFF7, AccessKind::Write, false).unwrap_err().contains("in guard"));
+        assert!(protection.check(0x10000, AccessKind::Write, false).unwrap_err().contains("in guard"));
+        assert!(protection.check(0x100FF, AccessKind::Write, false).unwrap_err().contains("in guard"));
+        assert!(protection.check(0x10100, AccessKind::Write, false).unwrap_err().contains("outside any segment"));
+
+        // A stack below every other segment gets a guard underneath instead
+        let low_stack = AssembledProgram { actual_text_start: 0x2000, actual_data_start: 0x3000, actual_stack_start: 0x1000, ..program() };
+        let protection = MemoryProtection::for_program(&low_stack, &Bus::new(), false);
+        assert!(protection.check(0xF00, AccessKind::Write, false).unwrap_err().contains("in guard"));
+        assert!(protection.check(0x2000, AccessKind::Execute, false).is_ok());
+    }
+
+    #[test]
+    fn devices_are_only_reachable_through_the_bus() {
+        let mut bus = Bus::new();
+        bus.map(0x10000, 0x1000, Box::new(crate::memory::bus::ConsoleDevice::new(0x1000))).unwrap();
+        let protection = MemoryProtection::for_program(&program(), &bus, false);
+        assert!(protection.check(0x10000, AccessKind::Write, true).is_ok());
+        assert!(protection.check(0x10000, AccessKind::Execute, true).is_err());
+        let fault = protection.check(0x10000, AccessKind::Read, false).unwrap_err();
+        assert_eq!(fault, "Protection fault: read at 0x00010000 in I/O console (RW-D) but no device is mapped there");
+        let fault = protection.check(0xEFF8, AccessKind::Write, true).unwrap_err();
+        assert_eq!(fault, "Protection fault: device write at 0x0000eff8 in data (RW-)");
+    }
+
+    #[test]
+    fn self_modifying_code_and_guards() {
+        let mut protection = MemoryProtection::for_program(&program(), &Bus::new(), true);
+        assert!(protection.check(0x4, AccessKind::Write, false).is_ok());
+        protection.add_guard(0xF000, 0x10);
+        assert!(protection.check(0xF008, AccessKind::Read, false).is_err());
+        assert!(protection.check(0xF010, AccessKind::Read, false).is_ok());
+        protection.enabled = false;
+        assert!(protection.check(0xF008, AccessKind::Read, false).is_ok());
+    }
+}
@@ -87,21 +87,11 @@ impl Registers {
         }
     }
     
-    /// Resets all registers to their initial default values.
     /// Resets all registers to their initial default values.
     pub fn reset(&mut self) {
         *self = Self::new();
     }
     
-    /// Retrieves the 32-bit value of a specified register.
-    ///
-    /// # Arguments
-    ///
-    /// * `reg` - A reference to the `Reg` enum variant representing the desired register.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<u32, String>` - The 32-bit value of the register on success, or an error message on failure.
     /// Retrieves the 32-bit value of a specified register.
     ///
     /// # Arguments
@@ -120,16 +110,6 @@ impl Registers {
         }
     }
     
-    /// Sets the 32-bit value of a specified register.
-    ///
-    /// # Arguments
-    ///
-    /// * `reg` - A reference to the `Reg` enum variant representing the target register.
-    /// * `value` - The `u32` value to set the register to.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<(), String>` - `Ok(())` on success, or an error message on failure.
     /// Sets the 32-bit value of a specified register.
     ///
     /// # Arguments
@@ -266,4 +246,30 @@ impl Registers {
         
         Ok((self.flags & (1 << bit_position)) != 0)
     }
+
+    /// Returns every register with its name, in display order.
+    pub fn named_values(&self) -> [(&'static str, u32); 14] {
+        [
+            ("AX", self.ax), ("BX", self.bx), ("CX", self.cx), ("DX", self.dx),
+            ("EX", self.ex), ("FX", self.fx), ("GX", self.gx), ("HX", self.hx),
+            ("SP", self.sp), ("BP", self.bp), ("SI", self.si), ("DI", self.di),
+            ("PC", self.pc), ("FLAGS", self.flags),
+        ]
+    }
+
+    /// Lists the registers whose value differs from an earlier state.
+    ///
+    /// # Arguments
+    ///
+    /// * `before` - The earlier register state.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(&'static str, u32, u32)>` - The name, old value and new value of each changed register.
+    pub fn changes_since(&self, before: &Registers) -> Vec<(&'static str, u32, u32)> {
+        before.named_values().iter().zip(self.named_values())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|(&(name, old), (_, new))| (name, old, new))
+            .collect()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,118 @@
+//! # Shadow Memory Module
+//!
+//! This module defines the `ShadowMemory` struct, which keeps one bit per
+//! byte of physical memory recording whether the byte has been initialized,
+//! either by loading a program or by a write. When the memcheck mode is
+//! enabled, `WorkMemory` reports data reads of bytes that were never
+//! initialized, which would otherwise silently read as zero.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::memory::sparse::BACKING_PAGE_SIZE;
+
+/// Number of 64-bit words holding the bits of one page.
+const WORDS_PER_PAGE: usize = BACKING_PAGE_SIZE / 64;
+
+/// A data read that touched uninitialized memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UninitializedRead {
+    /// The address of the access.
+    pub address: u32,
+    /// The size of the access in bytes.
+    pub size: u32,
+    /// The address of the first uninitialized byte in the access.
+    pub first_uninitialized: u32,
+}
+
+/// Tracks which bytes of memory have been initialized.
+///
+/// Initialization is always tracked, so enabling the check in the middle of a
+/// run does not report bytes that were written before.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowMemory {
+    /// Whether uninitialized reads are reported.
+    pub enabled: bool,
+    /// The initialization bits of every page that has been written, one bit per byte.
+    pages: HashMap<u32, Box<[u64; WORDS_PER_PAGE]>>,
+    /// The first uninitialized read since the last call to `take_pending`.
+    pending: RefCell<Option<UninitializedRead>>,
+}
+
+impl ShadowMemory {
+    /// Creates a shadow memory in which no byte is initialized and checking is disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks the byte at a physical address as initialized.
+    pub fn mark_initialized(&mut self, address: u32) {
+        let (page, word, bit) = Self::locate(address);
+        let bits = self.pages.entry(page).or_insert_with(|| Box::new([0; WORDS_PER_PAGE]));
+        bits[word] |= 1 << bit;
+    }
+
+    /// Marks the byte at a physical address as uninitialized again, e.g. for a reserved buffer.
+    pub fn mark_uninitialized(&mut self, address: u32) {
+        let (page, word, bit) = Self::locate(address);
+        if let Some(bits) = self.pages.get_mut(&page) {
+            bits[word] &= !(1 << bit);
+        }
+    }
+
+    /// Returns `true` if the byte at a physical address has been initialized.
+    pub fn is_initialized(&self, address: u32) -> bool {
+        let (page, word, bit) = Self::locate(address);
+        self.pages.get(&page).is_some_and(|bits| bits[word] & (1 << bit) != 0)
+    }
+
+    /// Records an uninitialized read unless one is already pending.
+    ///
+    /// # Arguments
+    ///
+    /// * `read` - The access that touched uninitialized memory.
+    pub fn report(&self, read: UninitializedRead) {
+        let mut pending = self.pending.borrow_mut();
+        if pending.is_none() {
+            *pending = Some(read);
+        }
+    }
+
+    /// Takes the first uninitialized read recorded since the last call.
+    pub fn take_pending(&self) -> Option<UninitializedRead> {
+        self.pending.take()
+    }
+
+    /// Splits an address into its page number, word index and bit index.
+    fn locate(address: u32) -> (u32, usize, usize) {
+        let offset = address as usize % BACKING_PAGE_SIZE;
+        (address / BACKING_PAGE_SIZE as u32, offset / 64, offset % 64)
+    }
+}
+
+#[cfg(test)]
+mod shadow_test {
+    use super::*;
+
+    #[test]
+    fn bytes_start_uninitialized() {
+        let mut shadow = ShadowMemory::new();
+        assert!(!shadow.is_initialized(0x1234));
+        shadow.mark_initialized(0x1234);
+        shadow.mark_initialized(0xFFFF_FFFF);
+        assert!(shadow.is_initialized(0x1234));
+        assert!(!shadow.is_initialized(0x1235));
+        assert!(shadow.is_initialized(0xFFFF_FFFF));
+        shadow.mark_uninitialized(0x1234);
+        assert!(!shadow.is_initialized(0x1234));
+    }
+
+    #[test]
+    fn only_the_first_report_is_kept() {
+        let shadow = ShadowMemory::new();
+        shadow.report(UninitializedRead { address: 0x10, size: 4, first_uninitialized: 0x12 });
+        shadow.report(UninitializedRead { address: 0x20, size: 1, first_uninitialized: 0x20 });
+        assert_eq!(shadow.take_pending().map(|read| read.address), Some(0x10));
+        assert_eq!(shadow.take_pending(), None);
+    }
+}
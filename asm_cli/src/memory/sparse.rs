@@ -0,0 +1,246 @@
+//! # Sparse Memory Module
+//!
+//! This module defines the `SparseMemory` struct, the backing store of
+//! `WorkMemory`. Memory is split into fixed-size pages that are only allocated
+//! when a non-zero byte is first written to them; untouched pages read as zero.
+//! Pages are found through a two-level table, so even the full 32-bit address
+//! space only costs a small directory until it is used.
+
+use std::ops::Index;
+
+/// Size of a backing page in bytes.
+pub const BACKING_PAGE_SIZE: usize = 0x1000; // 4KB
+/// Number of pages covered by one second-level table.
+const PAGES_PER_TABLE: usize = 1024;
+
+/// A backing page.
+type Page = Box<[u8; BACKING_PAGE_SIZE]>;
+/// A second-level table of pages.
+type PageTable = Box<[Option<Page>; PAGES_PER_TABLE]>;
+
+/// The value of every byte that has never been written.
+static ZERO: u8 = 0;
+
+/// A byte-addressable store that only allocates the pages that are written.
+#[derive(Debug, Clone)]
+pub struct SparseMemory {
+    /// The size of the address range in bytes.
+    size: usize,
+    /// The first-level directory of page tables.
+    directory: Vec<Option<PageTable>>,
+    /// The number of allocated pages.
+    allocated_pages: usize,
+}
+
+impl SparseMemory {
+    /// Creates a new `SparseMemory` covering `size` bytes, with no pages allocated.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the address range in bytes.
+    pub fn new(size: usize) -> Self {
+        let pages = size.div_ceil(BACKING_PAGE_SIZE);
+        Self {
+            size,
+            directory: vec![None; pages.div_ceil(PAGES_PER_TABLE)],
+            allocated_pages: 0,
+        }
+    }
+
+    /// Returns the size of the address range in bytes.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns `true` if the address range is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the number of pages that have been allocated.
+    pub fn allocated_pages(&self) -> usize {
+        self.allocated_pages
+    }
+
+    /// Reads the byte at `address`; bytes outside the range or never written read as zero.
+    pub fn get(&self, address: usize) -> u8 {
+        self.page(address).map_or(0, |page| page[address % BACKING_PAGE_SIZE])
+    }
+
+    /// Writes the byte at `address`, allocating its page if needed.
+    ///
+    /// Writing zero to a page that was never allocated does not allocate it.
+    /// Writes outside the range are ignored; callers check bounds first.
+    pub fn set(&mut self, address: usize, value: u8) {
+        if address >= self.size {
+            return;
+        }
+        if value == 0 && self.page(address).is_none() {
+            return;
+        }
+        self.page_mut(address)[address % BACKING_PAGE_SIZE] = value;
+    }
+
+    /// Copies bytes starting at `address` into `buffer`.
+    pub fn read_slice(&self, address: usize, buffer: &mut [u8]) {
+        let mut done = 0;
+        while done < buffer.len() {
+            let current = address + done;
+            let offset = current % BACKING_PAGE_SIZE;
+            let chunk = (BACKING_PAGE_SIZE - offset).min(buffer.len() - done);
+            match self.page(current) {
+                Some(page) => buffer[done..done + chunk].copy_from_slice(&page[offset..offset + chunk]),
+                None => buffer[done..done + chunk].fill(0),
+            }
+            done += chunk;
+        }
+    }
+
+    /// Copies `data` into memory starting at `address`; bytes past the end of the range are dropped.
+    pub fn write_slice(&mut self, address: usize, data: &[u8]) {
+        let mut done = 0;
+        while done < data.len() && address + done < self.size {
+            let current = address + done;
+            let offset = current % BACKING_PAGE_SIZE;
+            let chunk = (BACKING_PAGE_SIZE - offset).min(data.len() - done).min(self.size - current);
+            let bytes = &data[done..done + chunk];
+            if self.page(current).is_some() || bytes.iter().any(|&b| b != 0) {
+                self.page_mut(current)[offset..offset + chunk].copy_from_slice(bytes);
+            }
+            done += chunk;
+        }
+    }
+
+    /// Returns the allocated pages in address order, as their base address and contents.
+    pub fn allocated(&self) -> impl Iterator<Item = (usize, &[u8])> + '_ {
+        self.directory.iter().enumerate()
+            .filter_map(|(table_index, table)| table.as_ref().map(|table| (table_index, table)))
+            .flat_map(|(table_index, table)| {
+                table.iter().enumerate().filter_map(move |(page_index, page)| {
+                    page.as_ref().map(|page| {
+                        let base = (table_index * PAGES_PER_TABLE + page_index) * BACKING_PAGE_SIZE;
+                        (base, &page[..])
+                    })
+                })
+            })
+    }
+
+    /// Finds the first occurrence of `pattern` at or after `from`.
+    ///
+    /// A pattern containing a non-zero byte can only match where a page is
+    /// allocated, so only the neighbourhood of allocated pages is scanned.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The bytes to look for.
+    /// * `from` - The first address to consider.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<usize>` - The address of the first match, or `None` if there is none before the end of the range.
+    pub fn find(&self, pattern: &[u8], from: usize) -> Option<usize> {
+        if pattern.is_empty() || pattern.len() > self.size {
+            return None;
+        }
+        let last_start = self.size - pattern.len();
+        let matches_at = |address: usize| pattern.iter().enumerate().all(|(i, &byte)| self.get(address + i) == byte);
+
+        if pattern.iter().all(|&byte| byte == 0) {
+            return (from..=last_start).find(|&address| matches_at(address));
+        }
+
+        let mut next = from;
+        for (base, _) in self.allocated() {
+            let start = next.max(base.saturating_sub(pattern.len() - 1));
+            let end = (base + BACKING_PAGE_SIZE).min(last_start + 1);
+            if let Some(address) = (start..end).find(|&address| matches_at(address)) {
+                return Some(address);
+            }
+            next = next.max(end);
+        }
+        None
+    }
+
+    /// Finds the page holding `address`, if it has been allocated.
+    fn page(&self, address: usize) -> Option<&Page> {
+        if address >= self.size {
+            return None;
+        }
+        let page_number = address / BACKING_PAGE_SIZE;
+        self.directory[page_number / PAGES_PER_TABLE]
+            .as_ref()?[page_number % PAGES_PER_TABLE]
+            .as_ref()
+    }
+
+    /// Finds the page holding `address`, allocating it and its table if needed.
+    fn page_mut(&mut self, address: usize) -> &mut Page {
+        let page_number = address / BACKING_PAGE_SIZE;
+        let table = self.directory[page_number / PAGES_PER_TABLE]
+            .get_or_insert_with(|| Box::new(std::array::from_fn(|_| None)));
+        let slot = &mut table[page_number % PAGES_PER_TABLE];
+        if slot.is_none() {
+            self.allocated_pages += 1;
+        }
+        slot.get_or_insert_with(|| Box::new([0; BACKING_PAGE_SIZE]))
+    }
+}
+
+impl Index<usize> for SparseMemory {
+    type Output = u8;
+
+    /// Returns a reference to the byte at `address`, or to a zero byte if its page is not allocated.
+    fn index(&self, address: usize) -> &u8 {
+        match self.page(address) {
+            Some(page) => &page[address % BACKING_PAGE_SIZE],
+            None => &ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod sparse_test {
+    use super::*;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")] // A 4GB size does not fit in a 32-bit usize
+    fn only_touched_pages_are_allocated() {
+        let mut memory = SparseMemory::new(1 << 32);
+        assert_eq!(memory.get(0xFFFF_FFFF), 0);
+        memory.set(0x10, 0);
+        assert_eq!(memory.allocated_pages(), 0);
+
+        memory.set(0xFFFF_FFFF, 0xAB);
+        memory.set(0x10, 0x01);
+        assert_eq!(memory.allocated_pages(), 2);
+        assert_eq!(memory[0xFFFF_FFFF], 0xAB);
+        assert_eq!(memory.allocated().map(|(base, _)| base).collect::<Vec<_>>(), vec![0, 0xFFFF_F000]);
+    }
+
+    #[test]
+    fn slices_span_page_boundaries() {
+        let mut memory = SparseMemory::new(0x10000);
+        memory.write_slice(0xFFE, &[1, 2, 3, 4]);
+        let mut buffer = [0u8; 6];
+        memory.read_slice(0xFFD, &mut buffer);
+        assert_eq!(buffer, [0, 1, 2, 3, 4, 0]);
+        assert_eq!(memory.allocated_pages(), 2);
+
+        memory.write_slice(0x5000, &[0; 16]);
+        assert_eq!(memory.allocated_pages(), 2);
+        memory.write_slice(0xFFFE, &[7, 8, 9]);
+        assert_eq!(memory.get(0xFFFF), 8);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn find_scans_allocated_pages_and_their_boundaries() {
+        let mut memory = SparseMemory::new(1 << 32);
+        memory.write_slice(0x1FFE, b"ARC!");
+        memory.write_slice(0x8000_0000, b"ARC!");
+
+        assert_eq!(memory.find(b"ARC!", 0), Some(0x1FFE));
+        assert_eq!(memory.find(b"ARC!", 0x1FFF), Some(0x8000_0000));
+        assert_eq!(memory.find(b"ARC!", 0x8000_0001), None);
+        assert_eq!(memory.find(&[0, 0], 0x1FFE), Some(0x2002));
+    }
+}
@@ -0,0 +1,315 @@
+//! # Watchpoints Module
+//!
+//! This module defines the `Watchpoints` struct, which holds the memory
+//! watchpoints set by the user. `WorkMemory` checks every data read and write
+//! against them and records the first hit, so the emulator can stop after the
+//! instruction that touched the watched memory and report who changed it.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use crate::memory::protection::AccessKind;
+
+/// Number of bytes watched when a watchpoint does not give a size.
+pub const DEFAULT_WATCH_SIZE: u32 = 4;
+
+/// The accesses a watchpoint triggers on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    /// Data reads only.
+    Read,
+    /// Data writes only.
+    Write,
+    /// Both reads and writes.
+    Access,
+}
+
+impl WatchKind {
+    /// Returns `true` if an access of the given kind triggers this watchpoint.
+    pub fn matches(&self, access: AccessKind) -> bool {
+        matches!(
+            (self, access),
+            (WatchKind::Read, AccessKind::Read) | (WatchKind::Write, AccessKind::Write) | (WatchKind::Access, AccessKind::Read | AccessKind::Write)
+        )
+    }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Access => write!(f, "access"),
+        }
+    }
+}
+
+/// A watched address range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    /// The number shown to the user and used to delete the watchpoint.
+    pub id: u32,
+    /// The first watched address.
+    pub start: u32,
+    /// The number of watched bytes.
+    pub size: u32,
+    /// The accesses that trigger the watchpoint.
+    pub kind: WatchKind,
+    /// If set, the watchpoint only triggers when the value read or written equals it.
+    pub condition: Option<u32>,
+    /// The label the watchpoint was set on, re-resolved whenever a program is loaded.
+    pub label: Option<String>,
+}
+
+impl Watchpoint {
+    /// Returns `true` if the access `[address, address + len)` overlaps the watched range.
+    pub fn overlaps(&self, address: u32, len: usize) -> bool {
+        let start = address as u64;
+        let end = start + len as u64;
+        let watched = self.start as u64;
+        start < watched + self.size as u64 && watched < end
+    }
+
+    /// Returns a short description such as `counter:4 write == 0x5`.
+    pub fn describe(&self) -> String {
+        let target = match &self.label {
+            Some(label) => format!("{} (0x{:04X})", label, self.start),
+            None => format!("0x{:04X}", self.start),
+        };
+        let condition = match self.condition {
+            Some(value) => format!(" == 0x{:X}", value),
+            None => String::new(),
+        };
+        format!("#{} {}:{} {}{}", self.id, target, self.size, self.kind, condition)
+    }
+}
+
+/// A recorded watchpoint trigger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchpointHit {
+    /// The id of the watchpoint that triggered.
+    pub id: u32,
+    /// The address of the access.
+    pub address: u32,
+    /// The kind of access.
+    pub access: AccessKind,
+    /// The value in memory before the access (little-endian, up to 4 bytes).
+    pub old_value: u32,
+    /// The value read or written (little-endian, up to 4 bytes).
+    pub new_value: u32,
+}
+
+/// The set of watchpoints and the first hit since it was last taken.
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints {
+    /// The active watchpoints.
+    pub list: Vec<Watchpoint>,
+    /// The id given to the next watchpoint.
+    next_id: u32,
+    /// The first hit since the last call to `take_hit`.
+    hit: RefCell<Option<WatchpointHit>>,
+}
+
+impl Watchpoints {
+    /// Creates an empty set of watchpoints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a watchpoint, giving it a fresh id.
+    ///
+    /// # Returns
+    ///
+    /// * `u32` - The id of the new watchpoint.
+    pub fn add(&mut self, mut watchpoint: Watchpoint) -> u32 {
+        self.next_id += 1;
+        watchpoint.id = self.next_id;
+        self.list.push(watchpoint);
+        self.next_id
+    }
+
+    /// Removes the watchpoint with the given id.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - `Ok(())` on success, or an error message if no watchpoint has that id.
+    pub fn remove(&mut self, id: u32) -> Result<(), String> {
+        let index = self.list.iter().position(|w| w.id == id)
+            .ok_or(format!("No watchpoint #{}", id))?;
+        self.list.remove(index);
+        Ok(())
+    }
+
+    /// Removes every watchpoint.
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.hit.replace(None);
+    }
+
+    /// Updates the address of every watchpoint set on a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `resolve` - Returns the address of a label, or `None` if it no longer exists.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` - The labels that could not be resolved; their watchpoints are removed.
+    pub fn resolve_labels(&mut self, resolve: impl Fn(&str) -> Option<u32>) -> Vec<String> {
+        let mut missing = Vec::new();
+        self.list.retain_mut(|watchpoint| {
+            let Some(label) = &watchpoint.label else { return true };
+            match resolve(label) {
+                Some(address) => {
+                    watchpoint.start = address;
+                    true
+                }
+                None => {
+                    missing.push(label.clone());
+                    false
+                }
+            }
+        });
+        missing
+    }
+
+    /// Checks an access against the watchpoints, recording the first hit.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The first address of the access.
+    /// * `access` - Whether the access is a read or a write.
+    /// * `old` - The bytes in memory before the access.
+    /// * `new` - The bytes read or written.
+    pub fn check(&self, address: u32, access: AccessKind, old: &[u8], new: &[u8]) {
+        if self.list.is_empty() || self.hit.borrow().is_some() {
+            return;
+        }
+        let new_value = bytes_to_value(new);
+        let triggered = self.list.iter().find(|w| {
+            w.kind.matches(access) && w.overlaps(address, new.len()) && w.condition.is_none_or(|value| value == new_value)
+        });
+        if let Some(watchpoint) = triggered {
+            self.hit.replace(Some(WatchpointHit {
+                id: watchpoint.id,
+                address,
+                access,
+                old_value: bytes_to_value(old),
+                new_value,
+            }));
+        }
+    }
+
+    /// Takes the first hit recorded since the last call.
+    pub fn take_hit(&self) -> Option<WatchpointHit> {
+        self.hit.take()
+    }
+
+    /// Finds the watchpoint with the given id.
+    pub fn get(&self, id: u32) -> Option<&Watchpoint> {
+        self.list.iter().find(|w| w.id == id)
+    }
+}
+
+/// Parses a watchpoint specification.
+///
+/// The format is `TARGET[:SIZE] [r|w|a] [=VALUE]`, where `TARGET` is a label or
+/// an address, `SIZE` defaults to `DEFAULT_WATCH_SIZE` bytes and the kind defaults
+/// to `w`. For example `counter w`, `0x8000:2 a` or `total w =0x10`.
+///
+/// # Arguments
+///
+/// * `spec` - The specification typed by the user.
+/// * `resolve` - Returns the address of a label.
+///
+/// # Returns
+///
+/// * `Result<Watchpoint, String>` - The watchpoint (with id 0 until added), or an error message.
+pub fn parse_watchpoint(spec: &str, resolve: impl Fn(&str) -> Option<u32>) -> Result<Watchpoint, String> {
+    let mut parts = spec.split_whitespace();
+    let target = parts.next().ok_or("Missing watchpoint address or label".to_string())?;
+    let (target, size) = match target.split_once(':') {
+        Some((target, size)) => (target, parse_number(size)?),
+        None => (target, DEFAULT_WATCH_SIZE),
+    };
+    if size == 0 {
+        return Err("Watchpoint size must be at least 1 byte".to_string());
+    }
+
+    let (start, label) = match parse_number(target) {
+        Ok(address) => (address, None),
+        Err(_) => {
+            let address = resolve(target).ok_or(format!("Unknown label: {}", target))?;
+            (address, Some(target.to_string()))
+        }
+    };
+
+    let mut kind = WatchKind::Write;
+    let mut condition = None;
+    for part in parts {
+        match part.to_lowercase().as_str() {
+            "r" | "read" => kind = WatchKind::Read,
+            "w" | "write" => kind = WatchKind::Write,
+            "a" | "rw" | "access" => kind = WatchKind::Access,
+            other => {
+                let value = other.strip_prefix("==").or_else(|| other.strip_prefix('='))
+                    .ok_or(format!("Unexpected watchpoint option: {}", part))?;
+                condition = Some(parse_number(value)?);
+            }
+        }
+    }
+
+    Ok(Watchpoint { id: 0, start, size, kind, condition, label })
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+pub(crate) fn parse_number(text: &str) -> Result<u32, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse::<u32>(),
+    };
+    parsed.map_err(|_| format!("Invalid number: {}", text))
+}
+
+/// Converts up to 4 little-endian bytes into a value.
+fn bytes_to_value(bytes: &[u8]) -> u32 {
+    bytes.iter().take(4).rev().fold(0, |value, &byte| (value << 8) | byte as u32)
+}
+
+#[cfg(test)]
+mod watchpoints_test {
+    use super::*;
+
+    #[test]
+    fn parse_specifications() {
+        let resolve = |name: &str| if name == "counter" { Some(0xEFF8) } else { None };
+        let watchpoint = parse_watchpoint("counter", resolve).unwrap();
+        assert_eq!((watchpoint.start, watchpoint.size, watchpoint.kind), (0xEFF8, 4, WatchKind::Write));
+        assert_eq!(watchpoint.label.as_deref(), Some("counter"));
+
+        let watchpoint = parse_watchpoint("0x100:2 a ==0x10", resolve).unwrap();
+        assert_eq!((watchpoint.start, watchpoint.size, watchpoint.kind, watchpoint.condition), (0x100, 2, WatchKind::Access, Some(0x10)));
+
+        assert!(parse_watchpoint("missing", resolve).is_err());
+        assert!(parse_watchpoint("0x100 x", resolve).is_err());
+    }
+
+    #[test]
+    fn records_first_matching_access() {
+        let mut watchpoints = Watchpoints::new();
+        let id = watchpoints.add(parse_watchpoint("0x100 w =7", |_| None).unwrap());
+
+        watchpoints.check(0x100, AccessKind::Read, &[7, 0, 0, 0], &[7, 0, 0, 0]);
+        watchpoints.check(0x102, AccessKind::Write, &[0, 0], &[5, 0]);
+        assert_eq!(watchpoints.take_hit(), None);
+
+        watchpoints.check(0x0FE, AccessKind::Write, &[1, 0, 0, 0], &[0, 0, 7, 0]);
+        assert_eq!(watchpoints.take_hit(), None);
+        watchpoints.check(0x100, AccessKind::Write, &[1, 0, 0, 0], &[7, 0, 0, 0]);
+        let hit = watchpoints.take_hit().unwrap();
+        assert_eq!((hit.id, hit.old_value, hit.new_value), (id, 1, 7));
+
+        watchpoints.remove(id).unwrap();
+        assert!(watchpoints.remove(id).is_err());
+    }
+}
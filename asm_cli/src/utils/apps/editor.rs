@@ -20,6 +20,8 @@ use crate::utils::ui::{
     },
 };
 use crate::utils::workspaces::Workspace;
+use crate::memory::watchpoints::parse_watchpoint;
+use crate::utils::assembler::lint::lint_program;
 
 /// Holds the state of the text editor.
 pub struct TextEditorState {
@@ -41,6 +43,8 @@ pub struct TextEditorState {
     pub open_file_name: String,
     /// A list of assembly errors.
     pub assembly_errors: Vec<String>,
+    /// The warnings of the last lint pass.
+    pub lint_warnings: Vec<String>,
 }
 
 impl Default for TextEditorState {
@@ -70,6 +74,7 @@ impl Default for TextEditorState {
             show_open_dialog: false,
             open_file_name: String::new(),
             assembly_errors: Vec::new(),
+            lint_warnings: Vec::new(),
         }
     }
 }
@@ -183,19 +188,25 @@ pub fn handle_text_editor_input(
         }
         KeyCode::Char('e') if key.modifiers == KeyModifiers::ALT => {
             editor_state.assembly_errors.clear();
+            editor_state.lint_warnings.clear();
             let content_to_assemble = editor_state.get_content();
             let current_file_path = editor_state.file_path.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "Unnamed Program".to_string());
 
             match workspace.try_assemble_program(&content_to_assemble, app_status) {
-                Ok(_) => {
+                Ok(program) => {
+                    editor_state.lint_warnings = lint_program(&program).iter().map(|warning| warning.to_string()).collect();
                     if let Err(e) = workspace.assemble_and_load_program(&content_to_assemble, app_status) {
                         let error_msg = format!("Assembly error in {}: {}", current_file_path, e);
                         app_status.set_message(error_msg.clone());
                     }
-                    else {
+                    else if editor_state.lint_warnings.is_empty() {
                         app_status.set_message("Program assembled successfully! Switching to emulator...".to_string());
                         *app_state = AppState::Emulator;
                     }
+                    else {
+                        app_status.set_message(format!("Program assembled with {} lint warnings (Alt+L in the editor lists them). Switching to emulator...", editor_state.lint_warnings.len()));
+                        *app_state = AppState::Emulator;
+                    }
                 }
                 Err(errors) => {
                     let formatted_errors: Vec<String> = errors.iter().map(|e| {
@@ -208,6 +219,32 @@ pub fn handle_text_editor_input(
             }
             *handled = true;
         }
+        KeyCode::Char('l') if key.modifiers == KeyModifiers::ALT => {
+            editor_state.assembly_errors.clear();
+            editor_state.lint_warnings.clear();
+            let content = editor_state.get_content();
+            match workspace.try_assemble_program(&content, app_status) {
+                Ok(program) => {
+                    editor_state.lint_warnings = lint_program(&program).iter().map(|warning| warning.to_string()).collect();
+                    app_status.set_message(match editor_state.lint_warnings.len() {
+                        0 => "Lint found no problems".to_string(),
+                        count => format!("Lint found {} warnings", count),
+                    });
+                }
+                Err(errors) => {
+                    app_status.set_message(format!("Lint needs a program that assembles: {} errors", errors.len()));
+                    editor_state.assembly_errors = errors;
+                }
+            }
+            *handled = true;
+        }
+        KeyCode::Char('w') if key.modifiers == KeyModifiers::ALT => {
+            match toggle_label_watchpoint(editor_state, workspace, app_status) {
+                Ok(message) => app_status.set_message(message),
+                Err(e) => app_status.set_message(format!("Watchpoint error: {}", e)),
+            }
+            *handled = true;
+        }
         _ => {
             if editor_state.textarea.input(key) {
                 editor_state.is_editing = true;
@@ -217,6 +254,53 @@ pub fn handle_text_editor_input(
     }
 }
 
+/// Sets a write watchpoint on the label under the cursor, or removes it if one is already set.
+///
+/// The label is resolved by assembling the editor contents; the watchpoint is added
+/// to the workspace emulator and follows the label whenever the program is reloaded.
+///
+/// # Returns
+///
+/// * `Result<String, String>` - A confirmation message, or an error message.
+fn toggle_label_watchpoint(
+    editor_state: &TextEditorState,
+    workspace: &mut Workspace,
+    app_status: &mut AppStatus,
+) -> Result<String, String> {
+    let (row, column) = editor_state.textarea.cursor();
+    let label = editor_state.textarea.lines().get(row)
+        .and_then(|line| word_at(line, column))
+        .ok_or("No label under the cursor".to_string())?;
+
+    let watchpoints = &mut workspace.get_emulator().memory.watchpoints;
+    if let Some(id) = watchpoints.list.iter().find(|w| w.label.as_deref() == Some(label.as_str())).map(|w| w.id) {
+        watchpoints.remove(id)?;
+        return Ok(format!("Watchpoint on {} removed.", label));
+    }
+
+    let program = workspace.try_assemble_program(&editor_state.get_content(), app_status)
+        .map_err(|errors| format!("The program does not assemble ({} errors)", errors.len()))?;
+    let watchpoint = parse_watchpoint(&label, |name| program.label_address(name))?;
+    let id = workspace.get_emulator().memory.watchpoints.add(watchpoint);
+    Ok(format!("Watchpoint #{} set on writes to {}.", id, label))
+}
+
+/// Returns the label that contains the given character column, if any; dots are part of
+/// labels, so local labels such as `.loop` and `outer.inner` are selected whole.
+fn word_at(line: &str, column: usize) -> Option<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_' || *c == '.';
+    let mut start = column.min(chars.len());
+    while start > 0 && is_word(&chars[start - 1]) {
+        start -= 1;
+    }
+    let end = start + chars[start..].iter().take_while(|c| is_word(c)).count();
+    if end == start {
+        return None;
+    }
+    Some(chars[start..end].iter().collect())
+}
+
 /// Handles key events for the "save changes" dialog.
 fn handle_save_dialog_input(
     key: KeyEvent,
@@ -349,12 +433,14 @@ pub fn render_text_editor(
         .constraints([
             Constraint::Min(0),
             Constraint::Length(if editor_state.assembly_errors.is_empty() { 0 } else { editor_state.assembly_errors.len() as u16 + 2 }),
+            Constraint::Length(if editor_state.lint_warnings.is_empty() { 0 } else { editor_state.lint_warnings.len().min(8) as u16 + 2 }),
             Constraint::Length(1),
         ])
         .split(area);
 
     let editor_area = chunks[0];
     let error_area = chunks[1];
+    let warning_area = chunks[2];
 
 
     if editor_state.show_save_dialog {
@@ -383,6 +469,22 @@ pub fn render_text_editor(
         frame.render_widget(error_block, error_area);
     }
 
+    if !editor_state.lint_warnings.is_empty() {
+        let warning_text: Vec<Line> = editor_state.lint_warnings.iter()
+            .map(|w| Line::from(Span::styled(w, Style::default().fg(Color::Yellow))))
+            .collect();
+        let warning_block = Paragraph::new(Text::from(warning_text))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Double)
+                    .title(" Lint Warnings ")
+                    .border_style(Style::default().fg(Color::Yellow))
+            )
+            .wrap(Wrap { trim: true });
+        frame.render_widget(warning_block, warning_area);
+    }
+
 
 }
 
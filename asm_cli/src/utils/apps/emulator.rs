@@ -1,18 +1,126 @@
 use crate::utils::assembler::command_processor::AssembledProgram;
+use crate::utils::assembler::disassembler::{disassemble, Labels};
+use crate::utils::assembler::pseudo::recognize;
+use crate::utils::linker::executable::Executable;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    text::{Line, Span},
     widgets::{Block, Borders, Paragraph, List, ListItem, ListState, BorderType},
     Frame,
 };
 
 use crate::chips::cpu::CPU;
-use crate::memory::main_memory::WorkMemory;
+use crate::memory::bus::ConsoleDevice;
+use crate::memory::dump::{format_dump, parse_dump_range, DumpFormat};
+use crate::memory::journal::{Snapshot, StateDiff};
+use crate::memory::main_memory::{default_console_base, MemoryImage, WorkMemory, IO_SIZE};
+use crate::memory::mmu::{Mmu, PTE_EXECUTABLE, PTE_PRESENT, PTE_WRITABLE};
+use crate::memory::protection::{AccessKind, MemoryProtection, Permissions};
+use crate::memory::shadow::UninitializedRead;
+use crate::memory::watchpoints::{parse_watchpoint, WatchpointHit};
+use crate::utils::apps::memory_editor::{format_memory_row, parse_value_bytes, MemoryViewMode};
 use crate::utils::workspaces::Workspace;
 use crate::utils::ui::common::{AppStatus, AppState};
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
 
 use std::collections::HashSet;
+use std::path::Path;
+
+/// The number of innermost backtrace frames a fault report shows.
+const FAULT_REPORT_FRAMES: usize = 8;
+
+/// The panels that can be shown next to the raw memory view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SidePanel {
+    /// The disassembly of the loaded program.
+    #[default]
+    Disassembly,
+    /// Branch predictor accuracy, overall and per branch site.
+    BranchStats,
+    /// The symbolized backtrace of the CPU call stack.
+    Backtrace,
+    /// The MMU registers, TLB and page table.
+    PageTable,
+    /// The memory watchpoints.
+    Watchpoints,
+    /// The registers and memory changed by the last step or between two snapshots.
+    Changes,
+    /// The reads of uninitialized memory found by memcheck.
+    Memcheck,
+    /// The symbol table of the loaded program.
+    Symbols,
+    /// The assembler listing of the loaded program, following the PC.
+    Listing,
+}
+
+impl SidePanel {
+    /// Returns the panel that follows this one, wrapping around.
+    pub fn next(self) -> Self {
+        match self {
+            SidePanel::Disassembly => SidePanel::BranchStats,
+            SidePanel::BranchStats => SidePanel::Backtrace,
+            SidePanel::Backtrace => SidePanel::PageTable,
+            SidePanel::PageTable => SidePanel::Watchpoints,
+            SidePanel::Watchpoints => SidePanel::Changes,
+            SidePanel::Changes => SidePanel::Memcheck,
+            SidePanel::Memcheck => SidePanel::Symbols,
+            SidePanel::Symbols => SidePanel::Listing,
+            SidePanel::Listing => SidePanel::Disassembly,
+        }
+    }
+}
+
+/// The commands that can be typed into the emulator prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    /// Add or remove a memory watchpoint.
+    Watchpoint,
+    /// Move the memory view to an address or label.
+    Goto,
+    /// Write a value at the selected memory address.
+    Edit,
+    /// Search memory for a value.
+    Search,
+    /// Choose the snapshots compared in the changes panel.
+    Compare,
+    /// Export a range of memory to a file.
+    Export,
+}
+
+impl PromptKind {
+    /// Returns the text shown in front of the input.
+    pub fn label(&self) -> &'static str {
+        match self {
+            PromptKind::Watchpoint => "Watch (label|addr[:size] [r|w|a] [=value], del N, clear)",
+            PromptKind::Goto => "Go to (address or label)",
+            PromptKind::Edit => "Write at cursor (b 41 42 | h N | w N | f 1.5 | s text)",
+            PromptKind::Search => "Search (b 41 42 | h N | w N | f 1.5 | s text)",
+            PromptKind::Compare => "Compare snapshots (A B, A for A to now, step for the last step)",
+            PromptKind::Export => "Export (start:len|start..end|text|data|stack FILE [raw|ihex|hexdump|rust|c])",
+        }
+    }
+}
+
+/// A line of input being typed into the emulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmulatorPrompt {
+    /// What the input will be used for.
+    pub kind: PromptKind,
+    /// The text typed so far.
+    pub input: String,
+}
+
+/// An uninitialized read found by memcheck, together with where it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemcheckReport {
+    /// The address of the instruction that made the read.
+    pub pc: u32,
+    /// The read itself.
+    pub read: UninitializedRead,
+    /// How many times the same instruction read the same address uninitialized.
+    pub count: u32,
+}
 
 #[derive(Debug, Clone)]
 pub struct EmulatorState {
@@ -31,6 +139,26 @@ pub struct EmulatorState {
     pub program_loaded: bool,
     pub current_instruction: u32,
     pub breakpoints: HashSet<u32>,
+    pub side_panel: SidePanel,
+    pub prompt: Option<EmulatorPrompt>,
+    /// A message produced by the last step (a watchpoint hit or a memcheck error), until it is shown.
+    pub step_report: Option<String>,
+    pub memory_view_mode: MemoryViewMode,
+    /// The exact address selected in the memory view; the page and row follow it.
+    pub memory_cursor: u32,
+    /// The bytes of the last search, repeated with `N`.
+    pub last_search: Option<Vec<u8>>,
+    /// What the last step changed, highlighted in the register and memory panels.
+    pub step_diff: StateDiff,
+    /// The snapshots taken with `K`, numbered from 1.
+    pub snapshots: Vec<Snapshot>,
+    /// The snapshots compared in the changes panel (indices into `snapshots`; `None` means now),
+    /// or `None` to show the changes of the last step.
+    pub comparison: Option<(usize, Option<usize>)>,
+    /// The uninitialized reads found since the program was loaded, in the order they were first seen.
+    pub memcheck_reports: Vec<MemcheckReport>,
+    /// The address of the instruction the last step started at, which a fault report points to.
+    pub fault_pc: u32,
 }
 
 impl Default for EmulatorState {
@@ -41,9 +169,12 @@ impl Default for EmulatorState {
 
 impl EmulatorState {
     pub fn new(memory_size: usize) -> Self {
+        let mut memory = WorkMemory::new(memory_size);
+        memory.bus.map(default_console_base(memory_size), IO_SIZE, Box::new(ConsoleDevice::new(IO_SIZE)))
+            .expect("The default console window fits in the address space");
         let mut state = Self {
             cpu: CPU::new(),
-            memory: WorkMemory::new(memory_size),
+            memory,
             is_running: false,
             show_flags: false,
             selected_register: ListState::default(),
@@ -57,6 +188,17 @@ impl EmulatorState {
             program_loaded: false,
             current_instruction: 0,
             breakpoints: HashSet::new(),
+            side_panel: SidePanel::default(),
+            prompt: None,
+            step_report: None,
+            memory_view_mode: MemoryViewMode::default(),
+            memory_cursor: 0,
+            last_search: None,
+            step_diff: StateDiff::default(),
+            snapshots: Vec::new(),
+            comparison: None,
+            memcheck_reports: Vec::new(),
+            fault_pc: 0,
         };
         state.selected_register.select(Some(0));
         state.selected_memory_address.select(Some(0));
@@ -64,16 +206,62 @@ impl EmulatorState {
     }
 
     pub fn reset(&mut self, memory_size: usize) {
+        let predictor_kind = self.cpu.branch_predictor.kind;
         self.cpu = CPU::new();
+        self.cpu.branch_predictor.set_kind(predictor_kind);
+        let mut bus = std::mem::take(&mut self.memory.bus);
+        bus.reset();
+        let mmu_present = self.memory.mmu.present;
+        let watchpoints = std::mem::take(&mut self.memory.watchpoints);
+        let memcheck = self.memory.shadow.enabled;
         self.memory = WorkMemory::new(memory_size);
+        self.memory.bus = bus;
+        self.memory.mmu = Mmu::new(mmu_present);
+        self.memory.watchpoints = watchpoints;
+        self.memory.shadow.enabled = memcheck;
+        self.memcheck_reports.clear();
         self.is_running = false;
         self.show_flags = false;
         self.selected_register.select(Some(0));
         self.selected_memory_address.select(Some(0));
         self.selected_memory_page = 0;
+        self.memory_cursor = 0;
+        self.clear_changes();
+    }
+
+    /// Moves a device on the bus, e.g. the console, to another base address.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the device.
+    /// * `base` - Its new base address.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error message if no device has that name or the range does not fit.
+    pub fn move_device(&mut self, name: &str, base: u32) -> Result<(), String> {
+        self.memory.bus.move_device(name, base)
+    }
+
+    /// Checks that no segment of a program lies under a device, where its accesses would reach
+    /// the device instead of RAM.
+    fn check_segments_clear_of_devices(&self, program: &AssembledProgram) -> Result<(), String> {
+        for name in ["text", "data", "stack"] {
+            let Some((start, size)) = program.segment(name).filter(|&(_, size)| size > 0) else { continue };
+            let end = start as u64 + size as u64;
+            if let Some(mapping) = self.memory.bus.mappings.iter().find(|m| (m.base as u64) < end && start as u64 <= m.last_address() as u64) {
+                return Err(format!(
+                    "The {} segment (0x{:04X} - 0x{:04X}) overlaps device '{}' (0x{:04X} - 0x{:04X}); move the device with --device {}=ADDRESS",
+                    name, start, end, mapping.device.name(), mapping.base, mapping.last_address(), mapping.device.name()
+                ));
+            }
+        }
+        Ok(())
     }
 
     pub fn load_assembled_program(&mut self, assembled_program: &AssembledProgram) -> Result<(), String> {
+        self.check_segments_clear_of_devices(assembled_program)?;
+
         // Load text section
         self.memory.load_program(assembled_program.actual_text_start, &assembled_program.text)?;
         
@@ -81,9 +269,20 @@ impl EmulatorState {
         if !assembled_program.data.is_empty() {
             self.memory.load_data(assembled_program.actual_data_start, &assembled_program.data)?;
         }
+        // `.space` buffers are reserved, not initialized
+        for &(start, size) in &assembled_program.reserved {
+            for address in start..start + size {
+                self.memory.shadow.mark_uninitialized(address);
+            }
+        }
+        self.memcheck_reports.clear();
         
         self.cpu.registers.pc = assembled_program.actual_text_start; // Start at beginning of text segment
-        self.cpu.registers.sp = assembled_program.actual_stack_start; // Reset stack pointer to configured start
+        self.cpu.registers.sp = assembled_program.stack_top(); // The stack grows down from the end of its segment
+        self.cpu.cycles = 0;
+        self.cpu.branch_predictor.reset();
+        self.cpu.call_stack.clear();
+        self.clear_changes();
         self.program_loaded = true;
         self.current_instruction = if !assembled_program.text.is_empty() { 
             self.memory.read_u32(assembled_program.actual_text_start)?
@@ -94,6 +293,59 @@ impl EmulatorState {
         Ok(())
     }
 
+    /// Loads an executable, keeping its symbols and source map for the debugger panels.
+    ///
+    /// # Arguments
+    ///
+    /// * `executable` - The executable read from an `.arx` file.
+    /// * `allow_self_modifying_code` - Whether the text segment stays writable.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u32, String>` - The entry point, or an error message if the program does not fit in memory.
+    pub fn load_executable(&mut self, executable: &Executable, allow_self_modifying_code: bool) -> Result<u32, String> {
+        self.load_assembled_program(&executable.program)?;
+        self.cpu.registers.pc = executable.entry;
+        self.current_instruction = self.memory.read_u32(executable.entry).unwrap_or(0);
+        self.program_source = None;
+        self.assembled_program = Some(executable.program.clone());
+        self.apply_memory_protection(allow_self_modifying_code);
+        Ok(executable.entry)
+    }
+
+    /// Loads a memory image in place of an assembled program.
+    ///
+    /// Images carry no labels or segments, so memory protection is lifted and
+    /// the stack pointer is set as for a program without a `.stack_start`.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The parsed image.
+    /// * `entry` - The entry point; defaults to the one recorded in the image, then to its lowest address.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u32, String>` - The entry point, or an error message if the image does not fit in memory.
+    pub fn load_image(&mut self, image: &MemoryImage, entry: Option<u32>) -> Result<u32, String> {
+        self.memory.load_image(image)?;
+        let entry = entry.or(image.entry).or(image.lowest_address()).unwrap_or(0);
+        self.assembled_program = None;
+        self.program_source = None;
+        self.apply_memory_protection(true);
+        self.memcheck_reports.clear();
+
+        self.cpu.registers.pc = entry;
+        // The top of memory, wrapping to 0 for a full 4 GB memory
+        self.cpu.registers.sp = self.memory.size as u32;
+        self.cpu.cycles = 0;
+        self.cpu.branch_predictor.reset();
+        self.cpu.call_stack.clear();
+        self.clear_changes();
+        self.program_loaded = true;
+        self.current_instruction = self.memory.read_u32(entry).unwrap_or(0);
+        Ok(entry)
+    }
+
     pub fn toggle_running(&mut self) {
         self.is_running = !self.is_running;
     }
@@ -102,7 +354,7 @@ impl EmulatorState {
         if self.is_running {
             return Err("Cannot step while emulator is running. Pause first.".to_string());
         }
-        self.cpu.step(&mut self.memory)
+        self.execute_step()
     }
 
     pub fn run_full_speed(&mut self) -> Result<(), String> {
@@ -111,7 +363,253 @@ impl EmulatorState {
         }
         // In a real application, this would run in a separate thread
         // For a TUI, we'll just step once per frame while running
-        self.cpu.step(&mut self.memory)
+        self.execute_step()
+    }
+
+    /// Executes one instruction and pauses the emulator if it triggered a watchpoint.
+    fn execute_step(&mut self) -> Result<(), String> {
+        // Reads made while rendering can leave a stale hit or memcheck report behind
+        self.memory.watchpoints.take_hit();
+        self.memory.shadow.take_pending();
+        let pc = self.cpu.registers.pc;
+        self.fault_pc = pc;
+        let before = self.snapshot();
+        let result = self.cpu.step(&mut self.memory);
+        self.step_diff = StateDiff::between(&before, &self.snapshot(), &self.memory.journal).unwrap_or_default();
+        if let Some(hit) = self.memory.watchpoints.take_hit() {
+            self.is_running = false;
+            self.step_report = Some(self.describe_watchpoint_hit(&hit, pc));
+        }
+        if let Some(read) = self.memory.shadow.take_pending() {
+            self.record_uninitialized_read(read, pc);
+        }
+        result
+    }
+
+    /// Adds an uninitialized read to the memcheck reports, reporting it in the status bar the first time it is seen.
+    ///
+    /// # Arguments
+    ///
+    /// * `read` - The uninitialized read.
+    /// * `pc` - The address of the instruction that made the read.
+    fn record_uninitialized_read(&mut self, read: UninitializedRead, pc: u32) {
+        if let Some(report) = self.memcheck_reports.iter_mut().find(|r| r.pc == pc && r.read.address == read.address) {
+            report.count += 1;
+            return;
+        }
+        let report = MemcheckReport { pc, read, count: 1 };
+        if self.step_report.is_none() {
+            self.step_report = Some(format!("Memcheck: {}", self.describe_memcheck_report(&report)));
+        }
+        self.memcheck_reports.push(report);
+    }
+
+    /// Describes a memcheck report with symbolized addresses.
+    pub fn describe_memcheck_report(&self, report: &MemcheckReport) -> String {
+        let data = match &self.assembled_program {
+            Some(assembled_program) => assembled_program.symbolize_data(report.read.first_uninitialized),
+            None => format!("0x{:04X}", report.read.first_uninitialized),
+        };
+        format!(
+            "uninitialized read of {} bytes at 0x{:04X} (first uninitialized byte {}) by {}",
+            report.read.size, report.read.address, data, self.symbolize(report.pc)
+        )
+    }
+
+    /// Turns reporting of uninitialized reads on or off.
+    pub fn set_memcheck(&mut self, enabled: bool) {
+        self.memory.shadow.enabled = enabled;
+    }
+
+    /// Captures the current registers and journal position.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.cpu.registers.clone(),
+            cycles: self.cpu.cycles,
+            journal_position: self.memory.journal.position(),
+        }
+    }
+
+    /// Stores a snapshot for later comparison.
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - The number of the snapshot, starting at 1.
+    pub fn take_snapshot(&mut self) -> usize {
+        self.snapshots.push(self.snapshot());
+        self.snapshots.len()
+    }
+
+    /// Forgets the last step's changes and every snapshot.
+    fn clear_changes(&mut self) {
+        self.step_diff = StateDiff::default();
+        self.snapshots.clear();
+        self.comparison = None;
+    }
+
+    /// Returns the changes shown in the changes panel.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<StateDiff, String>` - The changes of the last step or between the compared snapshots,
+    ///   or an error message if they are no longer in the journal.
+    pub fn changes(&self) -> Result<StateDiff, String> {
+        let Some((from, to)) = self.comparison else {
+            return Ok(self.step_diff.clone());
+        };
+        let now = self.snapshot();
+        let after = to.map_or(&now, |to| &self.snapshots[to]);
+        StateDiff::between(&self.snapshots[from], after, &self.memory.journal)
+    }
+
+    /// Chooses the snapshots compared in the changes panel.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `A B` to compare two snapshots, `A` to compare a snapshot with now,
+    ///   or `step` to show the changes of the last step.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, String>` - A description of the comparison, or an error message.
+    pub fn compare_command(&mut self, input: &str) -> Result<String, String> {
+        let input = input.trim();
+        if input == "step" {
+            self.comparison = None;
+            return Ok("Showing the changes of the last step.".to_string());
+        }
+        let snapshot_index = |text: &str| -> Result<usize, String> {
+            let number = text.trim_start_matches('#').parse::<usize>()
+                .map_err(|_| format!("Invalid snapshot number: {}", text))?;
+            if number == 0 || number > self.snapshots.len() {
+                return Err(format!("No snapshot #{} ({} taken)", number, self.snapshots.len()));
+            }
+            Ok(number - 1)
+        };
+        let mut parts = input.split_whitespace();
+        let from = snapshot_index(parts.next().unwrap_or_default())?;
+        let to = parts.next().map(snapshot_index).transpose()?;
+        self.comparison = Some((from, to));
+        Ok(format!(
+            "Comparing snapshot #{} with {}.",
+            from + 1,
+            to.map_or("now".to_string(), |to| format!("snapshot #{}", to + 1))
+        ))
+    }
+
+    /// Formats a watchpoint hit for the status bar.
+    ///
+    /// # Arguments
+    ///
+    /// * `hit` - The recorded hit.
+    /// * `pc` - The address of the instruction that made the access.
+    fn describe_watchpoint_hit(&self, hit: &WatchpointHit, pc: u32) -> String {
+        let watchpoint = self.memory.watchpoints.get(hit.id)
+            .map(|w| w.describe())
+            .unwrap_or_else(|| format!("#{}", hit.id));
+        let value = match hit.access {
+            AccessKind::Write => format!("0x{:X} -> 0x{:X}", hit.old_value, hit.new_value),
+            _ => format!("value 0x{:X}", hit.new_value),
+        };
+        format!(
+            "Watchpoint {} hit: {} at 0x{:04X} by {}: {}",
+            watchpoint, hit.access, hit.address, self.symbolize(pc), value
+        )
+    }
+
+    /// Runs a watchpoint command typed into the prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - `clear`, `del N`, or a watchpoint specification such as `counter w =5`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, String>` - A confirmation message, or an error message.
+    pub fn watch_command(&mut self, command: &str) -> Result<String, String> {
+        let command = command.trim();
+        if command == "clear" {
+            self.memory.watchpoints.clear();
+            return Ok("All watchpoints removed.".to_string());
+        }
+        if let Some(id) = command.strip_prefix("del ").or_else(|| command.strip_prefix("delete ")) {
+            let id = id.trim().trim_start_matches('#').parse::<u32>()
+                .map_err(|_| format!("Invalid watchpoint number: {}", id.trim()))?;
+            self.memory.watchpoints.remove(id)?;
+            return Ok(format!("Watchpoint #{} removed.", id));
+        }
+        let program = self.assembled_program.as_ref();
+        let watchpoint = parse_watchpoint(command, |label| program.and_then(|p| p.label_address(label)))?;
+        let id = self.memory.watchpoints.add(watchpoint);
+        let description = self.memory.watchpoints.get(id).map(|w| w.describe()).unwrap_or_default();
+        Ok(format!("Watchpoint {} set.", description))
+    }
+
+    /// Derives the memory permissions from the segments of the loaded program.
+    ///
+    /// # Arguments
+    ///
+    /// * `allow_self_modifying_code` - Whether the text segment stays writable.
+    pub fn apply_memory_protection(&mut self, allow_self_modifying_code: bool) {
+        self.memory.protection = match &self.assembled_program {
+            Some(assembled_program) => MemoryProtection::for_program(assembled_program, &self.memory.bus, allow_self_modifying_code),
+            None => MemoryProtection::default(),
+        };
+    }
+
+    /// Makes the text segment writable or read-only, keeping the rest of the permission map.
+    pub fn set_self_modifying_code(&mut self, allowed: bool) {
+        let permissions = if allowed { Permissions::ALL } else { Permissions::READ_EXECUTE };
+        self.memory.protection.set_permissions("text", permissions);
+    }
+
+    /// Describes an address relative to the nearest label of the loaded program.
+    pub fn symbolize(&self, address: u32) -> String {
+        match &self.assembled_program {
+            Some(assembled_program) => assembled_program.symbolize(address),
+            None => format!("0x{:04X}", address),
+        }
+    }
+
+    /// Builds a symbolized backtrace, innermost frame first.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The code address of the innermost frame.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` - One line per frame; outer frames show their return address.
+    pub fn backtrace(&self, address: u32) -> Vec<String> {
+        let mut frames = vec![format!("#0 {}", self.symbolize(address))];
+        for (depth, &return_addr) in self.cpu.call_stack.stack.iter().rev().enumerate() {
+            frames.push(format!("#{} {} (return address)", depth + 1, self.symbolize(return_addr)));
+        }
+        if self.cpu.call_stack.dropped() > 0 {
+            frames.push(format!("... {} older frames not recorded", self.cpu.call_stack.dropped()));
+        }
+        frames
+    }
+
+    /// Formats an execution error together with the source line and the innermost frames of the
+    /// backtrace of the instruction the last step started at.
+    pub fn fault_report(&self, error: &str) -> String {
+        let location = self.assembled_program.as_ref()
+            .and_then(|program| program.source_location(self.fault_pc))
+            .map(|location| format!(" at {}", location))
+            .unwrap_or_default();
+        let mut frames = self.backtrace(self.fault_pc);
+        let hidden = frames.len().saturating_sub(FAULT_REPORT_FRAMES);
+        if hidden > 0 {
+            frames.truncate(FAULT_REPORT_FRAMES);
+            frames.push(format!("... ({} more)", hidden));
+        }
+        format!("{}{} | Backtrace: {}", error, location, frames.join(" <- "))
+    }
+
+    pub fn next_branch_predictor(&mut self) {
+        let next_kind = self.cpu.branch_predictor.kind.next();
+        self.cpu.branch_predictor.set_kind(next_kind);
     }
 
     pub fn next_register(&mut self) {
@@ -142,6 +640,26 @@ impl EmulatorState {
         self.selected_register.select(Some(i));
     }
 
+    /// Returns the number of bytes covered by one page of the memory view.
+    fn memory_page_bytes(&self) -> usize {
+        self.memory_page_size * self.memory_view_mode.row_bytes()
+    }
+
+    /// Moves the memory view to the page and row containing `memory_cursor`.
+    fn sync_memory_selection(&mut self) {
+        let page_bytes = self.memory_page_bytes();
+        let cursor = self.memory_cursor as usize;
+        self.selected_memory_page = cursor / page_bytes;
+        self.selected_memory_address.select(Some((cursor % page_bytes) / self.memory_view_mode.row_bytes()));
+    }
+
+    /// Moves the memory cursor to the given row of the current page.
+    fn select_memory_row(&mut self, row: usize) {
+        let address = self.selected_memory_page * self.memory_page_bytes() + row * self.memory_view_mode.row_bytes();
+        self.memory_cursor = address as u32;
+        self.selected_memory_address.select(Some(row));
+    }
+
     pub fn next_memory_address(&mut self) {
         let i = match self.selected_memory_address.selected() {
             Some(i) => {
@@ -153,7 +671,7 @@ impl EmulatorState {
             }
             None => 0,
         };
-        self.selected_memory_address.select(Some(i));
+        self.select_memory_row(i);
     }
 
     pub fn previous_memory_address(&mut self) {
@@ -167,25 +685,137 @@ impl EmulatorState {
             }
             None => 0,
         };
-        self.selected_memory_address.select(Some(i));
+        self.select_memory_row(i);
     }
 
     pub fn next_memory_page(&mut self) {
-        self.selected_memory_page = (self.selected_memory_page + 1) % self.memory.get_num_pages(self.memory_page_size);
-        self.selected_memory_address.select(Some(0)); // Reset address selection on page change
+        self.selected_memory_page = (self.selected_memory_page + 1) % self.memory.get_num_pages(self.memory_page_bytes());
+        self.select_memory_row(0); // Reset address selection on page change
     }
 
     pub fn previous_memory_page(&mut self) {
-        let num_pages = self.memory.get_num_pages(self.memory_page_size);
+        let num_pages = self.memory.get_num_pages(self.memory_page_bytes());
         self.selected_memory_page = (self.selected_memory_page + num_pages - 1) % num_pages;
-        self.selected_memory_address.select(Some(0)); // Reset address selection on page change
+        self.select_memory_row(0); // Reset address selection on page change
+    }
+
+    /// Switches the memory view to its next display mode, keeping the cursor.
+    pub fn next_memory_view_mode(&mut self) {
+        self.memory_view_mode = self.memory_view_mode.next();
+        self.sync_memory_selection();
+    }
+
+    /// Formats a range of memory as a dump.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - The range, in the format accepted by `parse_dump_range`; labels and
+    ///   segment names refer to the loaded program.
+    /// * `format` - The format of the dump.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u8>, String>` - The contents of the dump, or an error message.
+    pub fn dump_memory(&self, range: &str, format: DumpFormat) -> Result<Vec<u8>, String> {
+        let program = self.assembled_program.as_ref();
+        let (start, length) = parse_dump_range(
+            range,
+            |name| program.and_then(|p| p.segment(name)),
+            |label| program.and_then(|p| p.label_address(label)),
+        )?;
+        let bytes = self.memory.dump_range(start, length)?;
+        // Intel HEX dumps that contain the program's entry point record it, so they can be run directly
+        let entry = program.map(|p| p.actual_text_start).filter(|&entry| entry >= start && entry - start < length);
+        Ok(format_dump(format, start, &bytes, range.trim(), entry))
+    }
+
+    /// Exports a range of memory to a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - `RANGE FILE [FORMAT]`; the format defaults to the one implied by the file extension.
+    /// * `directory` - The directory relative file names are resolved against.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, String>` - A message describing the export, or an error message.
+    pub fn export_memory(&self, command: &str, directory: &Path) -> Result<String, String> {
+        let parts: Vec<&str> = command.split_whitespace().collect();
+        let (range, file, format) = match parts.as_slice() {
+            [range, file] => (*range, directory.join(file), None),
+            [range, file, format] => (*range, directory.join(file), Some(DumpFormat::from_name(format)?)),
+            _ => return Err("Expected RANGE FILE [FORMAT]".to_string()),
+        };
+        let format = format.unwrap_or_else(|| DumpFormat::from_path(&file));
+        let dump = self.dump_memory(range, format)?;
+        std::fs::write(&file, &dump).map_err(|e| format!("Error writing {}: {}", file.display(), e))?;
+        Ok(format!("Exported {} as {:?} to {}", range, format, file.display()))
+    }
+
+    /// Moves the memory view to an address or label.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - A decimal or `0x`-prefixed address, or a label of the loaded program.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u32, String>` - The selected address, or an error message.
+    pub fn goto_memory(&mut self, target: &str) -> Result<u32, String> {
+        let target = target.trim();
+        let parsed = match target.strip_prefix("0x").or_else(|| target.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => target.parse::<u32>().ok(),
+        };
+        let address = parsed
+            .or_else(|| self.assembled_program.as_ref().and_then(|p| p.label_address(target)))
+            .ok_or(format!("Unknown address or label: {}", target))?;
+        if address as usize >= self.memory.size {
+            return Err(format!("Address 0x{:04X} is outside memory", address));
+        }
+        self.memory_cursor = address;
+        self.sync_memory_selection();
+        Ok(address)
+    }
+
+    /// Writes a value at the memory cursor, bypassing memory protection and watchpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The value, in the format accepted by `parse_value_bytes`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<usize, String>` - The number of bytes written, or an error message.
+    pub fn edit_memory(&mut self, input: &str) -> Result<usize, String> {
+        let bytes = parse_value_bytes(input)?;
+        self.memory.load_data(self.memory_cursor, &bytes)?;
+        Ok(bytes.len())
+    }
+
+    /// Searches memory for a value after the cursor, wrapping around, and moves the cursor to the match.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The bytes to look for.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u32>` - The address of the match, or `None` if the value is nowhere in memory.
+    pub fn search_memory(&mut self, pattern: &[u8]) -> Option<u32> {
+        let from = self.memory_cursor as usize + 1;
+        let found = self.memory.memory.find(pattern, from)
+            .or_else(|| self.memory.memory.find(pattern, 0).filter(|&address| address < from))?;
+        self.memory_cursor = found as u32;
+        self.sync_memory_selection();
+        Some(found as u32)
     }
 }
 
 pub fn handle_emulator_input(
     key: KeyEvent,
     emulator_state: &mut EmulatorState,
-    _workspace: &mut Workspace,
+    workspace: &mut Workspace,
     app_state: &mut AppState,
     status: &mut AppStatus,
     handled: &mut bool,
@@ -194,6 +824,12 @@ pub fn handle_emulator_input(
         return;
     }
 
+    if emulator_state.prompt.is_some() {
+        handle_prompt_input(key, emulator_state, workspace, status);
+        *handled = true;
+        return;
+    }
+
     match key.code {
         KeyCode::Char('q') => {
             *app_state = AppState::StartMenu;
@@ -207,7 +843,10 @@ pub fn handle_emulator_input(
         KeyCode::Char('s') => {
             match emulator_state.step() {
                 Ok(_) => status.set_message("Stepped one instruction.".to_string()),
-                Err(e) => status.set_message(format!("Error stepping: {}", e)),
+                Err(e) => status.set_message(emulator_state.fault_report(&format!("Error stepping: {}", e))),
+            }
+            if let Some(report) = emulator_state.step_report.take() {
+                status.set_message(report);
             }
             *handled = true;
         }
@@ -224,6 +863,74 @@ pub fn handle_emulator_input(
             *app_state = AppState::IoDevices;
             *handled = true;
         }
+        KeyCode::Char('b') => {
+            emulator_state.next_branch_predictor();
+            status.set_message(format!("Branch predictor: {}", emulator_state.cpu.branch_predictor.kind.name()));
+            *handled = true;
+        }
+        KeyCode::Tab => {
+            emulator_state.side_panel = emulator_state.side_panel.next();
+            *handled = true;
+        }
+        KeyCode::Char('m') => {
+            workspace.allow_self_modifying_code = !workspace.allow_self_modifying_code;
+            emulator_state.set_self_modifying_code(workspace.allow_self_modifying_code);
+            status.set_message(format!(
+                "Self-modifying code {}",
+                if workspace.allow_self_modifying_code { "allowed (text is RWX)" } else { "blocked (text is R-X)" }
+            ));
+            *handled = true;
+        }
+        KeyCode::Char('w') => {
+            emulator_state.prompt = Some(EmulatorPrompt { kind: PromptKind::Watchpoint, input: String::new() });
+            *handled = true;
+        }
+        KeyCode::Char('g') => {
+            emulator_state.prompt = Some(EmulatorPrompt { kind: PromptKind::Goto, input: String::new() });
+            *handled = true;
+        }
+        KeyCode::Char('e') => {
+            emulator_state.prompt = Some(EmulatorPrompt { kind: PromptKind::Edit, input: String::new() });
+            *handled = true;
+        }
+        KeyCode::Char('/') => {
+            emulator_state.prompt = Some(EmulatorPrompt { kind: PromptKind::Search, input: String::new() });
+            *handled = true;
+        }
+        KeyCode::Char('n') => {
+            match emulator_state.last_search.clone() {
+                Some(pattern) => report_search(emulator_state, &pattern, status),
+                None => status.set_message("No previous search. Press / to search memory.".to_string()),
+            }
+            *handled = true;
+        }
+        KeyCode::Char('u') => {
+            let enabled = !emulator_state.memory.shadow.enabled;
+            emulator_state.set_memcheck(enabled);
+            if let Some(emulator) = &mut workspace.emulator {
+                emulator.set_memcheck(enabled);
+            }
+            status.set_message(format!("Memcheck {}", if enabled { "enabled: uninitialized reads are reported" } else { "disabled" }));
+            *handled = true;
+        }
+        KeyCode::Char('k') => {
+            let number = emulator_state.take_snapshot();
+            status.set_message(format!("Snapshot #{} taken (C: compare snapshots)", number));
+            *handled = true;
+        }
+        KeyCode::Char('c') => {
+            emulator_state.prompt = Some(EmulatorPrompt { kind: PromptKind::Compare, input: String::new() });
+            *handled = true;
+        }
+        KeyCode::Char('x') => {
+            emulator_state.prompt = Some(EmulatorPrompt { kind: PromptKind::Export, input: String::new() });
+            *handled = true;
+        }
+        KeyCode::Char('v') => {
+            emulator_state.next_memory_view_mode();
+            status.set_message(format!("Memory view: {}", emulator_state.memory_view_mode.name()));
+            *handled = true;
+        }
         KeyCode::Up => {
             emulator_state.previous_register();
             *handled = true;
@@ -254,6 +961,81 @@ pub fn handle_emulator_input(
     }
 }
 
+/// Handles key events while the emulator prompt is open.
+fn handle_prompt_input(
+    key: KeyEvent,
+    emulator_state: &mut EmulatorState,
+    workspace: &mut Workspace,
+    status: &mut AppStatus,
+) {
+    let Some(prompt) = &mut emulator_state.prompt else { return };
+    match key.code {
+        KeyCode::Backspace => {
+            prompt.input.pop();
+        }
+        KeyCode::Char(c) => prompt.input.push(c),
+        KeyCode::Enter => {
+            let EmulatorPrompt { kind, input } = emulator_state.prompt.take().unwrap();
+            // An empty line closes the prompt without doing anything
+            if input.trim().is_empty() {
+                return;
+            }
+            match kind {
+                PromptKind::Watchpoint => {
+                    match emulator_state.watch_command(&input) {
+                        Ok(message) => {
+                            // Keep the workspace copy in sync so the watchpoints survive reloading the program
+                            if let Some(emulator) = &mut workspace.emulator {
+                                emulator.memory.watchpoints = emulator_state.memory.watchpoints.clone();
+                            }
+                            status.set_message(message);
+                        }
+                        Err(e) => status.set_message(format!("Watchpoint error: {}", e)),
+                    }
+                }
+                PromptKind::Goto => match emulator_state.goto_memory(&input) {
+                    Ok(address) => status.set_message(format!("Memory view at 0x{:04X}", address)),
+                    Err(e) => status.set_message(format!("Go to error: {}", e)),
+                },
+                PromptKind::Edit => {
+                    let address = emulator_state.memory_cursor;
+                    match emulator_state.edit_memory(&input) {
+                        Ok(count) => status.set_message(format!("Wrote {} bytes at 0x{:04X}", count, address)),
+                        Err(e) => status.set_message(format!("Edit error: {}", e)),
+                    }
+                }
+                PromptKind::Compare => match emulator_state.compare_command(&input) {
+                    Ok(message) => {
+                        emulator_state.side_panel = SidePanel::Changes;
+                        status.set_message(message);
+                    }
+                    Err(e) => status.set_message(format!("Compare error: {}", e)),
+                },
+                PromptKind::Export => match emulator_state.export_memory(&input, &workspace.current_path) {
+                    Ok(message) => status.set_message(message),
+                    Err(e) => status.set_message(format!("Export error: {}", e)),
+                },
+                PromptKind::Search => match parse_value_bytes(&input) {
+                    Ok(pattern) => {
+                        report_search(emulator_state, &pattern, status);
+                        emulator_state.last_search = Some(pattern);
+                    }
+                    Err(e) => status.set_message(format!("Search error: {}", e)),
+                },
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Runs a memory search and reports where the value was found.
+fn report_search(emulator_state: &mut EmulatorState, pattern: &[u8], status: &mut AppStatus) {
+    match emulator_state.search_memory(pattern) {
+        Some(address) => status.set_message(format!("Found at 0x{:04X} (N: next match)", address)),
+        None => status.set_message("Value not found in memory.".to_string()),
+    }
+}
+
 pub fn on_enter_emulator(
     emulator_state: &mut EmulatorState,
     workspace: &mut Workspace,
@@ -351,7 +1133,7 @@ fn create_emulator_layout(area: Rect, _emulator_state: &EmulatorState) -> Emulat
 }
 
 fn render_general_registers(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState, emulator: &CPU) {
-    let registers_data = vec![
+    let registers_data = [
         ("AX", emulator.registers.ax),
         ("BX", emulator.registers.bx),
         ("CX", emulator.registers.cx),
@@ -367,6 +1149,8 @@ fn render_general_registers(frame: &mut Frame, area: Rect, emulator_state: &Emul
             let is_selected = emulator_state.selected_register.selected() == Some(i);
             let style = if is_selected {
                 Style::default().fg(Color::Black).bg(Color::LightYellow).add_modifier(Modifier::BOLD)
+            } else if emulator_state.step_diff.register_changed(name) {
+                Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD)
             } else {
                 Style::default().fg(Color::White)
             };
@@ -381,7 +1165,7 @@ fn render_general_registers(frame: &mut Frame, area: Rect, emulator_state: &Emul
 }
 
 fn render_pointer_registers(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState, emulator: &CPU) {
-    let registers_data = vec![
+    let registers_data = [
         ("SP", emulator.registers.sp),
         ("BP", emulator.registers.bp),
         ("SI", emulator.registers.si),
@@ -393,6 +1177,8 @@ fn render_pointer_registers(frame: &mut Frame, area: Rect, emulator_state: &Emul
             let is_selected = emulator_state.selected_register.selected() == Some(i + 8);
             let style = if is_selected {
                 Style::default().fg(Color::Black).bg(Color::LightYellow).add_modifier(Modifier::BOLD)
+            } else if emulator_state.step_diff.register_changed(name) {
+                Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD)
             } else {
                 Style::default().fg(Color::White)
             };
@@ -407,7 +1193,7 @@ fn render_pointer_registers(frame: &mut Frame, area: Rect, emulator_state: &Emul
 }
 
 fn render_control_registers(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState, emulator: &CPU) {
-    let registers_data = vec![
+    let registers_data = [
         ("PC", emulator.registers.pc),
         ("FLAGS", emulator.registers.flags),
     ];
@@ -417,6 +1203,8 @@ fn render_control_registers(frame: &mut Frame, area: Rect, emulator_state: &Emul
             let is_selected = emulator_state.selected_register.selected() == Some(i + 12);
             let style = if is_selected {
                 Style::default().fg(Color::Black).bg(Color::LightYellow).add_modifier(Modifier::BOLD)
+            } else if emulator_state.step_diff.register_changed(name) {
+                Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD)
             } else {
                 Style::default().fg(Color::White)
             };
@@ -435,42 +1223,95 @@ fn render_memory_view(frame: &mut Frame, area: Rect, emulator_state: &EmulatorSt
         .direction(Direction::Horizontal)
         .constraints([
             Constraint::Percentage(50), // Raw Memory
-            Constraint::Percentage(50), // Disassembly
+            Constraint::Percentage(50), // Side panel (disassembly, statistics)
         ])
         .split(area);
 
     let raw_memory_area = chunks[0];
-    let disassembly_area = chunks[1];
+    let side_panel_area = chunks[1];
 
-    // Render Raw Memory (existing logic)
-    let start_address = emulator_state.selected_memory_page * emulator_state.memory_page_size;
-    let end_address = (start_address + emulator_state.memory_page_size).min(memory.memory.len());
+    // Render Raw Memory in the selected view mode
+    let mode = emulator_state.memory_view_mode;
+    let row_bytes = mode.row_bytes();
+    let start_address = emulator_state.selected_memory_page * emulator_state.memory_page_size * row_bytes;
+    let end_address = (start_address + emulator_state.memory_page_size * row_bytes).min(memory.memory.len());
 
-    let memory_items: Vec<ListItem> = memory.memory[start_address..end_address].iter().enumerate()
-        .map(|(i, &val)| {
-            let current_address = start_address + i;
+    let labels = emulator_state.assembled_program.as_ref().map(|program| Labels::new(&program.symbols)).unwrap_or_default();
+    let memory_items: Vec<ListItem> = (start_address..end_address).step_by(row_bytes).enumerate()
+        .map(|(i, current_address)| {
+            let mut bytes = vec![0u8; row_bytes];
+            memory.memory.read_slice(current_address, &mut bytes);
             let is_selected = emulator_state.selected_memory_address.selected() == Some(i);
+            let changed: Vec<bool> = (0..row_bytes)
+                .map(|offset| emulator_state.step_diff.byte_changed((current_address + offset) as u32))
+                .collect();
             let style = if is_selected {
                 Style::default().fg(Color::Black).bg(Color::LightYellow).add_modifier(Modifier::BOLD)
+            } else if changed.contains(&true) && mode != MemoryViewMode::HexDump {
+                Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD)
             } else {
                 Style::default().fg(Color::White)
             };
-            ListItem::new(format!("0x{:04X}: 0x{:04X}", current_address, val)).style(style)
+            if mode == MemoryViewMode::HexDump && !is_selected && changed.contains(&true) {
+                return ListItem::new(hex_dump_line(current_address as u32, &bytes, &changed)).style(style);
+            }
+            ListItem::new(format_memory_row(mode, current_address as u32, &bytes, |word| disassemble(word, &labels))).style(style)
         })
         .collect();
 
     let memory_list = List::new(memory_items)
-        .block(Block::default().borders(Borders::ALL).title(format!("Memory (Page {})", emulator_state.selected_memory_page)).border_type(BorderType::Double));
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Memory (Page {}, {}) @ 0x{:04X}",
+            emulator_state.selected_memory_page, mode.name(), emulator_state.memory_cursor
+        )).border_type(BorderType::Double));
 
     frame.render_stateful_widget(memory_list, raw_memory_area, &mut emulator_state.selected_memory_address.clone());
 
-    // Render Disassembly
+    match emulator_state.side_panel {
+        SidePanel::Disassembly => render_disassembly(frame, side_panel_area, emulator_state),
+        SidePanel::BranchStats => render_branch_stats(frame, side_panel_area, &emulator_state.cpu),
+        SidePanel::Backtrace => render_backtrace(frame, side_panel_area, emulator_state),
+        SidePanel::PageTable => render_page_table(frame, side_panel_area, memory),
+        SidePanel::Watchpoints => render_watchpoints(frame, side_panel_area, memory),
+        SidePanel::Changes => render_changes(frame, side_panel_area, emulator_state),
+        SidePanel::Memcheck => render_memcheck(frame, side_panel_area, emulator_state),
+        SidePanel::Symbols => render_symbols(frame, side_panel_area, emulator_state),
+        SidePanel::Listing => render_listing(frame, side_panel_area, emulator_state),
+    }
+}
+
+/// Builds a hex dump row with the changed bytes highlighted.
+fn hex_dump_line(address: u32, bytes: &[u8], changed: &[bool]) -> Line<'static> {
+    let highlight = Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD);
+    let mut spans = vec![Span::raw(format!("0x{:04X}:", address))];
+    for (&byte, &is_changed) in bytes.iter().zip(changed) {
+        spans.push(Span::raw(" "));
+        let text = format!("{:02X}", byte);
+        spans.push(if is_changed { Span::styled(text, highlight) } else { Span::raw(text) });
+    }
+    spans.push(Span::raw("  "));
+    for (&byte, &is_changed) in bytes.iter().zip(changed) {
+        let character = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+        spans.push(if is_changed { Span::styled(character.to_string(), highlight) } else { Span::raw(character.to_string()) });
+    }
+    Line::from(spans)
+}
+
+fn render_disassembly(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState) {
     let mut disassembly_items: Vec<ListItem> = Vec::new();
     if let Some(assembled_program) = &emulator_state.assembled_program {
+        let labels = Labels::new(&assembled_program.symbols);
+        let pseudo_addresses = assembled_program.pseudo_addresses();
         for (i, &instruction) in assembled_program.text.iter().enumerate() {
-            let address = i * 4; // Corrected for 32-bit instructions (4 bytes per instruction)
-            let disassembled_line = disassemble_instruction(instruction);
-            let is_current_instruction = emulator_state.cpu.registers.pc == address as u32;
+            let address = assembled_program.actual_text_start.wrapping_add(i as u32 * 4);
+            let mut disassembled_line = disassemble(instruction, &labels);
+            // Name the pseudo-instruction on the first word of its expansion, as written in the source
+            if pseudo_addresses.contains(&address) {
+                if let Some((pseudo, _)) = recognize(&assembled_program.text[i..], &labels) {
+                    disassembled_line = format!("{:<24} ; {}", disassembled_line, pseudo);
+                }
+            }
+            let is_current_instruction = emulator_state.cpu.registers.pc == address;
             let style = if is_current_instruction {
                 Style::default().fg(Color::Black).bg(Color::LightGreen).add_modifier(Modifier::BOLD)
             } else {
@@ -483,248 +1324,184 @@ fn render_memory_view(frame: &mut Frame, area: Rect, emulator_state: &EmulatorSt
     let disassembly_list = List::new(disassembly_items)
         .block(Block::default().borders(Borders::ALL).title("Disassembly").border_type(BorderType::Double));
 
-    frame.render_widget(disassembly_list, disassembly_area);
-}
-
-fn disassemble_instruction(instruction: u32) -> String {
-    let opcode = (instruction >> 24) as u8;
-
-    match opcode {
-        0x01 => { // MOVI reg, imm
-            let reg_num = (instruction >> 16) & 0xFF;
-            let imm = (instruction & 0xFFFF) as u16;
-            format!("MOVI {}, 0x{:04X}", reg_num_to_name(reg_num as u8), imm)
-        },
-        0x02 => { // MOVW reg, reg/imm
-            let reg1_num = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Reg-Reg
-                let reg2_num = (instruction >> 8) & 0xFF;
-                format!("MOVW {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-            } else { // Reg-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("MOVW {}, 0x{:04X}", reg_num_to_name(reg1_num as u8), imm)
-            }
-        },
-        0x03 => { // LODI reg, imm/label
-            let reg_num = (instruction >> 16) & 0xFF;
-            let imm = (instruction & 0xFFFF) as u16;
-            format!("LODI {}, 0x{:04X}", reg_num_to_name(reg_num as u8), imm)
-        },
-        0x04 => { // LODW reg, [addr/label]
-            let reg_num = (instruction >> 16) & 0xFF;
-            let addr = instruction & 0xFFFF;
-            format!("LODW {}, [0x{:04X}]", reg_num_to_name(reg_num as u8), addr)
-        },
-        0x05 => { // STRI [addr/label], imm
-            let addr = (instruction >> 16) & 0xFF;
-            let imm = (instruction & 0xFFFF) as u16;
-            format!("STRI [0x{:02X}], 0x{:04X}", addr, imm)
-        },
-        0x06 => { // STRW [addr/label], reg/imm
-            let addr = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Addr-Reg
-                let reg_num = (instruction >> 8) & 0xFF;
-                format!("STRW [0x{:02X}], {}", addr, reg_num_to_name(reg_num as u8))
-            } else { // Addr-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("STRW [0x{:02X}], 0x{:04X}", addr, imm)
-            }
-        },
-        0x07 => { // PUSH reg/imm
-            if (instruction & 1) == 1 { // Register
-                let reg_num = (instruction >> 16) & 0xFF;
-                format!("PUSH {}", reg_num_to_name(reg_num as u8))
-            } else { // Immediate
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("PUSH 0x{:04X}", imm)
-            }
-        },
-        0x08 => { // POP reg
-            let reg_num = (instruction >> 16) & 0xFF;
-            format!("POP {}", reg_num_to_name(reg_num as u8))
-        },
-        0x09 => { // XCGH reg, reg
-            let reg1_num = (instruction >> 16) & 0xFF;
-            let reg2_num = (instruction >> 8) & 0xFF;
-            format!("XCGH {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-        },
-        0x10 => { // ADDW reg, reg/imm
-            let reg1_num = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Reg-Reg
-                let reg2_num = (instruction >> 8) & 0xFF;
-                format!("ADDW {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-            } else { // Reg-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("ADDW {}, 0x{:04X}", reg_num_to_name(reg1_num as u8), imm)
-            }
-        },
-        0x11 => { // SUBW reg, reg/imm
-            let reg1_num = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Reg-Reg
-                let reg2_num = (instruction >> 8) & 0xFF;
-                format!("SUBW {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-            } else { // Reg-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("SUBW {}, 0x{:04X}", reg_num_to_name(reg1_num as u8), imm)
-            }
-        },
-        0x12 => { // MUL reg, reg/imm
-            let reg1_num = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Reg-Reg
-                let reg2_num = (instruction >> 8) & 0xFF;
-                format!("MUL {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-            } else { // Reg-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("MUL {}, 0x{:04X}", reg_num_to_name(reg1_num as u8), imm)
-            }
-        },
-        0x13 => { // INC reg
-            let reg_num = (instruction >> 16) & 0xFF;
-            format!("INC {}", reg_num_to_name(reg_num as u8))
-        },
-        0x14 => { // DEC reg
-            let reg_num = (instruction >> 16) & 0xFF;
-            format!("DEC {}", reg_num_to_name(reg_num as u8))
-        },
-        0x15 => { // NEG reg
-            let reg_num = (instruction >> 16) & 0xFF;
-            format!("NEG {}", reg_num_to_name(reg_num as u8))
-        },
-        0x20 => { // NOT reg
-            let reg_num = (instruction >> 16) & 0xFF;
-            format!("NOT {}", reg_num_to_name(reg_num as u8))
-        },
-        0x21 => { // AND reg, reg/imm
-            let reg1_num = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Reg-Reg
-                let reg2_num = (instruction >> 8) & 0xFF;
-                format!("AND {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-            } else { // Reg-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("AND {}, 0x{:04X}", reg_num_to_name(reg1_num as u8), imm)
-            }
-        },
-        0x22 => { // OR reg, reg/imm
-            let reg1_num = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Reg-Reg
-                let reg2_num = (instruction >> 8) & 0xFF;
-                format!("OR {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-            } else { // Reg-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("OR {}, 0x{:04X}", reg_num_to_name(reg1_num as u8), imm)
-            }
-        },
-        0x23 => { // XOR reg, reg/imm
-            let reg1_num = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Reg-Reg
-                let reg2_num = (instruction >> 8) & 0xFF;
-                format!("XOR {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-            } else { // Reg-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("XOR {}, 0x{:04X}", reg_num_to_name(reg1_num as u8), imm)
-            }
-        },
-        0x30 => { // CMPW reg, reg/imm
-            let reg1_num = (instruction >> 16) & 0xFF;
-            if (instruction & 1) == 1 { // Reg-Reg
-                let reg2_num = (instruction >> 8) & 0xFF;
-                format!("CMPW {}, {}", reg_num_to_name(reg1_num as u8), reg_num_to_name(reg2_num as u8))
-            } else { // Reg-Imm
-                let imm = (instruction & 0xFFFF) as u16;
-                format!("CMPW {}, 0x{:04X}", reg_num_to_name(reg1_num as u8), imm)
-            }
-        },
-        0x40 => { // JMP addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JMP 0x{:06X}", addr)
-        },
-        0x41 => { // CALL addr
-            let addr = instruction & 0xFFFFFF;
-            format!("CALL 0x{:06X}", addr)
-        },
-        0x42 => { // RET
-            format!("RET")
-        },
-        0x43 => { // JE addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JE 0x{:06X}", addr)
-        },
-        0x44 => { // JNE addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JNE 0x{:06X}", addr)
-        },
-        0x45 => { // JGT addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JGT 0x{:06X}", addr)
-        },
-        0x46 => { // JGE addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JGE 0x{:06X}", addr)
-        },
-        0x47 => { // JLT addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JLT 0x{:06X}", addr)
-        },
-        0x48 => { // JLE addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JLE 0x{:06X}", addr)
-        },
-        0x49 => { // JS addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JS 0x{:06X}", addr)
-        },
-        0x4A => { // JCO addr
-            let addr = instruction & 0xFFFFFF;
-            format!("JCO 0x{:06X}", addr)
-        },
-        0x50 => { // IN reg
-            let reg_num = (instruction >> 16) & 0xFF;
-            format!("IN {}", reg_num_to_name(reg_num as u8))
-        },
-        0x51 => { // OUT reg/addr
-            if (instruction & 1) == 1 { // Register
-                let reg_num = (instruction >> 16) & 0xFF;
-                format!("OUT {}", reg_num_to_name(reg_num as u8))
-            } else { // Address (from assembler, this is the label address)
-                let addr = instruction & 0xFFFFFF;
-                format!("OUT 0x{:06X}", addr)
-            }
-        },
-        0x60 => { // SETF flag
-            let flag_id = instruction & 0xFF;
-            format!("SETF {}", flag_id_to_name(flag_id as u8))
-        },
-        0x61 => { // CLRF flag
-            let flag_id = instruction & 0xFF;
-            format!("CLRF {}", flag_id_to_name(flag_id as u8))
-        },
-        0xFF => { // HALT
-            format!("HALT")
-        },
-        _ => format!("UNKNOWN 0x{:08X}", instruction),
+    frame.render_widget(disassembly_list, area);
+}
+
+fn render_branch_stats(frame: &mut Frame, area: Rect, emulator: &CPU) {
+    let predictor = &emulator.branch_predictor;
+    let mut items = vec![
+        ListItem::new(format!("Predictor: {}", predictor.kind.name())).style(Style::default().fg(Color::Cyan)),
+        ListItem::new(format!(
+            "Overall: {:.1}% ({} branches, {} mispredicted, penalty {} cycles)",
+            predictor.accuracy(),
+            predictor.total_executed(),
+            predictor.total_mispredicted(),
+            predictor.misprediction_penalty,
+        )),
+    ];
+    for (site, stats) in &predictor.sites {
+        items.push(ListItem::new(format!(
+            "0x{:04X}: {:>5.1}%  exec {:<6} taken {:<6}",
+            site, stats.accuracy(), stats.executed, stats.taken,
+        )));
     }
+
+    let stats_list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Branch Prediction").border_type(BorderType::Double));
+
+    frame.render_widget(stats_list, area);
 }
 
-// Helper to convert register number to name
-fn reg_num_to_name(num: u8) -> &'static str {
-    match num {
-        0 => "AX", 1 => "BX", 2 => "CX", 3 => "DX",
-        4 => "EX", 5 => "FX", 6 => "GX", 7 => "HX",
-        8 => "SP", 9 => "BP", 10 => "SI", 11 => "DI",
-        12 => "PC", 13 => "FLAGS",
-        _ => "R??",
+fn render_backtrace(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState) {
+    let items: Vec<ListItem> = emulator_state.backtrace(emulator_state.cpu.registers.pc)
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let style = if i == 0 { Style::default().fg(Color::LightGreen) } else { Style::default().fg(Color::White) };
+            ListItem::new(line).style(style)
+        })
+        .collect();
+
+    let backtrace_list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(format!("Backtrace (depth {})", emulator_state.cpu.call_stack.depth())).border_type(BorderType::Double));
+
+    frame.render_widget(backtrace_list, area);
+}
+
+fn render_page_table(frame: &mut Frame, area: Rect, memory: &WorkMemory) {
+    let mmu = &memory.mmu;
+    let mut items = Vec::new();
+    if !mmu.present {
+        items.push(ListItem::new("No MMU installed (start with --mmu)."));
+    } else {
+        let stats = mmu.tlb_stats();
+        items.push(ListItem::new(format!(
+            "MMU {} | registers 0x{:04X} | page size 0x{:X} | PTBR 0x{:08X} | PTLR {}",
+            if mmu.enabled() { "enabled" } else { "disabled" },
+            mmu.base, mmu.effective_page_size(), mmu.ptbr, mmu.ptlr,
+        )).style(Style::default().fg(Color::Cyan)));
+        items.push(ListItem::new(format!(
+            "TLB: {} hits, {} misses ({:.1}% hit rate), {} flushes",
+            stats.hits, stats.misses, stats.hit_rate(), stats.flushes,
+        )));
+        items.push(ListItem::new(format!(
+            "Last fault: cause {} at 0x{:08X} (PC 0x{:08X}) | handler 0x{:08X}",
+            mmu.fault_cause, mmu.fault_address, mmu.fault_pc, mmu.fault_handler,
+        )));
+        for entry in mmu.tlb_entries() {
+            items.push(ListItem::new(format!("TLB  VPN 0x{:05X} -> {}", entry.vpn, format_pte(entry.pte, mmu.effective_page_size())))
+                .style(Style::default().fg(Color::LightGreen)));
+        }
+        for (vpn, pte) in memory.page_table(area.height as usize) {
+            items.push(ListItem::new(format!("PTE  VPN 0x{:05X} -> {}", vpn, format_pte(pte, mmu.effective_page_size()))));
+        }
     }
+
+    let page_table_list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Page Table").border_type(BorderType::Double));
+
+    frame.render_widget(page_table_list, area);
 }
 
-// Helper to convert flag ID to name
-fn flag_id_to_name(id: u8) -> &'static str {
-    match id {
-        0 => "ZERO",
-        1 => "SIGN",
-        2 => "CARRY",
-        3 => "OVERFLOW",
-        _ => "FLAG??",
+fn render_watchpoints(frame: &mut Frame, area: Rect, memory: &WorkMemory) {
+    let mut items: Vec<ListItem> = memory.watchpoints.list.iter()
+        .map(|watchpoint| ListItem::new(watchpoint.describe()))
+        .collect();
+    if items.is_empty() {
+        items.push(ListItem::new("No watchpoints (W to add one)."));
     }
+
+    let watchpoint_list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Watchpoints").border_type(BorderType::Double));
+
+    frame.render_widget(watchpoint_list, area);
+}
+
+fn render_changes(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState) {
+    let title = match emulator_state.comparison {
+        None => "Changes (last step)".to_string(),
+        Some((from, None)) => format!("Changes (snapshot #{} to now)", from + 1),
+        Some((from, Some(to))) => format!("Changes (snapshot #{} to #{})", from + 1, to + 1),
+    };
+    let items: Vec<ListItem> = match emulator_state.changes() {
+        Ok(diff) if diff.is_empty() => vec![ListItem::new("No changes.")],
+        Ok(diff) => diff.registers.iter()
+            .map(|(name, old, new)| ListItem::new(format!("{:<5} 0x{:08X} -> 0x{:08X}", name, old, new)).style(Style::default().fg(Color::Cyan)))
+            .chain(diff.memory.iter().map(|change| {
+                ListItem::new(format!("0x{:04X}  {:02X} -> {:02X}", change.address, change.old, change.new))
+            }))
+            .collect(),
+        Err(e) => vec![ListItem::new(e).style(Style::default().fg(Color::LightRed))],
+    };
+
+    let changes_list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title).border_type(BorderType::Double));
+
+    frame.render_widget(changes_list, area);
+}
+
+fn render_memcheck(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState) {
+    let mut items: Vec<ListItem> = emulator_state.memcheck_reports.iter()
+        .map(|report| ListItem::new(format!("{}x {}", report.count, emulator_state.describe_memcheck_report(report))))
+        .collect();
+    if items.is_empty() {
+        let message = if emulator_state.memory.shadow.enabled { "No uninitialized reads." } else { "Memcheck is disabled (U to enable)." };
+        items.push(ListItem::new(message));
+    }
+
+    let memcheck_list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(format!("Memcheck ({} errors)", emulator_state.memcheck_reports.len())).border_type(BorderType::Double));
+
+    frame.render_widget(memcheck_list, area);
+}
+
+fn render_symbols(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState) {
+    let (title, items): (String, Vec<ListItem>) = match &emulator_state.assembled_program {
+        Some(program) => (
+            format!("Symbols ({})", program.symbols.symbols().len()),
+            program.symbols.dump().lines().map(|line| ListItem::new(line.to_string())).collect(),
+        ),
+        None => ("Symbols".to_string(), vec![ListItem::new("No assembled program loaded.")]),
+    };
+
+    let symbols_list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title).border_type(BorderType::Double));
+
+    frame.render_widget(symbols_list, area);
+}
+
+fn render_listing(frame: &mut Frame, area: Rect, emulator_state: &EmulatorState) {
+    let mut state = ListState::default();
+    let items: Vec<ListItem> = match &emulator_state.assembled_program {
+        Some(program) => program.listing.rows().into_iter().enumerate()
+            .map(|(index, (address, row))| {
+                if address == Some(emulator_state.cpu.registers.pc) {
+                    state.select(Some(index));
+                    ListItem::new(row).style(Style::default().fg(Color::Black).bg(Color::LightGreen).add_modifier(Modifier::BOLD))
+                } else {
+                    ListItem::new(row)
+                }
+            })
+            .collect(),
+        None => vec![ListItem::new("No assembled program loaded.")],
+    };
+
+    let listing = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Listing").border_type(BorderType::Double));
+
+    frame.render_stateful_widget(listing, area, &mut state);
+}
+
+fn format_pte(pte: u32, page_size: u32) -> String {
+    if pte & PTE_PRESENT == 0 {
+        return "not present".to_string();
+    }
+    format!(
+        "frame 0x{:08X} P{}{}",
+        pte & !(page_size - 1),
+        if pte & PTE_WRITABLE != 0 { 'W' } else { '-' },
+        if pte & PTE_EXECUTABLE != 0 { 'X' } else { '-' },
+    )
 }
 
 fn render_flags_display(frame: &mut Frame, area: Rect, emulator: &CPU) {
@@ -750,12 +1527,15 @@ fn render_control_panel(frame: &mut Frame, area: Rect, emulator_state: &Emulator
         .split(area);
 
     let status_text = if emulator_state.is_running {
-        "Running (P: Pause, S: Step, R: Reset, F: Toggle Flags)"
+        "Running (P: Pause, S: Step, R: Reset, F: Toggle Flags, B: Predictor, M: Self-Modifying Code, W: Watch, G/E//: Go to/Edit/Search, V: View, K/C: Snapshot/Compare, U: Memcheck, Tab: Panel)"
     } else {
-        "Paused (P: Run, S: Step, R: Reset, F: Toggle Flags)"
+        "Paused (P: Run, S: Step, R: Reset, F: Toggle Flags, B: Predictor, M: Self-Modifying Code, W: Watch, G/E//: Go to/Edit/Search, V: View, K/C: Snapshot/Compare, U: Memcheck, Tab: Panel)"
     };
 
-    let pc_text = format!("PC: 0x{:04X}", emulator.registers.pc);
+    let pc_text = match &emulator_state.prompt {
+        Some(prompt) => format!("{}: {}_", prompt.kind.label(), prompt.input),
+        None => format!("PC: 0x{:04X} | Cycles: {}", emulator.registers.pc, emulator.cycles),
+    };
 
     let control_panel_text = format!("{}\n{}", status_text, pc_text);
 
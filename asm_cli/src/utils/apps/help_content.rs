@@ -58,8 +58,8 @@ pub fn get_help_sections() -> Vec<HelpSection> {
                 " ".to_string(),
                 "- `Text Section` (0x0000 - 0x7FFF): Stores the program code.".to_string(),
                 "- `Data Section` (0x8000 - 0xDFFF): Stores program data.".to_string(),
-                "- `I/O Region` (0xE000 - 0xEFFF): Used for memory-mapped I/O.".to_string(),
                 "- `Stack` (0xF000 - 0xFFFF): Used for the call stack.".to_string(),
+                "- `I/O Region` (0x10000 - 0x10FFF): The console, mapped right above RAM.".to_string(),
             ],
         },
         HelpSection {
@@ -115,6 +115,15 @@ pub fn get_help_sections() -> Vec<HelpSection> {
                 "- `HALT`: Halt the CPU.".to_string(),
                 "- `SETF FLAG`: Set a flag.".to_string(),
                 "- `CLRF FLAG`: Clear a flag.".to_string(),
+                " ".to_string(),
+                "`Pseudo-instructions` (expanded by the assembler)".to_string(),
+                "- `NOP`: Do nothing (`OR AX, 0`).".to_string(),
+                "- `CLR REG`: Clear a register (`XOR REG, REG`).".to_string(),
+                "- `LI REG, IMM32`: Load a 32-bit value (4 instructions).".to_string(),
+                "- `LA REG, LABEL`: Load the address of a label, which must be even (3 instructions).".to_string(),
+                "- `JZ REG, ADDR` / `JNZ REG, ADDR`: Jump if a register is / is not zero (`CMPW REG, 0` and `JE`/`JNE`).".to_string(),
+                "- `CALLR REG`: Call the address held in a register (`CALL [REG]`).".to_string(),
+                "- `PUSHA` / `POPA`: Push AX to HX / pop them back in reverse order.".to_string(),
             ],
         },
         HelpSection {
@@ -124,12 +133,19 @@ pub fn get_help_sections() -> Vec<HelpSection> {
                 " ".to_string(),
                 "- `.text`: Marks the beginning of the text section.".to_string(),
                 "- `.data`: Marks the beginning of the data section.".to_string(),
-                "- `.word VALUE`: Allocates a 32-bit word.".to_string(),
-                "- `.byte VALUE`: Allocates a byte.".to_string(),
-                "- `.string \"STRING\"`: Allocates a string.".to_string(),
+                "- `.word VALUE, ...`: Allocates 32-bit words.".to_string(),
+                "- `.half VALUE, ...`: Allocates 16-bit halfwords.".to_string(),
+                "- `.byte VALUE, ...`: Allocates bytes.".to_string(),
+                "- `.float VALUE, ...`: Allocates 32-bit floats.".to_string(),
+                "- `.ascii \"STRING\"`: Allocates a string without a terminator (escapes like `\\n` and `\\x41` are supported).".to_string(),
+                "- `.asciz \"STRING\"` / `.string \"STRING\"`: Allocates a zero-terminated string.".to_string(),
+                "- `.fill COUNT, SIZE, VALUE`: Allocates COUNT copies of a SIZE-byte value.".to_string(),
+                "- `.incbin \"FILE\"`: Includes the contents of a binary file.".to_string(),
                 "- `label: .equ VALUE`: Defines a constant.".to_string(),
                 "- `.align BOUNDARY`: Aligns the current address to a boundary.".to_string(),
                 "- `.space SIZE`: Reserves a block of memory.".to_string(),
+                "- `.global NAME, ...`: Exports symbols to other object files.".to_string(),
+                "- `.extern NAME, ...`: Declares symbols defined in other object files.".to_string(),
             ],
         },
         HelpSection {
@@ -144,6 +160,9 @@ pub fn get_help_sections() -> Vec<HelpSection> {
                 " ".to_string(),
                 "`.data`".to_string(),
                 "`hello_msg: .string \"Hello, World!\\n\"`".to_string(),
+                " ".to_string(),
+                "`Alt+L` in the editor lints the program and lists warnings below it: missing `HALT`, unreachable code, unused labels, writes to `PC`, float arithmetic on integer data, unbalanced `PUSH`/`POP` and `RET` without `CALL`.".to_string(),
+                "Silence a warning with a comment on its line: `; lint: allow(missing-halt)`, or `; lint: allow` for every lint.".to_string(),
             ],
         },
     ]
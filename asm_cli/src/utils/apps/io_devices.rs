@@ -8,10 +8,6 @@ use crate::utils::workspaces::Workspace;
 use crate::utils::ui::common::AppState;
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
 
-// Fixed I/O segment addresses
-const IO_START: u32 = 0xE000;
-const IO_SIZE: u32 = 0x1000;
-
 #[derive(Clone, Default)]
 pub struct IoDevicesState {
     pub input_buffer: String,
@@ -25,20 +21,22 @@ pub fn render_io_devices(
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Percentage(50),
-            Constraint::Percentage(50),
+            Constraint::Percentage(45),
+            Constraint::Percentage(45),
+            Constraint::Percentage(10),
         ])
         .split(area);
 
     let output_buffer = if let Some(emulator) = &workspace.emulator {
+        let (io_start, io_size) = emulator.memory.console_window();
         let mut buffer = Vec::new();
-        for i in 0..(IO_SIZE / 2) {
-            if let Ok(byte) = emulator.memory.read_u8(IO_START + IO_SIZE / 2 + i) {
-                if byte == 0 {
-                    break;
-                }
-                buffer.push(byte);
+        // Peek, so redrawing neither trips watchpoints nor records accesses
+        for i in 0..(io_size / 2) {
+            let byte = emulator.memory.peek_physical_byte(io_start + io_size / 2 + i);
+            if byte == 0 {
+                break;
             }
+            buffer.push(byte);
         }
         String::from_utf8_lossy(&buffer).to_string()
     } else {
@@ -52,8 +50,19 @@ pub fn render_io_devices(
     let input_paragraph = Paragraph::new(workspace.io_device.input_buffer.clone())
         .block(Block::default().borders(Borders::ALL).title("Input"));
 
+    let device_map = match &workspace.emulator {
+        Some(emulator) => emulator.memory.bus.mappings.iter()
+            .map(|m| format!("{}: 0x{:04X}-0x{:04X}", m.device.name(), m.base, m.last_address()))
+            .collect::<Vec<_>>()
+            .join("  "),
+        None => String::new(),
+    };
+    let devices_paragraph = Paragraph::new(device_map)
+        .block(Block::default().borders(Borders::ALL).title("Bus Devices"));
+
     frame.render_widget(output_paragraph, chunks[0]);
     frame.render_widget(input_paragraph, chunks[1]);
+    frame.render_widget(devices_paragraph, chunks[2]);
 }
 
 pub fn handle_io_devices_input(
@@ -74,22 +83,24 @@ pub fn handle_io_devices_input(
         KeyCode::Char(c) => {
             workspace.io_device.input_buffer.push(c);
             if let Some(emulator) = &mut workspace.emulator {
+                let (io_start, _) = emulator.memory.console_window();
                 let bytes = workspace.io_device.input_buffer.as_bytes();
                 for (i, &byte) in bytes.iter().enumerate() {
-                    emulator.memory.write_u8(IO_START + i as u32, byte).unwrap();
+                    emulator.memory.write_u8(io_start + i as u32, byte).unwrap();
                 }
-                emulator.memory.write_u8(IO_START + bytes.len() as u32, 0).unwrap();
+                emulator.memory.write_u8(io_start + bytes.len() as u32, 0).unwrap();
             }
             *handled = true;
         }
         KeyCode::Backspace => {
             workspace.io_device.input_buffer.pop();
             if let Some(emulator) = &mut workspace.emulator {
+                let (io_start, _) = emulator.memory.console_window();
                 let bytes = workspace.io_device.input_buffer.as_bytes();
                 for (i, &byte) in bytes.iter().enumerate() {
-                    emulator.memory.write_u8(IO_START + i as u32, byte).unwrap();
+                    emulator.memory.write_u8(io_start + i as u32, byte).unwrap();
                 }
-                emulator.memory.write_u8(IO_START + bytes.len() as u32, 0).unwrap();
+                emulator.memory.write_u8(io_start + bytes.len() as u32, 0).unwrap();
             }
             *handled = true;
         }
@@ -0,0 +1,181 @@
+//! # Memory Editor
+//!
+//! This module holds the pieces of the emulator's memory view that do not
+//! depend on the terminal: the display modes, the formatting of a row of
+//! memory, and the parsing of the values typed to edit or search memory.
+
+/// The ways the emulator memory view can display memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryViewMode {
+    /// Bytes in hexadecimal with their ASCII characters.
+    #[default]
+    HexDump,
+    /// 32-bit words in hexadecimal and decimal.
+    Words,
+    /// 32-bit floating-point numbers.
+    Floats,
+    /// 32-bit words decoded as instructions.
+    Disassembly,
+}
+
+impl MemoryViewMode {
+    /// Returns the mode that follows this one, wrapping around.
+    pub fn next(self) -> Self {
+        match self {
+            MemoryViewMode::HexDump => MemoryViewMode::Words,
+            MemoryViewMode::Words => MemoryViewMode::Floats,
+            MemoryViewMode::Floats => MemoryViewMode::Disassembly,
+            MemoryViewMode::Disassembly => MemoryViewMode::HexDump,
+        }
+    }
+
+    /// Returns the name shown in the memory view title.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryViewMode::HexDump => "Hex",
+            MemoryViewMode::Words => "Words",
+            MemoryViewMode::Floats => "Floats",
+            MemoryViewMode::Disassembly => "Disassembly",
+        }
+    }
+
+    /// Returns the number of bytes shown on each row.
+    pub fn row_bytes(&self) -> usize {
+        match self {
+            MemoryViewMode::HexDump => 8,
+            MemoryViewMode::Words | MemoryViewMode::Floats | MemoryViewMode::Disassembly => 4,
+        }
+    }
+}
+
+/// Formats one row of the memory view.
+///
+/// # Arguments
+///
+/// * `mode` - The display mode.
+/// * `address` - The address of the first byte of the row.
+/// * `bytes` - The bytes of the row (`mode.row_bytes()` of them).
+/// * `disassemble` - Decodes an instruction word, used in disassembly mode.
+///
+/// # Returns
+///
+/// * `String` - The formatted row.
+pub fn format_memory_row(mode: MemoryViewMode, address: u32, bytes: &[u8], disassemble: impl Fn(u32) -> String) -> String {
+    let word = bytes.get(..4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .unwrap_or(0);
+    match mode {
+        MemoryViewMode::HexDump => {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let ascii: String = bytes.iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+            format!("0x{:04X}: {}  {}", address, hex.join(" "), ascii)
+        }
+        MemoryViewMode::Words => format!("0x{:04X}: 0x{:08X}  {}", address, word, word as i32),
+        MemoryViewMode::Floats => format!("0x{:04X}: {}", address, f32::from_bits(word)),
+        MemoryViewMode::Disassembly => format!("0x{:04X}: 0x{:08X}  {}", address, word, disassemble(word)),
+    }
+}
+
+/// Parses a value typed into the memory editor or search prompt into the bytes it stores.
+///
+/// The input is a kind followed by the value: `b` for bytes (`b 41 42 0x43`),
+/// `h` for a 16-bit halfword, `w` for a 32-bit word, `f` for a 32-bit float and
+/// `s` for a string (stored without a terminator). Input without a kind is a word.
+/// Numbers are decimal, negative or `0x`-prefixed hexadecimal; bytes are always hexadecimal.
+///
+/// # Arguments
+///
+/// * `input` - The text typed by the user.
+///
+/// # Returns
+///
+/// * `Result<Vec<u8>, String>` - The bytes in memory order, or an error message.
+pub fn parse_value_bytes(input: &str) -> Result<Vec<u8>, String> {
+    let input = input.trim();
+    let (kind, value) = match input.split_once(char::is_whitespace) {
+        Some((kind, value)) if matches!(kind, "b" | "h" | "w" | "f" | "s") => (kind, value.trim()),
+        _ => ("w", input),
+    };
+    if value.is_empty() {
+        return Err("Missing value".to_string());
+    }
+    match kind {
+        "b" => value.split_whitespace()
+            .map(|byte| {
+                let digits = byte.strip_prefix("0x").unwrap_or(byte);
+                u8::from_str_radix(digits, 16).map_err(|_| format!("Invalid byte: {}", byte))
+            })
+            .collect(),
+        "h" => {
+            let number = parse_integer(value)?;
+            if !(i16::MIN as i64..=u16::MAX as i64).contains(&number) {
+                return Err(format!("Value out of range for a halfword: {}", value));
+            }
+            Ok((number as u16).to_le_bytes().to_vec())
+        }
+        "f" => value.parse::<f32>()
+            .map(|float| float.to_le_bytes().to_vec())
+            .map_err(|_| format!("Invalid float: {}", value)),
+        "s" => {
+            let text = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            Ok(text.as_bytes().to_vec())
+        }
+        _ => {
+            let number = parse_integer(value)?;
+            if !(i32::MIN as i64..=u32::MAX as i64).contains(&number) {
+                return Err(format!("Value out of range for a word: {}", value));
+            }
+            Ok((number as u32).to_le_bytes().to_vec())
+        }
+    }
+}
+
+/// Parses a decimal (possibly negative) or `0x`-prefixed hexadecimal integer.
+fn parse_integer(text: &str) -> Result<i64, String> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let magnitude = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse::<i64>(),
+    }.map_err(|_| format!("Invalid number: {}", text))?;
+    Ok(if negative { -magnitude } else { magnitude })
+}
+
+#[cfg(test)]
+mod memory_editor_test {
+    use super::*;
+
+    #[test]
+    fn values_parse_to_little_endian_bytes() {
+        assert_eq!(parse_value_bytes("b 41 0x42 ff").unwrap(), vec![0x41, 0x42, 0xFF]);
+        assert_eq!(parse_value_bytes("h 0x1234").unwrap(), vec![0x34, 0x12]);
+        assert_eq!(parse_value_bytes("w -1").unwrap(), vec![0xFF; 4]);
+        assert_eq!(parse_value_bytes("305419896").unwrap(), vec![0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(parse_value_bytes("f 1.0").unwrap(), 1.0f32.to_le_bytes().to_vec());
+        assert_eq!(parse_value_bytes("s \"hi there\"").unwrap(), b"hi there".to_vec());
+        assert!(parse_value_bytes("h 70000").is_err());
+        assert!(parse_value_bytes("b zz").is_err());
+        assert!(parse_value_bytes("w").is_err());
+    }
+
+    #[test]
+    fn rows_are_formatted_per_mode() {
+        let bytes = [0x41, 0x42, 0x00, 0x00, 0x7E, 0x0A, 0x20, 0x43];
+        assert_eq!(
+            format_memory_row(MemoryViewMode::HexDump, 0x10, &bytes, |_| String::new()),
+            "0x0010: 41 42 00 00 7E 0A 20 43  AB..~. C"
+        );
+        assert_eq!(
+            format_memory_row(MemoryViewMode::Words, 0x10, &bytes[..4], |_| String::new()),
+            "0x0010: 0x00004241  16961"
+        );
+        assert_eq!(
+            format_memory_row(MemoryViewMode::Disassembly, 0x10, &bytes[..4], |word| format!("op {:X}", word >> 24)),
+            "0x0010: 0x00004241  op 0"
+        );
+    }
+}
@@ -3,6 +3,7 @@ pub mod io_devices;
 pub mod settings;
 pub mod start_menu;
 pub mod emulator; 
+pub mod memory_editor;
 pub mod file_explorer;
 pub mod help_guide;
 pub mod help_content; 
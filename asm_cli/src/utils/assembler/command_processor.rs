@@ -5,25 +5,26 @@
 //! translates assembly instructions into machine code.
 
 
+use crate::utils::assembler::conditionals::apply_conditionals;
+use crate::utils::assembler::data::{assemble_data_directive, data_size, is_data_directive};
+use crate::utils::assembler::labels::resolve_local_labels;
+use crate::utils::assembler::listing::{Listing, ListingCode, ListingLine};
+use crate::utils::assembler::macros::{Macro, MacroExpander};
 use crate::utils::assembler::operands::{Operand, parse_operand};
+use crate::utils::assembler::pseudo::{expand_pseudo_instructions, resolve_load_address};
+use crate::utils::assembler::source::{strip_comment, SourceLine, SourceLocation};
+use crate::utils::assembler::relocations::find_relocations;
+use crate::utils::assembler::symbol_table::{SymbolSection, SymbolTable};
+use crate::utils::linker::object::{ObjectFile, ObjectLine, ObjectSymbol, OBJECT_VERSION};
 use crate::memory::registers::Reg;
 
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 
 // Default segment values if not specified by directives
 const DEFAULT_TEXT_START: u32 = 0x0000;
-const DEFAULT_STACK_SIZE: u32 = 0x1000; // 4KB
-
-/// Represents a macro definition in the assembly code.
-#[derive(Debug, Clone)]
-pub struct Macro {
-    /// The name of the macro.
-    pub name: String,
-    /// The arguments the macro accepts.
-    pub args: Vec<String>,
-    /// The body of the macro, consisting of a sequence of commands.
-    pub body: Vec<Command>,
-}
+pub const DEFAULT_STACK_SIZE: u32 = 0x1000; // 4KB
+const DEFAULT_DATA_END: u32 = 0x10000; // Highest address LODW's 16-bit field reaches, plus one
 
 /// Represents a single assembly command or directive.
 #[derive(Debug, Clone)]
@@ -36,16 +37,32 @@ pub struct Command {
     pub operand2: Option<Operand>,
     /// An optional label associated with this command.
     pub label: Option<String>,
-    /// The name of the macro if this command is a macro call.
-    pub macro_name: Option<String>,
-    /// The arguments passed to the macro if this command is a macro call.
-    pub macro_args: Option<Vec<String>>,
     /// Optional: The starting address for the text segment, if specified by a directive.
     pub text_start_address: Option<u32>,
     /// Optional: The starting address for the stack segment, if specified by a directive.
     pub stack_start_address: Option<u32>,
     /// Optional: The size of the stack segment, if specified by a directive.
     pub stack_segment_size: Option<u32>,
+    /// The file and line the command was read from, if known.
+    pub location: Option<SourceLocation>,
+    /// The operands as written, for directives that parse them themselves (e.g. the condition of `.if`).
+    pub raw_operands: Option<String>,
+    /// The source line the command was parsed from, as shown in listings.
+    pub text: String,
+    /// How many macro or repetition expansions deep the command was produced; 0 for a line of the source.
+    pub expansion_depth: usize,
+    /// The pseudo-instruction (e.g. `LI`) the command was expanded from, if any.
+    pub pseudo: Option<String>,
+}
+
+impl Command {
+    /// Prefixes an error message with the file and line of the command, if known.
+    pub fn error_at(&self, error: String) -> String {
+        match &self.location {
+            Some(location) => format!("{}: {}", location, error),
+            None => error,
+        }
+    }
 }
 
 /// Represents the current section of the assembly code (e.g., `.text` or `.data`).
@@ -72,6 +89,113 @@ pub struct AssembledProgram {
     pub actual_stack_start: u32,
     /// The actual size of the stack segment.
     pub actual_stack_size: u32,
+    /// Every label and constant with its section, absolute address and size.
+    pub symbols: SymbolTable,
+    /// The absolute address and size of every `.space` buffer, which is reserved but not initialized.
+    pub reserved: Vec<(u32, u32)>,
+    /// The source location of each instruction in `text`, if known.
+    pub source_map: Vec<Option<SourceLocation>>,
+    /// The address and code of every assembled line, for listings.
+    pub listing: Listing,
+}
+
+impl AssembledProgram {
+    /// Describes an address relative to the nearest text label at or below it.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The code address to describe.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - `label` or `label+0xN`, or just the hex address when no label precedes it.
+    pub fn symbolize(&self, address: u32) -> String {
+        describe_address(&self.symbols, SymbolSection::Text, address)
+    }
+
+    /// Describes a data address relative to the nearest data label at or below it,
+    /// or as a stack address if it falls inside the stack segment.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The data address to describe.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - `label`, `label+0xN` or `stack+0xN`, or just the hex address when no label precedes it.
+    pub fn symbolize_data(&self, address: u32) -> String {
+        if address >= self.actual_stack_start && address - self.actual_stack_start < self.actual_stack_size {
+            return format!("0x{:04X} <stack+0x{:X}>", address, address - self.actual_stack_start);
+        }
+        describe_address(&self.symbols, SymbolSection::Data, address)
+    }
+
+    /// Finds the address of a text or data label.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The label name.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u32>` - The absolute address of the label, or `None` if it is not defined.
+    pub fn label_address(&self, label: &str) -> Option<u32> {
+        self.symbols.get(label)
+            .filter(|symbol| matches!(symbol.section, SymbolSection::Text | SymbolSection::Data))
+            .map(|symbol| symbol.address)
+    }
+
+    /// Returns the addresses of the first instruction of every pseudo-instruction expansion in the source.
+    pub fn pseudo_addresses(&self) -> BTreeSet<u32> {
+        let mut addresses = BTreeSet::new();
+        // Every expansion follows the line of the pseudo-instruction itself, which is not marked
+        let mut previous_was_pseudo = false;
+        for line in &self.listing.lines {
+            if line.pseudo && !previous_was_pseudo && matches!(line.code, ListingCode::Instruction(_)) {
+                addresses.extend(line.address);
+            }
+            previous_was_pseudo = line.pseudo;
+        }
+        addresses
+    }
+
+    /// Returns the file and line the instruction at `address` was assembled from.
+    pub fn source_location(&self, address: u32) -> Option<&SourceLocation> {
+        let offset = address.checked_sub(self.actual_text_start)?;
+        if offset % 4 != 0 {
+            return None;
+        }
+        self.source_map.get((offset / 4) as usize)?.as_ref()
+    }
+
+    /// Returns the start address and size of the `text`, `data` or `stack` segment.
+    pub fn segment(&self, name: &str) -> Option<(u32, u32)> {
+        match name {
+            "text" => Some((self.actual_text_start, self.text.len() as u32 * 4)),
+            "data" => Some((self.actual_data_start, self.data.len() as u32)),
+            "stack" => Some((self.actual_stack_start, self.actual_stack_size)),
+            _ => None,
+        }
+    }
+
+    /// Returns the initial stack pointer: the end of the stack segment, since the stack grows down.
+    ///
+    /// A stack ending at the top of a 4 GB address space wraps to 0, so the first push lands at `0xFFFFFFFC`.
+    pub fn stack_top(&self) -> u32 {
+        self.actual_stack_start.wrapping_add(self.actual_stack_size)
+    }
+}
+
+/// Describes an address relative to the nearest label at or below it.
+fn describe_address(symbols: &SymbolTable, section: SymbolSection, address: u32) -> String {
+    let nearest = symbols.symbols().iter()
+        .filter(|symbol| symbol.section == section && symbol.address <= address)
+        .max_by(|a, b| a.address.cmp(&b.address).then(b.name.cmp(&a.name)));
+    match nearest {
+        Some(symbol) if symbol.address == address => format!("0x{:04X} <{}>", address, symbol.name),
+        Some(symbol) => format!("0x{:04X} <{}+0x{:X}>", address, symbol.name, address - symbol.address),
+        None => format!("0x{:04X}", address),
+    }
 }
 
 /// Parses a single line of assembly code into a `Command` structure.
@@ -88,13 +212,7 @@ pub struct AssembledProgram {
 /// * `Result<Command, String>` - `Ok(Command)` on successful parsing, or `Err(String)` on failure.
 pub fn parse_command(input: &str) -> Result<Command, String> {
 
-    let comment_start = input.find(';');
-    let without_comment = if let Some(index) = comment_start {
-        &input[..index]
-    } else {
-        input
-    };
-    let trimmed_input = without_comment.trim();
+    let trimmed_input = strip_comment(input).trim();
 
     if trimmed_input.is_empty() {
         return Ok(Command {
@@ -102,11 +220,14 @@ pub fn parse_command(input: &str) -> Result<Command, String> {
             operand1: None,
             operand2: None,
             label: None,
-            macro_name: None,
-            macro_args: None,
             text_start_address: None,
             stack_start_address: None,
             stack_segment_size: None,
+            location: None,
+            raw_operands: None,
+            text: input.trim_end().to_string(),
+            expansion_depth: 0,
+            pseudo: None,
         });
     }
 
@@ -128,49 +249,60 @@ pub fn parse_command(input: &str) -> Result<Command, String> {
         operand1: None,
         operand2: None,
         label: label.clone(), // Clone the label here
-        macro_name: None,
-        macro_args: None,
         text_start_address: None,
         stack_start_address: None,
         stack_segment_size: None,
+        location: None,
+        raw_operands: operands_str.map(|operands| operands.trim().to_string()).filter(|operands| !operands.is_empty()),
+        text: input.trim_end().to_string(),
+        expansion_depth: 0,
+        pseudo: None,
     };
 
     if opcode.starts_with('.') {
         match opcode.as_str() {
+            // Conditions are expressions, evaluated from `raw_operands` once symbols are known
+            ".if" | ".elif" | ".ifdef" | ".ifndef" | ".else" | ".endif" => {}
+            // Macro definitions are read from the source text by the macro expander
+            ".macro" | ".endmacro" | ".endm" => {}
+            // Data directives take lists of values, parsed from `raw_operands` when they are assembled
+            _ if is_data_directive(&opcode) => {}
+            // Symbol declarations take lists of names
+            ".global" | ".extern" => {}
             ".text_start" => {
                 if let Some(op_str) = operands_str {
                     let parsed_op = parse_operand(op_str)?;
                     if let Operand::Immediate(addr) = parsed_op {
-                        command.text_start_address = Some(addr as u32);
+                        command.text_start_address = Some(addr);
                     } else {
-                        return Err(format!("Invalid operand for .text_start directive. Expected immediate address."));
+                        return Err("Invalid operand for .text_start directive. Expected immediate address.".to_string());
                     }
                 } else {
-                    return Err(format!("Missing operand for .text_start directive. Expected immediate address."));
+                    return Err("Missing operand for .text_start directive. Expected immediate address.".to_string());
                 }
             },
             ".stack_start" => {
                 if let Some(op_str) = operands_str {
                     let parsed_op = parse_operand(op_str)?;
                     if let Operand::Immediate(addr) = parsed_op {
-                        command.stack_start_address = Some(addr as u32);
+                        command.stack_start_address = Some(addr);
                     } else {
-                        return Err(format!("Invalid operand for .stack_start directive. Expected immediate address."));
+                        return Err("Invalid operand for .stack_start directive. Expected immediate address.".to_string());
                     }
                 } else {
-                    return Err(format!("Missing operand for .stack_start directive. Expected immediate address."));
+                    return Err("Missing operand for .stack_start directive. Expected immediate address.".to_string());
                 }
             },
             ".stack_size" => {
                 if let Some(op_str) = operands_str {
                     let parsed_op = parse_operand(op_str)?;
                     if let Operand::Immediate(size) = parsed_op {
-                        command.stack_segment_size = Some(size as u32);
+                        command.stack_segment_size = Some(size);
                     } else {
-                        return Err(format!("Invalid operand for .stack_size directive. Expected immediate size."));
+                        return Err("Invalid operand for .stack_size directive. Expected immediate size.".to_string());
                     }
                 } else {
-                    return Err(format!("Missing operand for .stack_size directive. Expected immediate size."));
+                    return Err("Missing operand for .stack_size directive. Expected immediate size.".to_string());
                 }
             },
             _ => {
@@ -186,17 +318,6 @@ pub fn parse_command(input: &str) -> Result<Command, String> {
         return Ok(command);
     }
 
-    if opcode == ".macro" {
-        let mut parts = operands_str.unwrap_or("").split_whitespace();
-        let name = parts.next().unwrap_or("").to_string();
-        let args: Vec<String> = parts.map(|s| s.to_string()).collect();
-        command.macro_name = Some(name);
-        command.macro_args = Some(args);
-        return Ok(command);
-    } else if opcode == ".endmacro" {
-        return Ok(command);
-    }
-
     if opcode.is_empty() && label.is_some() {
         return Ok(command);
     }
@@ -236,48 +357,223 @@ pub fn parse_command(input: &str) -> Result<Command, String> {
 // ... (rest of the file)
 
 
+/// Replaces every macro call with the expansion of the macro.
+///
+/// Calls are expanded from their text (label, opcode and `raw_operands`), and the expanded
+/// lines are parsed like any other line. Conditional blocks in the bodies see the `.equ`
+/// constants defined before the call.
+///
+/// # Arguments
+///
+/// * `commands` - The commands of the program.
+/// * `macros` - The macros that may be called.
+///
+/// # Returns
+///
+/// * `Result<Vec<Command>, String>` - The expanded commands, or an error message for an invalid call.
+fn expand_macros(commands: &[Command], macros: &[Macro]) -> Result<Vec<Command>, String> {
+    let mut expander = MacroExpander::new(macros.to_vec());
+    let mut expanded_commands = Vec::new();
+    for command in commands {
+        if let (".equ", Some(label), Some(Operand::Immediate(value))) = (command.opcode.as_str(), &command.label, &command.operand1) {
+            expander.symbols.insert(label.clone(), *value as i64);
+        }
+        if !macros.iter().any(|m| m.name == command.opcode) {
+            expanded_commands.push(command.clone());
+            continue;
+        }
+        let label = command.label.as_ref().map(|label| format!("{}: ", label)).unwrap_or_default();
+        let text = format!("{}{} {}", label, command.opcode, command.raw_operands.as_deref().unwrap_or(""));
+        let location = command.location.clone().unwrap_or(SourceLocation { file: None, line: 0 });
+        for line in expander.expand(&[SourceLine { location, text, depth: 0 }])? {
+            let mut expanded = parse_command(&line.text).map_err(|e| format!("{}: {}", line.location, e))?;
+            expanded.location = Some(line.location);
+            expanded.expansion_depth = line.depth;
+            expanded_commands.push(expanded);
+        }
+    }
+    Ok(expanded_commands)
+}
+
+/// The layout directives of a program, or the settings of a linker script.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentLayout {
+    /// The start of the text segment, set by `.text_start`.
+    pub text_start: Option<u32>,
+    /// The start of the data segment; only linker scripts set it, otherwise data ends where the stack starts.
+    pub data_start: Option<u32>,
+    /// The start of the stack segment, set by `.stack_start`.
+    pub stack_start: Option<u32>,
+    /// The size of the stack segment, set by `.stack_size`.
+    pub stack_size: Option<u32>,
+}
+
+/// The addresses chosen for the segments of a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segments {
+    /// The start of the text segment.
+    pub text_start: u32,
+    /// The start of the data segment.
+    pub data_start: u32,
+    /// The start of the stack segment.
+    pub stack_start: u32,
+    /// The size of the stack segment.
+    pub stack_size: u32,
+}
+
+/// What pass 1 learns about a program.
+struct FirstPass {
+    /// The labels at their offsets in their sections, the constants and the `.extern` symbols.
+    symbols: SymbolTable,
+    /// The size of the text section in bytes.
+    text_size: u32,
+    /// The size of the data section in bytes.
+    data_size: u32,
+    /// The largest `.align` boundary of the data section, which its start must be a multiple of.
+    data_align: u32,
+    /// The layout directives.
+    layout: SegmentLayout,
+    /// The names exported with `.global`.
+    globals: Vec<String>,
+}
+
 pub fn assemble_program(commands: &[Command], macros: &[Macro], total_memory_size: usize) -> Result<AssembledProgram, String> {
-    let mut symbol_table = HashMap::new();
+    let commands = prepare_commands(commands, macros)?;
+    let FirstPass { mut symbols, text_size, data_size, data_align, layout, .. } = first_pass(&commands)?;
+    if let Some(external) = symbols.symbols().iter().find(|symbol| symbol.section == SymbolSection::External) {
+        return Err(format!(".extern symbol {} can only be resolved by linking; assemble the file as an object", external.name));
+    }
+    let segments = place_segments(&layout, text_size, data_size, data_align, total_memory_size)?;
+
+    // Every label now gets its absolute address
+    symbols.relocate((segments.text_start, text_size), (segments.data_start, data_size));
+
+    let mut assembled_program = AssembledProgram {
+        actual_text_start: segments.text_start,
+        actual_data_start: segments.data_start,
+        actual_stack_start: segments.stack_start,
+        actual_stack_size: segments.stack_size,
+        ..AssembledProgram::default()
+    };
+    second_pass(&commands, &symbols.values(), segments.text_start, segments.data_start, &mut assembled_program)?;
+    assembled_program.symbols = symbols;
+    Ok(assembled_program)
+}
+
+/// Assembles a program into a relocatable object file.
+///
+/// Labels are assembled as offsets into their sections, and every use of a label or of an
+/// `.extern` symbol is recorded as a relocation for the linker to patch.
+///
+/// # Arguments
+///
+/// * `commands` - The parsed commands of the program.
+/// * `macros` - The macros that may be called.
+/// * `name` - The name of the object, used in linker diagnostics.
+///
+/// # Returns
+///
+/// * `Result<ObjectFile, String>` - The object file, or an error message.
+pub fn assemble_object(commands: &[Command], macros: &[Macro], name: &str) -> Result<ObjectFile, String> {
+    let commands = prepare_commands(commands, macros)?;
+    let FirstPass { mut symbols, text_size, data_size, data_align, layout, globals } = first_pass(&commands)?;
+
+    // Sections start at 0, so labels keep their offsets; `.extern` symbols assemble as 0
+    let offsets: HashMap<String, u32> = symbols.symbols().iter().map(|symbol| (symbol.name.clone(), symbol.address)).collect();
+    let mut program = AssembledProgram::default();
+    second_pass(&commands, &offsets, 0, 0, &mut program)?;
+    let relocations = find_relocations(&commands, &program.listing, &symbols)?;
+    symbols.relocate((0, text_size), (0, data_size));
+
+    Ok(ObjectFile {
+        version: OBJECT_VERSION,
+        name: name.to_string(),
+        layout,
+        text: program.text,
+        data: program.data,
+        data_align,
+        reserved: program.reserved,
+        symbols: symbols.symbols().iter()
+            .filter(|symbol| symbol.section != SymbolSection::External)
+            .map(|symbol| ObjectSymbol {
+                name: symbol.name.clone(),
+                section: symbol.section,
+                value: symbol.address,
+                size: symbol.size,
+                global: globals.contains(&symbol.name),
+            })
+            .collect(),
+        externs: symbols.symbols().iter()
+            .filter(|symbol| symbol.section == SymbolSection::External)
+            .map(|symbol| symbol.name.clone())
+            .collect(),
+        relocations,
+        lines: program.source_map.into_iter().enumerate()
+            .filter_map(|(index, location)| location.map(|location| ObjectLine { instruction: index as u32, location }))
+            .collect(),
+    })
+}
+
+/// Expands macros, drops the branches of conditional blocks that are not taken, expands
+/// pseudo-instructions and qualifies local labels, so both passes see the same commands.
+fn prepare_commands(commands: &[Command], macros: &[Macro]) -> Result<Vec<Command>, String> {
+    let expanded_commands = expand_macros(commands, macros)?;
+    let assembled_commands = apply_conditionals(&expanded_commands)?;
+    let real_commands = expand_pseudo_instructions(&assembled_commands)?;
+    resolve_local_labels(&real_commands)
+}
+
+/// Pass 1: sizes every command, records the labels at their section offsets and collects the
+/// layout directives and the `.global` and `.extern` declarations.
+fn first_pass(commands: &[Command]) -> Result<FirstPass, String> {
+    // Labels are recorded as offsets into their section until the segment starts are known
+    let mut symbols = SymbolTable::default();
+    let mut globals = Vec::new();
     let mut text_address_counter = DEFAULT_TEXT_START; // Default text start
     let mut data_address_counter = 0; // Data address counter will be relative to actual_data_start
+    let mut data_align = 1;
     let mut current_section = Section::Text;
 
     // Configured segment values (from directives)
-    let mut configured_text_start: Option<u32> = None;
-    let mut configured_stack_start: Option<u32> = None;
-    let mut configured_stack_size: Option<u32> = None;
+    let mut layout = SegmentLayout::default();
 
-    // Pass 1: Build symbol table and process directives
     for command in commands {
         if command.opcode == ".equ" {
             if let (Some(label), Some(Operand::Immediate(value))) = (&command.label, &command.operand1) {
-                if symbol_table.contains_key(label) {
-                    return Err(format!("Duplicate label: {}", label));
-                }
-                symbol_table.insert(label.clone(), *value as u32);
+                symbols.define(label, SymbolSection::Absolute, *value).map_err(|e| command.error_at(e))?;
             } else {
-                return Err(".equ directive requires a label and an immediate value".to_string());
+                return Err(command.error_at(".equ directive requires a label and an immediate value".to_string()));
+            }
+            continue;
+        }
+        if command.opcode == ".global" || command.opcode == ".extern" {
+            for name in symbol_names(command)? {
+                if command.opcode == ".global" {
+                    globals.push(name);
+                } else {
+                    symbols.define(&name, SymbolSection::External, 0).map_err(|e| command.error_at(e))?;
+                }
             }
             continue;
         }
 
         if let Some(ts_addr) = command.text_start_address {
-            if configured_text_start.is_some() {
+            if layout.text_start.is_some() {
                 return Err("Multiple .text_start directives found. Only one is allowed.".to_string());
             }
-            configured_text_start = Some(ts_addr);
+            layout.text_start = Some(ts_addr);
         }
         if let Some(ss_addr) = command.stack_start_address {
-            if configured_stack_start.is_some() {
+            if layout.stack_start.is_some() {
                 return Err("Multiple .stack_start directives found. Only one is allowed.".to_string());
             }
-            configured_stack_start = Some(ss_addr);
+            layout.stack_start = Some(ss_addr);
         }
         if let Some(ss_size) = command.stack_segment_size {
-            if configured_stack_size.is_some() {
+            if layout.stack_size.is_some() {
                 return Err("Multiple .stack_size directives found. Only one is allowed.".to_string());
             }
-            configured_stack_size = Some(ss_size);
+            layout.stack_size = Some(ss_size);
         }
 
         if command.opcode == ".text" {
@@ -289,21 +585,30 @@ pub fn assemble_program(commands: &[Command], macros: &[Macro], total_memory_siz
         }
 
         if let Some(label) = &command.label {
-            if symbol_table.contains_key(label) {
-                return Err(format!("Duplicate label: {}", label));
-            }
-            let address = match current_section {
-                Section::Text => text_address_counter,
-                Section::Data => data_address_counter,
+            let (section, offset) = match current_section {
+                Section::Text => (SymbolSection::Text, text_address_counter),
+                Section::Data => (SymbolSection::Data, data_address_counter),
             };
-            symbol_table.insert(label.clone(), address);
+            symbols.define(label, section, offset).map_err(|e| command.error_at(e))?;
         }
 
-        if !command.opcode.is_empty() && !command.opcode.starts_with('.') { // Only count instructions for text_address_counter
-            let (size, padding) = get_instruction_or_data_size(command, data_address_counter)?;
+        // Text directives take no space; in the data section every directive except the layout ones does
+        let occupies_space = match current_section {
+            Section::Text => !command.opcode.starts_with('.'),
+            Section::Data => !matches!(command.opcode.as_str(), ".text_start" | ".stack_start" | ".stack_size" | ".global" | ".extern"),
+        };
+        if !command.opcode.is_empty() && occupies_space {
+            let (size, padding) = get_instruction_or_data_size(command, data_address_counter, &symbols).map_err(|e| command.error_at(e))?;
             match current_section {
                 Section::Text => text_address_counter += size,
                 Section::Data => {
+                    if command.opcode == ".align" {
+                        if let Some(Operand::Immediate(boundary)) = command.operand1 {
+                            data_align = data_align.max(boundary);
+                        }
+                        // The padding ends the symbol before it
+                        symbols.mark_end(SymbolSection::Data, data_address_counter);
+                    }
                     data_address_counter += padding;
                     data_address_counter += size;
                 }
@@ -311,12 +616,50 @@ pub fn assemble_program(commands: &[Command], macros: &[Macro], total_memory_siz
         }
     }
 
-    // Apply configured values or defaults after Pass 1
-    let actual_text_start = configured_text_start.unwrap_or(DEFAULT_TEXT_START);
-    let actual_stack_size = configured_stack_size.unwrap_or(DEFAULT_STACK_SIZE);
-    let actual_stack_start = configured_stack_start.unwrap_or_else(|| {
+    if let Some(undefined) = globals.iter().find(|name| symbols.get(name).is_none_or(|symbol| symbol.section == SymbolSection::External)) {
+        return Err(format!("Global symbol {} is not defined", undefined));
+    }
+    Ok(FirstPass { symbols, text_size: text_address_counter, data_size: data_address_counter, data_align, layout, globals })
+}
+
+/// Returns the comma-separated symbol names of a `.global` or `.extern`.
+fn symbol_names(command: &Command) -> Result<Vec<String>, String> {
+    let names: Vec<String> = command.raw_operands.as_deref().unwrap_or("")
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .collect();
+    if names.iter().any(|name| name.is_empty() || name.contains(char::is_whitespace)) {
+        return Err(command.error_at(format!("{} expects a comma-separated list of symbol names", command.opcode)));
+    }
+    Ok(names)
+}
+
+/// Chooses the addresses of the segments and checks that they fit in memory without overlapping.
+///
+/// The text segment starts at `.text_start` (default 0) and the stack at `.stack_start` (default
+/// the top of memory minus its size). The data segment starts at `data_start` if the layout sets
+/// one, and otherwise ends at the last multiple of `data_align` that leaves it room below the
+/// stack, below 0x10000 when memory is larger and it fits there, so `LODW` can reach it. Either way its start is a multiple of `data_align`, so the `.align` padding pass 1
+/// computes from section offsets is the padding of the absolute addresses as well.
+///
+/// # Arguments
+///
+/// * `layout` - The layout directives or linker script settings.
+/// * `text_size` - The size of the text segment in bytes.
+/// * `data_size` - The size of the data segment in bytes.
+/// * `data_align` - The largest alignment the data segment needs, a power of two.
+/// * `total_memory_size` - The size of the memory.
+///
+/// # Returns
+///
+/// * `Result<Segments, String>` - The addresses of the segments, or an error message.
+pub fn place_segments(layout: &SegmentLayout, text_size: u32, data_size: u32, data_align: u32, total_memory_size: usize) -> Result<Segments, String> {
+    // Apply configured values or defaults
+    let actual_text_start = layout.text_start.unwrap_or(DEFAULT_TEXT_START);
+    let actual_stack_size = layout.stack_size.unwrap_or(DEFAULT_STACK_SIZE);
+    let actual_stack_start = layout.stack_start.unwrap_or_else(|| {
         // Default stack starts at total_memory_size - actual_stack_size
-        (total_memory_size as u32).checked_sub(actual_stack_size).unwrap_or(0)
+        total_memory_size.saturating_sub(actual_stack_size as usize) as u32
     });
 
     // Validate segment boundaries
@@ -327,80 +670,91 @@ pub fn assemble_program(commands: &[Command], macros: &[Macro], total_memory_siz
         return Err(format!(".stack_start address (0x{:04X}) is outside total memory (0x{:04X}).", actual_stack_start, total_memory_size));
     }
     if (actual_stack_start as usize + actual_stack_size as usize) > total_memory_size {
-        return Err(format!("Stack segment (0x{:04X} - 0x{:04X}) exceeds total memory (0x{:04X}).", actual_stack_start, actual_stack_start + actual_stack_size, total_memory_size));
-    }
-
-    // Data segment starts right after the stack segment (growing downwards)
-    let actual_data_start = actual_stack_start.checked_sub(data_address_counter).unwrap_or(0);
-
-    // Ensure text and data don't overlap
-    if actual_text_start + text_address_counter > actual_data_start {
-        return Err(format!("Text segment (0x{:04X} - 0x{:04X}) overlaps with Data segment (0x{:04X}).", actual_text_start, actual_text_start + text_address_counter, actual_data_start));
+        return Err(format!("Stack segment (0x{:04X} - 0x{:04X}) exceeds total memory (0x{:04X}).", actual_stack_start, actual_stack_start as u64 + actual_stack_size as u64, total_memory_size));
     }
 
-    // Pass 2: Assemble
-    let mut expanded_commands = Vec::new();
-    for command in commands {
-        if let Some(macro_to_expand) = macros.iter().find(|m| m.name == command.opcode) {
-            let mut expanded_macro = macro_to_expand.body.clone();
-            for (i, arg) in macro_to_expand.args.iter().enumerate() {
-                let value = match i {
-                    0 => command.operand1.clone(),
-                    1 => command.operand2.clone(),
-                    _ => None,
-                };
-                for cmd in &mut expanded_macro {
-                    if let Some(op1) = &mut cmd.operand1 {
-                        if let Operand::Label(l) = op1 {
-                            if l == arg {
-                                *op1 = value.clone().ok_or_else(|| format!("Missing argument for macro parameter: {}", arg))?;
-                            }
-                        }
-                    }
-                    if let Some(op2) = &mut cmd.operand2 {
-                        if let Operand::Label(l) = op2 {
-                            if l == arg {
-                                *op2 = value.clone().ok_or_else(|| format!("Missing argument for macro parameter: {}", arg))?;
-                            }
-                        }
-                    }
+    let text_end = actual_text_start as u64 + text_size as u64;
+    let actual_data_start = match layout.data_start {
+        Some(data_start) => {
+            if data_start % data_align != 0 {
+                return Err(format!("Data segment start 0x{:04X} is not a multiple of its alignment ({} bytes).", data_start, data_align));
+            }
+            let data_end = data_start as u64 + data_size as u64;
+            if data_end > total_memory_size as u64 {
+                return Err(format!("Data segment (0x{:04X} - 0x{:04X}) exceeds total memory (0x{:04X}).", data_start, data_end, total_memory_size));
+            }
+            if data_size > 0 && (data_start as u64) < actual_stack_start as u64 + actual_stack_size as u64 && data_end > actual_stack_start as u64 {
+                return Err(format!("Data segment (0x{:04X} - 0x{:04X}) overlaps with Stack segment (0x{:04X}).", data_start, data_end, actual_stack_start));
+            }
+            if data_size > 0 && (actual_text_start as u64) < data_end && (data_start as u64) < text_end {
+                return Err(format!("Text segment (0x{:04X} - 0x{:04X}) overlaps with Data segment (0x{:04X}).", actual_text_start, text_end, data_start));
+            }
+            data_start
+        }
+        None => {
+            // Data segment ends below the stack segment (growing downwards), within reach of LODW
+            // unless it only fits right below the stack
+            let below = |end: u32| end.checked_sub(data_size).map(|start| start / data_align * data_align).filter(|&start| start as u64 >= text_end);
+            match below(actual_stack_start.min(DEFAULT_DATA_END)).or_else(|| below(actual_stack_start)) {
+                Some(actual_data_start) => actual_data_start,
+                None => {
+                    let actual_data_start = actual_stack_start.saturating_sub(data_size) / data_align * data_align;
+                    return Err(format!("Text segment (0x{:04X} - 0x{:04X}) overlaps with Data segment (0x{:04X}).", actual_text_start, text_end, actual_data_start));
                 }
             }
-            expanded_commands.extend(expanded_macro);
-        } else {
-            expanded_commands.push(command.clone());
         }
-    }
+    };
 
-    let mut assembled_program = AssembledProgram::default();
-    assembled_program.actual_text_start = actual_text_start;
-    assembled_program.actual_data_start = actual_data_start;
-    assembled_program.actual_stack_start = actual_stack_start;
-    assembled_program.actual_stack_size = actual_stack_size;
+    Ok(Segments { text_start: actual_text_start, data_start: actual_data_start, stack_start: actual_stack_start, stack_size: actual_stack_size })
+}
 
-    current_section = Section::Text;
-    let mut data_address_counter_pass2 = actual_data_start;
+/// Pass 2: assembles the commands into `program`, recording a listing line for every command.
+///
+/// # Arguments
+///
+/// * `commands` - The prepared commands.
+/// * `symbol_table` - The value of every symbol.
+/// * `text_start` - The address of the first instruction.
+/// * `data_start` - The address of the first data byte.
+/// * `assembled_program` - The program the code, data, source map and listing are added to.
+fn second_pass(commands: &[Command], symbol_table: &HashMap<String, u32>, text_start: u32, data_start: u32, assembled_program: &mut AssembledProgram) -> Result<(), String> {
+    let mut current_section = Section::Text;
+    let mut data_address_counter_pass2 = data_start;
 
-    for command in &expanded_commands {
+    for command in commands {
+        // Every command gets a listing line, which is filled in with the code it produces
+        assembled_program.listing.lines.push(ListingLine::new(command));
         if command.opcode == ".text" {
             current_section = Section::Text;
             continue;
         } else if command.opcode == ".data" {
             current_section = Section::Data;
             continue;
-        } else if command.opcode == ".equ" || command.opcode == ".text_start" || command.opcode == ".stack_start" || command.opcode == ".stack_size" {
+        } else if matches!(command.opcode.as_str(), ".equ" | ".text_start" | ".stack_start" | ".stack_size" | ".global" | ".extern") {
             continue;
         }
 
+        let address = match current_section {
+            Section::Text => text_start + 4 * assembled_program.text.len() as u32,
+            Section::Data => data_address_counter_pass2,
+        };
+        let listing_line = assembled_program.listing.lines.last_mut().expect("a listing line was just added");
+        if command.label.is_some() {
+            listing_line.address = Some(address);
+        }
+
         if command.opcode == ".align" {
             if current_section == Section::Data {
                 if let Some(Operand::Immediate(boundary)) = command.operand1 {
-                    let boundary = boundary as u32;
                     let padding = (boundary - (data_address_counter_pass2 % boundary)) % boundary;
                     for _ in 0..padding {
                         assembled_program.data.push(0);
                     }
                     data_address_counter_pass2 += padding;
+                    if padding > 0 {
+                        listing_line.address = Some(address);
+                        listing_line.code = ListingCode::Data(vec![0; padding as usize]);
+                    }
                 }
             }
             continue;
@@ -413,19 +767,30 @@ pub fn assemble_program(commands: &[Command], macros: &[Macro], total_memory_siz
         match current_section {
             Section::Text => {
                 if !command.opcode.starts_with('.') {
-                    let instruction = assemble_instruction(command, &symbol_table)?;
+                    let resolved = resolve_load_address(command, symbol_table).map_err(|e| command.error_at(e))?;
+                    let instruction = assemble_instruction(resolved.as_ref().unwrap_or(command), symbol_table).map_err(|e| command.error_at(e))?;
                     assembled_program.text.push(instruction);
+                    assembled_program.source_map.push(command.location.clone());
+                    listing_line.address = Some(address);
+                    listing_line.code = ListingCode::Instruction(instruction);
                 }
             }
             Section::Data => {
-                let data_bytes = assemble_data(command, &symbol_table)?;
+                let data_bytes = assemble_data(command, symbol_table).map_err(|e| command.error_at(e))?;
+                if command.opcode == ".space" && !data_bytes.is_empty() {
+                    assembled_program.reserved.push((data_address_counter_pass2, data_bytes.len() as u32));
+                }
+                if !data_bytes.is_empty() {
+                    listing_line.address = Some(address);
+                    listing_line.code = ListingCode::Data(data_bytes.clone());
+                }
                 data_address_counter_pass2 += data_bytes.len() as u32;
                 assembled_program.data.extend(data_bytes);
             }
         }
     }
 
-    Ok(assembled_program)
+    Ok(())
 }
 
 /// Determines the size of an instruction or data directive.
@@ -436,34 +801,28 @@ pub fn assemble_program(commands: &[Command], macros: &[Macro], total_memory_siz
 ///
 /// * `command` - The `Command` to evaluate.
 /// * `current_address` - The current address counter, used for `.align` directive.
+/// * `symbols` - The symbols defined so far; `.fill` counts may use the constants among them.
 ///
 /// # Returns
 ///
 /// * `Result<(u32, u32), String>` - A tuple containing `(size, padding)` on success,
 ///   or `Err(String)` if an unknown directive or invalid operand is encountered.
-fn get_instruction_or_data_size(command: &Command, current_address: u32) -> Result<(u32, u32), String> {
+fn get_instruction_or_data_size(command: &Command, current_address: u32, symbols: &SymbolTable) -> Result<(u32, u32), String> {
     if command.opcode.starts_with('.') { // Directive
         match command.opcode.as_str() {
-            ".text" | ".data" | ".equ" => Ok((0, 0)),
-            ".word" => Ok((4, 0)),
-            ".byte" => Ok((1, 0)),
-            ".string" => {
-                if let Some(Operand::String(s)) = &command.operand1 {
-                    Ok(((s.len() + 1) as u32, 0))
-                } else {
-                    Err(".string directive requires a string operand".to_string())
-                }
+            ".text" | ".data" | ".equ" | ".global" | ".extern" => Ok((0, 0)),
+            opcode if is_data_directive(opcode) => {
+                Ok((data_size(command, |name| symbols.constant(name).map(|value| value as i64))?, 0))
             }
             ".space" => {
                 if let Some(Operand::Immediate(value)) = command.operand1 {
-                    Ok((value as u32, 0))
+                    Ok((value, 0))
                 } else {
                     Err(".space directive requires an immediate value".to_string())
                 }
             }
             ".align" => {
                 if let Some(Operand::Immediate(boundary)) = command.operand1 {
-                    let boundary = boundary as u32;
                     if boundary == 0 || !boundary.is_power_of_two() {
                         return Err(".align boundary must be a power of two".to_string());
                     }
@@ -495,7 +854,7 @@ fn get_instruction_or_data_size(command: &Command, current_address: u32) -> Resu
 /// # Arguments
 ///
 /// * `command` - The `Command` representing the data directive.
-/// * `_symbol_table` - The symbol table (unused for data assembly, but required by signature).
+/// * `symbol_table` - The symbol table, for labels and constants used in values.
 ///
 /// # Returns
 ///
@@ -503,31 +862,8 @@ fn get_instruction_or_data_size(command: &Command, current_address: u32) -> Resu
 ///   or `Err(String)` if an unknown directive or invalid operand is encountered.
 fn assemble_data(command: &Command, symbol_table: &HashMap<String, u32>) -> Result<Vec<u8>, String> {
     match command.opcode.as_str() {
-        ".word" => {
-            let value = match command.operand1 {
-                Some(Operand::Immediate(value)) => value as u32,
-                Some(Operand::Label(ref label)) => {
-                    *symbol_table.get(label).ok_or(format!("Unknown label: {}", label))?
-                }
-                _ => return Err(".word directive requires an immediate value or a label".to_string()),
-            };
-            Ok(value.to_le_bytes().to_vec())
-        }
-        ".byte" => {
-            if let Some(Operand::Immediate(value)) = command.operand1 {
-                Ok(vec![value as u8])
-            } else {
-                Err(".byte directive requires an immediate value".to_string())
-            }
-        }
-        ".string" => {
-            if let Some(Operand::String(s)) = &command.operand1 {
-                let mut bytes = s.as_bytes().to_vec();
-                bytes.push(0x00); // Append null terminator
-                Ok(bytes)
-            } else {
-                Err(".string directive requires a string operand".to_string())
-            }
+        opcode if is_data_directive(opcode) => {
+            assemble_data_directive(command, |name| symbol_table.get(name).map(|&value| value as i64))
         }
         ".space" => {
             if let Some(Operand::Immediate(value)) = command.operand1 {
@@ -644,7 +980,7 @@ fn assemble_flag_op(opcode: u32, op1: Option<&Operand>) -> Result<u32, String> {
 /// # Returns
 ///
 /// * `Result<u8, String>` - The numeric representation of the register, or an error if the register is invalid.
-fn register_to_number(reg: &Reg) -> Result<u8, String> {
+pub(crate) fn register_to_number(reg: &Reg) -> Result<u8, String> {
     match reg {
         Reg::AX => Ok(0), Reg::BX => Ok(1), Reg::CX => Ok(2), Reg::DX => Ok(3),
         Reg::EX => Ok(4), Reg::FX => Ok(5), Reg::GX => Ok(6), Reg::HX => Ok(7),
@@ -658,7 +994,7 @@ fn assemble_reg_imm(opcode: u32, op1: Option<&Operand>, op2: Option<&Operand>, s
     if let Some(Operand::Register(reg)) = op1 {
         let reg_num = register_to_number(reg)?;
         let imm_value = match op2 {
-            Some(Operand::Immediate(imm)) => *imm as u32,
+            Some(Operand::Immediate(imm)) => *imm,
             Some(Operand::Label(label)) => *symbol_table.get(label).ok_or(format!("Unknown label: {}", label))?,
             _ => return Err("Invalid second operand for register-immediate instruction".to_string()),
         };
@@ -692,7 +1028,7 @@ fn assemble_reg_reg_or_reg_imm(opcode: u32, op1: Option<&Operand>, op2: Option<&
                 Ok((opcode << 24) | ((reg1_num as u32) << 16) | ((reg2_num as u32) << 8) | 1)
             }
             Some(Operand::Immediate(imm)) => {
-                Ok((opcode << 24) | ((reg1_num as u32) << 16) | (*imm as u32 & 0xFFFF))
+                Ok((opcode << 24) | ((reg1_num as u32) << 16) | (*imm & 0xFFFF))
             }
             _ => Err("Invalid second operand".to_string()),
         }
@@ -716,9 +1052,9 @@ fn assemble_addr(opcode: u32, op1: Option<&Operand>, symbol_table: &HashMap<Stri
     if let Some(operand) = op1 {
 
         let addr_val: u32 = match operand {
-            Operand::Address(addr) => *addr,
-            Operand::Immediate(imm) => *imm,
-            Operand::Label(label) => *symbol_table.get(label).ok_or(format!("Unknown label: {}", label))?,
+            Operand::Address(addr) => fit_address(*addr, 23)?,
+            Operand::Immediate(imm) => fit_address(*imm, 23)?,
+            Operand::Label(label) => fit_address(*symbol_table.get(label).ok_or(format!("Unknown label: {}", label))?, 23)?,
             Operand::AddressRegister(reg) => {
                 let reg_num = register_to_number(reg)?;
                 (1 << 23) | ((reg_num as u32) << 19)
@@ -743,7 +1079,7 @@ fn assemble_reg_addr(opcode: u32, op1: Option<&Operand>, op2: Option<&Operand>,
                 Operand::Label(label) => *symbol_table.get(label).ok_or(format!("Unknown label: {}", label))?,
                 _ => return Err("Invalid second operand for register-address instruction".to_string()),
             };
-            Ok((opcode << 24) | ((reg_num as u32) << 16) | (addr & 0xFFFF))
+            Ok((opcode << 24) | ((reg_num as u32) << 16) | fit_address(addr, 16)?)
         } else {
             Err("Missing second operand for register-address instruction".to_string())
         }
@@ -756,7 +1092,7 @@ fn assemble_reg_addr(opcode: u32, op1: Option<&Operand>, op2: Option<&Operand>,
 fn assemble_addr_imm(opcode: u32, op1: Option<&Operand>, op2: Option<&Operand>, symbol_table: &HashMap<String, u32>) -> Result<u32, String> {
     let addr_encoded = assemble_8bit_addr_or_reg_indirect(op1, symbol_table)?;
     let imm_value = match op2 {
-        Some(Operand::Immediate(imm)) => *imm as u32,
+        Some(Operand::Immediate(imm)) => *imm,
         None => 0, // Default to I/O slot 0 if not provided
         _ => return Err("Invalid second operand for address-immediate instruction. Expected immediate or none.".to_string()),
     };
@@ -772,10 +1108,10 @@ fn assemble_addr_reg_or_addr_imm(opcode: u32, op1: Option<&Operand>, op2: Option
             Ok((opcode << 24) | ((addr_encoded as u32) << 16) | ((reg_num as u32) << 8) | 1)
         }
         Some(Operand::Immediate(imm)) => {
-            Ok((opcode << 24) | ((addr_encoded as u32) << 16) | (*imm as u32 & 0xFFFF))
+            Ok((opcode << 24) | ((addr_encoded as u32) << 16) | (*imm & 0xFFFF))
         }
         None => { // Default to I/O slot 0 if not provided
-            Ok((opcode << 24) | ((addr_encoded as u32) << 16) | (0 & 0xFFFF))
+            Ok((opcode << 24) | ((addr_encoded as u32) << 16))
         }
         _ => Err("Invalid second operand. Expected register, immediate, or none.".to_string()),
     }
@@ -791,11 +1127,11 @@ fn assemble_reg_or_imm(opcode: u32, op1: Option<&Operand>, symbol_table: &HashMa
                 Ok((opcode << 24) | ((reg_num as u32) << 16) | 1)
             }
             Operand::Immediate(imm) => {
-                Ok((opcode << 24) | (*imm as u32 & 0xFFFF))
+                Ok((opcode << 24) | (*imm & 0xFFFF))
             }
             Operand::Label(label) => {
                 let imm_value = *symbol_table.get(label).ok_or(format!("Unknown label: {}", label))?;
-                Ok((opcode << 24) | (imm_value & 0xFFFF))
+                Ok((opcode << 24) | fit_address(imm_value, 16)?)
             }
             Operand::AddressRegister(reg) => { // NEW
                 let reg_num = register_to_number(reg)?;
@@ -818,6 +1154,7 @@ fn assemble_reg_or_imm(opcode: u32, op1: Option<&Operand>, symbol_table: &HashMa
 ///   - Bit 7 (MSB) is set to 1 to indicate register-indirect.
 ///   - Bits 6-3 encode the 4-bit register number.
 ///   - Bits 2-0 are unused (or can be used for a small offset if needed).
+///
 /// Encoding for direct address:
 ///   - Bit 7 (MSB) is 0.
 ///   - Bits 6-0 encode the 7-bit direct address.
@@ -834,18 +1171,18 @@ fn assemble_8bit_addr_or_reg_indirect(operand: Option<&Operand>, symbol_table: &
     if let Some(op) = operand {
 
         match op {
-            Operand::Address(addr) => Ok((*addr & 0x7F) as u8), // Direct 7-bit address
+            Operand::Address(addr) => Ok(fit_address(*addr, 7)? as u8), // Direct 7-bit address
             Operand::Label(label) => {
                 let addr = *symbol_table.get(label).ok_or(format!("Unknown label: {}", label))?;
-                Ok((addr & 0x7F) as u8) // Direct 7-bit address from label
+                Ok(fit_address(addr, 7)? as u8) // Direct 7-bit address from label
             }
             Operand::AddressRegister(reg) => {
                 let reg_num = register_to_number(reg)?;
                 // Encode register-indirect: bit 7 is flag, bits 6-3 are reg_num
-                Ok(((1 << 7) | (reg_num << 3)) as u8)
+                Ok((1 << 7) | (reg_num << 3))
             }
             Operand::Register(reg) => {
-                return Err(format!("Instruction does not support register direct addressing. Use an address or label, or define a new instruction for register-indirect I/O. Encountered register: {:?}", reg));
+                Err(format!("Instruction does not support register direct addressing. Use an address or label, or define a new instruction for register-indirect I/O. Encountered register: {:?}", reg))
             }
             _ => Err("Invalid operand for 8-bit address or register indirect".to_string()),
         }
@@ -854,12 +1191,40 @@ fn assemble_8bit_addr_or_reg_indirect(operand: Option<&Operand>, symbol_table: &
     }
 }
 
+/// Checks that a direct address fits in an instruction's address field.
+///
+/// # Arguments
+///
+/// * `address` - The address to encode.
+/// * `bits` - The width of the address field.
+///
+/// # Returns
+///
+/// * `Result<u32, String>` - The address, or an error if it needs more than `bits` bits.
+fn fit_address(address: u32, bits: u32) -> Result<u32, String> {
+    if address >> bits != 0 {
+        return Err(format!("Address 0x{:X} does not fit in {}-bit field.", address, bits));
+    }
+    Ok(address)
+}
+
 
 
 #[cfg(test)]
 mod command_processor_tests {
     use super::*;
     use crate::memory::registers::Reg;
+    use crate::utils::linker::object::{Relocation, RelocationField, RelocationTarget};
+
+    /// Defines a macro from its source lines, `.macro` and `.endmacro` included.
+    fn define(lines: &[&str]) -> Macro {
+        let lines: Vec<SourceLine> = lines.iter().enumerate()
+            .map(|(index, text)| SourceLine { location: SourceLocation { file: None, line: index + 1 }, text: text.to_string(), depth: 0 })
+            .collect();
+        let mut expander = MacroExpander::default();
+        expander.expand(&lines).unwrap();
+        expander.macros.pop().unwrap()
+    }
 
     #[test]
     fn test_parse_command_with_immediate() {
@@ -916,4 +1281,183 @@ mod command_processor_tests {
         assert_eq!(command.operand1, Some(Operand::Register(Reg::AX)));
         assert_eq!(command.operand2, Some(Operand::Register(Reg::BX)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_symbolize_uses_nearest_text_label() {
+        let commands: Vec<Command> = ["main: CALL helper", "HALT", "helper: INC AX", "RET"]
+            .iter()
+            .map(|line| parse_command(line).unwrap())
+            .collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        assert_eq!(program.symbolize(0x0), "0x0000 <main>");
+        assert_eq!(program.symbolize(0x4), "0x0004 <main+0x4>");
+        assert_eq!(program.symbolize(0xC), "0x000C <helper+0x4>");
+    }
+
+    #[test]
+    fn test_label_address_resolves_data_labels_absolutely() {
+        let commands: Vec<Command> = ["main: HALT", ".data", "first: .word 1", "second: .word 2"]
+            .iter()
+            .map(|line| parse_command(line).unwrap())
+            .collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        assert_eq!(program.label_address("first"), Some(program.actual_data_start));
+        assert_eq!(program.label_address("second"), Some(program.actual_data_start + 4));
+        assert_eq!(program.label_address("main"), Some(0));
+        assert_eq!(program.label_address("missing"), None);
+    }
+
+    #[test]
+    fn test_instructions_and_data_use_relocated_symbol_addresses() {
+        let commands: Vec<Command> = [
+            ".text_start 0x1000", "main: LODW AX, value", "JMP main", "LIMIT: .equ 3",
+            ".data", "value: .word 7", "pointer: .word value, main",
+        ]
+            .iter()
+            .map(|line| parse_command(line).unwrap())
+            .collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        let value = program.actual_data_start;
+        assert_eq!(program.label_address("main"), Some(0x1000));
+        assert_eq!(program.text[0] & 0xFFFF, value & 0xFFFF);
+        assert_eq!(program.text[1] & 0xFFFF, 0x1000);
+        assert_eq!(program.data[4..12], [value.to_le_bytes(), 0x1000u32.to_le_bytes()].concat());
+
+        let pointer = program.symbols.get("pointer").unwrap();
+        assert_eq!((pointer.section, pointer.address, pointer.size), (SymbolSection::Data, value + 4, 8));
+        assert_eq!(program.symbols.get("LIMIT").map(|symbol| (symbol.section, symbol.address)), Some((SymbolSection::Absolute, 3)));
+        assert!(program.symbols.dump().contains("0x00001000         8  text      main"));
+    }
+
+    #[test]
+    fn test_data_stays_within_reach_and_wider_addresses_are_rejected() {
+        let assemble = |lines: &[&str], memory_size: usize| {
+            let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+            assemble_program(&commands, &[], memory_size)
+        };
+        let program = assemble(&["main: LODW AX, w", "HALT", ".data", "w: .word 1"], 0x100000).unwrap();
+        assert_eq!(program.label_address("w"), Some(0xFFFC));
+        assert_eq!(program.text[0] & 0xFFFF, 0xFFFC);
+
+        // Too much data to fit below 0x10000, so it sits below the stack where LODW can't reach
+        let error = assemble(&["main: LODW AX, w", "HALT", ".data", "buf: .space 0x10000", "w: .word 1"], 0x100000).unwrap_err();
+        assert!(error.contains("does not fit in 16-bit field"), "{}", error);
+        let error = assemble(&["main: STRW buf, AX", "HALT", ".data", "buf: .word 0"], 0x10000).unwrap_err();
+        assert!(error.contains("does not fit in 7-bit field"), "{}", error);
+        let error = assemble(&["main: JMP 0x800000"], 0x10000).unwrap_err();
+        assert!(error.contains("does not fit in 23-bit field"), "{}", error);
+    }
+
+    #[test]
+    fn test_aligned_data_is_emitted_at_its_symbol_address() {
+        let commands: Vec<Command> = ["main: LODW AX, x", "HALT", ".data", "b: .byte 1", ".align 4", "x: .word 0x11223344", "c: .byte 2"]
+            .iter()
+            .map(|line| parse_command(line).unwrap())
+            .collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        let x = program.symbols.get("x").unwrap().address;
+        assert_eq!(x % 4, 0);
+        assert_eq!(program.actual_data_start % 4, 0);
+        let offset = (x - program.actual_data_start) as usize;
+        assert_eq!(program.data[offset..offset + 4], 0x11223344u32.to_le_bytes());
+        assert_eq!(program.text[0] & 0xFFFF, x & 0xFFFF);
+        assert_eq!(program.data.len(), 9);
+        assert_eq!(program.symbols.get("b").map(|symbol| symbol.size), Some(1));
+        assert_eq!(program.symbols.get("c").map(|symbol| symbol.address), Some(x + 4));
+    }
+
+    #[test]
+    fn test_space_buffers_are_reserved_and_symbolized() {
+        let commands: Vec<Command> = ["main: HALT", ".data", "count: .word 1", "buffer: .space 8"]
+            .iter()
+            .map(|line| parse_command(line).unwrap())
+            .collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        let buffer = program.label_address("buffer").unwrap();
+        assert_eq!(program.reserved, vec![(buffer, 8)]);
+        assert_eq!(program.symbolize_data(buffer + 2), format!("0x{:04X} <buffer+0x2>", buffer + 2));
+        assert_eq!(program.symbolize_data(program.actual_stack_start + 4), format!("0x{:04X} <stack+0x4>", program.actual_stack_start + 4));
+    }
+
+    #[test]
+    fn test_local_and_anonymous_labels_in_routines_and_macros() {
+        let parse = |lines: &[&str]| -> Vec<Command> { lines.iter().map(|line| parse_command(line).unwrap()).collect() };
+        let spin = define(&[".macro spin", "1: DEC AX", "JNE 1b", ".endmacro"]);
+        let commands = parse(&[
+            "first: JMP .loop", ".loop: spin", "JMP .loop",
+            "second: spin", ".loop: JMP 1f", "1: JMP first.loop",
+        ]);
+        let program = assemble_program(&commands, &[spin], 0x10000).unwrap();
+        assert_eq!(program.label_address("first.loop"), Some(0x4));
+        assert_eq!(program.label_address("second.loop"), Some(0x18));
+        assert_eq!(program.label_address("1@2"), Some(0x10));
+        // JMP .loop in the first routine, and the macro's JNE 1b in the second expansion
+        assert_eq!(program.text[0] & 0xFFFF, 0x4);
+        assert_eq!(program.text[5] & 0xFFFF, 0x10);
+        assert_eq!(program.text[6] & 0xFFFF, 0x1C);
+    }
+
+    #[test]
+    fn test_macro_expansions_are_sized_in_pass_one() {
+        let parse = |lines: &[&str]| -> Vec<Command> { lines.iter().map(|line| parse_command(line).unwrap()).collect() };
+        let fill = define(&[".macro fill reg, value=0", ".again: MOVI reg, value", "DEC CX", "JNE .again", ".endmacro"]);
+        let commands = parse(&["fill AX", "fill reg=BX, value=7", "after: JMP after"]);
+        let program = assemble_program(&commands, &[fill], 0x10000).unwrap();
+        assert_eq!(program.label_address("after"), Some(0x18));
+        assert_eq!(program.label_address("fill.again@2"), Some(0xC));
+        // The second expansion jumps back to its own private label
+        assert_eq!(program.text[5] & 0xFFFF, 0xC);
+    }
+
+    #[test]
+    fn test_conditional_blocks_are_assembled_after_macro_expansion() {
+        let parse = |lines: &[&str]| -> Vec<Command> { lines.iter().map(|line| parse_command(line).unwrap()).collect() };
+        let trace = define(&[".macro trace", ".ifdef DEBUG", "NOT AX", ".endif", ".endmacro"]);
+        let commands = parse(&[
+            "DEBUG: .equ 1", "trace", ".if DEBUG > 1", "end: INC AX", ".else", "end: DEC AX", ".endif", "HALT",
+        ]);
+        let program = assemble_program(&commands, &[trace], 0x10000).unwrap();
+        assert_eq!(program.text.len(), 3);
+        assert_eq!(program.label_address("end"), Some(0x4));
+        assert!(assemble_program(&parse(&[".if 1", "HALT"]), &[], 0x10000).is_err());
+    }
+
+    #[test]
+    fn test_source_locations_reach_the_source_map_and_errors() {
+        let located = |line: &str, number: usize| {
+            let mut command = parse_command(line).unwrap();
+            command.location = Some(SourceLocation { file: Some("lib/util.arc".into()), line: number });
+            command
+        };
+        let program = assemble_program(&[located("INC AX", 3), located("HALT", 4)], &[], 0x10000).unwrap();
+        assert_eq!(program.source_location(0x4).map(|l| l.to_string()), Some("lib/util.arc:4".to_string()));
+        assert_eq!(program.source_location(0x8), None);
+
+        let error = assemble_program(&[located("JMP nowhere", 7)], &[], 0x10000).unwrap_err();
+        assert_eq!(error, "lib/util.arc:7: Unknown label: nowhere");
+    }
+
+    #[test]
+    fn test_objects_record_relocations_and_externs_need_linking() {
+        let parse = |lines: &[&str]| -> Vec<Command> { lines.iter().map(|line| parse_command(line).unwrap()).collect() };
+        let commands = parse(&[
+            ".global main", ".extern helper", "main: CALL helper", "JMP main",
+            ".data", "size: .word end - table", "table: .word table + 4", "end: .byte 0",
+        ]);
+        let object = assemble_object(&commands, &[], "main.arc").unwrap();
+        assert_eq!(object.externs, vec!["helper".to_string()]);
+        assert!(object.symbols.iter().any(|symbol| symbol.name == "main" && symbol.global));
+        assert_eq!(object.relocations, vec![
+            Relocation { section: SymbolSection::Text, offset: 0, field: RelocationField::Address24, target: RelocationTarget::Symbol("helper".into()), addend: 0 },
+            Relocation { section: SymbolSection::Text, offset: 4, field: RelocationField::Address24, target: RelocationTarget::Section(SymbolSection::Text), addend: 0 },
+            Relocation { section: SymbolSection::Data, offset: 4, field: RelocationField::Word, target: RelocationTarget::Section(SymbolSection::Data), addend: 8 },
+        ]);
+        // `end - table` does not move, so it is assembled in place
+        assert_eq!(object.data[0..4], 4u32.to_le_bytes());
+
+        let error = assemble_program(&commands, &[], 0x10000).unwrap_err();
+        assert!(error.contains("helper can only be resolved by linking"));
+        assert!(assemble_object(&parse(&[".global missing", "HALT"]), &[], "a.arc").unwrap_err().contains("missing is not defined"));
+        assert!(assemble_object(&parse(&["main: HALT", ".data", "x: .word main * 2"]), &[], "a.arc").is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! # Conditionals Module
+//!
+//! This module implements conditional assembly. Blocks opened by `.if EXPR`,
+//! `.ifdef NAME` or `.ifndef NAME` and closed by `.endif`, with optional
+//! `.elif EXPR` and `.else` branches, keep only the lines of the branch
+//! whose condition holds. Conditions are evaluated against the `.equ`
+//! constants defined before them, which include the `-D NAME=VALUE` defines
+//! given on the command line. Blocks can be nested.
+//!
+//! The macro expander evaluates conditionals as it reads the source, so an
+//! `.include` or a `.macro` in a branch that is not taken is never read.
+//! Blocks in a macro or repetition body are evaluated in each expansion.
+
+use std::collections::HashMap;
+
+use crate::utils::assembler::command_processor::Command;
+use crate::utils::assembler::expressions::evaluate;
+use crate::utils::assembler::operands::Operand;
+use crate::utils::assembler::source::SourceLocation;
+
+/// An open conditional block.
+struct Block {
+    /// The `.if`, `.ifdef` or `.ifndef` that opened the block.
+    opening: String,
+    /// Where the block was opened, if known.
+    location: Option<SourceLocation>,
+    /// Whether the code around the block is assembled.
+    parent_active: bool,
+    /// Whether a branch of the block has already been taken.
+    taken: bool,
+    /// Whether the current branch is assembled.
+    active: bool,
+    /// Whether the `.else` branch has started.
+    in_else: bool,
+}
+
+/// The conditional blocks open at a point of a program.
+#[derive(Default)]
+pub struct Conditions {
+    /// The open blocks, innermost last.
+    blocks: Vec<Block>,
+}
+
+impl Conditions {
+    /// Returns whether the code at this point is assembled.
+    pub fn active(&self) -> bool {
+        self.blocks.last().is_none_or(|block| block.active)
+    }
+
+    /// Applies a conditional directive.
+    ///
+    /// Conditions in branches that are not taken are not evaluated, so they may refer to
+    /// symbols that are not defined.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - The opcode of the line.
+    /// * `operands` - The operands of the line, as written.
+    /// * `location` - Where the line comes from, if known.
+    /// * `symbols` - Returns the value of a constant defined so far.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, String>` - Whether `opcode` is a conditional directive, or an error message
+    ///   for an invalid condition or a directive without its `.if`.
+    pub fn apply(&mut self, opcode: &str, operands: &str, location: Option<&SourceLocation>, symbols: impl Fn(&str) -> Option<i64>) -> Result<bool, String> {
+        let active = self.active();
+        match opcode {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let condition = active && condition(opcode, operands, symbols)?;
+                self.blocks.push(Block { opening: opcode.to_string(), location: location.cloned(), parent_active: active, taken: condition, active: condition, in_else: false });
+            }
+            ".elif" => {
+                let block = self.open_block(opcode)?;
+                let condition = block.parent_active && !block.taken && condition(opcode, operands, symbols)?;
+                block.active = condition;
+                block.taken |= condition;
+            }
+            ".else" => {
+                let block = self.open_block(opcode)?;
+                block.active = block.parent_active && !block.taken;
+                block.taken = true;
+                block.in_else = true;
+            }
+            ".endif" => {
+                self.blocks.pop().ok_or_else(|| ".endif without .if".to_string())?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Checks that every block has been closed.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error message with the location of the innermost open block.
+    pub fn finish(&self) -> Result<(), String> {
+        match self.blocks.last() {
+            Some(Block { opening, location: Some(location), .. }) => Err(format!("{}: {} without .endif", location, opening)),
+            Some(Block { opening, .. }) => Err(format!("{} without .endif", opening)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the innermost open block for an `.elif` or `.else`, checking that it has no `.else` yet.
+    fn open_block(&mut self, opcode: &str) -> Result<&mut Block, String> {
+        match self.blocks.last_mut() {
+            None => Err(format!("{} without .if", opcode)),
+            Some(block) if block.in_else => Err(format!("{} after .else", opcode)),
+            Some(block) => Ok(block),
+        }
+    }
+}
+
+/// Removes the commands of the branches of conditional blocks that are not taken.
+///
+/// # Arguments
+///
+/// * `commands` - The commands of the program.
+///
+/// # Returns
+///
+/// * `Result<Vec<Command>, String>` - The commands that are assembled, without the conditional
+///   directives, or an error message for an invalid condition or an unbalanced block.
+pub fn apply_conditionals(commands: &[Command]) -> Result<Vec<Command>, String> {
+    let mut symbols: HashMap<&str, i64> = HashMap::new();
+    let mut conditions = Conditions::default();
+    let mut kept = Vec::new();
+    for command in commands {
+        let operands = command.raw_operands.as_deref().unwrap_or("");
+        if conditions.apply(&command.opcode, operands, command.location.as_ref(), |name| symbols.get(name).copied()).map_err(|e| command.error_at(e))? {
+            continue;
+        }
+        if conditions.active() {
+            if let (".equ", Some(label), Some(Operand::Immediate(value))) = (command.opcode.as_str(), &command.label, &command.operand1) {
+                symbols.insert(label, *value as i64);
+            }
+            kept.push(command.clone());
+        }
+    }
+    conditions.finish()?;
+    Ok(kept)
+}
+
+/// Evaluates the condition of an `.if`, `.elif`, `.ifdef` or `.ifndef`.
+fn condition(opcode: &str, operands: &str, symbols: impl Fn(&str) -> Option<i64>) -> Result<bool, String> {
+    match opcode {
+        ".ifdef" | ".ifndef" => {
+            let mut names = operands.split_whitespace();
+            let (Some(name), None) = (names.next(), names.next()) else {
+                return Err(format!("{} expects a single symbol name", opcode));
+            };
+            Ok(symbols(name).is_some() == (opcode == ".ifdef"))
+        }
+        _ => evaluate(operands, symbols).map(|value| value != 0),
+    }
+}
+
+/// Parses a command-line define of the form `NAME` (which defines it as 1) or `NAME=VALUE`.
+///
+/// # Returns
+///
+/// * `Result<(String, u32), String>` - The name and value, or an error message.
+pub fn parse_define(text: &str) -> Result<(String, u32), String> {
+    let (name, value) = match text.split_once('=') {
+        Some((name, value)) => (name.trim(), evaluate(value, |_| None)?),
+        None => (text.trim(), 1),
+    };
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("Invalid symbol name in define: {}", name));
+    }
+    Ok((name.to_string(), value as u32))
+}
+
+#[cfg(test)]
+mod conditionals_test {
+    use super::*;
+    use crate::utils::assembler::command_processor::parse_command;
+
+    fn kept(lines: &[&str]) -> Result<Vec<String>, String> {
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        Ok(apply_conditionals(&commands)?.into_iter().map(|command| command.opcode).collect())
+    }
+
+    #[test]
+    fn only_the_taken_branches_are_kept() {
+        let lines = [
+            "LEVEL: .equ 2",
+            ".if LEVEL > 2", "INC", ".elif LEVEL == 2", "DEC",
+                ".ifdef DEBUG", "OUT", ".if UNDEFINED", ".endif", ".else", "NEG", ".endif",
+            ".else", "NOT", ".endif",
+            ".ifndef LEVEL", "HALT", ".endif",
+        ];
+        assert_eq!(kept(&lines).unwrap(), vec![".equ", "DEC", "NEG"]);
+        assert_eq!(kept(&[".if 0", "INC", ".elif 1", "DEC", ".elif 1", "NEG", ".endif"]).unwrap(), vec!["DEC"]);
+    }
+
+    #[test]
+    fn unbalanced_blocks_and_bad_conditions_are_errors() {
+        assert_eq!(kept(&[".if 1", "INC"]).unwrap_err(), ".if without .endif");
+        assert_eq!(kept(&[".endif"]).unwrap_err(), ".endif without .if");
+        assert_eq!(kept(&[".else"]).unwrap_err(), ".else without .if");
+        assert_eq!(kept(&[".if 1", ".else", ".elif 1", ".endif"]).unwrap_err(), ".elif after .else");
+        assert_eq!(kept(&[".if MISSING", ".endif"]).unwrap_err(), "Unknown symbol: MISSING");
+        assert!(kept(&[".ifdef A B", ".endif"]).is_err());
+    }
+
+    #[test]
+    fn defines_parse_names_and_values() {
+        assert_eq!(parse_define("DEBUG"), Ok(("DEBUG".to_string(), 1)));
+        assert_eq!(parse_define("LEVEL=0x10"), Ok(("LEVEL".to_string(), 16)));
+        assert_eq!(parse_define("NEG=-1"), Ok(("NEG".to_string(), u32::MAX)));
+        assert!(parse_define("1X=2").is_err());
+        assert!(parse_define("X=Y").is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! # Data Module
+//!
+//! This module assembles the data directives that take a list of values:
+//!
+//! | Directive                       | Emits                                              |
+//! |---------------------------------|----------------------------------------------------|
+//! | `.word V, ...`                  | 32-bit little-endian words                         |
+//! | `.half V, ...`                  | 16-bit little-endian halfwords                     |
+//! | `.byte V, ...`                  | bytes                                              |
+//! | `.float F, ...`                 | 32-bit IEEE 754 floats                             |
+//! | `.ascii "S", ...`               | the bytes of the strings                           |
+//! | `.asciz "S", ...` / `.string`   | the bytes of the strings, each followed by a 0     |
+//! | `.fill COUNT[, SIZE[, VALUE]]`  | `COUNT` copies of a `SIZE`-byte (1, 2 or 4) value  |
+//! | `.incbin "FILE"`                | the contents of a file                             |
+//!
+//! Values are expressions over numbers, `.equ` constants and labels. Strings
+//! understand the escapes `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\'` and `\xNN`.
+//! `.incbin` files are looked up relative to the file containing the directive.
+
+use std::path::PathBuf;
+
+use crate::utils::assembler::command_processor::Command;
+use crate::utils::assembler::expressions::evaluate;
+use crate::utils::assembler::macros::split_arguments;
+
+/// Returns `true` if `opcode` is one of the directives assembled by this module.
+pub fn is_data_directive(opcode: &str) -> bool {
+    matches!(opcode, ".word" | ".half" | ".byte" | ".float" | ".ascii" | ".asciz" | ".string" | ".fill" | ".incbin")
+}
+
+/// Computes the number of bytes a data directive emits, without resolving labels.
+///
+/// # Arguments
+///
+/// * `command` - The data directive.
+/// * `resolve` - Returns the value of a constant; `.fill` counts and sizes may use constants.
+///
+/// # Returns
+///
+/// * `Result<u32, String>` - The size in bytes, or an error message for invalid operands.
+pub fn data_size(command: &Command, resolve: impl Fn(&str) -> Option<i64>) -> Result<u32, String> {
+    let values = values(command)?;
+    let size = match command.opcode.as_str() {
+        ".word" | ".float" => 4 * values.len(),
+        ".half" => 2 * values.len(),
+        ".byte" => values.len(),
+        ".fill" => {
+            let (count, size) = fill_shape(command, &values, &resolve)?;
+            count * size
+        }
+        // Strings and files are assembled in full, as they do not depend on symbols
+        _ => return assemble_data_directive(command, resolve).map(|bytes| bytes.len() as u32),
+    };
+    Ok(size as u32)
+}
+
+/// Assembles a data directive into bytes.
+///
+/// # Arguments
+///
+/// * `command` - The data directive.
+/// * `resolve` - Returns the value of a label or constant.
+///
+/// # Returns
+///
+/// * `Result<Vec<u8>, String>` - The bytes, or an error message for an invalid operand, a value
+///   that does not fit or a file that cannot be read.
+pub fn assemble_data_directive(command: &Command, resolve: impl Fn(&str) -> Option<i64>) -> Result<Vec<u8>, String> {
+    let values = values(command)?;
+    let mut bytes = Vec::new();
+    match command.opcode.as_str() {
+        ".word" | ".half" | ".byte" => {
+            let width = match command.opcode.as_str() {
+                ".word" => 4,
+                ".half" => 2,
+                _ => 1,
+            };
+            for value in &values {
+                let number = evaluate(value, &resolve)?;
+                bytes.extend(encode(number, width).ok_or(format!("{} value out of range: {}", command.opcode, value))?);
+            }
+        }
+        ".float" => {
+            for value in &values {
+                let number: f32 = value.parse().map_err(|_| format!("Invalid float: {}", value))?;
+                bytes.extend(number.to_le_bytes());
+            }
+        }
+        ".ascii" | ".asciz" | ".string" => {
+            for value in &values {
+                bytes.extend(parse_string(value)?);
+                if command.opcode != ".ascii" {
+                    bytes.push(0);
+                }
+            }
+        }
+        ".fill" => {
+            let (count, size) = fill_shape(command, &values, &resolve)?;
+            let value = match values.get(2) {
+                Some(value) => evaluate(value, &resolve)?,
+                None => 0,
+            };
+            let pattern = encode(value, size).ok_or(format!(".fill value out of range: {}", value))?;
+            for _ in 0..count {
+                bytes.extend(&pattern);
+            }
+        }
+        ".incbin" => {
+            let [file] = values.as_slice() else {
+                return Err(".incbin expects a quoted file name".to_string());
+            };
+            let name = String::from_utf8_lossy(&parse_string(file)?).into_owned();
+            let path = incbin_path(command, &name);
+            bytes = std::fs::read(&path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        }
+        _ => return Err(format!("Unknown data directive: {}", command.opcode)),
+    }
+    Ok(bytes)
+}
+
+/// Returns the width in bytes and the values of a `.word`, `.half` or `.byte` list.
+///
+/// # Returns
+///
+/// * `Option<Result<(usize, Vec<String>), String>>` - `None` for other directives, otherwise the
+///   width and the value expressions, or an error message for an empty list.
+pub fn list_values(command: &Command) -> Option<Result<(usize, Vec<String>), String>> {
+    let width = match command.opcode.as_str() {
+        ".word" => 4,
+        ".half" => 2,
+        ".byte" => 1,
+        _ => return None,
+    };
+    Some(values(command).map(|values| (width, values)))
+}
+
+/// Returns the comma-separated operands of a data directive, requiring at least one.
+fn values(command: &Command) -> Result<Vec<String>, String> {
+    let values = split_arguments(command.raw_operands.as_deref().unwrap_or(""));
+    if values.is_empty() || values.iter().any(String::is_empty) {
+        return Err(format!("{} expects a comma-separated list of values", command.opcode));
+    }
+    Ok(values)
+}
+
+/// Returns the count and the element size of a `.fill`.
+fn fill_shape(command: &Command, values: &[String], resolve: &impl Fn(&str) -> Option<i64>) -> Result<(usize, usize), String> {
+    if values.len() > 3 {
+        return Err(format!("{} expects COUNT[, SIZE[, VALUE]]", command.opcode));
+    }
+    let count = evaluate(&values[0], resolve)?;
+    let size = match values.get(1) {
+        Some(size) => evaluate(size, resolve)?,
+        None => 1,
+    };
+    if count < 0 {
+        return Err(format!(".fill count cannot be negative: {}", count));
+    }
+    if !matches!(size, 1 | 2 | 4) {
+        return Err(format!(".fill size must be 1, 2 or 4, got {}", size));
+    }
+    Ok((count as usize, size as usize))
+}
+
+/// Encodes a value in `width` little-endian bytes, accepting both signed and unsigned values
+/// that fit.
+fn encode(value: i64, width: usize) -> Option<Vec<u8>> {
+    let bits = 8 * width as u32;
+    let fits = value >= -(1i64 << (bits - 1)) && value < (1i64 << bits);
+    fits.then(|| value.to_le_bytes()[..width].to_vec())
+}
+
+/// Finds the file of an `.incbin`, relative to the file containing the directive.
+fn incbin_path(command: &Command, name: &str) -> PathBuf {
+    let directory = command.location.as_ref()
+        .and_then(|location| location.file.as_ref())
+        .and_then(|file| file.parent());
+    match directory {
+        Some(directory) => directory.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Parses a double-quoted string literal into bytes, processing its escapes.
+pub fn parse_string(literal: &str) -> Result<Vec<u8>, String> {
+    let inner = literal.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+        .ok_or(format!("Expected a double-quoted string, got: {}", literal))?;
+    unescape(inner)
+}
+
+/// Replaces the escape sequences of the text of a string or character literal.
+pub fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        let escaped = chars.next().ok_or("Unterminated escape sequence".to_string())?;
+        bytes.push(match escaped {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            '0' => 0,
+            '\\' | '"' | '\'' => escaped as u8,
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&digits, 16).ok().filter(|_| digits.len() == 2)
+                    .ok_or(format!("Invalid escape sequence: \\x{}", digits))?
+            }
+            other => return Err(format!("Invalid escape sequence: \\{}", other)),
+        });
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod data_test {
+    use super::*;
+    use crate::utils::assembler::command_processor::parse_command;
+
+    fn assemble(line: &str) -> Result<Vec<u8>, String> {
+        let command = parse_command(line).unwrap();
+        let bytes = assemble_data_directive(&command, |name| (name == "BASE").then_some(0x100))?;
+        assert_eq!(data_size(&command, |_| None).ok(), Some(bytes.len() as u32), "size of {}", line);
+        Ok(bytes)
+    }
+
+    #[test]
+    fn lists_of_numbers_and_floats() {
+        assert_eq!(assemble(".word 1, BASE + 2, -1").unwrap(), vec![1, 0, 0, 0, 2, 1, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(assemble(".half 0x1234, -2").unwrap(), vec![0x34, 0x12, 0xFE, 0xFF]);
+        assert_eq!(assemble(".byte 1, 'A', '\\n', 255, -128").unwrap(), vec![1, 65, 10, 255, 0x80]);
+        assert_eq!(assemble(".float 1.5, -2").unwrap(), [1.5f32.to_le_bytes(), (-2.0f32).to_le_bytes()].concat());
+        assert!(assemble(".byte 256").unwrap_err().contains("out of range"));
+        assert!(assemble(".word").is_err());
+        assert!(assemble(".word 1,").is_err());
+        assert!(assemble(".float pi").is_err());
+    }
+
+    #[test]
+    fn strings_escapes_and_fill() {
+        assert_eq!(assemble(".ascii \"a,b\", \"\\t\\x41\\\"\"").unwrap(), b"a,b\tA\"".to_vec());
+        assert_eq!(assemble(".asciz \"hi\", \"\"").unwrap(), b"hi\0\0".to_vec());
+        assert_eq!(assemble(".string \"x;y\\0\" ; comment").unwrap(), b"x;y\0\0".to_vec());
+        assert!(assemble(".ascii \"\\q\"").unwrap_err().contains("Invalid escape"));
+        assert!(assemble(".ascii plain").is_err());
+        assert_eq!(assemble(".fill 3").unwrap(), vec![0, 0, 0]);
+        assert_eq!(assemble(".fill 2, 2, 0xABCD").unwrap(), vec![0xCD, 0xAB, 0xCD, 0xAB]);
+        assert!(assemble(".fill 1, 3").unwrap_err().contains("1, 2 or 4"));
+    }
+
+    #[test]
+    fn incbin_reads_files_next_to_the_source() {
+        let directory = std::env::temp_dir().join(format!("arcs-incbin-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("logo.bin"), [1, 2, 3]).unwrap();
+        let mut command = parse_command(".incbin \"logo.bin\"").unwrap();
+        command.location = Some(crate::utils::assembler::source::SourceLocation { file: Some(directory.join("main.arc")), line: 1 });
+        assert_eq!(assemble_data_directive(&command, |_| None).unwrap(), vec![1, 2, 3]);
+        assert_eq!(data_size(&command, |_| None).unwrap(), 3);
+        assert!(assemble(".incbin \"missing.bin\"").unwrap_err().contains("Error reading"));
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
@@ -0,0 +1,416 @@
+//! # Disassembler Module
+//!
+//! This module turns 32-bit instruction words back into assembly. `decode`
+//! splits a word into its mnemonic and operands, recovering the operand forms
+//! the assembler encodes (registers, immediates, direct addresses and
+//! register-indirect `[REG]` addresses), and the text it formats assembles
+//! back to the same word. Addresses that a symbol table names are shown as
+//! their labels, and `disassemble_range` can mark the sequences that match the
+//! expansion of a pseudo-instruction with a comment naming it. A word alone
+//! cannot tell `CLR AX` from a hand-written `XOR AX, AX`, so this is opt-in.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::chips::cpu::number_to_register;
+use crate::memory::registers::Reg;
+use crate::utils::assembler::operands::Operand;
+use crate::utils::assembler::pseudo::recognize;
+use crate::utils::assembler::symbol_table::{SymbolSection, SymbolTable};
+
+/// A decoded instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// The instruction word.
+    pub word: u32,
+    /// The opcode, the top byte of the word.
+    pub opcode: u8,
+    /// The mnemonic, in upper case.
+    pub mnemonic: &'static str,
+    /// The operands, in source order.
+    pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+    /// Formats the instruction, naming addresses after the labels defined at them.
+    ///
+    /// # Arguments
+    ///
+    /// * `labels` - The labels to show in place of addresses.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The instruction as it would be written in a source file.
+    pub fn format(&self, labels: &Labels) -> String {
+        let operands: Vec<String> = self.operands.iter().map(|operand| self.format_operand(operand, labels)).collect();
+        if operands.is_empty() {
+            self.mnemonic.to_string()
+        } else {
+            format!("{} {}", self.mnemonic, operands.join(", "))
+        }
+    }
+
+    fn format_operand(&self, operand: &Operand, labels: &Labels) -> String {
+        match operand {
+            Operand::Register(reg) => register_name(reg).to_string(),
+            Operand::AddressRegister(reg) => format!("[{}]", register_name(reg)),
+            Operand::Immediate(value) => format!("0x{:04X}", value),
+            Operand::Address(address) => {
+                let (digits, labelled) = match address_field(self.opcode) {
+                    AddressField::Bits24 => (6, true),
+                    AddressField::Bits16 => (4, true),
+                    AddressField::Bits7 => (2, false),
+                };
+                match labels.get(*address).filter(|_| labelled) {
+                    Some(label) => label.to_string(),
+                    None if is_branch(self.opcode) => format!("0x{:0digits$X}", address),
+                    None => format!("[0x{:0digits$X}]", address),
+                }
+            }
+            Operand::Flag(flag) => flag_name(*flag).unwrap_or("?").to_string(),
+            Operand::Label(name) => name.clone(),
+            Operand::String(text) => format!("\"{}\"", text),
+            Operand::None => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(&Labels::default()))
+    }
+}
+
+/// The labels of a program by address, for naming jump targets and memory operands.
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    by_address: HashMap<u32, String>,
+}
+
+impl Labels {
+    /// Collects the text and data labels of a symbol table. When several labels share an
+    /// address, the first one defined is used.
+    pub fn new(symbols: &SymbolTable) -> Self {
+        let mut by_address = HashMap::new();
+        for symbol in symbols.symbols().iter().filter(|symbol| matches!(symbol.section, SymbolSection::Text | SymbolSection::Data)) {
+            by_address.entry(symbol.address).or_insert_with(|| symbol.name.clone());
+        }
+        Self { by_address }
+    }
+
+    /// Returns the label defined at an address.
+    pub fn get(&self, address: u32) -> Option<&str> {
+        self.by_address.get(&address).map(String::as_str)
+    }
+}
+
+/// The width of the address field of an instruction.
+enum AddressField {
+    /// Bits 0-23, or `[REG]` when bit 23 is set (jumps, `CALL`, `IN`, `OUT`, `INSI`, `PUSH`, `OUTI`).
+    Bits24,
+    /// Bits 0-15 (`LODW`).
+    Bits16,
+    /// Bits 16-22, or `[REG]` when bit 23 is set (`STRI`, `STRW`, `INSW`, `OUTW`).
+    Bits7,
+}
+
+fn address_field(opcode: u8) -> AddressField {
+    match opcode {
+        0x04 => AddressField::Bits16,
+        0x05 | 0x06 | 0x54 | 0x55 => AddressField::Bits7,
+        _ => AddressField::Bits24,
+    }
+}
+
+fn is_branch(opcode: u8) -> bool {
+    matches!(opcode, 0x40 | 0x41 | 0x43..=0x4A)
+}
+
+/// Decodes an instruction word.
+///
+/// # Arguments
+///
+/// * `word` - The instruction word.
+///
+/// # Returns
+///
+/// * `Result<Instruction, String>` - The instruction, or an error message for an unknown opcode,
+///   register or flag.
+pub fn decode(word: u32) -> Result<Instruction, String> {
+    let opcode = (word >> 24) as u8;
+    let register = |shift: u32| number_to_register(((word >> shift) & 0xFF) as u8).map(Operand::Register);
+    let immediate = Operand::Immediate(word & 0xFFFF);
+    let register_or_immediate = || if word & 1 == 1 { register(8) } else { Ok(immediate.clone()) };
+    // 24-bit addresses with bit 23 set hold a register in bits 19-22
+    let address24 = || if (word >> 23) & 1 == 1 {
+        number_to_register(((word >> 19) & 0xF) as u8).map(Operand::AddressRegister)
+    } else {
+        Ok(Operand::Address(word & 0xFF_FFFF))
+    };
+    // 8-bit address fields with bit 7 set hold a register in bits 3-6
+    let address7 = || {
+        let field = (word >> 16) & 0xFF;
+        if field & 0x80 != 0 {
+            number_to_register(((field >> 3) & 0xF) as u8).map(Operand::AddressRegister)
+        } else {
+            Ok(Operand::Address(field & 0x7F))
+        }
+    };
+    // PUSH and OUTI take a register (bit 0), a `[REG]` address (bit 23) or a 16-bit immediate
+    let value = || if word & 1 == 1 {
+        register(16)
+    } else if (word >> 23) & 1 == 1 {
+        address24()
+    } else {
+        Ok(immediate.clone())
+    };
+
+    let (mnemonic, operands) = match opcode {
+        0x01 => ("MOVI", vec![register(16)?, immediate.clone()]),
+        0x02 => ("MOVW", vec![register(16)?, register_or_immediate()?]),
+        0x03 => ("LODI", vec![register(16)?, immediate.clone()]),
+        0x04 => ("LODW", vec![register(16)?, Operand::Address(word & 0xFFFF)]),
+        0x05 => ("STRI", vec![address7()?, immediate.clone()]),
+        0x06 => ("STRW", vec![address7()?, register_or_immediate()?]),
+        0x07 => ("PUSH", vec![value()?]),
+        0x08 => ("POP", vec![register(16)?]),
+        0x09 => ("XCGH", vec![register(16)?, register(8)?]),
+        0x10 => ("ADDW", vec![register(16)?, register_or_immediate()?]),
+        0x11 => ("SUBW", vec![register(16)?, register_or_immediate()?]),
+        0x12 => ("MUL", vec![register(16)?, register_or_immediate()?]),
+        0x13 => ("INC", vec![register(16)?]),
+        0x14 => ("DEC", vec![register(16)?]),
+        0x15 => ("NEG", vec![register(16)?]),
+        0x20 => ("NOT", vec![register(16)?]),
+        0x21 => ("AND", vec![register(16)?, register_or_immediate()?]),
+        0x22 => ("OR", vec![register(16)?, register_or_immediate()?]),
+        0x23 => ("XOR", vec![register(16)?, register_or_immediate()?]),
+        0x24 => ("SHL", vec![register(16)?, register_or_immediate()?]),
+        0x25 => ("SHR", vec![register(16)?, register_or_immediate()?]),
+        0x30 => ("CMPW", vec![register(16)?, register_or_immediate()?]),
+        0x40 => ("JMP", vec![address24()?]),
+        0x41 => ("CALL", vec![address24()?]),
+        0x42 => ("RET", vec![]),
+        0x43 => ("JE", vec![address24()?]),
+        0x44 => ("JNE", vec![address24()?]),
+        0x45 => ("JGT", vec![address24()?]),
+        0x46 => ("JGE", vec![address24()?]),
+        0x47 => ("JLT", vec![address24()?]),
+        0x48 => ("JLE", vec![address24()?]),
+        0x49 => ("JS", vec![address24()?]),
+        0x4A => ("JCO", vec![address24()?]),
+        0x50 => ("IN", vec![address24()?]),
+        0x51 => ("OUT", vec![address24()?]),
+        0x52 => ("INSI", vec![address24()?]),
+        0x53 => ("OUTI", vec![value()?]),
+        0x54 => ("INSW", vec![address7()?, immediate.clone()]),
+        0x55 => ("OUTW", vec![address7()?, register_or_immediate()?]),
+        0x60 | 0x61 => {
+            let flag = (word & 0xFF) as u8;
+            flag_name(flag).ok_or_else(|| format!("Invalid flag number: {}", flag))?;
+            (if opcode == 0x60 { "SETF" } else { "CLRF" }, vec![Operand::Flag(flag)])
+        }
+        0xFF => ("HALT", vec![]),
+        _ => return Err(format!("Unknown opcode: {:#04x}", opcode)),
+    };
+    Ok(Instruction { word, opcode, mnemonic, operands })
+}
+
+/// Disassembles an instruction word, or shows it as `UNKNOWN` when it does not decode.
+///
+/// # Arguments
+///
+/// * `word` - The instruction word.
+/// * `labels` - The labels to show in place of addresses.
+///
+/// # Returns
+///
+/// * `String` - The instruction as assembly text.
+pub fn disassemble(word: u32, labels: &Labels) -> String {
+    match decode(word) {
+        Ok(instruction) => instruction.format(labels),
+        Err(_) => format!("UNKNOWN 0x{:08X}", word),
+    }
+}
+
+/// Disassembles consecutive instruction words, with a line for every label defined at their addresses.
+///
+/// When `mark_pseudo` is set, the first word of every sequence matching the expansion of a
+/// pseudo-instruction ends in a `; pseudo` comment with the pseudo-instruction, unless a label
+/// inside the sequence shows that it is not one. The instructions themselves are always shown
+/// as they are encoded.
+///
+/// # Arguments
+///
+/// * `start` - The address of the first word.
+/// * `words` - The instruction words.
+/// * `labels` - The labels of the program.
+/// * `mark_pseudo` - Whether to name the pseudo-instructions the words may have been expanded from.
+///
+/// # Returns
+///
+/// * `String` - One line per word with its address, the word and the instruction.
+pub fn disassemble_range(start: u32, words: &[u32], labels: &Labels, mark_pseudo: bool) -> String {
+    let mut text = String::new();
+    // The index of the first word after the pseudo-instruction last recognized
+    let mut pseudo_end = 0;
+    for (index, &word) in words.iter().enumerate() {
+        let address = start.wrapping_add(4 * index as u32);
+        if let Some(label) = labels.get(address) {
+            text.push_str(&format!("{}:\n", label));
+        }
+        let line = format!("0x{:08X}  {:08X}  {}", address, word, disassemble(word, labels));
+        let pseudo = recognize(&words[index..], labels).filter(|_| mark_pseudo).filter(|(_, count)| {
+            index >= pseudo_end && (1..*count).all(|offset| labels.get(address.wrapping_add(4 * offset as u32)).is_none())
+        });
+        match pseudo {
+            Some((pseudo, count)) => {
+                pseudo_end = index + count;
+                text.push_str(&format!("{:<48}; pseudo {}\n", line, pseudo));
+            }
+            None => text.push_str(&format!("{}\n", line)),
+        }
+    }
+    text
+}
+
+/// Returns the name of a register as written in source.
+pub fn register_name(reg: &Reg) -> &'static str {
+    match reg {
+        Reg::AX => "AX", Reg::BX => "BX", Reg::CX => "CX", Reg::DX => "DX",
+        Reg::EX => "EX", Reg::FX => "FX", Reg::GX => "GX", Reg::HX => "HX",
+        Reg::SP => "SP", Reg::BP => "BP", Reg::SI => "SI", Reg::DI => "DI",
+        Reg::PC => "PC", Reg::FLAGS => "FLAGS",
+    }
+}
+
+/// Returns the name of a flag operand as written in source, matching `parse_operand`.
+fn flag_name(flag: u8) -> Option<&'static str> {
+    match flag {
+        0 => Some("CARRY"),
+        1 => Some("ZERO"),
+        2 => Some("SIGN"),
+        3 => Some("INTERRUPT"),
+        5 => Some("OVERFLOW"),
+        6 => Some("MACRO"),
+        7 => Some("STACK_DIR"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod disassembler_test {
+    use super::*;
+    use crate::utils::assembler::command_processor::{assemble_program, parse_command, Command};
+
+    /// Assembles lines into instruction words, with `.equ` constants and labels allowed.
+    fn assemble(lines: &[String]) -> Vec<u32> {
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        assemble_program(&commands, &[], 0x10000).unwrap_or_else(|e| panic!("{}: {:?}", e, lines)).text
+    }
+
+    /// One instruction of every form of every opcode.
+    const FORMS: &[&str] = &[
+        "MOVI BX, 0x1234", "MOVW CX, DX", "MOVW AX, 0x0010", "LODI FLAGS, 0xFFFF", "LODW SI, [0x2000]",
+        "STRI [0x7F], 0x0042", "STRI [BX], 0x0001", "STRW [0x10], AX", "STRW [DI], 0x0100",
+        "PUSH HX", "PUSH 0x0102", "PUSH [BP]", "POP GX", "XCGH AX, PC",
+        "ADDW AX, BX", "ADDW AX, 0x0004", "SUBW EX, FX", "MUL AX, 0x0300", "INC AX", "DEC SP", "NEG BX", "NOT CX",
+        "AND AX, BX", "OR AX, 0x00F0", "XOR DX, DX", "SHL AX, 0x0002", "SHR AX, CX", "CMPW AX, 0x0000",
+        "JMP 0x001000", "JMP [AX]", "CALL 0x7FFFFC", "RET", "JE 0x000004", "JNE [DI]", "JGT 0x000008", "JGE 0x00000C",
+        "JLT 0x000010", "JLE 0x000014", "JS 0x000018", "JCO 0x00001C",
+        "IN [0x00E000]", "OUT [0x00E004]", "OUT [CX]", "INSI [0x000100]", "OUTI AX", "OUTI 0x0040", "OUTI [SI]",
+        "INSW [0x20], 0x0003", "INSW [AX], 0x0000", "OUTW [0x21], BX", "OUTW [0x22], 0x0002",
+        "SETF CARRY", "CLRF ZERO", "SETF STACK_DIR", "HALT",
+    ];
+
+    #[test]
+    fn every_opcode_round_trips() {
+        let lines: Vec<String> = FORMS.iter().map(|line| line.to_string()).collect();
+        let words = assemble(&lines);
+        let text: Vec<String> = words.iter().map(|&word| decode(word).unwrap().to_string()).collect();
+        assert_eq!(text, lines);
+        assert_eq!(assemble(&text), words);
+
+        let mut opcodes: Vec<u8> = words.iter().map(|word| (word >> 24) as u8).collect();
+        opcodes.sort_unstable();
+        opcodes.dedup();
+        assert_eq!(opcodes.len(), 42);
+    }
+
+    #[test]
+    fn every_register_and_immediate_round_trips() {
+        let registers = ["AX", "BX", "CX", "DX", "EX", "FX", "GX", "HX", "SP", "BP", "SI", "DI", "PC", "FLAGS"];
+        let mut lines = Vec::new();
+        for first in registers {
+            for second in registers {
+                lines.push(format!("ADDW {}, {}", first, second));
+            }
+            lines.push(format!("JMP [{}]", first));
+            lines.push(format!("STRW [{}], {}", first, first));
+        }
+        for value in [0u32, 2, 0x7E, 0x100, 0xFFFE] {
+            lines.push(format!("MOVI AX, {}", value));
+            lines.push(format!("SUBW AX, {}", value));
+        }
+        let words = assemble(&lines);
+        let text: Vec<String> = words.iter().map(|&word| disassemble(word, &Labels::default())).collect();
+        assert_eq!(assemble(&text), words);
+    }
+
+    #[test]
+    fn addresses_are_named_after_labels() {
+        let lines: Vec<String> = ["main: JMP end", "LODW AX, value", "end: HALT", ".data", "value: .word 1"]
+            .iter().map(|line| line.to_string()).collect();
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        let labels = Labels::new(&program.symbols);
+        assert_eq!(disassemble(program.text[0], &labels), "JMP end");
+        assert_eq!(disassemble(program.text[1], &labels), "LODW AX, value");
+
+        let listing = disassemble_range(0, &program.text, &labels, false);
+        assert!(listing.starts_with("main:\n0x00000000  40000008  JMP end\n"));
+        assert!(listing.contains("end:\n0x00000008  FF000000  HALT\n"));
+    }
+
+    #[test]
+    fn pseudo_instructions_are_marked() {
+        let lines: Vec<String> = ["main: LI AX, 0x00012345", "JZ AX, main", "XOR AX, AX"].iter().map(|line| line.to_string()).collect();
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        let listing = disassemble_range(0, &program.text, &Labels::new(&program.symbols), true);
+        let rows: Vec<&str> = listing.lines().collect();
+        assert_eq!(rows[1], format!("{:<48}; pseudo LI AX, 0x00012345", "0x00000000  0100FFFE  MOVI AX, 0xFFFE"));
+        assert_eq!(rows[2], "0x00000004  24000010  SHL AX, 0x0010");
+        assert!(rows[5].ends_with("; pseudo JZ AX, main"));
+        assert!(rows[7].ends_with("; pseudo CLR AX"));
+
+        // A label inside a sequence means it is not an expansion
+        let split = ["MOVI AX, 1", "inside: SHL AX, 16", "OR AX, 2"].iter().map(|line| line.to_string()).collect::<Vec<_>>();
+        let commands: Vec<Command> = split.iter().map(|line| parse_command(line).unwrap()).collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        assert!(!disassemble_range(0, &program.text, &Labels::new(&program.symbols), true).contains("pseudo"));
+    }
+
+    #[test]
+    fn hand_written_instructions_are_not_renamed() {
+        let lines: Vec<String> = ["XOR AX, AX", "OR AX, 0", "CLR BX", "CLR CX"].iter().map(|line| line.to_string()).collect();
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        let listing = disassemble_range(0, &program.text, &Labels::new(&program.symbols), false);
+        let rows: Vec<&str> = listing.lines().map(|row| &row[22..]).collect();
+        assert_eq!(rows, ["XOR AX, AX", "OR AX, 0x0000", "XOR BX, BX", "XOR CX, CX"]);
+        // The text assembles back to the same words
+        let commands: Vec<Command> = rows.iter().map(|row| parse_command(row).unwrap()).collect();
+        assert_eq!(assemble_program(&commands, &[], 0x10000).unwrap().text, program.text);
+        // Only the source knows which words came from a pseudo-instruction
+        assert_eq!(program.pseudo_addresses().into_iter().collect::<Vec<_>>(), vec![8, 12]);
+    }
+
+    #[test]
+    fn invalid_words_are_reported() {
+        assert_eq!(decode(0x7700_0000).unwrap_err(), "Unknown opcode: 0x77");
+        assert_eq!(decode(0x1320_0000).unwrap_err(), "Invalid register number: 32");
+        assert_eq!(decode(0x6000_0004).unwrap_err(), "Invalid flag number: 4");
+        assert_eq!(disassemble(0x7700_0000, &Labels::default()), "UNKNOWN 0x77000000");
+    }
+}
@@ -0,0 +1,259 @@
+//! # Expressions Module
+//!
+//! This module evaluates the integer expressions used by assembler directives,
+//! such as the conditions of `.if`. Expressions are made of numbers (decimal,
+//! `0x` hexadecimal, `0b` binary or a `'c'` character, which may be an escape
+//! such as `'\n'`), symbols, parentheses
+//! and the C operators, with the C precedences:
+//!
+//! | Precedence | Operators                   |
+//! |------------|-----------------------------|
+//! | highest    | unary `-`, `~`, `!`         |
+//! |            | `*`, `/`, `%`               |
+//! |            | `+`, `-`                    |
+//! |            | `<<`, `>>`                  |
+//! |            | `<`, `<=`, `>`, `>=`        |
+//! |            | `==`, `!=`                  |
+//! |            | `&`                         |
+//! |            | `^`                         |
+//! |            | `|`                         |
+//! |            | `&&`                        |
+//! | lowest     | `||`                        |
+//!
+//! Comparisons and logical operators evaluate to 1 or 0.
+
+use crate::utils::assembler::data::unescape;
+
+/// A token of an expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+/// The binary operators, longest first so that `<<` is not read as `<`.
+const OPERATORS: [&str; 18] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "+", "-", "*", "/", "%", "<", ">", "&", "^", "|",
+];
+
+/// Returns the precedence of a binary operator; higher binds tighter.
+fn precedence(operator: &str) -> u8 {
+    match operator {
+        "*" | "/" | "%" => 10,
+        "+" | "-" => 9,
+        "<<" | ">>" => 8,
+        "<" | "<=" | ">" | ">=" => 7,
+        "==" | "!=" => 6,
+        "&" => 5,
+        "^" => 4,
+        "|" => 3,
+        "&&" => 2,
+        "||" => 1,
+        _ => 0,
+    }
+}
+
+/// Evaluates an integer expression.
+///
+/// # Arguments
+///
+/// * `expression` - The text of the expression.
+/// * `resolve` - Returns the value of a symbol, or `None` if it is not defined.
+///
+/// # Returns
+///
+/// * `Result<i64, String>` - The value, or an error message for a syntax error, an unknown
+///   symbol or a division by zero.
+pub fn evaluate(expression: &str, resolve: impl Fn(&str) -> Option<i64>) -> Result<i64, String> {
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Err("Missing expression".to_string());
+    }
+    let mut parser = Parser { tokens: &tokens, position: 0, resolve: &resolve };
+    let value = parser.binary(1)?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(value),
+        Some(token) => Err(format!("Unexpected {} in expression: {}", describe(token), expression.trim())),
+    }
+}
+
+/// Splits an expression into tokens.
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            i += 1;
+        } else if c == '\'' {
+            let close = (i + 2..chars.len()).find(|&j| chars[j] == '\'' && chars[j - 1] != '\\')
+                .ok_or(format!("Invalid character literal in expression: {}", expression.trim()))?;
+            let inner: String = chars[i + 1..close].iter().collect();
+            let value = match (inner.chars().count(), unescape(&inner)?.as_slice()) {
+                (1, _) => inner.chars().next().map_or(0, |c| c as i64),
+                (_, [byte]) => *byte as i64,
+                _ => return Err(format!("Invalid character literal in expression: {}", expression.trim())),
+            };
+            tokens.push(Token::Number(value));
+            i = close + 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.' || chars[i] == '@') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(if c.is_ascii_digit() { Token::Number(parse_number(&word)?) } else { Token::Symbol(word) });
+        } else if c == '~' || (c == '!' && chars.get(i + 1) != Some(&'=')) {
+            tokens.push(Token::Operator(if c == '~' { "~" } else { "!" }));
+            i += 1;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let operator = OPERATORS.iter().find(|operator| rest.starts_with(*operator))
+                .ok_or(format!("Unexpected character '{}' in expression: {}", c, expression.trim()))?;
+            tokens.push(Token::Operator(operator));
+            i += operator.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary number.
+fn parse_number(text: &str) -> Result<i64, String> {
+    let lower = text.to_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lower.parse::<i64>()
+    };
+    parsed.map_err(|_| format!("Invalid number in expression: {}", text))
+}
+
+/// Describes a token for error messages.
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(value) => format!("number {}", value),
+        Token::Symbol(name) => format!("symbol {}", name),
+        Token::Operator(operator) => format!("'{}'", operator),
+        Token::Open => "'('".to_string(),
+        Token::Close => "')'".to_string(),
+    }
+}
+
+/// A precedence-climbing parser that evaluates while it parses.
+struct Parser<'a, F: Fn(&str) -> Option<i64>> {
+    tokens: &'a [Token],
+    position: usize,
+    resolve: &'a F,
+}
+
+impl<F: Fn(&str) -> Option<i64>> Parser<'_, F> {
+    /// Parses binary operators of at least the given precedence.
+    fn binary(&mut self, min_precedence: u8) -> Result<i64, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            let operator_precedence = precedence(operator);
+            if operator_precedence < min_precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(operator_precedence + 1)?;
+            left = apply(operator, left, right)?;
+        }
+        Ok(left)
+    }
+
+    /// Parses a unary operator, a parenthesized expression, a number or a symbol.
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.position).ok_or("Unexpected end of expression".to_string())?;
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(*value),
+            Token::Symbol(name) => (self.resolve)(name).ok_or(format!("Unknown symbol: {}", name)),
+            Token::Operator("-") => Ok(self.unary()?.wrapping_neg()),
+            Token::Operator("~") => Ok(!self.unary()?),
+            Token::Operator("!") => Ok((self.unary()? == 0) as i64),
+            Token::Open => {
+                let value = self.binary(1)?;
+                match self.tokens.get(self.position) {
+                    Some(Token::Close) => {
+                        self.position += 1;
+                        Ok(value)
+                    }
+                    _ => Err("Missing ')' in expression".to_string()),
+                }
+            }
+            token => Err(format!("Unexpected {} in expression", describe(token))),
+        }
+    }
+}
+
+/// Applies a binary operator.
+fn apply(operator: &str, left: i64, right: i64) -> Result<i64, String> {
+    Ok(match operator {
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err("Division by zero in expression".to_string()),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "<" => (left < right) as i64,
+        "<=" => (left <= right) as i64,
+        ">" => (left > right) as i64,
+        ">=" => (left >= right) as i64,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "&" => left & right,
+        "^" => left ^ right,
+        "|" => left | right,
+        "&&" => (left != 0 && right != 0) as i64,
+        "||" => (left != 0 || right != 0) as i64,
+        _ => return Err(format!("Unknown operator: {}", operator)),
+    })
+}
+
+#[cfg(test)]
+mod expressions_test {
+    use super::*;
+
+    fn eval(expression: &str) -> Result<i64, String> {
+        evaluate(expression, |name| match name {
+            "LEVEL" => Some(3),
+            "table.end" => Some(0x40),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn operators_follow_c_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("1 << 4 | 1"), Ok(17));
+        assert_eq!(eval("-LEVEL + ~0"), Ok(-4));
+        assert_eq!(eval("LEVEL > 2 && !(LEVEL == 4) || 0"), Ok(1));
+        assert_eq!(eval("0x10 + 0b11 + 'A' + table.end % 7"), Ok(16 + 3 + 65 + 1));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("'\\n' + '\\x41' + '\\''"), Ok(10 + 65 + 39));
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        assert_eq!(eval("DEBUG"), Err("Unknown symbol: DEBUG".to_string()));
+        assert!(eval("1 / (LEVEL - 3)").unwrap_err().contains("Division by zero"));
+        assert!(eval("(1 + 2").unwrap_err().contains("Missing ')'"));
+        assert!(eval("1 2").unwrap_err().contains("Unexpected number 2"));
+        assert!(eval("").is_err());
+        assert!(eval("1 $ 2").is_err());
+    }
+}
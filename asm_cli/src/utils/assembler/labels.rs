@@ -0,0 +1,162 @@
+//! # Labels Module
+//!
+//! This module resolves the two kinds of labels that are not global before
+//! the assembler assigns addresses:
+//!
+//! - Local labels start with a dot (`.loop`) and belong to the closest global
+//!   label defined before them, so `.loop` after `strlen:` becomes
+//!   `strlen.loop`. The qualified name can be used from anywhere.
+//! - Anonymous labels are numbers (`1:`) that may be defined any number of
+//!   times. `1f` refers to the next definition of `1` and `1b` to the latest
+//!   one, including a definition on the same line.
+//!
+//! Both are rewritten into unique global names, so the passes of
+//! `assemble_program` only ever see global labels.
+
+use std::collections::HashMap;
+
+use crate::utils::assembler::command_processor::Command;
+use crate::utils::assembler::operands::Operand;
+
+/// Returns `true` if `name` is a local label such as `.loop`.
+pub fn is_local_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next() == Some('.') && chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+}
+
+/// Returns `true` if `name` is an anonymous label such as `1`.
+pub fn is_anonymous_label(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Returns the unique name given to the `occurrence`-th definition (counting from 1) of an anonymous label.
+fn anonymous_name(number: &str, occurrence: usize) -> String {
+    format!("{}@{}", number, occurrence)
+}
+
+/// Rewrites local and anonymous labels, both definitions and references, into unique global names.
+///
+/// # Arguments
+///
+/// * `commands` - The commands of the program, with macros already expanded.
+///
+/// # Returns
+///
+/// * `Result<Vec<Command>, String>` - The rewritten commands, or an error message for a local label
+///   without an enclosing global label or an anonymous reference without a matching definition.
+pub fn resolve_local_labels(commands: &[Command]) -> Result<Vec<Command>, String> {
+    // The definitions of each anonymous label, as the indices of the commands defining them
+    let mut anonymous: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, command) in commands.iter().enumerate() {
+        if let Some(label) = command.label.as_deref().filter(|label| is_anonymous_label(label)) {
+            anonymous.entry(label).or_default().push(index);
+        }
+    }
+
+    let mut scope: Option<String> = None;
+    let mut resolved = Vec::with_capacity(commands.len());
+    for (index, command) in commands.iter().enumerate() {
+        let original = command;
+        let mut command = command.clone();
+        if let Some(label) = command.label.take() {
+            command.label = Some(if is_local_label(&label) {
+                qualify(&scope, &label).map_err(|e| original.error_at(e))?
+            } else if is_anonymous_label(&label) {
+                let occurrence = anonymous[label.as_str()].iter().position(|&i| i == index).unwrap_or(0) + 1;
+                anonymous_name(&label, occurrence)
+            } else {
+                // Constants and the private labels of macro expansions (`spin.loop@2`)
+                // do not open a new scope for local labels
+                if command.opcode != ".equ" && !label.contains('@') {
+                    scope = Some(label.clone());
+                }
+                label
+            });
+        }
+        for operand in [&mut command.operand1, &mut command.operand2].into_iter().flatten() {
+            if let Operand::Label(name) = operand {
+                if is_local_label(name) {
+                    *name = qualify(&scope, name).map_err(|e| original.error_at(e))?;
+                } else if let Some(target) = resolve_anonymous_reference(name, index, &anonymous).map_err(|e| original.error_at(e))? {
+                    *name = target;
+                }
+            }
+        }
+        resolved.push(command);
+    }
+    Ok(resolved)
+}
+
+/// Prefixes a local label with the global label it belongs to.
+fn qualify(scope: &Option<String>, local: &str) -> Result<String, String> {
+    match scope {
+        Some(global) => Ok(format!("{}{}", global, local)),
+        None => Err(format!("Local label {} is not preceded by a global label", local)),
+    }
+}
+
+/// Resolves a reference such as `1f` or `2b` made by the command at `index`.
+///
+/// # Returns
+///
+/// * `Result<Option<String>, String>` - The unique name of the referenced definition, `None` if the
+///   name is not an anonymous reference, or an error message if there is no matching definition.
+fn resolve_anonymous_reference(name: &str, index: usize, anonymous: &HashMap<&str, Vec<usize>>) -> Result<Option<String>, String> {
+    let (number, forward) = match name.strip_suffix('f') {
+        Some(number) => (number, true),
+        None => match name.strip_suffix('b') {
+            Some(number) => (number, false),
+            None => return Ok(None),
+        },
+    };
+    if !is_anonymous_label(number) {
+        return Ok(None);
+    }
+    let definitions = anonymous.get(number).map(Vec::as_slice).unwrap_or_default();
+    let occurrence = if forward {
+        definitions.iter().position(|&i| i > index)
+    } else {
+        definitions.iter().rposition(|&i| i <= index)
+    };
+    match occurrence {
+        Some(occurrence) => Ok(Some(anonymous_name(number, occurrence + 1))),
+        None => Err(format!(
+            "No anonymous label {} {} reference {}",
+            number, if forward { "after" } else { "before" }, name
+        )),
+    }
+}
+
+#[cfg(test)]
+mod labels_test {
+    use super::*;
+    use crate::utils::assembler::command_processor::parse_command;
+
+    fn resolve(lines: &[&str]) -> Result<Vec<Command>, String> {
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        resolve_local_labels(&commands)
+    }
+
+    #[test]
+    fn local_labels_belong_to_the_preceding_global_label() {
+        let commands = resolve(&["first: INC AX", ".loop: JMP .loop", "SIZE: .equ 4", "second: JMP .loop", ".loop: JMP first.loop"]).unwrap();
+        assert_eq!(commands[1].label.as_deref(), Some("first.loop"));
+        assert_eq!(commands[1].operand1, Some(Operand::Label("first.loop".to_string())));
+        assert_eq!(commands[3].operand1, Some(Operand::Label("second.loop".to_string())));
+        assert_eq!(commands[4].label.as_deref(), Some("second.loop"));
+        assert_eq!(commands[4].operand1, Some(Operand::Label("first.loop".to_string())));
+        assert!(resolve(&[".orphan: HALT"]).unwrap_err().contains("not preceded"));
+    }
+
+    #[test]
+    fn anonymous_references_find_the_nearest_definition() {
+        let commands = resolve(&["1: JMP 1f", "JMP 1b", "1: JMP 1b", "JMP 1b"]).unwrap();
+        assert_eq!(commands[0].label.as_deref(), Some("1@1"));
+        assert_eq!(commands[0].operand1, Some(Operand::Label("1@2".to_string())));
+        assert_eq!(commands[1].operand1, Some(Operand::Label("1@1".to_string())));
+        assert_eq!(commands[2].operand1, Some(Operand::Label("1@2".to_string())));
+        assert_eq!(commands[3].operand1, Some(Operand::Label("1@2".to_string())));
+        assert!(resolve(&["1: JMP 1f"]).unwrap_err().contains("after"));
+        assert!(resolve(&["JMP 2b", "2: HALT"]).unwrap_err().contains("before"));
+    }
+}
@@ -0,0 +1,656 @@
+//! # Lint Module
+//!
+//! This module looks for mistakes that assemble fine. It decodes the text
+//! segment of an assembled program into a control-flow graph, with an edge
+//! for every fall-through and direct jump, and walks it from the start of the
+//! program and from every routine (the targets of `CALL` and the text labels
+//! whose address is taken, e.g. by `LA` for `CALLR`). Each warning names the
+//! lint that produced it, and a line can silence warnings with a comment:
+//!
+//! ```text
+//! spin:   JMP spin        ; lint: allow(missing-halt)
+//! table:  .word 0         ; lint: allow
+//! ```
+//!
+//! `lint: allow` without a list silences every lint on that line.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+use crate::memory::registers::Reg;
+use crate::utils::assembler::command_processor::{register_to_number, AssembledProgram};
+use crate::utils::assembler::disassembler::{decode, register_name, Instruction, Labels};
+use crate::utils::assembler::listing::ListingCode;
+use crate::utils::assembler::operands::Operand;
+use crate::utils::assembler::source::{strip_comment, SourceLocation};
+use crate::utils::assembler::symbol_table::SymbolSection;
+
+/// The number of registers tracked by the data-flow checks.
+const REGISTERS: usize = 14;
+
+/// A kind of mistake the lint pass looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lint {
+    /// Execution can run past the last instruction, or never reaches `HALT`.
+    MissingHalt,
+    /// Instructions that no path from the start of the program or a routine reaches.
+    UnreachableCode,
+    /// A label that nothing refers to.
+    UnusedLabel,
+    /// An instruction other than a jump that writes `PC`.
+    PcWrite,
+    /// Float arithmetic (`ADDW`, `INC`, `CMPW`, ...) on a register holding integer data.
+    FloatOnInteger,
+    /// A routine that returns with values still pushed, or pops more than it pushed.
+    UnbalancedStack,
+    /// A `RET` reached from the start of the program without a `CALL`.
+    RetWithoutCall,
+}
+
+impl Lint {
+    /// Every lint, in the order they are documented.
+    pub const ALL: [Lint; 7] = [
+        Lint::MissingHalt, Lint::UnreachableCode, Lint::UnusedLabel, Lint::PcWrite,
+        Lint::FloatOnInteger, Lint::UnbalancedStack, Lint::RetWithoutCall,
+    ];
+
+    /// Returns the name used in warnings and suppression comments.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::MissingHalt => "missing-halt",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::UnusedLabel => "unused-label",
+            Lint::PcWrite => "pc-write",
+            Lint::FloatOnInteger => "float-on-integer",
+            Lint::UnbalancedStack => "unbalanced-stack",
+            Lint::RetWithoutCall => "ret-without-call",
+        }
+    }
+
+    /// Returns the lint with the given name.
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+/// A warning produced by the lint pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    /// The lint that produced the warning.
+    pub lint: Lint,
+    /// The source line the warning is about, if known.
+    pub location: Option<SourceLocation>,
+    /// The address of the instruction or label the warning is about.
+    pub address: u32,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: warning: {} [{}]", location, self.message, self.lint.name()),
+            None => write!(f, "0x{:08X}: warning: {} [{}]", self.address, self.message, self.lint.name()),
+        }
+    }
+}
+
+/// A node of the control-flow graph: one instruction word of the text segment.
+struct Node {
+    /// The decoded instruction, or `None` for a word that does not decode.
+    instruction: Option<Instruction>,
+    /// The nodes execution may continue at within the same routine.
+    next: Vec<usize>,
+    /// The routine started by a direct `CALL`.
+    call: Option<usize>,
+    /// Whether execution continues past the end of the text segment.
+    falls_off: bool,
+}
+
+/// The control-flow graph of a program's text segment.
+struct Graph<'a> {
+    program: &'a AssembledProgram,
+    nodes: Vec<Node>,
+}
+
+impl<'a> Graph<'a> {
+    fn new(program: &'a AssembledProgram) -> Self {
+        let start = program.actual_text_start;
+        let count = program.text.len();
+        let index = |address: u32| {
+            let offset = address.checked_sub(start)?;
+            (offset % 4 == 0 && ((offset / 4) as usize) < count).then_some((offset / 4) as usize)
+        };
+        let nodes = program.text.iter().enumerate().map(|(i, &word)| {
+            let instruction = decode(word).ok();
+            let mut node = Node { instruction: None, next: Vec::new(), call: None, falls_off: false };
+            let mut falls_through = true;
+            if let Some(instruction) = &instruction {
+                let target = match instruction.operands.first() {
+                    Some(Operand::Address(address)) => index(*address),
+                    _ => None,
+                };
+                match instruction.mnemonic {
+                    "HALT" | "RET" => falls_through = false,
+                    "JMP" => {
+                        falls_through = false;
+                        node.next.extend(target);
+                    }
+                    "CALL" => node.call = target,
+                    "JE" | "JNE" | "JGT" | "JGE" | "JLT" | "JLE" | "JS" | "JCO" => node.next.extend(target),
+                    _ => {}
+                }
+            } else {
+                falls_through = false;
+            }
+            if falls_through {
+                if i + 1 < count {
+                    node.next.push(i + 1);
+                } else {
+                    node.falls_off = true;
+                }
+            }
+            node.instruction = instruction;
+            node
+        }).collect();
+        Self { program, nodes }
+    }
+
+    /// Returns the address of a node.
+    fn address(&self, node: usize) -> u32 {
+        self.program.actual_text_start.wrapping_add(4 * node as u32)
+    }
+
+    /// Returns the source line of a node.
+    fn location(&self, node: usize) -> Option<SourceLocation> {
+        self.program.source_map.get(node).cloned().flatten()
+    }
+
+    /// Returns the nodes reached from `root` without entering called routines, in the order found.
+    fn reach(&self, root: usize) -> Vec<usize> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut order = Vec::new();
+        let mut queue = VecDeque::from([root]);
+        seen[root] = true;
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            for &next in &self.nodes[node].next {
+                if !seen[next] {
+                    seen[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        order
+    }
+
+    /// Returns the nodes of text labels whose address is used other than as a jump or call target,
+    /// including the labels exported with `.global`.
+    fn address_taken(&self) -> Vec<usize> {
+        let mut names = BTreeSet::new();
+        for line in &self.program.listing.lines {
+            let direct_branch = match &line.code {
+                ListingCode::Instruction(word) => decode(*word).is_ok_and(|instruction| {
+                    is_jump(instruction.mnemonic) && matches!(instruction.operands.first(), Some(Operand::Address(_)))
+                }),
+                // `.global` names are entry points for other files
+                ListingCode::Data(_) | ListingCode::None => false,
+            };
+            if !direct_branch {
+                names.extend(line.references.iter().map(String::as_str));
+            }
+        }
+        names.into_iter()
+            .filter_map(|name| self.program.symbols.get(name).filter(|symbol| symbol.section == SymbolSection::Text))
+            .filter_map(|symbol| symbol.address.checked_sub(self.program.actual_text_start).map(|offset| (offset / 4) as usize))
+            .filter(|&node| node < self.nodes.len())
+            .collect()
+    }
+}
+
+/// Returns `true` for the mnemonics that transfer control to their address operand.
+fn is_jump(mnemonic: &str) -> bool {
+    matches!(mnemonic, "JMP" | "CALL" | "JE" | "JNE" | "JGT" | "JGE" | "JLT" | "JLE" | "JS" | "JCO")
+}
+
+/// Returns the register an instruction writes, for the instructions that write one.
+fn destination(instruction: &Instruction) -> Option<&Reg> {
+    match (instruction.mnemonic, instruction.operands.first()) {
+        ("MOVI" | "MOVW" | "LODI" | "LODW" | "POP" | "XCGH" | "ADDW" | "SUBW" | "MUL" | "INC" | "DEC" | "NEG"
+            | "NOT" | "AND" | "OR" | "XOR" | "SHL" | "SHR", Some(Operand::Register(reg))) => Some(reg),
+        _ => None,
+    }
+}
+
+/// Returns the registers an instruction writes: its destination, and both registers of `XCGH`.
+fn written_registers(instruction: &Instruction) -> Vec<&Reg> {
+    let mut registers: Vec<&Reg> = destination(instruction).into_iter().collect();
+    if let ("XCGH", Some(Operand::Register(second))) = (instruction.mnemonic, instruction.operands.get(1)) {
+        registers.push(second);
+    }
+    registers
+}
+
+fn register_index(reg: &Reg) -> usize {
+    register_to_number(reg).unwrap_or(0) as usize
+}
+
+/// Runs every lint on an assembled program.
+///
+/// # Arguments
+///
+/// * `program` - The assembled program, with its listing and source map.
+///
+/// # Returns
+///
+/// * `Vec<LintWarning>` - The warnings that are not suppressed, in source order.
+pub fn lint_program(program: &AssembledProgram) -> Vec<LintWarning> {
+    let graph = Graph::new(program);
+    let labels = Labels::new(&program.symbols);
+    let mut warnings = Vec::new();
+
+    if !graph.nodes.is_empty() {
+        // The start of the program, then every routine found from it
+        let main = graph.reach(0);
+        let mut routines: Vec<usize> = Vec::new();
+        let mut pending: Vec<usize> = graph.address_taken();
+        pending.extend(main.iter().filter_map(|&node| graph.nodes[node].call));
+        let mut reachable = vec![false; graph.nodes.len()];
+        for &node in &main {
+            reachable[node] = true;
+        }
+        while let Some(root) = pending.pop() {
+            if routines.contains(&root) {
+                continue;
+            }
+            routines.push(root);
+            for node in graph.reach(root) {
+                reachable[node] = true;
+                pending.extend(graph.nodes[node].call);
+            }
+        }
+        routines.sort_unstable();
+
+        check_halt(&graph, &main, &reachable, &mut warnings);
+        check_unreachable(&graph, &reachable, &mut warnings);
+        check_pc_writes(&graph, &mut warnings);
+        check_float_on_integer(&graph, &main, &routines, &mut warnings);
+        for &routine in &routines {
+            check_stack_balance(&graph, routine, &labels, &mut warnings);
+        }
+        for &node in &main {
+            if graph.nodes[node].instruction.as_ref().is_some_and(|instruction| instruction.mnemonic == "RET") {
+                warnings.push(LintWarning {
+                    lint: Lint::RetWithoutCall,
+                    location: graph.location(node),
+                    address: graph.address(node),
+                    message: "RET is reached without a CALL, so it returns to whatever is on the stack".to_string(),
+                });
+            }
+        }
+    }
+    check_unused_labels(program, &mut warnings);
+
+    let suppressions = suppressions(program);
+    warnings.retain(|warning| {
+        let Some(location) = &warning.location else { return true };
+        match suppressions.get(&(location.file.clone(), location.line)) {
+            Some(None) => false,
+            Some(Some(lints)) => !lints.contains(&warning.lint),
+            None => true,
+        }
+    });
+    warnings.sort_by_key(|warning| (warning.location.as_ref().map(|location| (location.file.clone(), location.line)), warning.address));
+    warnings
+}
+
+/// Warns about reachable instructions that fall off the end of the text, and about programs that
+/// cannot reach `HALT` at all.
+fn check_halt(graph: &Graph, main: &[usize], reachable: &[bool], warnings: &mut Vec<LintWarning>) {
+    let mut falls_off = false;
+    for (node, _) in graph.nodes.iter().enumerate().filter(|(node, info)| reachable[*node] && info.falls_off) {
+        falls_off = true;
+        warnings.push(LintWarning {
+            lint: Lint::MissingHalt,
+            location: graph.location(node),
+            address: graph.address(node),
+            message: "execution runs past the end of the program; add a HALT".to_string(),
+        });
+    }
+    let halts = main.iter().any(|&node| graph.nodes[node].instruction.as_ref().is_some_and(|instruction| instruction.mnemonic == "HALT"));
+    if !halts && !falls_off {
+        warnings.push(LintWarning {
+            lint: Lint::MissingHalt,
+            location: graph.location(0),
+            address: graph.address(0),
+            message: "no HALT is reachable from the start of the program".to_string(),
+        });
+    }
+}
+
+/// Warns once about every run of instructions that nothing reaches.
+fn check_unreachable(graph: &Graph, reachable: &[bool], warnings: &mut Vec<LintWarning>) {
+    let mut node = 0;
+    while node < graph.nodes.len() {
+        if reachable[node] {
+            node += 1;
+            continue;
+        }
+        let first = node;
+        while node < graph.nodes.len() && !reachable[node] {
+            node += 1;
+        }
+        let count = node - first;
+        warnings.push(LintWarning {
+            lint: Lint::UnreachableCode,
+            location: graph.location(first),
+            address: graph.address(first),
+            message: format!("unreachable code: {} instruction{} can never run", count, if count == 1 { "" } else { "s" }),
+        });
+    }
+}
+
+/// Warns about instructions other than jumps that write `PC`.
+fn check_pc_writes(graph: &Graph, warnings: &mut Vec<LintWarning>) {
+    for (node, info) in graph.nodes.iter().enumerate() {
+        let Some(instruction) = &info.instruction else { continue };
+        if written_registers(instruction).contains(&&Reg::PC) {
+            warnings.push(LintWarning {
+                lint: Lint::PcWrite,
+                location: graph.location(node),
+                address: graph.address(node),
+                message: format!("{} writes PC directly; use JMP, CALL or RET to change the flow of control", instruction.mnemonic),
+            });
+        }
+    }
+}
+
+/// What a register may hold, as a set of bits.
+type Kinds = u8;
+/// Integer data, e.g. loaded by `MOVI` or produced by a bitwise instruction.
+const INTEGER: Kinds = 1;
+/// A float, produced by float arithmetic.
+const FLOAT: Kinds = 2;
+
+/// Returns what a register holds after a value has been loaded into it.
+fn loaded(value: u32) -> Kinds {
+    // Zero is the same in both representations
+    if value == 0 { 0 } else { INTEGER }
+}
+
+/// Follows what every register may hold along the graph, and warns where float arithmetic reads
+/// a register that may hold integer data.
+fn check_float_on_integer(graph: &Graph, main: &[usize], routines: &[usize], warnings: &mut Vec<LintWarning>) {
+    let mut states: Vec<Option<[Kinds; REGISTERS]>> = vec![None; graph.nodes.len()];
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    for root in main.first().into_iter().chain(routines) {
+        states[*root] = Some([0; REGISTERS]);
+        queue.push_back(*root);
+    }
+    while let Some(node) = queue.pop_front() {
+        let Some(state) = states[node] else { continue };
+        let info = &graph.nodes[node];
+        let after = match &info.instruction {
+            // A called routine may change any register
+            Some(instruction) if instruction.mnemonic == "CALL" => [0; REGISTERS],
+            Some(instruction) => transfer(instruction, state),
+            None => state,
+        };
+        for &next in &info.next {
+            let merged = match states[next] {
+                Some(existing) => {
+                    let mut merged = existing;
+                    for (kinds, new) in merged.iter_mut().zip(after) {
+                        *kinds |= new;
+                    }
+                    merged
+                }
+                None => after,
+            };
+            if states[next] != Some(merged) {
+                states[next] = Some(merged);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    for (node, state) in states.iter().enumerate() {
+        let (Some(state), Some(instruction)) = (state, &graph.nodes[node].instruction) else { continue };
+        let holds_integer = |operand: Option<&Operand>| match operand {
+            Some(Operand::Register(reg)) if state[register_index(reg)] & INTEGER != 0 => Some(register_name(reg)),
+            _ => None,
+        };
+        let (first, second) = (instruction.operands.first(), instruction.operands.get(1));
+        let suspect = match instruction.mnemonic {
+            "ADDW" | "SUBW" | "MUL" => holds_integer(first).or(holds_integer(second)),
+            "INC" | "DEC" | "NEG" => holds_integer(first),
+            // Comparing with 0 is a zero test, which works on integers as well
+            "CMPW" => match (first, second) {
+                (_, Some(Operand::Immediate(0))) => None,
+                (_, Some(Operand::Immediate(_))) => holds_integer(first),
+                (Some(Operand::Register(a)), Some(Operand::Register(b))) => {
+                    let (a_kinds, b_kinds) = (state[register_index(a)], state[register_index(b)]);
+                    if a_kinds & INTEGER != 0 && b_kinds & FLOAT != 0 {
+                        Some(register_name(a))
+                    } else if b_kinds & INTEGER != 0 && a_kinds & FLOAT != 0 {
+                        Some(register_name(b))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(name) = suspect {
+            warnings.push(LintWarning {
+                lint: Lint::FloatOnInteger,
+                location: graph.location(node),
+                address: graph.address(node),
+                message: format!("{} treats {} as a float, but it may hold integer data", instruction.mnemonic, name),
+            });
+        }
+    }
+}
+
+/// Returns what every register may hold after an instruction.
+fn transfer(instruction: &Instruction, mut state: [Kinds; REGISTERS]) -> [Kinds; REGISTERS] {
+    let operands = &instruction.operands;
+    let Some(dest) = destination(instruction).map(register_index) else { return state };
+    state[dest] = match (instruction.mnemonic, operands.get(1)) {
+        ("MOVI" | "LODI" | "MOVW", Some(Operand::Immediate(value))) => loaded(*value),
+        ("MOVW", Some(Operand::Register(source))) => state[register_index(source)],
+        ("XCGH", Some(Operand::Register(source))) => {
+            let source = register_index(source);
+            let kinds = state[source];
+            state[source] = state[dest];
+            kinds
+        }
+        ("ADDW" | "SUBW" | "MUL" | "INC" | "DEC" | "NEG", _) => FLOAT,
+        // `XOR REG, REG` clears the register; `OR`, `XOR` and shifts with 0 leave it as it was
+        ("XOR", Some(Operand::Register(source))) if register_index(source) == dest => 0,
+        ("OR" | "XOR" | "SHL" | "SHR", Some(Operand::Immediate(0))) => state[dest],
+        ("NOT" | "AND" | "OR" | "XOR" | "SHL" | "SHR", _) => INTEGER,
+        // Values loaded from memory or the stack are not known
+        _ => 0,
+    };
+    state
+}
+
+/// Follows the stack depth through a routine, and warns where it returns with values still pushed
+/// or with more popped than pushed, and where paths reach an instruction with different depths.
+fn check_stack_balance(graph: &Graph, routine: usize, labels: &Labels, warnings: &mut Vec<LintWarning>) {
+    let name = labels.get(graph.address(routine)).map(str::to_string).unwrap_or_else(|| format!("0x{:08X}", graph.address(routine)));
+    let mut depths: Vec<Option<i32>> = vec![None; graph.nodes.len()];
+    let mut mismatched = vec![false; graph.nodes.len()];
+    let mut found = Vec::new();
+    depths[routine] = Some(0);
+    let mut queue = VecDeque::from([routine]);
+    while let Some(node) = queue.pop_front() {
+        let Some(depth) = depths[node] else { continue };
+        let Some(instruction) = &graph.nodes[node].instruction else { continue };
+        // A routine that moves SP itself cannot be followed
+        if written_registers(instruction).contains(&&Reg::SP) {
+            return;
+        }
+        let after = match instruction.mnemonic {
+            "PUSH" => depth + 1,
+            "POP" => depth - 1,
+            "RET" => {
+                if depth != 0 {
+                    let message = if depth > 0 {
+                        format!("RET in {} leaves {} value{} pushed, so it returns to the wrong address", name, depth, if depth == 1 { "" } else { "s" })
+                    } else {
+                        format!("RET in {} pops {} value{} more than it pushed, including its return address", name, -depth, if depth == -1 { "" } else { "s" })
+                    };
+                    found.push((node, false, message));
+                }
+                continue;
+            }
+            _ => depth,
+        };
+        for &next in &graph.nodes[node].next {
+            match depths[next] {
+                None => {
+                    depths[next] = Some(after);
+                    queue.push_back(next);
+                }
+                Some(existing) if existing != after && !mismatched[next] => {
+                    mismatched[next] = true;
+                    found.push((next, true, format!("paths through {} reach this instruction with {} and {} values pushed", name, existing.min(after), existing.max(after))));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    // Past a join with different depths the depth at a RET is a guess, so only the join is reported
+    if mismatched.contains(&true) {
+        found.retain(|(_, join, _)| *join);
+    }
+    warnings.extend(found.into_iter().map(|(node, _, message)| LintWarning {
+        lint: Lint::UnbalancedStack,
+        location: graph.location(node),
+        address: graph.address(node),
+        message,
+    }));
+}
+
+/// Warns about text and data labels that no line refers to. Labels at the start of the program,
+/// the private labels of macro expansions and anonymous labels are left alone.
+fn check_unused_labels(program: &AssembledProgram, warnings: &mut Vec<LintWarning>) {
+    let used: BTreeSet<&str> = program.listing.lines.iter().flat_map(|line| line.references.iter().map(String::as_str)).collect();
+    for symbol in program.symbols.symbols() {
+        if !matches!(symbol.section, SymbolSection::Text | SymbolSection::Data)
+            || used.contains(symbol.name.as_str())
+            || symbol.name.contains('@')
+            || (symbol.section == SymbolSection::Text && symbol.address == program.actual_text_start) {
+            continue;
+        }
+        let location = program.listing.lines.iter()
+            .find(|line| line.label.as_deref() == Some(symbol.name.as_str()))
+            .and_then(|line| line.location.clone());
+        warnings.push(LintWarning {
+            lint: Lint::UnusedLabel,
+            location,
+            address: symbol.address,
+            message: format!("label {} is never used", symbol.name),
+        });
+    }
+}
+
+/// The lints silenced on a line: `None` for all of them.
+type Suppression = Option<Vec<Lint>>;
+
+/// Collects the `lint: allow` comments of the program, by file and line.
+fn suppressions(program: &AssembledProgram) -> HashMap<(Option<std::path::PathBuf>, usize), Suppression> {
+    let mut suppressions = HashMap::new();
+    for line in &program.listing.lines {
+        let Some(location) = &line.location else { continue };
+        if let Some(suppression) = parse_suppression(&line.text) {
+            suppressions.insert((location.file.clone(), location.line), suppression);
+        }
+    }
+    suppressions
+}
+
+/// Parses the `lint: allow` or `lint: allow(NAME, ...)` comment of a source line.
+fn parse_suppression(text: &str) -> Option<Suppression> {
+    let comment = text[strip_comment(text).len()..].trim_start_matches(';').trim();
+    let rest = comment.strip_prefix("lint:")?.trim_start().strip_prefix("allow")?.trim();
+    if rest.is_empty() {
+        return Some(None);
+    }
+    let names = rest.strip_prefix('(')?.split(')').next()?;
+    Some(Some(names.split(',').filter_map(|name| Lint::from_name(name.trim())).collect()))
+}
+
+#[cfg(test)]
+mod lint_test {
+    use super::*;
+    use crate::utils::assembler::command_processor::{assemble_program, parse_command, Command};
+
+    /// Assembles numbered lines and returns the lints found with their line numbers.
+    fn lint(lines: &[&str]) -> Vec<(usize, Lint)> {
+        let commands: Vec<Command> = lines.iter().enumerate()
+            .map(|(index, line)| {
+                let mut command = parse_command(line).unwrap();
+                command.location = Some(SourceLocation { file: None, line: index + 1 });
+                command
+            })
+            .collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        lint_program(&program).into_iter().map(|warning| (warning.location.unwrap().line, warning.lint)).collect()
+    }
+
+    #[test]
+    fn clean_programs_have_no_warnings() {
+        assert_eq!(lint(&[
+            "main: LA DX, square", "CALL double", "CALLR DX", "CLR CX", "JZ CX, done", "NOP",
+            "done: HALT",
+            "double: PUSH BX", "MOVI BX, 0", "ADDW AX, AX", "POP BX", "RET",
+            "square: MUL AX, AX", "RET",
+        ]), vec![]);
+    }
+
+    #[test]
+    fn missing_halt_and_unreachable_code() {
+        assert_eq!(lint(&["main: INC AX", "JE main", "MOVW AX, BX"]), vec![(3, Lint::MissingHalt)]);
+        assert_eq!(lint(&["main: JMP main", "HALT", "INC AX"]), vec![(1, Lint::MissingHalt), (2, Lint::UnreachableCode)]);
+    }
+
+    #[test]
+    fn unused_labels_and_pc_writes() {
+        assert_eq!(lint(&["main: MOVW PC, AX", "spare: HALT", ".data", "buffer: .word 0"]), vec![
+            (1, Lint::PcWrite), (2, Lint::UnusedLabel), (4, Lint::UnusedLabel),
+        ]);
+        assert_eq!(lint(&[".global helper", "main: HALT", "helper: RET"]), vec![]);
+    }
+
+    #[test]
+    fn float_arithmetic_on_integer_data() {
+        assert_eq!(lint(&["MOVI CX, 10", "loop: DEC CX", "CMPW CX, 0", "JNE loop", "HALT"]), vec![(2, Lint::FloatOnInteger)]);
+        assert_eq!(lint(&["MOVI AX, 0", "INC AX", "LODW BX, [0x100]", "ADDW AX, BX", "SHL AX, 1", "CMPW AX, 0", "HALT"]), vec![]);
+        assert_eq!(lint(&["LI AX, 0x3F800000", "MOVW BX, AX", "NEG BX", "HALT"]), vec![(3, Lint::FloatOnInteger)]);
+    }
+
+    #[test]
+    fn stack_balance_and_returns() {
+        assert_eq!(lint(&[
+            "main: CALL leaky", "CALL greedy", "HALT",
+            "leaky: PUSH AX", "RET",
+            "greedy: POP AX", "RET",
+        ]), vec![(5, Lint::UnbalancedStack), (7, Lint::UnbalancedStack)]);
+        assert_eq!(lint(&["main: CALL pick", "HALT", "pick: JZ AX, skip", "PUSH AX", "skip: POP AX", "RET"]), vec![
+            (5, Lint::UnbalancedStack),
+        ]);
+        assert_eq!(lint(&["main: INC AX", "RET"]), vec![(1, Lint::MissingHalt), (2, Lint::RetWithoutCall)]);
+    }
+
+    #[test]
+    fn comments_suppress_warnings_on_their_line() {
+        assert_eq!(lint(&["main: JMP main ; lint: allow(missing-halt)", "spare: HALT ; lint: allow"]), vec![]);
+        assert_eq!(lint(&["main: MOVW PC, AX ; lint: allow(unused-label)", "HALT"]), vec![(1, Lint::PcWrite)]);
+        assert_eq!(parse_suppression("HALT ; lint: allow(pc-write, unreachable-code)"), Some(Some(vec![Lint::PcWrite, Lint::UnreachableCode])));
+        assert_eq!(parse_suppression("HALT ; \"lint: allow\""), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! # Listing Module
+//!
+//! This module records what every line of a program assembled to, so
+//! encoding problems can be traced back to the source. Pass 2 adds one
+//! `ListingLine` per command with its address and the instruction word or
+//! data bytes it produced. `Listing::render` formats the listing followed by
+//! the symbol table and a cross-reference table of where every symbol is
+//! defined and used. Lines produced by macro or repetition expansions are
+//! marked with `+` and their nesting depth, and the instructions a
+//! pseudo-instruction expands to are marked with `P` below its source line.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::utils::assembler::command_processor::Command;
+use crate::utils::assembler::data::is_data_directive;
+use crate::utils::assembler::macros::split_arguments;
+use crate::utils::assembler::operands::Operand;
+use crate::utils::assembler::source::SourceLocation;
+use crate::utils::assembler::symbol_table::SymbolTable;
+
+/// How many data bytes are shown on one row of the listing.
+const BYTES_PER_ROW: usize = 8;
+
+/// What a line of the listing assembled to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListingCode {
+    /// The line produced no code.
+    None,
+    /// A 32-bit instruction word in the text segment.
+    Instruction(u32),
+    /// Bytes in the data segment.
+    Data(Vec<u8>),
+}
+
+/// A line of the listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    /// The file and line the command was read from, if known.
+    pub location: Option<SourceLocation>,
+    /// How many macro or repetition expansions deep the line was produced.
+    pub depth: usize,
+    /// Whether the line is an instruction of the expansion of a pseudo-instruction.
+    pub pseudo: bool,
+    /// The address of the line, for lines that define a label or produce code.
+    pub address: Option<u32>,
+    /// The code the line assembled to.
+    pub code: ListingCode,
+    /// The source text of the line.
+    pub text: String,
+    /// The label defined by the line, after local labels have been qualified.
+    pub label: Option<String>,
+    /// The names referenced by the operands of the line.
+    pub references: Vec<String>,
+}
+
+impl ListingLine {
+    /// Creates the line of a command, with no address or code yet.
+    pub fn new(command: &Command) -> Self {
+        Self {
+            location: command.location.clone(),
+            depth: command.expansion_depth,
+            pseudo: command.pseudo.is_some(),
+            address: None,
+            code: ListingCode::None,
+            text: command.text.clone(),
+            label: command.label.clone(),
+            references: references(command),
+        }
+    }
+}
+
+/// The listing of an assembled program.
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    /// The lines, in the order they were assembled.
+    pub lines: Vec<ListingLine>,
+}
+
+impl Listing {
+    /// Formats the listing, one row per line plus continuation rows for long data.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(Option<u32>, String)>` - Each row with the address of the instruction it shows, if any.
+    pub fn rows(&self) -> Vec<(Option<u32>, String)> {
+        let mut rows = vec![(None, format!("{:<16}  {:<10}  {:<23}  {:<3} {}", "Location", "Address", "Code", "", "Source"))];
+        for line in &self.lines {
+            let location = line.location.as_ref().map(short_location).unwrap_or_default();
+            let address = line.address.map(|address| format!("0x{:08X}", address)).unwrap_or_default();
+            let mut marker = if line.depth > 0 { format!("+{}", line.depth) } else { String::new() };
+            if line.pseudo {
+                marker.push('P');
+            }
+            let (code, rest) = match &line.code {
+                ListingCode::None => (String::new(), &[][..]),
+                ListingCode::Instruction(word) => (format!("{:08X}", word), &[][..]),
+                ListingCode::Data(bytes) => {
+                    let (first, rest) = bytes.split_at(bytes.len().min(BYTES_PER_ROW));
+                    (hex_bytes(first), rest)
+                }
+            };
+            let instruction = matches!(line.code, ListingCode::Instruction(_)).then_some(line.address).flatten();
+            rows.push((instruction, format!("{:<16}  {:<10}  {:<23}  {:<3} {}", location, address, code, marker, line.text).trim_end().to_string()));
+            let start = line.address.unwrap_or(0) as usize + BYTES_PER_ROW;
+            for (index, chunk) in rest.chunks(BYTES_PER_ROW).enumerate() {
+                let address = start + index * BYTES_PER_ROW;
+                rows.push((None, format!("{:<16}  0x{:08X}  {}", "", address, hex_bytes(chunk))));
+            }
+        }
+        rows
+    }
+
+    /// Formats where every symbol is defined and which lines refer to it, sorted by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - The symbol table of the program.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - A header followed by one line per symbol.
+    pub fn cross_reference(&self, symbols: &SymbolTable) -> String {
+        let mut table: BTreeMap<&str, (String, Vec<String>)> = symbols.symbols().iter()
+            .map(|symbol| (symbol.name.as_str(), ("-".to_string(), Vec::new())))
+            .collect();
+        for line in &self.lines {
+            let location = line.location.as_ref().map(short_location).unwrap_or_else(|| "-".to_string());
+            if let Some(entry) = line.label.as_deref().and_then(|label| table.get_mut(label)) {
+                entry.0 = location.clone();
+            }
+            for name in &line.references {
+                if let Some(entry) = table.get_mut(name.as_str()) {
+                    if !entry.1.contains(&location) {
+                        entry.1.push(location.clone());
+                    }
+                }
+            }
+        }
+        let mut text = format!("{:<24}  {:<16}  {}\n", "Name", "Defined", "References");
+        for (name, (defined, references)) in table {
+            let row = format!("{:<24}  {:<16}  {}", name, defined, references.join(", "));
+            let _ = writeln!(text, "{}", row.trim_end());
+        }
+        text
+    }
+
+    /// Formats the whole listing file: the listing, the symbol table and the cross-reference table.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - The symbol table of the program.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The contents of a `.lst` file.
+    pub fn render(&self, symbols: &SymbolTable) -> String {
+        let mut text = String::new();
+        for (_, row) in self.rows() {
+            text.push_str(&row);
+            text.push('\n');
+        }
+        let _ = write!(text, "\nSymbols\n\n{}\nCross-reference\n\n{}", symbols.dump(), self.cross_reference(symbols));
+        text
+    }
+}
+
+/// Returns the names a command refers to: its label operands, the names of a `.global` or
+/// `.extern`, and the words of the values of a data directive outside string literals.
+fn references(command: &Command) -> Vec<String> {
+    let mut names: Vec<String> = [&command.operand1, &command.operand2].into_iter()
+        .filter_map(|operand| match operand {
+            Some(Operand::Label(name)) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    if command.opcode == ".global" || command.opcode == ".extern" {
+        names.extend(command.raw_operands.as_deref().unwrap_or("").split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string));
+    }
+    if is_data_directive(&command.opcode) {
+        let values = split_arguments(command.raw_operands.as_deref().unwrap_or(""));
+        for value in values.iter().filter(|value| !value.starts_with(['"', '\''])) {
+            names.extend(value.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@'))
+                .filter(|word| word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+                .map(str::to_string));
+        }
+    }
+    names
+}
+
+/// Formats a location with the file name only, as full paths make the listing hard to read.
+fn short_location(location: &SourceLocation) -> String {
+    match location.file.as_ref().and_then(|file| file.file_name()) {
+        Some(name) => format!("{}:{}", name.to_string_lossy(), location.line),
+        None => format!("{}", location.line),
+    }
+}
+
+/// Formats bytes as space-separated hex pairs.
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod listing_test {
+    use super::*;
+    use crate::utils::assembler::command_processor::{assemble_program, parse_command};
+    use crate::utils::assembler::macros::MacroExpander;
+    use crate::utils::assembler::source::SourceLine;
+
+    fn listing(texts: &[&str]) -> (Listing, SymbolTable) {
+        let lines: Vec<SourceLine> = texts.iter().enumerate()
+            .map(|(index, text)| SourceLine { location: SourceLocation { file: None, line: index + 1 }, text: text.to_string(), depth: 0 })
+            .collect();
+        let commands: Vec<Command> = MacroExpander::default().expand(&lines).unwrap().into_iter()
+            .map(|line| {
+                let mut command = parse_command(&line.text).unwrap();
+                command.location = Some(line.location);
+                command.expansion_depth = line.depth;
+                command
+            })
+            .collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        (program.listing, program.symbols)
+    }
+
+    #[test]
+    fn lines_show_addresses_code_and_expansions() {
+        let (listing, _) = listing(&[
+            ".macro twice", "    INC AX", "    INC AX", ".endmacro",
+            "main: MOVI AX, 1 ; start", "    twice", "    HALT",
+            ".data", "text: .ascii \"abcdefghij\"",
+        ]);
+        let rows: Vec<String> = listing.rows().into_iter().map(|(_, row)| row).collect();
+        assert!(rows[0].starts_with("Location"));
+        assert_eq!(rows[1], format!("{:<16}  0x00000000  {:<23}  {:<3} main: MOVI AX, 1 ; start", "5", "01000001", ""));
+        assert_eq!(rows[2], format!("{:<16}  0x00000004  {:<23}  {:<3}     INC AX", "2", "13000000", "+1"));
+        assert!(rows[5].starts_with("8 "));
+        assert!(rows[6].contains("61 62 63 64 65 66 67 68") && rows[6].ends_with("text: .ascii \"abcdefghij\""));
+        assert!(rows[7].trim_start().starts_with("0x") && rows[7].ends_with("69 6A"));
+
+        let instruction_rows: Vec<Option<u32>> = listing.rows().into_iter().filter_map(|(address, _)| address).map(Some).collect();
+        assert_eq!(instruction_rows, vec![Some(0), Some(4), Some(8), Some(12)]);
+    }
+
+    #[test]
+    fn pseudo_instruction_expansions_are_marked() {
+        let (listing, _) = listing(&["start: CLR AX", "    LA BX, start"]);
+        let rows: Vec<String> = listing.rows().into_iter().map(|(_, row)| row).collect();
+        assert_eq!(rows[1], format!("{:<16}  0x00000000  {:<23}  {:<3} start: CLR AX", "1", "", ""));
+        assert_eq!(rows[2], format!("{:<16}  0x00000000  {:<23}  {:<3}     XOR AX, AX", "1", "23000001", "P"));
+        assert_eq!(rows[3], format!("{:<16}  {:<10}  {:<23}  {:<3}     LA BX, start", "2", "", "", ""));
+        assert_eq!(rows[6], format!("{:<16}  0x0000000C  {:<23}  {:<3}     OR BX, start & 0xFFFF", "2", "22010000", "P"));
+    }
+
+    #[test]
+    fn cross_reference_lists_definitions_and_uses() {
+        let (listing, symbols) = listing(&[
+            "LIMIT: .equ 3", "main: MOVI AX, LIMIT", "    JMP main", "    JMP main", ".data", "table: .word main, main + LIMIT * 2", "unused: .byte 0",
+        ]);
+        let table = listing.cross_reference(&symbols);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], format!("{:<24}  {:<16}  References", "Name", "Defined"));
+        assert_eq!(lines[1], format!("{:<24}  {:<16}  2, 6", "LIMIT", "1"));
+        assert_eq!(lines[2], format!("{:<24}  {:<16}  3, 4, 6", "main", "2"));
+        assert_eq!(lines[4], format!("{:<24}  7", "unused"));
+        assert!(listing.render(&symbols).contains("\nSymbols\n\nAddress"));
+    }
+}
@@ -0,0 +1,630 @@
+//! # Macros Module
+//!
+//! This module defines and expands macros. Macros work on source text, so a
+//! call may pass any operand (registers, addresses, expressions, strings) and
+//! the expanded lines are parsed like any other line. A definition looks like
+//!
+//! ```text
+//! .macro name first, second=default, rest...
+//!     ...
+//! .endmacro
+//! ```
+//!
+//! - Parameters are replaced wherever their name appears as a whole word in
+//!   the body, except inside string literals and comments.
+//! - A parameter with `=default` may be left out of a call.
+//! - The last parameter may end in `...`; it receives all remaining
+//!   arguments, separated by commas.
+//! - Calls pass arguments by position or by name (`name second=4, AX`).
+//! - Bodies may call other macros and define new ones.
+//! - Local labels defined in a body (`.loop:`) are private to each expansion:
+//!   they are renamed to `name.loop@N`, where `N` counts expansions.
+//!
+//! Repetition blocks are expanded the same way, each ending in `.endr`:
+//!
+//! - `.rept COUNT[, VAR]` repeats its body `COUNT` times, with `VAR` replaced
+//!   by the iteration number counting from 0.
+//! - `.irp VAR, VALUE...` repeats its body once for every value.
+//! - `.irpc VAR, CHARS` repeats its body once for every character.
+//!
+//! `COUNT` is an expression over numbers and the `.equ` constants defined
+//! before the block. Local labels in a repetition body are private to each
+//! iteration (`rept.loop@N`).
+//!
+//! The expander also reads `.include` files and evaluates conditional blocks
+//! as it goes, so a branch that is not taken neither defines macros nor
+//! includes files.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::utils::assembler::conditionals::Conditions;
+use crate::utils::assembler::expressions::evaluate;
+use crate::utils::assembler::labels::is_local_label;
+use crate::utils::assembler::source::{strip_comment, Includes, SourceLine, SourceLocation};
+
+/// How deeply expansions may nest before expansion is stopped, which catches recursive macros.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// How many times a repetition block may repeat its body.
+const MAX_REPETITIONS: i64 = 0x10000;
+
+/// A parameter of a macro.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroParam {
+    /// The name replaced in the body.
+    pub name: String,
+    /// The value used when a call does not pass the parameter.
+    pub default: Option<String>,
+    /// Whether the parameter collects all remaining arguments.
+    pub variadic: bool,
+}
+
+/// Represents a macro definition in the assembly code.
+#[derive(Debug, Clone)]
+pub struct Macro {
+    /// The name of the macro.
+    pub name: String,
+    /// The parameters the macro accepts.
+    pub params: Vec<MacroParam>,
+    /// The body of the macro, as the source lines between `.macro` and `.endmacro`.
+    pub body: Vec<SourceLine>,
+}
+
+impl Macro {
+    /// Parses the operands of a `.macro` line: the name followed by the parameters,
+    /// separated by commas or spaces.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Macro, String>` - A macro with an empty body, or an error message.
+    pub fn parse_header(operands: &str) -> Result<Macro, String> {
+        let mut words = operands.split(|c: char| c == ',' || c.is_whitespace()).filter(|word| !word.is_empty());
+        let name = words.next().ok_or(".macro expects a name".to_string())?;
+        if !is_identifier(name) {
+            return Err(format!("Invalid macro name: {}", name));
+        }
+        let mut params: Vec<MacroParam> = Vec::new();
+        for word in words {
+            if params.last().is_some_and(|param| param.variadic) {
+                return Err(format!("The variadic parameter must be the last parameter of macro {}", name));
+            }
+            let (param, default) = match word.split_once('=') {
+                Some((param, default)) => (param, Some(default.to_string())),
+                None => (word, None),
+            };
+            let (param, variadic) = match param.strip_suffix("...") {
+                Some(param) => (param, true),
+                None => (param, false),
+            };
+            if !is_identifier(param) {
+                return Err(format!("Invalid parameter name in macro {}: {}", name, param));
+            }
+            if params.iter().any(|existing| existing.name == param) {
+                return Err(format!("Duplicate parameter in macro {}: {}", name, param));
+            }
+            params.push(MacroParam { name: param.to_string(), default, variadic });
+        }
+        Ok(Macro { name: name.to_string(), params, body: Vec::new() })
+    }
+
+    /// Matches the arguments of a call with the parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `arguments` - The operands of the call, as written.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashMap<&str, String>, String>` - The value of every parameter, or an error message
+    ///   for an unknown, repeated, missing or extra argument.
+    fn bind(&self, arguments: &str) -> Result<HashMap<&str, String>, String> {
+        let mut pieces = split_arguments(arguments);
+        // Calls without commas may separate the arguments with spaces, as in `swap AX BX`
+        if pieces.len() == 1 && self.params.len() > 1 && !arguments.contains(',') {
+            pieces = arguments.split_whitespace().map(str::to_string).collect();
+        }
+
+        let mut values: HashMap<&str, String> = HashMap::new();
+        let mut positional = Vec::new();
+        for piece in pieces {
+            let named = piece.split_once('=')
+                .filter(|(name, value)| !value.starts_with('=') && self.params.iter().any(|param| param.name == name.trim()));
+            match named {
+                Some((name, value)) => {
+                    let param = self.params.iter().find(|param| param.name == name.trim()).map(|param| param.name.as_str()).unwrap_or_default();
+                    if values.insert(param, value.trim().to_string()).is_some() {
+                        return Err(format!("Argument {} of macro {} is given twice", param, self.name));
+                    }
+                }
+                None => positional.push(piece),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        for param in &self.params {
+            if param.variadic {
+                let rest: Vec<String> = positional.by_ref().collect();
+                if !rest.is_empty() || !values.contains_key(param.name.as_str()) {
+                    let value = if rest.is_empty() { param.default.clone().unwrap_or_default() } else { rest.join(", ") };
+                    values.insert(&param.name, value);
+                }
+                continue;
+            }
+            if values.contains_key(param.name.as_str()) {
+                continue;
+            }
+            match positional.next().or_else(|| param.default.clone()) {
+                Some(value) => {
+                    values.insert(&param.name, value);
+                }
+                None => return Err(format!("Missing argument for macro parameter: {}", param.name)),
+            }
+        }
+        if positional.next().is_some() {
+            return Err(format!("Too many arguments for macro {} (expects {})", self.name, self.params.len()));
+        }
+        Ok(values)
+    }
+}
+
+/// A block whose lines are being collected until its closing directive.
+enum OpenBlock<'a> {
+    /// A macro definition, closed by `.endmacro`.
+    Macro(Macro),
+    /// A `.rept`, `.irp` or `.irpc` block, closed by `.endr`.
+    Repeat { directive: &'a str, operands: &'a str, body: Vec<SourceLine> },
+}
+
+impl OpenBlock<'_> {
+    /// Returns whether a directive opens and whether it closes a block of the same kind.
+    fn nests(&self, opcode: &str) -> (bool, bool) {
+        match self {
+            OpenBlock::Macro(_) => (opcode == ".macro", opcode == ".endmacro" || opcode == ".endm"),
+            OpenBlock::Repeat { .. } => (is_repetition(opcode), opcode == ".endr"),
+        }
+    }
+}
+
+/// Returns `true` if `opcode` opens a repetition block.
+fn is_repetition(opcode: &str) -> bool {
+    matches!(opcode, ".rept" | ".irp" | ".irpc")
+}
+
+/// Expands macro calls and repetition blocks in source lines, collecting the macro definitions
+/// it meets on the way.
+///
+/// One expander should be used for a whole program, so the labels made private to each
+/// expansion are unique across it.
+#[derive(Debug, Clone, Default)]
+pub struct MacroExpander {
+    /// The macros defined so far.
+    pub macros: Vec<Macro>,
+    /// The `.equ` constants defined so far, which repetition counts and conditions may use.
+    pub symbols: HashMap<String, i64>,
+    /// Reads the files named by `.include` lines.
+    pub includes: Includes,
+    /// The number of expansions so far, used to make local labels unique.
+    expansions: usize,
+}
+
+impl MacroExpander {
+    /// Creates an expander that knows the given macros.
+    pub fn new(macros: Vec<Macro>) -> Self {
+        Self { macros, ..Self::default() }
+    }
+
+    /// Removes macro definitions from the lines and replaces every macro call and repetition
+    /// block with its expansion, every `.include` with the lines of the file, and every
+    /// conditional block with the lines of the branch that is taken.
+    ///
+    /// A label on a call or a repetition block is kept on a line of its own in front of the
+    /// expansion. Expanded lines keep the location of the line of the body they come from and
+    /// record how deeply nested the expansion that produced them is.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines of the program.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<SourceLine>, String>` - The expanded lines, or an error message with the location
+    ///   of an invalid definition, call, block, condition or include.
+    pub fn expand(&mut self, lines: &[SourceLine]) -> Result<Vec<SourceLine>, String> {
+        let mut expanded = Vec::new();
+        self.expand_into(lines, 0, &mut expanded)?;
+        Ok(expanded)
+    }
+
+    /// Appends the expansion of `lines`, which are nested `depth` expansions deep.
+    fn expand_into(&mut self, lines: &[SourceLine], depth: usize, expanded: &mut Vec<SourceLine>) -> Result<(), String> {
+        // The block being collected, how many blocks of its kind are open inside it, and where it started
+        let mut open: Option<(OpenBlock, usize, &SourceLocation)> = None;
+        // Blocks in macro and repetition bodies are evaluated when the body is expanded
+        let mut conditions = Conditions::default();
+        for line in lines {
+            let (label, opcode, operands) = split_statement(&line.text);
+            let located = |e: String| format!("{}: {}", line.location, e);
+            if let Some((block, nesting, _)) = &mut open {
+                let (opens, closes) = block.nests(opcode);
+                if closes && *nesting == 0 {
+                    let Some((block, _, location)) = open.take() else { unreachable!() };
+                    match block {
+                        OpenBlock::Macro(macro_def) => self.define(macro_def),
+                        OpenBlock::Repeat { directive, operands, body } => {
+                            let iterations = self.repeat(directive, operands, &body).map_err(|e| format!("{}: {}", location, e))?;
+                            self.expand_into(&iterations, depth + 1, expanded)?;
+                        }
+                    }
+                    continue;
+                }
+                if opens {
+                    *nesting += 1;
+                } else if closes {
+                    *nesting -= 1;
+                }
+                match block {
+                    OpenBlock::Macro(macro_def) => macro_def.body.push(line.clone()),
+                    OpenBlock::Repeat { body, .. } => body.push(line.clone()),
+                }
+                continue;
+            }
+            if conditions.apply(opcode, operands, Some(&line.location), |name| self.symbols.get(name).copied()).map_err(located)? || !conditions.active() {
+                continue;
+            }
+            if let Some(included) = self.includes.enter(line)? {
+                let result = self.expand_into(&included, depth, expanded);
+                self.includes.leave();
+                result?;
+                continue;
+            }
+            if depth >= MAX_EXPANSION_DEPTH && (is_repetition(opcode) || self.macros.iter().any(|m| m.name == opcode)) {
+                return Err(located(format!("Expansions nested too deeply (is {} recursive?)", opcode)));
+            }
+            match opcode {
+                ".macro" => open = Some((OpenBlock::Macro(Macro::parse_header(operands).map_err(located)?), 0, &line.location)),
+                ".endmacro" | ".endm" | ".endr" => return Err(located(format!("{} without a matching block", opcode))),
+                _ if is_repetition(opcode) => {
+                    if let Some(label) = label {
+                        expanded.push(SourceLine { location: line.location.clone(), text: format!("{}:", label), depth });
+                    }
+                    open = Some((OpenBlock::Repeat { directive: opcode, operands, body: Vec::new() }, 0, &line.location));
+                }
+                _ => match self.macros.iter().position(|macro_def| macro_def.name == opcode) {
+                    Some(index) => {
+                        if let Some(label) = label {
+                            expanded.push(SourceLine { location: line.location.clone(), text: format!("{}:", label), depth });
+                        }
+                        let body = self.instantiate(index, operands).map_err(located)?;
+                        self.expand_into(&body, depth + 1, expanded)?;
+                    }
+                    None => {
+                        if let (".equ", Some(label)) = (opcode, label) {
+                            if let Ok(value) = evaluate(operands, |name| self.symbols.get(name).copied()) {
+                                self.symbols.insert(label.to_string(), value);
+                            }
+                        }
+                        expanded.push(SourceLine { depth, ..line.clone() });
+                    }
+                },
+            }
+        }
+        match open {
+            Some((OpenBlock::Macro(macro_def), _, location)) => Err(format!("{}: Unclosed macro definition: {}", location, macro_def.name)),
+            Some((OpenBlock::Repeat { directive, .. }, _, location)) => Err(format!("{}: {} without .endr", location, directive)),
+            None => conditions.finish(),
+        }
+    }
+
+    /// Adds a macro, replacing an earlier macro with the same name.
+    fn define(&mut self, macro_def: Macro) {
+        match self.macros.iter_mut().find(|existing| existing.name == macro_def.name) {
+            Some(existing) => *existing = macro_def,
+            None => self.macros.push(macro_def),
+        }
+    }
+
+    /// Returns the body of a macro with the arguments of a call substituted and its local labels renamed.
+    fn instantiate(&mut self, index: usize, arguments: &str) -> Result<Vec<SourceLine>, String> {
+        self.expansions += 1;
+        let macro_def = &self.macros[index];
+        let values = macro_def.bind(arguments)?;
+        Ok(instantiate(&macro_def.name, &macro_def.body, &values, self.expansions))
+    }
+
+    /// Returns the iterations of a `.rept`, `.irp` or `.irpc` block, one after the other.
+    fn repeat(&mut self, directive: &str, operands: &str, body: &[SourceLine]) -> Result<Vec<SourceLine>, String> {
+        let arguments = split_arguments(operands);
+        let (variable, values): (Option<&str>, Vec<String>) = match directive {
+            ".rept" => {
+                let (count, variable) = match arguments.as_slice() {
+                    [count] => (count, None),
+                    [count, variable] => (count, Some(variable.as_str())),
+                    _ => return Err(".rept expects a count and an optional counter name".to_string()),
+                };
+                let count = evaluate(count, |name| self.symbols.get(name).copied())?;
+                if !(0..=MAX_REPETITIONS).contains(&count) {
+                    return Err(format!(".rept count must be between 0 and {}, got {}", MAX_REPETITIONS, count));
+                }
+                (variable, (0..count).map(|index| index.to_string()).collect())
+            }
+            ".irp" => match arguments.split_first() {
+                Some((variable, values)) => (Some(variable.as_str()), values.to_vec()),
+                None => return Err(".irp expects a name and a list of values".to_string()),
+            },
+            _ => match arguments.as_slice() {
+                [variable, characters] => {
+                    let characters = characters.strip_prefix('"').and_then(|c| c.strip_suffix('"')).unwrap_or(characters);
+                    (Some(variable.as_str()), characters.chars().map(String::from).collect())
+                }
+                _ => return Err(".irpc expects a name and a string of characters".to_string()),
+            },
+        };
+        if let Some(variable) = variable.filter(|variable| !is_identifier(variable)) {
+            return Err(format!("Invalid name in {}: {}", directive, variable));
+        }
+
+        let mut iterations = Vec::new();
+        for value in values {
+            self.expansions += 1;
+            let bindings: HashMap<&str, String> = variable.map(|variable| (variable, value)).into_iter().collect();
+            iterations.extend(instantiate(&directive[1..], body, &bindings, self.expansions));
+        }
+        Ok(iterations)
+    }
+}
+
+/// Returns a copy of a body with parameters replaced by their values and the local labels it
+/// defines renamed to `{prefix}{label}@{expansion}`.
+fn instantiate(prefix: &str, body: &[SourceLine], values: &HashMap<&str, String>, expansion: usize) -> Vec<SourceLine> {
+    // Labels inside nested macro definitions and repetition blocks are renamed when those expand
+    let mut private: HashSet<&str> = HashSet::new();
+    let mut nesting = 0usize;
+    for line in body {
+        let (label, opcode, _) = split_statement(&line.text);
+        if nesting == 0 {
+            private.extend(label.filter(|label| is_local_label(label)));
+        }
+        match opcode {
+            ".macro" | ".rept" | ".irp" | ".irpc" => nesting += 1,
+            ".endmacro" | ".endm" | ".endr" => nesting = nesting.saturating_sub(1),
+            _ => {}
+        }
+    }
+    let replace = |word: &str| match values.get(word) {
+        Some(value) => Some(value.clone()),
+        None => private.contains(word).then(|| format!("{}{}@{}", prefix, word, expansion)),
+    };
+    body.iter()
+        .map(|line| SourceLine { text: substitute(&line.text, replace), ..line.clone() })
+        .collect()
+}
+
+/// Returns `true` if `name` can name a macro or a parameter.
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a line into its label, opcode and operands, ignoring the comment.
+fn split_statement(text: &str) -> (Option<&str>, &str, &str) {
+    let (first, rest) = split_word(strip_comment(text));
+    match first.strip_suffix(':') {
+        Some(label) => {
+            let (opcode, operands) = split_word(rest);
+            (Some(label), opcode, operands)
+        }
+        None => (None, first, rest),
+    }
+}
+
+/// Splits off the first word of a text.
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim()),
+        None => (text, ""),
+    }
+}
+
+/// Splits a list of arguments or values at the commas that are not inside quotes, brackets or
+/// parentheses.
+pub(crate) fn split_arguments(arguments: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut nesting = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in arguments.chars() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => nesting += 1,
+            (None, ')' | ']') => nesting = nesting.saturating_sub(1),
+            (None, ',') if nesting == 0 => {
+                pieces.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() || !pieces.is_empty() {
+        pieces.push(current.trim().to_string());
+    }
+    pieces
+}
+
+/// Replaces the whole words of a line for which `replace` returns a value, leaving string
+/// literals and the comment untouched.
+fn substitute(text: &str, replace: impl Fn(&str) -> Option<String>) -> String {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@';
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c == ';' {
+            result.push_str(&text[start..]);
+            break;
+        }
+        if c == '"' || c == '\'' {
+            let mut end = text.len();
+            let mut escaped = false;
+            for (index, next) in chars.by_ref() {
+                if escaped {
+                    escaped = false;
+                } else if next == '\\' {
+                    escaped = true;
+                } else if next == c {
+                    end = index + 1;
+                    break;
+                }
+            }
+            result.push_str(&text[start..end]);
+        } else if is_word(c) {
+            let mut end = start + c.len_utf8();
+            while let Some(&(index, next)) = chars.peek() {
+                if !is_word(next) {
+                    break;
+                }
+                end = index + next.len_utf8();
+                chars.next();
+            }
+            let word = &text[start..end];
+            result.push_str(&replace(word).unwrap_or_else(|| word.to_string()));
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod macros_test {
+    use super::*;
+
+    fn lines(texts: &[&str]) -> Vec<SourceLine> {
+        texts.iter().enumerate()
+            .map(|(index, text)| SourceLine { location: SourceLocation { file: None, line: index + 1 }, text: text.to_string(), depth: 0 })
+            .collect()
+    }
+
+    fn expand(texts: &[&str]) -> Result<Vec<String>, String> {
+        let expanded = MacroExpander::default().expand(&lines(texts))?;
+        Ok(expanded.into_iter().map(|line| line.text.trim().to_string()).collect())
+    }
+
+    #[test]
+    fn arguments_bind_by_position_name_default_and_rest() {
+        let expanded = expand(&[
+            ".macro store value, address=[0x80], regs...",
+            "    MOVI AX, value ; value stays in comments",
+            "    STRW address, AX",
+            "    .byte regs",
+            "    .ascii \"value\"",
+            ".endmacro",
+            "start: store 5",
+            "store address=[0x90], 0x10, 1, 2, (3 + 4)",
+        ]).unwrap();
+        assert_eq!(expanded, vec![
+            "start:",
+            "MOVI AX, 5 ; value stays in comments", "STRW [0x80], AX", ".byte", ".ascii \"value\"",
+            "MOVI AX, 0x10 ; value stays in comments", "STRW [0x90], AX", ".byte 1, 2, (3 + 4)", ".ascii \"value\"",
+        ]);
+    }
+
+    #[test]
+    fn nested_calls_definitions_and_private_labels() {
+        let expanded = expand(&[
+            ".macro spin reg",
+            ".loop: DEC reg",
+            "    JNE .loop",
+            ".endmacro",
+            ".macro twice reg",
+            "    spin reg",
+            "    spin reg",
+            "    .macro inner",
+            "    NOP",
+            "    .endmacro",
+            ".endmacro",
+            "twice CX",
+            "inner",
+        ]).unwrap();
+        assert_eq!(expanded, vec![
+            "spin.loop@2: DEC CX", "JNE spin.loop@2",
+            "spin.loop@3: DEC CX", "JNE spin.loop@3",
+            "NOP",
+        ]);
+    }
+
+    #[test]
+    fn invalid_definitions_and_calls_are_errors() {
+        assert_eq!(expand(&[".macro swap a b", "MOV a, b", ".endmacro", "swap AX BX"]).unwrap(), vec!["MOV AX, BX"]);
+        assert_eq!(expand(&[".macro one a", ".endmacro", "one"]).unwrap_err(), "Line 3: Missing argument for macro parameter: a");
+        assert!(expand(&[".macro one a", ".endmacro", "one 1, 2"]).unwrap_err().contains("Too many arguments"));
+        assert!(expand(&[".macro one a", ".endmacro", "one a=1, a=2"]).unwrap_err().contains("given twice"));
+        assert!(expand(&[".macro bad rest..., a", ".endmacro"]).unwrap_err().contains("must be the last"));
+        assert!(expand(&[".macro bad a a", ".endmacro"]).unwrap_err().contains("Duplicate parameter"));
+        assert_eq!(expand(&["NOP", ".macro open", "NOP"]).unwrap_err(), "Line 2: Unclosed macro definition: open");
+        assert_eq!(expand(&[".endmacro"]).unwrap_err(), "Line 1: .endmacro without a matching block");
+        assert!(expand(&[".macro forever", "forever", ".endmacro", "forever"]).unwrap_err().contains("recursive"));
+    }
+
+    #[test]
+    fn repetition_blocks_expand_with_their_counters() {
+        let expanded = expand(&[
+            "SIZE: .equ 2",
+            "table: .rept SIZE * 2, i",
+            "    .word i * 4",
+            ".endr",
+            ".irp reg, AX, BX",
+            "    .irpc d, \"01\"",
+            "        .if d == 1",
+            "        PUSH reg",
+            "        .endif",
+            "    .endr",
+            ".endr",
+        ]).unwrap();
+        assert_eq!(expanded, vec![
+            "SIZE: .equ 2", "table:", ".word 0 * 4", ".word 1 * 4", ".word 2 * 4", ".word 3 * 4",
+            "PUSH AX", "PUSH BX",
+        ]);
+    }
+
+    #[test]
+    fn macros_defined_in_branches_not_taken_are_ignored() {
+        let source = [
+            ".ifdef FAST", ".macro step", "INC AX", ".endmacro",
+            ".else", ".macro step", "INC BX", ".endmacro", ".endif",
+            "step",
+        ];
+        let mut expander = MacroExpander::default();
+        expander.symbols.insert("FAST".to_string(), 1);
+        let expanded: Vec<String> = expander.expand(&lines(&source)).unwrap().into_iter().map(|line| line.text).collect();
+        assert_eq!(expanded, vec!["INC AX"]);
+        assert_eq!(expand(&source).unwrap(), vec!["INC BX"]);
+        assert_eq!(expand(&[".if 1", "NOP"]).unwrap_err(), "Line 1: .if without .endif");
+        assert_eq!(expand(&[".else"]).unwrap_err(), "Line 1: .else without .if");
+    }
+
+    #[test]
+    fn repetitions_nest_with_macros_and_keep_labels_private() {
+        let expanded = expand(&[
+            ".macro unroll times, reg",
+            "    .rept times",
+            ".next: INC reg",
+            "    JMP .next",
+            "    .endr",
+            ".endmacro",
+            "unroll 2, CX",
+        ]).unwrap();
+        assert_eq!(expanded, vec!["rept.next@2: INC CX", "JMP rept.next@2", "rept.next@3: INC CX", "JMP rept.next@3"]);
+        assert_eq!(expand(&[".rept 2", "NOP"]).unwrap_err(), "Line 1: .rept without .endr");
+        assert_eq!(expand(&[".endr"]).unwrap_err(), "Line 1: .endr without a matching block");
+        assert!(expand(&[".rept -1", ".endr"]).unwrap_err().contains("between 0 and"));
+        assert!(expand(&[".rept COUNT", ".endr"]).unwrap_err().contains("Unknown symbol: COUNT"));
+        assert!(expand(&[".irp", ".endr"]).is_err());
+    }
+}
@@ -1,8 +1,23 @@
 //! # Assembler Module
 //!
 //! This module contains the core components for the ARC assembly process,
-//! including command parsing, operand handling, symbol management, and program assembly.
+//! including command parsing, operand handling, data directives, macros,
+//! expressions, conditional assembly, local and anonymous labels,
+//! pseudo-instructions, symbol management, listings, program assembly,
+//! disassembly and linting.
 
 pub mod command_processor;
+pub mod conditionals;
+pub mod data;
+pub mod disassembler;
+pub mod expressions;
+pub mod labels;
+pub mod lint;
+pub mod listing;
+pub mod macros;
 pub mod operands;
+pub mod pseudo;
+pub mod relocations;
+pub mod source;
+pub mod symbol_table;
 pub mod symbols;
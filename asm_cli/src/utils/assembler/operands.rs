@@ -1,4 +1,5 @@
 use crate::memory::registers::Reg;
+use crate::utils::assembler::expressions::evaluate;
 // Removed: use crate::utils::logger; // Import the logger module
 
 /// Represents the different types of operands an assembly instruction can have.
@@ -51,6 +52,7 @@ fn parse_register(input: &str) -> Result<Reg, String> {
 /// - Flag names (case-insensitive)
 /// - Memory addresses (enclosed in square brackets, can be decimal or hexadecimal)
 /// - Immediate values (decimal, hexadecimal with `0x` prefix, or binary with `0b` prefix)
+/// - Constant expressions over numbers (e.g. `2 * 4 + 1`)
 /// - Labels (any other valid identifier)
 ///
 /// # Arguments
@@ -154,7 +156,12 @@ pub fn parse_operand(input: &str) -> Result<Operand, String> {
         return Ok(Operand::Immediate(num));
     }
 
-    // 6. If all else fails, it's a Label
+    // 6. Constant expressions, such as the `i * 4` of a `.rept` body after its counter is substituted
+    if let Ok(value) = evaluate(input, |_| None) {
+        return Ok(Operand::Immediate(value as u32));
+    }
+
+    // 7. If all else fails, it's a Label
     // Basic validation: labels shouldn't contain whitespace or brackets.
     if !input.is_empty() && !input.contains(|c: char| c.is_whitespace() || c == '[' || c == ']') {
         return Ok(Operand::Label(input.to_string()));
@@ -0,0 +1,339 @@
+//! # Pseudo-instructions Module
+//!
+//! This module expands pseudo-instructions, common idioms that the assembler
+//! turns into a fixed sequence of real instructions, and recognizes those
+//! sequences again in disassembly. Every pseudo-instruction always expands to
+//! the same number of words, so pass 1 can size it before any label is known:
+//!
+//! | Pseudo-instruction | Words | Expansion |
+//! |---|---|---|
+//! | `NOP` | 1 | `OR AX, 0` |
+//! | `CLR reg` | 1 | `XOR reg, reg` |
+//! | `LI reg, imm32` | 4 | `MOVI reg, hi` / `SHL reg, 16` / `OR reg, lo` / `NOP`, or with the halves inverted and `NOT reg` last when `imm32` is odd |
+//! | `LA reg, label` | 3 | `MOVI reg, label >> 16` / `SHL reg, 16` / `OR reg, label & 0xFFFF` |
+//! | `JZ reg, target` | 2 | `CMPW reg, 0` / `JE target` |
+//! | `JNZ reg, target` | 2 | `CMPW reg, 0` / `JNE target` |
+//! | `CALLR reg` | 1 | `CALL [reg]` |
+//! | `PUSHA` | 8 | `PUSH AX` ... `PUSH HX` |
+//! | `POPA` | 8 | `POP HX` ... `POP AX` |
+//!
+//! The register form of `OR` is told apart from the immediate form by bit 0,
+//! so `OR` can only load even low halves; `LI` loads an odd value by building
+//! its complement and inverting it. `LA` has no such choice, as the address is
+//! only known in pass 2, so the address must be even. `JZ` and `JNZ` compare
+//! the way `CMPW` does, which also treats `0x80000000` (-0.0) as zero.
+
+use std::collections::HashMap;
+
+use crate::memory::registers::Reg;
+use crate::utils::assembler::command_processor::Command;
+use crate::utils::assembler::disassembler::{decode, register_name, Instruction, Labels};
+use crate::utils::assembler::operands::Operand;
+
+/// The registers saved by `PUSHA`, in the order they are pushed; `POPA` pops them in reverse.
+const SAVED_REGISTERS: [Reg; 8] = [Reg::AX, Reg::BX, Reg::CX, Reg::DX, Reg::EX, Reg::FX, Reg::GX, Reg::HX];
+
+/// Returns `true` if `opcode` names a pseudo-instruction.
+pub fn is_pseudo_instruction(opcode: &str) -> bool {
+    matches!(opcode.to_uppercase().as_str(), "NOP" | "CLR" | "LI" | "LA" | "JZ" | "JNZ" | "CALLR" | "PUSHA" | "POPA")
+}
+
+/// Replaces every pseudo-instruction with its expansion.
+///
+/// The line of a pseudo-instruction is kept without its opcode, so its label and its source
+/// text stay in the listing, and is followed by the real instructions, each marked with the
+/// name of the pseudo-instruction in `Command::pseudo`.
+///
+/// # Arguments
+///
+/// * `commands` - The commands of the program, with macros expanded and conditional blocks applied.
+///
+/// # Returns
+///
+/// * `Result<Vec<Command>, String>` - The expanded commands, or an error message for a
+///   pseudo-instruction with invalid operands.
+pub fn expand_pseudo_instructions(commands: &[Command]) -> Result<Vec<Command>, String> {
+    let mut expanded = Vec::with_capacity(commands.len());
+    for command in commands {
+        if !is_pseudo_instruction(&command.opcode) {
+            expanded.push(command.clone());
+            continue;
+        }
+        let instructions = expand(command).map_err(|e| command.error_at(e))?;
+        let mut line = command.clone();
+        line.opcode = String::new();
+        line.operand1 = None;
+        line.operand2 = None;
+        line.raw_operands = None;
+        expanded.push(line);
+        expanded.extend(instructions);
+    }
+    Ok(expanded)
+}
+
+/// Returns the instructions a pseudo-instruction expands to.
+fn expand(command: &Command) -> Result<Vec<Command>, String> {
+    let name = command.opcode.to_uppercase();
+    let instruction = |opcode: &str, operand1: Option<Operand>, operand2: Option<Operand>, text: String| Command {
+        opcode: opcode.to_string(),
+        operand1,
+        operand2,
+        label: None,
+        text_start_address: None,
+        stack_start_address: None,
+        stack_segment_size: None,
+        location: command.location.clone(),
+        raw_operands: None,
+        text: format!("    {}", text),
+        expansion_depth: command.expansion_depth,
+        pseudo: Some(name.clone()),
+    };
+    let register = || match &command.operand1 {
+        Some(Operand::Register(reg)) => Ok(reg.clone()),
+        _ => Err(format!("{} expects a register as its first operand", name)),
+    };
+    let no_operands = || match (&command.operand1, &command.operand2) {
+        (None, None) => Ok(()),
+        _ => Err(format!("{} takes no operands", name)),
+    };
+    let nop = || instruction("OR", Some(Operand::Register(Reg::AX)), Some(Operand::Immediate(0)), "OR AX, 0".to_string());
+
+    match name.as_str() {
+        "NOP" => {
+            no_operands()?;
+            Ok(vec![nop()])
+        }
+        "CLR" => {
+            let reg = register()?;
+            let r = register_name(&reg);
+            Ok(vec![instruction("XOR", Some(Operand::Register(reg.clone())), Some(Operand::Register(reg)), format!("XOR {}, {}", r, r))])
+        }
+        "LI" => {
+            let reg = register()?;
+            let r = register_name(&reg);
+            let value = match &command.operand2 {
+                Some(Operand::Immediate(value)) => *value,
+                Some(Operand::Label(name)) => return Err(format!("LI needs a numeric value, not {}; use LA to load the address of a label", name)),
+                _ => return Err("LI expects a register and a numeric value".to_string()),
+            };
+            // `OR` cannot encode an odd immediate, so odd values are loaded inverted and then flipped
+            let odd = value & 1 == 1;
+            let loaded = if odd { !value } else { value };
+            let (high, low) = (loaded >> 16, loaded & 0xFFFF);
+            let last = if odd {
+                instruction("NOT", Some(Operand::Register(reg.clone())), None, format!("NOT {}", r))
+            } else {
+                nop()
+            };
+            Ok(vec![
+                instruction("MOVI", Some(Operand::Register(reg.clone())), Some(Operand::Immediate(high)), format!("MOVI {}, 0x{:04X}", r, high)),
+                instruction("SHL", Some(Operand::Register(reg.clone())), Some(Operand::Immediate(16)), format!("SHL {}, 16", r)),
+                instruction("OR", Some(Operand::Register(reg)), Some(Operand::Immediate(low)), format!("OR {}, 0x{:04X}", r, low)),
+                last,
+            ])
+        }
+        "LA" => {
+            let reg = register()?;
+            let r = register_name(&reg);
+            let (high, low, target) = match &command.operand2 {
+                // The halves of a label are filled in by `resolve_load_address` in pass 2
+                Some(Operand::Label(label)) => (Operand::Label(label.clone()), Operand::Label(label.clone()), label.clone()),
+                Some(Operand::Immediate(address)) | Some(Operand::Address(address)) => {
+                    if address & 1 == 1 {
+                        return Err(format!("LA needs an even address, not 0x{:X}", address));
+                    }
+                    (Operand::Immediate(address >> 16), Operand::Immediate(address & 0xFFFF), format!("0x{:X}", address))
+                }
+                _ => return Err("LA expects a register and a label".to_string()),
+            };
+            Ok(vec![
+                instruction("MOVI", Some(Operand::Register(reg.clone())), Some(high), format!("MOVI {}, {} >> 16", r, target)),
+                instruction("SHL", Some(Operand::Register(reg.clone())), Some(Operand::Immediate(16)), format!("SHL {}, 16", r)),
+                instruction("OR", Some(Operand::Register(reg)), Some(low), format!("OR {}, {} & 0xFFFF", r, target)),
+            ])
+        }
+        "JZ" | "JNZ" => {
+            let reg = register()?;
+            let r = register_name(&reg);
+            let target = command.operand2.clone().ok_or_else(|| format!("{} expects a register and a jump target", name))?;
+            let target_text = command.raw_operands.as_deref().and_then(|operands| operands.split_once(',')).map(|(_, target)| target.trim()).unwrap_or_default();
+            let jump = if name == "JZ" { "JE" } else { "JNE" };
+            Ok(vec![
+                instruction("CMPW", Some(Operand::Register(reg)), Some(Operand::Immediate(0)), format!("CMPW {}, 0", r)),
+                instruction(jump, Some(target), None, format!("{} {}", jump, target_text)),
+            ])
+        }
+        "CALLR" => {
+            let reg = register()?;
+            let r = register_name(&reg);
+            Ok(vec![instruction("CALL", Some(Operand::AddressRegister(reg)), None, format!("CALL [{}]", r))])
+        }
+        "PUSHA" => {
+            no_operands()?;
+            Ok(SAVED_REGISTERS.iter()
+                .map(|reg| instruction("PUSH", Some(Operand::Register(reg.clone())), None, format!("PUSH {}", register_name(reg))))
+                .collect())
+        }
+        "POPA" => {
+            no_operands()?;
+            Ok(SAVED_REGISTERS.iter().rev()
+                .map(|reg| instruction("POP", Some(Operand::Register(reg.clone())), None, format!("POP {}", register_name(reg))))
+                .collect())
+        }
+        _ => Err(format!("Unknown pseudo-instruction: {}", name)),
+    }
+}
+
+/// Fills in the address halves loaded by the `MOVI` and `OR` of an `LA` expansion.
+///
+/// # Arguments
+///
+/// * `command` - A command of pass 2.
+/// * `symbol_table` - The symbol table of the program.
+///
+/// # Returns
+///
+/// * `Result<Option<Command>, String>` - The command with the half as an immediate, `None` for
+///   any other command, or an error message for an unknown label or an odd address.
+pub fn resolve_load_address(command: &Command, symbol_table: &HashMap<String, u32>) -> Result<Option<Command>, String> {
+    let Some(Operand::Label(label)) = command.operand2.as_ref().filter(|_| command.pseudo.as_deref() == Some("LA")) else {
+        return Ok(None);
+    };
+    let address = *symbol_table.get(label).ok_or(format!("Unknown label: {}", label))?;
+    if address & 1 == 1 {
+        return Err(format!("LA needs an even address, but {} is at 0x{:X}; align it with .align 2", label, address));
+    }
+    let half = if command.opcode == "MOVI" { address >> 16 } else { address & 0xFFFF };
+    let mut resolved = command.clone();
+    resolved.operand2 = Some(Operand::Immediate(half));
+    Ok(Some(resolved))
+}
+
+/// Recognizes the expansion of a pseudo-instruction at the start of a run of instruction words.
+///
+/// # Arguments
+///
+/// * `words` - The instruction words, starting at the word to look at.
+/// * `labels` - The labels of the program, to name `LA` addresses and jump targets.
+///
+/// # Returns
+///
+/// * `Option<(String, usize)>` - The pseudo-instruction as it would be written in a source
+///   file and the number of words of its expansion, or `None`.
+pub fn recognize(words: &[u32], labels: &Labels) -> Option<(String, usize)> {
+    let decoded: Vec<Instruction> = words.iter().take(SAVED_REGISTERS.len()).map_while(|&word| decode(word).ok()).collect();
+    let operands = |index: usize| decoded.get(index).map(|instruction| (instruction.mnemonic, instruction.operands.as_slice()));
+    match operands(0)? {
+        ("OR", [Operand::Register(Reg::AX), Operand::Immediate(0)]) => Some(("NOP".to_string(), 1)),
+        ("XOR", [Operand::Register(first), Operand::Register(second)]) if first == second => {
+            Some((format!("CLR {}", register_name(first)), 1))
+        }
+        ("CALL", [Operand::AddressRegister(reg)]) => Some((format!("CALLR {}", register_name(reg)), 1)),
+        ("CMPW", [Operand::Register(reg), Operand::Immediate(0)]) => {
+            let jump = decoded.get(1)?;
+            let name = match jump.mnemonic {
+                "JE" => "JZ",
+                "JNE" => "JNZ",
+                _ => return None,
+            };
+            let text = jump.format(labels);
+            let target = text.split_once(' ').map(|(_, target)| target).unwrap_or_default();
+            Some((format!("{} {}, {}", name, register_name(reg), target), 2))
+        }
+        ("MOVI", [Operand::Register(reg), Operand::Immediate(high)]) => {
+            let same = |operand: &Operand| matches!(operand, Operand::Register(other) if other == reg);
+            if !matches!(operands(1)?, ("SHL", [first, Operand::Immediate(16)]) if same(first)) {
+                return None;
+            }
+            let ("OR", [first, Operand::Immediate(low)]) = operands(2)? else { return None };
+            if !same(first) {
+                return None;
+            }
+            let value = (high << 16) | low;
+            let r = register_name(reg);
+            match operands(3) {
+                Some(("OR", [Operand::Register(Reg::AX), Operand::Immediate(0)])) => Some((format!("LI {}, 0x{:08X}", r, value), 4)),
+                Some(("NOT", [last])) if same(last) => Some((format!("LI {}, 0x{:08X}", r, !value), 4)),
+                _ => match labels.get(value) {
+                    Some(label) => Some((format!("LA {}, {}", r, label), 3)),
+                    None => Some((format!("LA {}, 0x{:08X}", r, value), 3)),
+                },
+            }
+        }
+        ("PUSH", _) | ("POP", _) => {
+            let (mnemonic, order): (&str, Vec<&Reg>) = if decoded[0].mnemonic == "PUSH" {
+                ("PUSH", SAVED_REGISTERS.iter().collect())
+            } else {
+                ("POP", SAVED_REGISTERS.iter().rev().collect())
+            };
+            let saved = order.iter().enumerate().all(|(index, reg)| {
+                matches!(operands(index), Some((m, [Operand::Register(other)])) if m == mnemonic && other == *reg)
+            });
+            saved.then(|| (format!("{}A", mnemonic), SAVED_REGISTERS.len()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod pseudo_test {
+    use super::*;
+    use crate::utils::assembler::command_processor::{assemble_program, parse_command};
+
+    fn assemble(lines: &[&str]) -> Result<Vec<u32>, String> {
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        assemble_program(&commands, &[], 0x10000).map(|program| program.text)
+    }
+
+    #[test]
+    fn pseudo_instructions_expand_to_fixed_sequences() {
+        assert_eq!(assemble(&["NOP"]).unwrap(), vec![0x2200_0000]);
+        assert_eq!(assemble(&["CLR CX"]).unwrap(), vec![0x2302_0201]);
+        assert_eq!(assemble(&["LI BX, 0x12345678"]).unwrap(), vec![0x0101_1234, 0x2401_0010, 0x2201_5678, 0x2200_0000]);
+        assert_eq!(assemble(&["LI BX, 0x12345679"]).unwrap(), vec![0x0101_EDCB, 0x2401_0010, 0x2201_A986, 0x2001_0000]);
+        assert_eq!(assemble(&["JZ AX, 0x40", "JNZ AX, 0x40"]).unwrap(), vec![0x3000_0000, 0x4300_0040, 0x3000_0000, 0x4400_0040]);
+        assert_eq!(assemble(&["CALLR DX"]).unwrap(), vec![0x4198_0000]);
+        assert_eq!(assemble(&["PUSHA"]).unwrap().len(), 8);
+        assert_eq!(assemble(&["POPA"]).unwrap()[0], 0x0807_0000);
+    }
+
+    #[test]
+    fn labels_after_pseudo_instructions_account_for_their_size() {
+        let lines = ["main: LI AX, 1", "PUSHA", "LA BX, end", "JZ AX, main", "end: HALT"];
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        let program = assemble_program(&commands, &[], 0x10000).unwrap();
+        assert_eq!(program.label_address("end"), Some(4 * (4 + 8 + 3 + 2)));
+        assert_eq!(program.text[12..15], [0x0101_0000, 0x2401_0010, 0x2201_0044]);
+        assert_eq!(program.text[16], 0x4300_0000);
+    }
+
+    #[test]
+    fn invalid_operands_are_reported() {
+        assert!(assemble(&["LI AX, SIZE"]).unwrap_err().contains("use LA"));
+        assert!(assemble(&["CLR 5"]).unwrap_err().contains("expects a register"));
+        assert!(assemble(&["NOP AX"]).unwrap_err().contains("takes no operands"));
+        assert!(assemble(&["LA AX, text", "HALT", ".data", ".byte 1", "text: .byte 2"]).unwrap_err().contains("even address"));
+    }
+
+    #[test]
+    fn expansions_are_recognized() {
+        let lines = ["NOP", "CLR CX", "LI BX, 0x12345679", "LI BX, 0x00010000", "LA AX, main", "JNZ AX, main", "CALLR DX", "PUSHA", "POPA"];
+        let mut source = vec!["main: INC AX"];
+        source.extend(lines);
+        let words = assemble(&source).unwrap();
+        let commands: Vec<Command> = source.iter().map(|line| parse_command(line).unwrap()).collect();
+        let labels = Labels::new(&assemble_program(&commands, &[], 0x10000).unwrap().symbols);
+
+        let mut index = 1;
+        let mut recognized = Vec::new();
+        while index < words.len() {
+            let (text, count) = recognize(&words[index..], &labels).unwrap();
+            recognized.push(text);
+            index += count;
+        }
+        assert_eq!(recognized, [
+            "NOP", "CLR CX", "LI BX, 0x12345679", "LI BX, 0x00010000", "LA AX, main", "JNZ AX, main", "CALLR DX", "PUSHA", "POPA",
+        ]);
+        assert_eq!(recognize(&[0x0100_0001], &labels), None);
+    }
+}
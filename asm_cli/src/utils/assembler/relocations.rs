@@ -0,0 +1,158 @@
+//! # Relocations Module
+//!
+//! This module finds the fields of an object file that the linker has to
+//! patch. An instruction operand naming a label is relocated against the
+//! label's section, and one naming an `.extern` symbol against that symbol.
+//! Values of `.word`, `.half` and `.byte` lists may be expressions: each is
+//! evaluated again with the sections and external symbols moved, and the
+//! value must move along with exactly one of them (as in `table + 4`) or
+//! with none (as in `end - start`). Any other use of a relocatable symbol
+//! cannot be expressed as a relocation and is reported as an error.
+
+use std::collections::HashSet;
+
+use crate::utils::assembler::command_processor::Command;
+use crate::utils::assembler::data::list_values;
+use crate::utils::assembler::expressions::evaluate;
+use crate::utils::assembler::listing::{Listing, ListingCode};
+use crate::utils::assembler::operands::Operand;
+use crate::utils::assembler::symbol_table::{SymbolSection, SymbolTable};
+use crate::utils::linker::object::{Relocation, RelocationField, RelocationTarget};
+
+/// How far a section or external symbol is moved to see whether a value depends on it. It is odd
+/// so that masks and shifts of an address are caught as well.
+const SHIFT: i64 = 0x0100_0193;
+
+/// What an address moves with when the linker places the sections.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Base {
+    Section(SymbolSection),
+    External(String),
+}
+
+/// Finds the relocations of a program assembled with its sections at address 0.
+///
+/// # Arguments
+///
+/// * `commands` - The prepared commands, in the order pass 2 assembled them.
+/// * `listing` - The listing of pass 2, with one line per command.
+/// * `symbols` - The symbols, with labels at their section offsets.
+///
+/// # Returns
+///
+/// * `Result<Vec<Relocation>, String>` - The relocations, or an error message for a value that
+///   cannot be relocated.
+pub fn find_relocations(commands: &[Command], listing: &Listing, symbols: &SymbolTable) -> Result<Vec<Relocation>, String> {
+    let mut relocations = Vec::new();
+    for (command, line) in commands.iter().zip(&listing.lines) {
+        let Some(offset) = line.address else { continue };
+        match &line.code {
+            ListingCode::Instruction(_) => {
+                for name in [&command.operand1, &command.operand2].into_iter().flatten().filter_map(|operand| match operand {
+                    Operand::Label(name) => Some(name),
+                    _ => None,
+                }) {
+                    let Some(symbol) = symbols.get(name) else { continue };
+                    let Some(base) = symbol_base(symbol.section, name) else { continue };
+                    let (target, addend) = target(base, symbol.address as i64);
+                    let field = instruction_field(command)
+                        .ok_or_else(|| command.error_at(format!("{} cannot refer to the relocatable symbol {}", command.opcode, name)))?;
+                    relocations.push(Relocation { section: SymbolSection::Text, offset, field, target, addend });
+                }
+            }
+            ListingCode::Data(_) => match list_values(command) {
+                Some(values) => {
+                    let (width, values) = values.map_err(|e| command.error_at(e))?;
+                    let field = match width {
+                        4 => RelocationField::Word,
+                        2 => RelocationField::Half,
+                        _ => RelocationField::Byte,
+                    };
+                    for (index, value) in values.iter().enumerate() {
+                        if let Some((target, addend)) = relocate_value(value, symbols).map_err(|e| command.error_at(e))? {
+                            relocations.push(Relocation { section: SymbolSection::Data, offset: offset + (index * width) as u32, field, target, addend });
+                        }
+                    }
+                }
+                // Strings and files hold no symbols; `.fill` values are repeated, so they cannot be patched
+                None if command.opcode == ".fill" && !bases(command.raw_operands.as_deref().unwrap_or(""), symbols).is_empty() => {
+                    return Err(command.error_at(".fill values cannot refer to relocatable symbols in object files".to_string()));
+                }
+                None => {}
+            },
+            ListingCode::None => {}
+        }
+    }
+    Ok(relocations)
+}
+
+/// Returns the field holding the address operand of an instruction.
+fn instruction_field(command: &Command) -> Option<RelocationField> {
+    let opcode = command.opcode.to_lowercase();
+    // The `MOVI` and `OR` of an `LA` load the two halves of the address
+    if command.pseudo.as_deref() == Some("LA") {
+        return match opcode.as_str() {
+            "movi" => Some(RelocationField::High16),
+            "or" => Some(RelocationField::Low16),
+            _ => None,
+        };
+    }
+    match opcode.as_str() {
+        "movi" | "lodi" => Some(RelocationField::Immediate16),
+        "lodw" | "push" | "outi" => Some(RelocationField::Address16),
+        "jmp" | "call" | "je" | "jne" | "jgt" | "jge" | "jlt" | "jle" | "js" | "jco" | "in" | "out" | "insi" => Some(RelocationField::Address24),
+        "stri" | "strw" | "insw" | "outw" => Some(RelocationField::Address7),
+        _ => None,
+    }
+}
+
+/// Returns what a symbol moves with, or `None` for a constant.
+fn symbol_base(section: SymbolSection, name: &str) -> Option<Base> {
+    match section {
+        SymbolSection::Absolute => None,
+        SymbolSection::External => Some(Base::External(name.to_string())),
+        section => Some(Base::Section(section)),
+    }
+}
+
+/// Returns the relocation target of a base together with the addend.
+fn target(base: Base, addend: i64) -> (RelocationTarget, i64) {
+    match base {
+        Base::Section(section) => (RelocationTarget::Section(section), addend),
+        Base::External(name) => (RelocationTarget::Symbol(name), addend),
+    }
+}
+
+/// Returns the bases of the relocatable symbols named in a text.
+fn bases(text: &str, symbols: &SymbolTable) -> HashSet<Base> {
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@'))
+        .filter_map(|word| symbols.get(word).and_then(|symbol| symbol_base(symbol.section, word)))
+        .collect()
+}
+
+/// Works out how a data value moves with the sections and external symbols.
+///
+/// # Returns
+///
+/// * `Result<Option<(RelocationTarget, i64)>, String>` - The target and addend, `None` for a value
+///   that does not move, or an error message for a value that cannot be relocated.
+fn relocate_value(value: &str, symbols: &SymbolTable) -> Result<Option<(RelocationTarget, i64)>, String> {
+    let evaluate_moved = |moved: Option<&Base>| evaluate(value, |name| {
+        let symbol = symbols.get(name)?;
+        let shift = match (moved, symbol_base(symbol.section, name)) {
+            (Some(moved), Some(base)) if *moved == base => SHIFT,
+            _ => 0,
+        };
+        Some(symbol.address as i64 + shift)
+    });
+    let unmoved = evaluate_moved(None)?;
+    let mut moving = None;
+    for base in bases(value, symbols) {
+        match evaluate_moved(Some(&base))? - unmoved {
+            0 => {}
+            SHIFT if moving.is_none() => moving = Some(base),
+            _ => return Err(format!("The value {} cannot be relocated", value)),
+        }
+    }
+    Ok(moving.map(|base| target(base, unmoved)))
+}
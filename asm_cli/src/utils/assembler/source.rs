@@ -0,0 +1,236 @@
+//! # Source Module
+//!
+//! This module splits source files into lines and reads the files named by
+//! `.include "file.arc"` lines, which are looked up relative to the including
+//! file first and then in the include path. The macro expander replaces an
+//! `.include` with the lines of the file when it reaches it, so includes in
+//! conditional branches that are not taken are never read. Every line
+//! remembers the file and line it came from, so diagnostics and the source
+//! map of the assembled program point to the right place.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// The file and line a command was read from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    /// The file, or `None` for source that was not read from a file (e.g. an unsaved buffer).
+    pub file: Option<PathBuf>,
+    /// The line number, counting from 1.
+    pub line: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}", file.display(), self.line),
+            None => write!(f, "Line {}", self.line),
+        }
+    }
+}
+
+/// A line of source together with where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// The location of the line.
+    pub location: SourceLocation,
+    /// The text of the line.
+    pub text: String,
+    /// How many macro or repetition expansions deep the line was produced; 0 for a line of the source.
+    pub depth: usize,
+}
+
+/// Splits the source of a file into lines.
+///
+/// # Arguments
+///
+/// * `source` - The source of the file.
+/// * `file` - The path of the file, if it has one.
+///
+/// # Returns
+///
+/// * `Vec<SourceLine>` - The lines, each with its location.
+pub fn source_lines(source: &str, file: Option<&Path>) -> Vec<SourceLine> {
+    source.lines().enumerate()
+        .map(|(index, text)| SourceLine { location: SourceLocation { file: file.map(Path::to_path_buf), line: index + 1 }, text: text.to_string(), depth: 0 })
+        .collect()
+}
+
+/// Reads the files named by `.include` lines, keeping track of the files being included so
+/// include cycles are reported.
+#[derive(Debug, Clone, Default)]
+pub struct Includes {
+    /// The directories searched for included files not found next to the including file.
+    include_paths: Vec<PathBuf>,
+    /// The directory includes of a source without a file are resolved against.
+    base_directory: PathBuf,
+    /// The files being included, outermost first.
+    stack: Vec<PathBuf>,
+}
+
+impl Includes {
+    /// Creates the includes of a program.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The path of the main file, if it has one; includes are resolved relative to its directory.
+    /// * `include_paths` - The directories searched for included files not found next to the including file.
+    /// * `base_directory` - The directory includes of a source without a file are resolved against.
+    pub fn new(file: Option<&Path>, include_paths: &[PathBuf], base_directory: &Path) -> Self {
+        Self {
+            include_paths: include_paths.to_vec(),
+            base_directory: base_directory.to_path_buf(),
+            stack: file.map(canonical).into_iter().collect(),
+        }
+    }
+
+    /// Reads the file an `.include` line names and marks it as being included until `leave`.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - A line of the program.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Vec<SourceLine>>, String>` - The lines of the included file, `None` if the
+    ///   line is not an `.include`, or an error message with the location of the line (a missing
+    ///   file or an include cycle).
+    pub fn enter(&mut self, line: &SourceLine) -> Result<Option<Vec<SourceLine>>, String> {
+        let location = &line.location;
+        let Some(name) = parse_include(&line.text).map_err(|e| format!("{}: {}", location, e))? else {
+            return Ok(None);
+        };
+        let directory = location.file.as_deref().and_then(Path::parent).unwrap_or(&self.base_directory);
+        let path = resolve_include(&name, directory, &self.include_paths)
+            .ok_or(format!("{}: Included file not found: {}", location, name))?;
+        let key = canonical(&path);
+        if let Some(start) = self.stack.iter().position(|included| *included == key) {
+            let cycle: Vec<String> = self.stack[start..].iter().chain([&key]).map(|p| p.display().to_string()).collect();
+            return Err(format!("{}: Include cycle: {}", location, cycle.join(" -> ")));
+        }
+        let included = std::fs::read_to_string(&path)
+            .map_err(|e| format!("{}: Error reading {}: {}", location, path.display(), e))?;
+        self.stack.push(key);
+        Ok(Some(source_lines(&included, Some(&path))))
+    }
+
+    /// Marks the innermost included file as read.
+    pub fn leave(&mut self) {
+        self.stack.pop();
+    }
+}
+
+/// Returns a line without its comment, which starts at the first `;` outside a string or
+/// character literal.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ';' => return &line[..index],
+            None => {}
+        }
+    }
+    line
+}
+
+/// Returns the file named by an `.include` line, `None` for any other line.
+fn parse_include(line: &str) -> Result<Option<String>, String> {
+    let code = strip_comment(line).trim();
+    let Some(rest) = code.strip_prefix(".include") else { return Ok(None) };
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return Ok(None);
+    }
+    let name = rest.trim();
+    match name.strip_prefix('"').and_then(|name| name.strip_suffix('"')) {
+        Some(name) if !name.is_empty() => Ok(Some(name.to_string())),
+        _ => Err(format!(".include expects a quoted file name, got: {}", name)),
+    }
+}
+
+/// Finds an included file next to the including file or in the include path.
+fn resolve_include(name: &str, directory: &Path, include_paths: &[PathBuf]) -> Option<PathBuf> {
+    let name = Path::new(name);
+    if name.is_absolute() {
+        return name.is_file().then(|| name.to_path_buf());
+    }
+    std::iter::once(directory)
+        .chain(include_paths.iter().map(PathBuf::as_path))
+        .map(|directory| directory.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Returns a path that is the same for every way of naming a file, used to detect cycles.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod source_test {
+    use super::*;
+    use crate::utils::assembler::macros::MacroExpander;
+
+    /// Expands the includes of a program the way the assembler does.
+    fn expand_includes(source: &str, file: Option<&Path>, include_paths: &[PathBuf], base_directory: &Path) -> Result<Vec<SourceLine>, String> {
+        let mut expander = MacroExpander::default();
+        expander.includes = Includes::new(file, include_paths, base_directory);
+        expander.expand(&source_lines(source, file))
+    }
+
+    /// Creates a fresh directory for the files of one test.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("arcs-source-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(directory.join("lib")).unwrap();
+        directory
+    }
+
+    #[test]
+    fn includes_are_expanded_with_their_locations() {
+        let directory = test_directory("expand");
+        std::fs::write(directory.join("util.arc"), "INC AX\n.include \"math.arc\" ; from the include path\n").unwrap();
+        std::fs::write(directory.join("lib/math.arc"), "DEC AX\n").unwrap();
+        let main = directory.join("main.arc");
+
+        let lines = expand_includes("MOVI AX, 1\n.include \"util.arc\"\nHALT", Some(&main), &[directory.join("lib")], &directory).unwrap();
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, vec!["MOVI AX, 1", "INC AX", "DEC AX", "HALT"]);
+        assert_eq!(lines[2].location, SourceLocation { file: Some(directory.join("lib").join("math.arc")), line: 1 });
+        assert_eq!(lines[3].location.to_string(), format!("{}:3", main.display()));
+
+        let error = expand_includes(".include \"missing.arc\"", None, &[], &directory).unwrap_err();
+        assert_eq!(error, "Line 1: Included file not found: missing.arc");
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn includes_in_branches_not_taken_are_not_read() {
+        let directory = test_directory("conditional");
+        std::fs::write(directory.join("fast.arc"), "INC AX\n").unwrap();
+        let source = ".ifdef SLOW\n.include \"missing.arc\"\n.else\n.include \"fast.arc\"\n.endif\nHALT";
+        let lines = expand_includes(source, None, &[], &directory).unwrap();
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, vec!["INC AX", "HALT"]);
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let directory = test_directory("cycle");
+        std::fs::write(directory.join("a.arc"), ".include \"b.arc\"\n").unwrap();
+        std::fs::write(directory.join("b.arc"), "NOP\n.include \"a.arc\"\n").unwrap();
+
+        let error = expand_includes(".include \"a.arc\"", None, &[], &directory).unwrap_err();
+        assert!(error.starts_with(&format!("{}:2: Include cycle:", directory.join("b.arc").display())), "{}", error);
+        assert!(parse_include(".include util.arc").is_err());
+        assert_eq!(parse_include(".included"), Ok(None));
+        assert_eq!(strip_comment(".ascii \"a;\\\"b\", ';' ; comment"), ".ascii \"a;\\\"b\", ';' ");
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
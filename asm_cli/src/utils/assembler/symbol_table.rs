@@ -0,0 +1,211 @@
+//! # Symbol Table Module
+//!
+//! This module records the symbols of a program. Pass 1 defines every label
+//! with its section and its offset inside that section, because segment
+//! start addresses are only known once the whole program has been sized.
+//! `relocate` then turns the offsets into absolute addresses and computes the
+//! size of every symbol, so pass 2 and the emulator only see final addresses.
+//! `.equ` constants are absolute and never relocated, and `.extern` symbols
+//! are left for the linker.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+/// The section a symbol belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SymbolSection {
+    /// A label in the text (code) section.
+    Text,
+    /// A label in the data section.
+    Data,
+    /// A constant defined with `.equ`, which is not an address in any section.
+    Absolute,
+    /// A symbol declared with `.extern`, defined by another object file.
+    External,
+}
+
+impl SymbolSection {
+    /// Returns the name of the section as shown in symbol dumps.
+    pub fn name(self) -> &'static str {
+        match self {
+            SymbolSection::Text => "text",
+            SymbolSection::Data => "data",
+            SymbolSection::Absolute => "absolute",
+            SymbolSection::External => "extern",
+        }
+    }
+}
+
+/// A label or constant of an assembled program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name of the symbol.
+    pub name: String,
+    /// The section the symbol belongs to.
+    pub section: SymbolSection,
+    /// The offset in the section before relocation, the absolute address after it, or the value of a constant.
+    pub address: u32,
+    /// The number of bytes up to the next symbol of the section, alignment padding or the section end; 0 for constants.
+    pub size: u32,
+}
+
+/// The symbols of a program, in the order they are defined.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    index: HashMap<String, usize>,
+    /// Section offsets where the symbol before them ends, such as the start of alignment padding.
+    ends: Vec<(SymbolSection, u32)>,
+}
+
+impl SymbolTable {
+    /// Defines a symbol.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the symbol.
+    /// * `section` - The section it belongs to.
+    /// * `address` - Its offset in the section, or its value for a constant.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error message if the name is already defined.
+    pub fn define(&mut self, name: &str, section: SymbolSection, address: u32) -> Result<(), String> {
+        if self.index.contains_key(name) {
+            return Err(format!("Duplicate label: {}", name));
+        }
+        self.index.insert(name.to_string(), self.symbols.len());
+        self.symbols.push(Symbol { name: name.to_string(), section, address, size: 0 });
+        Ok(())
+    }
+
+    /// Adds a symbol that is already relocated and sized, as read back from an executable.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error message if the name is already defined.
+    pub fn insert(&mut self, symbol: Symbol) -> Result<(), String> {
+        self.define(&symbol.name, symbol.section, symbol.address)?;
+        if let Some(last) = self.symbols.last_mut() {
+            last.size = symbol.size;
+        }
+        Ok(())
+    }
+
+    /// Returns the symbol with the given name.
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.index.get(name).map(|&index| &self.symbols[index])
+    }
+
+    /// Returns the value of a constant; labels have no usable value before relocation.
+    pub fn constant(&self, name: &str) -> Option<u32> {
+        self.get(name).filter(|symbol| symbol.section == SymbolSection::Absolute).map(|symbol| symbol.address)
+    }
+
+    /// Returns all symbols in the order they were defined.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Marks a section offset where the symbol before it ends, so padding is not counted in its size.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section of the offset.
+    /// * `offset` - The offset in the section, before relocation.
+    pub fn mark_end(&mut self, section: SymbolSection, offset: u32) {
+        self.ends.push((section, offset));
+    }
+
+    /// Moves the labels of each section to the start of its segment and computes their sizes.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The start address and size of the text segment.
+    /// * `data` - The start address and size of the data segment.
+    pub fn relocate(&mut self, text: (u32, u32), data: (u32, u32)) {
+        for (section, (start, size)) in [(SymbolSection::Text, text), (SymbolSection::Data, data)] {
+            let mut offsets: Vec<u32> = self.symbols.iter()
+                .filter(|symbol| symbol.section == section)
+                .map(|symbol| symbol.address)
+                .chain(self.ends.iter().filter(|(end_section, _)| *end_section == section).map(|&(_, offset)| offset))
+                .collect();
+            offsets.sort_unstable();
+            offsets.dedup();
+            for symbol in self.symbols.iter_mut().filter(|symbol| symbol.section == section) {
+                let end = offsets.iter().find(|&&offset| offset > symbol.address).copied().unwrap_or(size);
+                symbol.size = end.saturating_sub(symbol.address);
+                symbol.address = start.wrapping_add(symbol.address);
+            }
+        }
+    }
+
+    /// Returns the address or value of every symbol defined in the program by name.
+    pub fn values(&self) -> HashMap<String, u32> {
+        self.symbols.iter()
+            .filter(|symbol| symbol.section != SymbolSection::External)
+            .map(|symbol| (symbol.name.clone(), symbol.address))
+            .collect()
+    }
+
+    /// Returns the address of every label of a section by name.
+    pub fn section_labels(&self, section: SymbolSection) -> HashMap<String, u32> {
+        self.symbols.iter()
+            .filter(|symbol| symbol.section == section)
+            .map(|symbol| (symbol.name.clone(), symbol.address))
+            .collect()
+    }
+
+    /// Formats the table with one symbol per line, sorted by section and address.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - A header followed by the address, size, section and name of every symbol.
+    pub fn dump(&self) -> String {
+        let mut symbols: Vec<&Symbol> = self.symbols.iter().collect();
+        symbols.sort_by(|a, b| (a.section, a.address, &a.name).cmp(&(b.section, b.address, &b.name)));
+        let mut dump = format!("{:<10}  {:>8}  {:<8}  {}\n", "Address", "Size", "Section", "Name");
+        for symbol in symbols {
+            let _ = writeln!(dump, "0x{:08X}  {:>8}  {:<8}  {}", symbol.address, symbol.size, symbol.section.name(), symbol.name);
+        }
+        dump
+    }
+}
+
+#[cfg(test)]
+mod symbol_table_test {
+    use super::*;
+
+    #[test]
+    fn labels_are_relocated_to_their_segments_and_sized() {
+        let mut table = SymbolTable::default();
+        table.define("main", SymbolSection::Text, 0).unwrap();
+        table.define("loop", SymbolSection::Text, 8).unwrap();
+        table.define("SIZE", SymbolSection::Absolute, 16).unwrap();
+        table.define("message", SymbolSection::Data, 0).unwrap();
+        table.define("alias", SymbolSection::Data, 0).unwrap();
+        table.define("count", SymbolSection::Data, 6).unwrap();
+        assert_eq!(table.define("main", SymbolSection::Data, 4).unwrap_err(), "Duplicate label: main");
+        assert_eq!(table.constant("SIZE"), Some(16));
+        assert_eq!(table.constant("main"), None);
+
+        table.relocate((0x1000, 12), (0x8000, 10));
+        let describe = |name: &str| table.get(name).map(|symbol| (symbol.address, symbol.size));
+        assert_eq!(describe("main"), Some((0x1000, 8)));
+        assert_eq!(describe("loop"), Some((0x1008, 4)));
+        assert_eq!(describe("message"), Some((0x8000, 6)));
+        assert_eq!(describe("alias"), Some((0x8000, 6)));
+        assert_eq!(describe("count"), Some((0x8006, 4)));
+        assert_eq!(describe("SIZE"), Some((16, 0)));
+        assert_eq!(table.section_labels(SymbolSection::Data).len(), 3);
+
+        let dump = table.dump();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "Address         Size  Section   Name");
+        assert_eq!(lines[1], "0x00001000         8  text      main");
+        assert_eq!(lines[3], "0x00008000         6  data      alias");
+        assert_eq!(lines[6], "0x00000010         0  absolute  SIZE");
+    }
+}
@@ -62,7 +62,7 @@ impl ConfigManager {
         for entry in fs::read_dir(&profiles_dir).map_err(ConfigError::IoError)? {
             let entry = entry.map_err(ConfigError::IoError)?;
             let path = entry.path();
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "toml") {
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "toml") {
                 let content = fs::read_to_string(&path).map_err(ConfigError::IoError)?;
                 let config: AppConfig = toml::from_str(&content).map_err(ConfigError::ParseError)?;
                 configs.push(config);
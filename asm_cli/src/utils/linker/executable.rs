@@ -0,0 +1,394 @@
+//! # Executable Module
+//!
+//! This module reads and writes ARC executables (`.arx`), the on-disk form of
+//! an assembled or linked program. All numbers are little-endian.
+//!
+//! ```text
+//! Offset  Size     Field
+//! 0       4        Magic, "ARCX"
+//! 4       2        Format version, currently 1
+//! 6       2        Number of sections
+//! 8       4        Entry point
+//! 12      16 * N   Section table: kind, address, file offset, size (u32 each)
+//! ```
+//!
+//! Section kinds:
+//!
+//! * `1` text: the instructions, loaded at the address.
+//! * `2` data: the initialized data, loaded at the address (its size may be 0).
+//! * `3` stack: the stack start and size; no contents.
+//! * `4` reserved: a `.space` buffer, which is reserved but not initialized; no contents.
+//! * `5` symbols (optional): one record per symbol, an address (u32), a size (u32), a section
+//!   (u8: 0 text, 1 data, 2 absolute), a zero byte and the length of the name (u16) followed by
+//!   the name in UTF-8.
+//! * `6` lines (optional): the number of files (u16) and each file name as a length (u16) and
+//!   UTF-8 bytes, then one record per instruction with an address (u32), a line (u32) and the
+//!   index of its file (u16, `0xFFFF` for none).
+//!
+//! A program has exactly one text, data and stack section. Readers skip
+//! section kinds they do not know, so later versions can add tables.
+
+use std::path::{Path, PathBuf};
+
+use crate::utils::assembler::command_processor::AssembledProgram;
+use crate::utils::assembler::source::SourceLocation;
+use crate::utils::assembler::symbol_table::{Symbol, SymbolSection, SymbolTable};
+
+/// The first bytes of every executable.
+pub const EXECUTABLE_MAGIC: [u8; 4] = *b"ARCX";
+
+/// The version written to executables; files with another version are rejected.
+pub const EXECUTABLE_VERSION: u16 = 1;
+
+const HEADER_SIZE: usize = 12;
+const SECTION_ENTRY_SIZE: usize = 16;
+
+const SECTION_TEXT: u32 = 1;
+const SECTION_DATA: u32 = 2;
+const SECTION_STACK: u32 = 3;
+const SECTION_RESERVED: u32 = 4;
+const SECTION_SYMBOLS: u32 = 5;
+const SECTION_LINES: u32 = 6;
+
+/// The index written for instructions with no source file.
+const NO_FILE: u16 = 0xFFFF;
+
+/// A program ready to be written to or read from an executable.
+#[derive(Debug, Clone)]
+pub struct Executable {
+    /// The address execution starts at.
+    pub entry: u32,
+    /// The segments, symbols and source map of the program.
+    pub program: AssembledProgram,
+}
+
+impl Executable {
+    /// Wraps a program, entering it at the start of its text segment.
+    pub fn new(program: AssembledProgram) -> Self {
+        Self { entry: program.actual_text_start, program }
+    }
+
+    /// Drops the symbol and line tables, which are not needed to run the program.
+    pub fn strip(&mut self) {
+        self.program.symbols = SymbolTable::default();
+        self.program.source_map.clear();
+    }
+
+    /// Encodes the executable. The symbol and line tables are only written when the program has them.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u8>, String>` - The contents of the file, or an error message if a name is too long.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let program = &self.program;
+        let text: Vec<u8> = program.text.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let mut sections: Vec<(u32, u32, Vec<u8>, u32)> = vec![
+            (SECTION_TEXT, program.actual_text_start, text, 0),
+            (SECTION_DATA, program.actual_data_start, program.data.clone(), 0),
+            (SECTION_STACK, program.actual_stack_start, Vec::new(), program.actual_stack_size),
+        ];
+        sections.extend(program.reserved.iter().map(|&(address, size)| (SECTION_RESERVED, address, Vec::new(), size)));
+        if !program.symbols.symbols().is_empty() {
+            sections.push((SECTION_SYMBOLS, 0, encode_symbols(&program.symbols)?, 0));
+        }
+        if program.source_map.iter().any(Option::is_some) {
+            sections.push((SECTION_LINES, 0, encode_lines(program)?, 0));
+        }
+
+        let count = u16::try_from(sections.len()).map_err(|_| "Too many sections for an executable".to_string())?;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&EXECUTABLE_MAGIC);
+        bytes.extend_from_slice(&EXECUTABLE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes.extend_from_slice(&self.entry.to_le_bytes());
+        let mut offset = HEADER_SIZE + SECTION_ENTRY_SIZE * sections.len();
+        for (kind, address, contents, size) in &sections {
+            let (file_offset, size) = if contents.is_empty() && *kind != SECTION_DATA { (0, *size) } else { (offset as u32, contents.len() as u32) };
+            for value in [*kind, *address, file_offset, size] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            offset += contents.len();
+        }
+        for (_, _, contents, _) in &sections {
+            bytes.extend_from_slice(contents);
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes an executable.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The contents of the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, String>` - The executable, or an error message if the file is not a valid
+    ///   executable of this version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if !is_executable(bytes) {
+            return Err("Not an ARC executable (bad magic)".to_string());
+        }
+        let mut reader = Reader { bytes, position: EXECUTABLE_MAGIC.len() };
+        let version = reader.u16()?;
+        if version != EXECUTABLE_VERSION {
+            return Err(format!("Unsupported executable version {}, expected {}", version, EXECUTABLE_VERSION));
+        }
+        let count = reader.u16()?;
+        let entry = reader.u32()?;
+
+        let mut program = AssembledProgram::default();
+        let (mut text, mut data, mut stack) = (None, None, None);
+        let (mut symbols, mut lines) = (None, None);
+        for _ in 0..count {
+            let (kind, address, offset, size) = (reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?);
+            let contents = || bytes.get(offset as usize..offset as usize + size as usize)
+                .ok_or_else(|| format!("Section at 0x{:X} extends past the end of the file", offset));
+            let slot = match kind {
+                SECTION_TEXT => &mut text,
+                SECTION_DATA => &mut data,
+                SECTION_STACK => &mut stack,
+                SECTION_SYMBOLS => &mut symbols,
+                SECTION_LINES => &mut lines,
+                SECTION_RESERVED => {
+                    program.reserved.push((address, size));
+                    continue;
+                }
+                _ => continue,
+            };
+            let section = match kind {
+                SECTION_STACK => (address, &[][..], size),
+                _ => (address, contents()?, size),
+            };
+            if slot.replace(section).is_some() {
+                return Err(format!("Executable has more than one section of kind {}", kind));
+            }
+        }
+
+        let (Some(text), Some(data), Some((stack_start, _, stack_size))) = (text, data, stack) else {
+            return Err("Executable is missing its text, data or stack section".to_string());
+        };
+        if text.1.len() % 4 != 0 {
+            return Err("Text section is not a whole number of instructions".to_string());
+        }
+        program.actual_text_start = text.0;
+        program.text = text.1.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect();
+        program.actual_data_start = data.0;
+        program.data = data.1.to_vec();
+        program.actual_stack_start = stack_start;
+        program.actual_stack_size = stack_size;
+        if let Some((_, contents, _)) = symbols {
+            program.symbols = decode_symbols(contents)?;
+        }
+        if let Some((_, contents, _)) = lines {
+            program.source_map = decode_lines(contents, &program)?;
+        }
+        Ok(Self { entry, program })
+    }
+
+    /// Reads an executable file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        Self::from_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Writes the executable to a file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes()?).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+    }
+}
+
+/// Returns whether a file starts with the executable magic.
+pub fn is_executable(bytes: &[u8]) -> bool {
+    bytes.starts_with(&EXECUTABLE_MAGIC)
+}
+
+/// Reads little-endian numbers from the contents of a file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8], String> {
+        let bytes = self.bytes.get(self.position..self.position + length).ok_or_else(|| "Executable is truncated".to_string())?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u16()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|_| "Executable has a name that is not UTF-8".to_string())
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+}
+
+/// Appends a name as its length and UTF-8 bytes.
+fn push_string(bytes: &mut Vec<u8>, text: &str) -> Result<(), String> {
+    let length = u16::try_from(text.len()).map_err(|_| format!("Name too long for an executable: {}", text))?;
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+    Ok(())
+}
+
+fn encode_symbols(symbols: &SymbolTable) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for symbol in symbols.symbols() {
+        let section = match symbol.section {
+            SymbolSection::Text => 0,
+            SymbolSection::Data => 1,
+            SymbolSection::Absolute => 2,
+            SymbolSection::External => return Err(format!("Symbol {} is still external; link the program first", symbol.name)),
+        };
+        bytes.extend_from_slice(&symbol.address.to_le_bytes());
+        bytes.extend_from_slice(&symbol.size.to_le_bytes());
+        bytes.extend_from_slice(&[section, 0]);
+        push_string(&mut bytes, &symbol.name)?;
+    }
+    Ok(bytes)
+}
+
+fn decode_symbols(contents: &[u8]) -> Result<SymbolTable, String> {
+    let mut reader = Reader { bytes: contents, position: 0 };
+    let mut symbols = SymbolTable::default();
+    while !reader.at_end() {
+        let (address, size) = (reader.u32()?, reader.u32()?);
+        let section = match reader.u8()? {
+            0 => SymbolSection::Text,
+            1 => SymbolSection::Data,
+            2 => SymbolSection::Absolute,
+            other => return Err(format!("Unknown symbol section {}", other)),
+        };
+        reader.u8()?;
+        let name = reader.string()?;
+        symbols.insert(Symbol { name, section, address, size })?;
+    }
+    Ok(symbols)
+}
+
+fn encode_lines(program: &AssembledProgram) -> Result<Vec<u8>, String> {
+    let mut files: Vec<&PathBuf> = Vec::new();
+    let mut records = Vec::new();
+    for (index, location) in program.source_map.iter().enumerate() {
+        let Some(location) = location else { continue };
+        let file = match &location.file {
+            Some(file) => match files.iter().position(|known| *known == file) {
+                Some(position) => position as u16,
+                None => {
+                    files.push(file);
+                    u16::try_from(files.len() - 1).ok().filter(|&index| index != NO_FILE)
+                        .ok_or_else(|| "Too many source files for an executable".to_string())?
+                }
+            },
+            None => NO_FILE,
+        };
+        let address = program.actual_text_start.wrapping_add(4 * index as u32);
+        records.extend_from_slice(&address.to_le_bytes());
+        records.extend_from_slice(&(location.line as u32).to_le_bytes());
+        records.extend_from_slice(&file.to_le_bytes());
+    }
+    let mut bytes = (files.len() as u16).to_le_bytes().to_vec();
+    for file in files {
+        push_string(&mut bytes, &file.to_string_lossy())?;
+    }
+    bytes.extend_from_slice(&records);
+    Ok(bytes)
+}
+
+fn decode_lines(contents: &[u8], program: &AssembledProgram) -> Result<Vec<Option<SourceLocation>>, String> {
+    let mut reader = Reader { bytes: contents, position: 0 };
+    let files = (0..reader.u16()?).map(|_| reader.string().map(PathBuf::from)).collect::<Result<Vec<_>, _>>()?;
+    let mut source_map = vec![None; program.text.len()];
+    while !reader.at_end() {
+        let (address, line, file) = (reader.u32()?, reader.u32()?, reader.u16()?);
+        let file = match file {
+            NO_FILE => None,
+            index => Some(files.get(index as usize).cloned().ok_or_else(|| format!("Unknown source file index {}", index))?),
+        };
+        let index = (address.wrapping_sub(program.actual_text_start) / 4) as usize;
+        let slot = source_map.get_mut(index).ok_or_else(|| format!("Line record for 0x{:X} is outside the text section", address))?;
+        *slot = Some(SourceLocation { file, line: line as usize });
+    }
+    Ok(source_map)
+}
+
+#[cfg(test)]
+mod executable_test {
+    use super::*;
+    use crate::utils::assembler::command_processor::{assemble_program, parse_command, Command};
+
+    fn program() -> AssembledProgram {
+        let commands: Vec<Command> = [".text_start 0x1000", "main: MOVI AX, 1", "JMP main", "LIMIT: .equ 3", ".data", "value: .word 7", "buffer: .space 8"]
+            .iter()
+            .enumerate()
+            .map(|(index, line)| {
+                let mut command = parse_command(line).unwrap();
+                command.location = Some(SourceLocation { file: Some("main.arc".into()), line: index + 1 });
+                command
+            })
+            .collect();
+        assemble_program(&commands, &[], 0x10000).unwrap()
+    }
+
+    #[test]
+    fn programs_round_trip_with_their_tables() {
+        let program = program();
+        let bytes = Executable::new(program.clone()).to_bytes().unwrap();
+        assert_eq!(bytes[0..4], *b"ARCX");
+        assert_eq!(bytes[8..12], 0x1000u32.to_le_bytes());
+
+        let decoded = Executable::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.entry, 0x1000);
+        let decoded = decoded.program;
+        assert_eq!(decoded.text, program.text);
+        assert_eq!(decoded.data, program.data);
+        assert_eq!(
+            (decoded.actual_text_start, decoded.actual_data_start, decoded.actual_stack_start, decoded.actual_stack_size),
+            (program.actual_text_start, program.actual_data_start, program.actual_stack_start, program.actual_stack_size),
+        );
+        assert_eq!(decoded.reserved, program.reserved);
+        assert_eq!(decoded.symbols.symbols(), program.symbols.symbols());
+        assert_eq!(decoded.label_address("value"), program.label_address("value"));
+        assert_eq!(decoded.source_location(0x1004).map(|l| l.to_string()), Some("main.arc:3".to_string()));
+    }
+
+    #[test]
+    fn stripped_executables_have_no_tables() {
+        let mut executable = Executable::new(program());
+        let full = executable.to_bytes().unwrap().len();
+        executable.strip();
+        let bytes = executable.to_bytes().unwrap();
+        assert!(bytes.len() < full);
+        let decoded = Executable::from_bytes(&bytes).unwrap().program;
+        assert!(decoded.symbols.symbols().is_empty() && decoded.source_map.is_empty());
+        assert_eq!(decoded.text, executable.program.text);
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let bytes = Executable::new(program()).to_bytes().unwrap();
+        assert_eq!(Executable::from_bytes(b"ELF").unwrap_err(), "Not an ARC executable (bad magic)");
+        let mut version = bytes.clone();
+        version[4] = 9;
+        assert!(Executable::from_bytes(&version).unwrap_err().starts_with("Unsupported executable version 9"));
+        assert_eq!(Executable::from_bytes(&bytes[..20]).unwrap_err(), "Executable is truncated");
+        assert!(Executable::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err().contains("past the end of the file"));
+    }
+}
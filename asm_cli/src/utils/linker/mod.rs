@@ -0,0 +1,340 @@
+//! # Linker Module
+//!
+//! This module combines relocatable object files into a program. Every
+//! object given to the linker is included, in order; members of static
+//! archives are included only when they define a global symbol that is
+//! still undefined, repeating until no member adds anything. The text
+//! sections are placed one after the other from the start of the text
+//! segment, so execution starts at the first instruction of the first
+//! object, and the data sections follow each other at offsets aligned to
+//! 4 bytes or to their largest `.align`, from a data segment start aligned
+//! to the largest of these. Segment addresses come from a linker script,
+//! then from the layout directives of the objects, then from the usual
+//! defaults.
+//!
+//! Every undefined and duplicate global symbol is reported, one per line of
+//! the error message.
+
+pub mod executable;
+pub mod object;
+pub mod script;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::utils::assembler::command_processor::{place_segments, AssembledProgram, SegmentLayout};
+use crate::utils::assembler::symbol_table::{SymbolSection, SymbolTable};
+use object::{Archive, ObjectFile, RelocationTarget};
+
+/// Where the sections of an object are placed, as offsets into the text and data segments.
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    text: u32,
+    data: u32,
+}
+
+/// Links object files and archives into a program.
+///
+/// # Arguments
+///
+/// * `objects` - The object files, all of which are included.
+/// * `archives` - The archives members are taken from to define undefined symbols.
+/// * `script` - The settings of the linker script, which override the layout directives of the objects.
+/// * `total_memory_size` - The size of the memory the program is laid out in.
+///
+/// # Returns
+///
+/// * `Result<AssembledProgram, String>` - The linked program, or an error message listing every
+///   undefined or duplicate symbol, layout conflict and relocation that does not fit.
+pub fn link(objects: &[ObjectFile], archives: &[Archive], script: &SegmentLayout, total_memory_size: usize) -> Result<AssembledProgram, String> {
+    let objects = select_objects(objects, archives);
+    let mut errors = Vec::new();
+
+    // Global symbols must be defined exactly once, and every `.extern` must find one
+    let mut definitions: HashMap<&str, &ObjectFile> = HashMap::new();
+    for object in &objects {
+        for symbol in object.globals() {
+            if let Some(first) = definitions.insert(&symbol.name, object) {
+                errors.push(format!("Duplicate symbol {} defined in {} and {}", symbol.name, first.name, object.name));
+            }
+        }
+    }
+    for object in &objects {
+        for name in object.externs.iter().filter(|name| !definitions.contains_key(name.as_str())) {
+            errors.push(format!("Undefined symbol {} referenced in {}", name, object.name));
+        }
+    }
+    let layout = merge_layouts(&objects, script).unwrap_or_else(|e| {
+        errors.push(e);
+        *script
+    });
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    let mut placements = Vec::with_capacity(objects.len());
+    let (mut text_size, mut data_size, mut data_align) = (0u32, 0u32, 4u32);
+    for object in &objects {
+        let align = object.data_align.max(4);
+        data_align = data_align.max(align);
+        data_size = data_size.next_multiple_of(align);
+        placements.push(Placement { text: text_size, data: data_size });
+        text_size += 4 * object.text.len() as u32;
+        data_size += object.data.len() as u32;
+    }
+    let segments = place_segments(&layout, text_size, data_size, data_align, total_memory_size)?;
+
+    let mut program = AssembledProgram {
+        actual_text_start: segments.text_start,
+        actual_data_start: segments.data_start,
+        actual_stack_start: segments.stack_start,
+        actual_stack_size: segments.stack_size,
+        ..AssembledProgram::default()
+    };
+    for (object, placement) in objects.iter().zip(&placements) {
+        program.text.extend(&object.text);
+        program.data.resize(placement.data as usize, 0);
+        program.data.extend(&object.data);
+        program.reserved.extend(object.reserved.iter().map(|&(offset, size)| (segments.data_start + placement.data + offset, size)));
+        let mut source_map = vec![None; object.text.len()];
+        for line in &object.lines {
+            if let Some(location) = source_map.get_mut(line.instruction as usize) {
+                *location = Some(line.location.clone());
+            }
+        }
+        program.source_map.extend(source_map);
+    }
+
+    program.symbols = link_symbols(&objects, &placements).map_err(|e| e.join("\n"))?;
+    program.symbols.relocate((segments.text_start, text_size), (segments.data_start, data_size));
+
+    for (object, placement) in objects.iter().zip(&placements) {
+        for relocation in &object.relocations {
+            let target = match &relocation.target {
+                RelocationTarget::Section(SymbolSection::Text) => segments.text_start + placement.text,
+                RelocationTarget::Section(SymbolSection::Data) => segments.data_start + placement.data,
+                RelocationTarget::Section(_) => 0,
+                RelocationTarget::Symbol(name) => program.symbols.get(name).map(|symbol| symbol.address).unwrap_or(0),
+            };
+            let value = target as i64 + relocation.addend;
+            let width = relocation.field.width();
+            let patched = match relocation.section {
+                SymbolSection::Text => {
+                    let index = ((placement.text + relocation.offset) / 4) as usize;
+                    match program.text.get_mut(index) {
+                        Some(word) => {
+                            let mut bytes = word.to_le_bytes();
+                            let patched = relocation.field.patch(&mut bytes, value);
+                            *word = u32::from_le_bytes(bytes);
+                            patched
+                        }
+                        None => Err("the field is outside the text section".to_string()),
+                    }
+                }
+                _ => {
+                    let start = (placement.data + relocation.offset) as usize;
+                    match program.data.get_mut(start..start + width) {
+                        Some(bytes) => relocation.field.patch(bytes, value),
+                        None => Err("the field is outside the data section".to_string()),
+                    }
+                }
+            };
+            if let Err(e) = patched {
+                errors.push(format!("{}: relocation at {}+0x{:X}: {}", object.name, relocation.section.name(), relocation.offset, e));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    Ok(program)
+}
+
+/// Returns the objects to link: all the given objects, then the archive members that define a
+/// symbol that is still undefined.
+fn select_objects<'a>(objects: &'a [ObjectFile], archives: &'a [Archive]) -> Vec<&'a ObjectFile> {
+    let mut selected: Vec<&ObjectFile> = objects.iter().collect();
+    loop {
+        let defined: HashSet<&str> = selected.iter().flat_map(|object| object.globals()).map(|symbol| symbol.name.as_str()).collect();
+        let undefined: HashSet<&str> = selected.iter()
+            .flat_map(|object| &object.externs)
+            .map(String::as_str)
+            .filter(|name| !defined.contains(name))
+            .collect();
+        let member = archives.iter()
+            .flat_map(|archive| &archive.members)
+            .filter(|member| !selected.iter().any(|object| std::ptr::eq(*object, *member)))
+            .find(|member| member.globals().any(|symbol| undefined.contains(symbol.name.as_str())));
+        match member {
+            Some(member) => selected.push(member),
+            None => return selected,
+        }
+    }
+}
+
+/// Combines the layout directives of the objects with the linker script; a directive may only
+/// be given by one object unless the script overrides it.
+fn merge_layouts(objects: &[&ObjectFile], script: &SegmentLayout) -> Result<SegmentLayout, String> {
+    let merge = |directive: &str, scripted: Option<u32>, value: fn(&SegmentLayout) -> Option<u32>| {
+        let given: Vec<(&str, u32)> = objects.iter()
+            .filter_map(|object| value(&object.layout).map(|v| (object.name.as_str(), v)))
+            .collect();
+        match (scripted, given.as_slice()) {
+            (Some(scripted), _) => Ok(Some(scripted)),
+            (None, [_, _, ..]) => {
+                let names: Vec<&str> = given.iter().map(|(name, _)| *name).collect();
+                Err(format!("Multiple {} directives found in {}. Only one is allowed; set it in a linker script instead.", directive, names.join(", ")))
+            }
+            (None, given) => Ok(given.first().map(|(_, v)| *v)),
+        }
+    };
+    Ok(SegmentLayout {
+        text_start: merge(".text_start", script.text_start, |layout| layout.text_start)?,
+        data_start: script.data_start,
+        stack_start: merge(".stack_start", script.stack_start, |layout| layout.stack_start)?,
+        stack_size: merge(".stack_size", script.stack_size, |layout| layout.stack_size)?,
+    })
+}
+
+/// Builds the symbol table of the linked program with labels at their offsets in the combined
+/// sections. Global symbols keep their names; a local symbol whose name is already taken is
+/// renamed to `name@object`.
+fn link_symbols(objects: &[&ObjectFile], placements: &[Placement]) -> Result<SymbolTable, Vec<String>> {
+    let mut symbols = SymbolTable::default();
+    let mut errors = Vec::new();
+    for global in [true, false] {
+        for (object, placement) in objects.iter().zip(placements) {
+            for symbol in object.symbols.iter().filter(|symbol| symbol.global == global) {
+                let offset = match symbol.section {
+                    SymbolSection::Text => placement.text + symbol.value,
+                    SymbolSection::Data => placement.data + symbol.value,
+                    _ => symbol.value,
+                };
+                let name = match symbols.get(&symbol.name) {
+                    Some(_) if !global => format!("{}@{}", symbol.name, object.name),
+                    _ => symbol.name.clone(),
+                };
+                if let Err(e) = symbols.define(&name, symbol.section, offset) {
+                    errors.push(format!("{}: {}", object.name, e));
+                }
+            }
+        }
+    }
+    if errors.is_empty() { Ok(symbols) } else { Err(errors) }
+}
+
+#[cfg(test)]
+mod linker_test {
+    use super::*;
+    use super::script::parse_linker_script;
+    use crate::utils::assembler::command_processor::{assemble_object, parse_command, Command};
+    use super::object::OBJECT_VERSION;
+
+    fn object(name: &str, lines: &[&str]) -> ObjectFile {
+        let commands: Vec<Command> = lines.iter().map(|line| parse_command(line).unwrap()).collect();
+        assemble_object(&commands, &[], name).unwrap()
+    }
+
+    fn main_object() -> ObjectFile {
+        object("main.arc", &[
+            ".global main", ".extern add_one", ".extern counter",
+            "main: MOVI AX, counter", "CALL add_one", "JMP done", "done: HALT",
+            ".data", "ptrs: .word main, counter + 4",
+        ])
+    }
+
+    fn library() -> ObjectFile {
+        object("lib.arc", &[".global add_one", ".global counter", "add_one: INC AX", "done: RET", ".data", "pad: .byte 1", "counter: .word 7"])
+    }
+
+    #[test]
+    fn sections_are_placed_and_relocations_patched() {
+        let script = parse_linker_script("text_start = 0x1000 # code\n; data\ndata_start = 0x4000\n").unwrap();
+        let program = link(&[main_object(), library()], &[], &script, 0x10000).unwrap();
+        assert_eq!((program.actual_text_start, program.actual_data_start), (0x1000, 0x4000));
+        assert_eq!(program.text.len(), 6);
+        // The library's data starts at the next 4-byte boundary after main's 8 bytes
+        let counter = 0x4000 + 8 + 1;
+        assert_eq!(program.text[0], 0x0100_0000 | counter);
+        assert_eq!(program.text[1] & 0xFF_FFFF, 0x1010);
+        assert_eq!(program.text[2] & 0xFF_FFFF, 0x100C);
+        assert_eq!(program.data[0..8], [0x1000u32.to_le_bytes(), (counter + 4).to_le_bytes()].concat());
+        assert_eq!(program.label_address("counter"), Some(counter));
+        // Both objects define a local `done`; the second one is renamed
+        assert_eq!(program.label_address("done"), Some(0x100C));
+        assert_eq!(program.label_address("done@lib.arc"), Some(0x1014));
+    }
+
+    #[test]
+    fn load_address_halves_are_patched() {
+        let main = object("main.arc", &[".extern table", "LA BX, table", "HALT"]);
+        let table = |first: &str| object("table.arc", &[".global table", ".data", first, "table: .word 1"]);
+        let script = parse_linker_script("data_start = 0x12344\n").unwrap();
+        let program = link(&[main.clone(), table(".word 0")], &[], &script, 0x20000).unwrap();
+        assert_eq!(program.text[0..3], [0x0101_0001, 0x2401_0010, 0x2201_2348]);
+
+        let error = link(&[main, table(".byte 0")], &[], &script, 0x20000).unwrap_err();
+        assert!(error.contains("even address"));
+    }
+
+    #[test]
+    fn relocations_that_do_not_fit_their_field_are_reported() {
+        let main = object("main.arc", &[".extern table", "LODW AX, table", "STRW table, AX", "HALT"]);
+        let table = object("table.arc", &[".global table", ".data", "table: .word 1"]);
+        let script = parse_linker_script("data_start = 0xFEFFC\n").unwrap();
+        let error = link(&[main, table], &[], &script, 0x100000).unwrap_err();
+        assert_eq!(error, "main.arc: relocation at text+0x0: value 0xFEFFC does not fit in 16-bit field\n\
+            main.arc: relocation at text+0x4: value 0xFEFFC does not fit in 7-bit field");
+    }
+
+    #[test]
+    fn data_is_placed_at_aligned_addresses() {
+        let main = object("main.arc", &["HALT", ".data", "flag: .byte 1"]);
+        let table = object("table.arc", &[".global table", ".data", "first: .byte 2", ".align 16", "table: .word 0x11223344"]);
+        let program = link(&[main, table], &[], &SegmentLayout::default(), 0x10000).unwrap();
+        assert_eq!(program.actual_data_start % 16, 0);
+        let table = program.label_address("table").unwrap();
+        assert_eq!(table % 16, 0);
+        let offset = (table - program.actual_data_start) as usize;
+        assert_eq!(program.data[offset..offset + 4], 0x11223344u32.to_le_bytes());
+    }
+
+    #[test]
+    fn undefined_and_duplicate_symbols_are_all_reported() {
+        let error = link(&[main_object()], &[], &SegmentLayout::default(), 0x10000).unwrap_err();
+        assert_eq!(error, "Undefined symbol add_one referenced in main.arc\nUndefined symbol counter referenced in main.arc");
+
+        let error = link(&[main_object(), library(), library()], &[], &SegmentLayout::default(), 0x10000).unwrap_err();
+        assert_eq!(error.lines().count(), 2);
+        assert!(error.starts_with("Duplicate symbol add_one defined in lib.arc and lib.arc"));
+    }
+
+    #[test]
+    fn archive_members_are_linked_only_when_needed() {
+        let unused = object("unused.arc", &[".global never", "never: HALT"]);
+        let archive = Archive { version: OBJECT_VERSION, members: vec![unused, library()] };
+        let program = link(&[main_object()], std::slice::from_ref(&archive), &SegmentLayout::default(), 0x10000).unwrap();
+        assert_eq!(program.text.len(), 6);
+        assert_eq!(program.label_address("never"), None);
+    }
+
+    #[test]
+    fn layout_directives_conflict_unless_scripted() {
+        let first = object("a.arc", &[".text_start 0x2000", "HALT"]);
+        let second = object("b.arc", &[".text_start 0x3000", "HALT"]);
+        let error = link(&[first.clone(), second.clone()], &[], &SegmentLayout::default(), 0x10000).unwrap_err();
+        assert!(error.starts_with("Multiple .text_start directives found in a.arc, b.arc"));
+
+        let script = parse_linker_script("text_start = 0x1000").unwrap();
+        assert_eq!(link(&[first.clone(), second], &[], &script, 0x10000).unwrap().actual_text_start, 0x1000);
+        assert_eq!(link(&[first], &[], &SegmentLayout::default(), 0x10000).unwrap().actual_text_start, 0x2000);
+        assert!(parse_linker_script("text_start = 1\ntext_start = 2").unwrap_err().starts_with("Line 2:"));
+        assert!(parse_linker_script("heap = 1").is_err());
+    }
+
+    #[test]
+    fn objects_round_trip_through_toml() {
+        let object = main_object();
+        let decoded: ObjectFile = toml::from_str(&toml::to_string(&object).unwrap()).unwrap();
+        assert_eq!(decoded, object);
+    }
+}
@@ -0,0 +1,228 @@
+//! # Object Module
+//!
+//! This module defines relocatable object files. An object holds the text
+//! and data sections of one source file assembled as if both started at
+//! address 0, its symbols, and a relocation record for every field that
+//! refers to a label or to an `.extern` symbol. Objects are stored as TOML,
+//! like the configuration files, so they can be inspected with any editor.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::assembler::command_processor::SegmentLayout;
+use crate::utils::assembler::source::SourceLocation;
+use crate::utils::assembler::symbol_table::SymbolSection;
+
+/// The version written to object files and archives; files with another version are rejected.
+pub const OBJECT_VERSION: u32 = 2;
+
+/// The field of an instruction or data value a relocation patches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelocationField {
+    /// Bits 0-15 of an instruction, which must hold the whole value (`MOVI`, `LODI`).
+    Immediate16,
+    /// Bits 0-15 of an instruction, which must hold the whole address (`LODW`, `PUSH`, `OUTI`).
+    Address16,
+    /// Bits 0-22 of an instruction, which must hold the whole address, since bit 23 marks a
+    /// register-indirect operand (jumps, `CALL`, `IN`, `OUT`, `INSI`).
+    Address24,
+    /// Bits 16-22 of an instruction, which must hold the whole address (`STRI`, `STRW`, `INSW`, `OUTW`).
+    Address7,
+    /// Bits 0-15 of an instruction, holding the upper half of the value (the `MOVI` of `LA`).
+    High16,
+    /// Bits 0-15 of an instruction, holding the lower half of the value, which must be even (the `OR` of `LA`).
+    Low16,
+    /// A 32-bit little-endian data value (`.word`).
+    Word,
+    /// A 16-bit little-endian data value (`.half`).
+    Half,
+    /// A data byte (`.byte`).
+    Byte,
+}
+
+impl RelocationField {
+    /// Writes a value into the field of an instruction or into data bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The little-endian bytes of the instruction or the data value.
+    /// * `value` - The relocated value.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error message if the value does not fit the field.
+    pub fn patch(self, bytes: &mut [u8], value: i64) -> Result<(), String> {
+        let (shift, mask): (u32, u32) = match self {
+            RelocationField::Immediate16 | RelocationField::Address16 => (0, 0xFFFF),
+            RelocationField::Address24 => (0, 0x7F_FFFF),
+            RelocationField::Address7 => (16, 0x7F),
+            RelocationField::High16 => return RelocationField::Address16.patch(bytes, value >> 16),
+            RelocationField::Low16 => {
+                // An odd immediate would be read as the register form of `OR`
+                if value & 1 == 1 {
+                    return Err(format!("LA needs an even address, not 0x{:X}", value));
+                }
+                return RelocationField::Address16.patch(bytes, value & 0xFFFF);
+            }
+            RelocationField::Word | RelocationField::Half | RelocationField::Byte => {
+                let bits = 8 * bytes.len() as u32;
+                if value < -(1i64 << (bits - 1)) || value >= 1i64 << bits {
+                    return Err(format!("value 0x{:X} does not fit in {} bytes", value, bytes.len()));
+                }
+                bytes.copy_from_slice(&value.to_le_bytes()[..bytes.len()]);
+                return Ok(());
+            }
+        };
+        if !(0..=mask as i64).contains(&value) {
+            return Err(format!("value 0x{:X} does not fit in {}-bit field", value, mask.count_ones()));
+        }
+        let word = u32::from_le_bytes(bytes.try_into().map_err(|_| "instruction relocations patch 4 bytes".to_string())?);
+        let patched = (word & !(mask << shift)) | (((value as u32) & mask) << shift);
+        bytes.copy_from_slice(&patched.to_le_bytes());
+        Ok(())
+    }
+
+    /// Returns the number of bytes the field's instruction or data value occupies.
+    pub fn width(self) -> usize {
+        match self {
+            RelocationField::Half => 2,
+            RelocationField::Byte => 1,
+            _ => 4,
+        }
+    }
+}
+
+/// What a relocated field refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelocationTarget {
+    /// The start of the object's own text or data section.
+    Section(SymbolSection),
+    /// A global symbol of another object.
+    Symbol(String),
+}
+
+/// A field whose value depends on where sections are placed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Relocation {
+    /// The section containing the field.
+    pub section: SymbolSection,
+    /// The offset of the instruction or data value in that section.
+    pub offset: u32,
+    /// The field to patch.
+    pub field: RelocationField,
+    /// The address the field refers to.
+    pub target: RelocationTarget,
+    /// The value added to the address of the target.
+    pub addend: i64,
+}
+
+/// A symbol of an object file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectSymbol {
+    /// The name of the symbol.
+    pub name: String,
+    /// The section of a label, or `Absolute` for a constant.
+    pub section: SymbolSection,
+    /// The offset of a label in its section, or the value of a constant.
+    pub value: u32,
+    /// The size of a label in bytes.
+    pub size: u32,
+    /// Whether the symbol is exported with `.global`.
+    pub global: bool,
+}
+
+/// The source location of an instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectLine {
+    /// The index of the instruction in the text section.
+    pub instruction: u32,
+    /// The file and line it was assembled from.
+    pub location: SourceLocation,
+}
+
+/// A relocatable object file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectFile {
+    /// The format version, `OBJECT_VERSION`.
+    pub version: u32,
+    /// The name of the object, usually its source file.
+    pub name: String,
+    /// The layout directives of the source (`.text_start`, `.stack_start`, `.stack_size`).
+    pub layout: SegmentLayout,
+    /// The instructions of the text section.
+    pub text: Vec<u32>,
+    /// The bytes of the data section.
+    pub data: Vec<u8>,
+    /// The largest `.align` boundary of the data section, which its placement must be a multiple of.
+    pub data_align: u32,
+    /// The offset and size of every `.space` buffer in the data section.
+    pub reserved: Vec<(u32, u32)>,
+    /// The labels and constants defined by the object.
+    pub symbols: Vec<ObjectSymbol>,
+    /// The symbols declared with `.extern`.
+    pub externs: Vec<String>,
+    /// The fields to patch once the sections are placed.
+    pub relocations: Vec<Relocation>,
+    /// The source locations of the instructions.
+    pub lines: Vec<ObjectLine>,
+}
+
+impl ObjectFile {
+    /// Returns the global symbols the object defines.
+    pub fn globals(&self) -> impl Iterator<Item = &ObjectSymbol> {
+        self.symbols.iter().filter(|symbol| symbol.global)
+    }
+
+    /// Reads an object file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        let object: ObjectFile = toml::from_str(&contents).map_err(|e| format!("{} is not an object file: {}", path.display(), e))?;
+        check_version(object.version, path)?;
+        Ok(object)
+    }
+
+    /// Writes the object file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        save_toml(self, path)
+    }
+}
+
+/// A static archive: object files the linker takes members from only when they define a
+/// symbol that is still undefined.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Archive {
+    /// The format version, `OBJECT_VERSION`.
+    pub version: u32,
+    /// The object files of the archive.
+    pub members: Vec<ObjectFile>,
+}
+
+impl Archive {
+    /// Reads an archive.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        let archive: Archive = toml::from_str(&contents).map_err(|e| format!("{} is not an archive: {}", path.display(), e))?;
+        check_version(archive.version, path)?;
+        Ok(archive)
+    }
+
+    /// Writes the archive.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        save_toml(self, path)
+    }
+}
+
+/// Rejects files written by another version of the format.
+fn check_version(version: u32, path: &Path) -> Result<(), String> {
+    if version != OBJECT_VERSION {
+        return Err(format!("{} has format version {}, expected {}", path.display(), version, OBJECT_VERSION));
+    }
+    Ok(())
+}
+
+/// Serializes a value as TOML into a file.
+fn save_toml(value: &impl Serialize, path: &Path) -> Result<(), String> {
+    let contents = toml::to_string(value).map_err(|e| format!("Error encoding {}: {}", path.display(), e))?;
+    std::fs::write(path, contents).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
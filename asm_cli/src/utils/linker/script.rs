@@ -0,0 +1,55 @@
+//! # Script Module
+//!
+//! This module parses linker scripts. A script sets where the segments of the
+//! linked program go, one `NAME = VALUE` per line:
+//!
+//! ```text
+//! # Code at 4KB, data at 32KB, a 2KB stack at the top of 64KB
+//! text_start  = 0x1000
+//! data_start  = 0x8000
+//! stack_start = 0xF800
+//! stack_size  = 0x800
+//! ```
+//!
+//! Values are expressions over numbers. Settings left out fall back to the
+//! `.text_start`, `.stack_start` and `.stack_size` directives of the objects,
+//! then to the defaults of the assembler.
+
+use crate::utils::assembler::command_processor::SegmentLayout;
+use crate::utils::assembler::expressions::evaluate;
+
+/// Parses a linker script.
+///
+/// # Arguments
+///
+/// * `script` - The text of the script. `#` and `;` start comments.
+///
+/// # Returns
+///
+/// * `Result<SegmentLayout, String>` - The settings of the script, or an error message with the
+///   line of an invalid or repeated setting.
+pub fn parse_linker_script(script: &str) -> Result<SegmentLayout, String> {
+    let mut layout = SegmentLayout::default();
+    for (index, line) in script.lines().enumerate() {
+        let located = |e: String| format!("Line {}: {}", index + 1, e);
+        let line = line.split(['#', ';']).next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = line.split_once('=').ok_or_else(|| located(format!("Expected NAME = VALUE, got: {}", line)))?;
+        let name = name.trim();
+        let value = evaluate(value, |_| None).map_err(located)?;
+        let value = u32::try_from(value).map_err(|_| located(format!("{} is out of range: {}", name, value)))?;
+        let setting = match name {
+            "text_start" => &mut layout.text_start,
+            "data_start" => &mut layout.data_start,
+            "stack_start" => &mut layout.stack_start,
+            "stack_size" => &mut layout.stack_size,
+            _ => return Err(located(format!("Unknown setting: {} (expected text_start, data_start, stack_start or stack_size)", name))),
+        };
+        if setting.replace(value).is_some() {
+            return Err(located(format!("{} is set more than once", name)));
+        }
+    }
+    Ok(layout)
+}
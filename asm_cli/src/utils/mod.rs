@@ -2,11 +2,14 @@
 //!
 //! This module provides various utility functionalities used across the application,
 //! including application-specific logic, assembler components, configuration management,
-//! terminal user interface (TUI) utilities, workspace management, and general UI components.
+//! terminal user interface (TUI) utilities, workspace management, general UI components,
+//! and the headless runner.
 
 pub mod apps;
 pub mod assembler;
 pub mod config;
+pub mod linker;
+pub mod runner;
 pub mod tui;
 pub mod workspaces;
 pub mod ui;
@@ -0,0 +1,295 @@
+//! # Headless Runner
+//!
+//! This module runs a program without the terminal user interface: it
+//! assembles the source, executes it until `HALT`, a fault or a step limit,
+//! and then writes the requested memory dumps. It backs the `arcs run`
+//! subcommand, which lets expected memory contents be produced and checked
+//! from scripts and tests, and the `arcs symbols`, `arcs listing` and
+//! `arcs object` subcommands. `arcs run` also runs `.arx` executables, and
+//! `arcs disasm` disassembles executables and memory images.
+
+use std::path::{Path, PathBuf};
+
+use crate::memory::dump::DumpFormat;
+use crate::memory::main_memory::{ImageFormat, MemoryImage};
+use crate::memory::mmu::Mmu;
+use crate::utils::apps::emulator::EmulatorState;
+use crate::utils::assembler::command_processor::AssembledProgram;
+use crate::utils::assembler::disassembler::{disassemble_range, Labels};
+use crate::utils::linker::executable::Executable;
+use crate::utils::linker::object::ObjectFile;
+use crate::utils::ui::resources::AppStatus;
+use crate::utils::workspaces::Workspace;
+
+/// Number of instructions executed before a headless run gives up.
+pub const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+/// A memory dump written when a headless run halts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpRequest {
+    /// The range to dump, in the format accepted by `parse_dump_range`.
+    pub range: String,
+    /// The file to write, or `-` for standard output.
+    pub file: PathBuf,
+    /// The format of the dump; guessed from the file extension when `None`.
+    pub format: Option<DumpFormat>,
+}
+
+impl DumpRequest {
+    /// Parses a dump request of the form `RANGE=FILE[,FORMAT]`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (range, target) = spec.split_once('=')
+            .ok_or(format!("Expected RANGE=FILE[,FORMAT], got: {}", spec))?;
+        let (file, format) = match target.rsplit_once(',') {
+            Some((file, format)) => (file, Some(DumpFormat::from_name(format)?)),
+            None => (target, None),
+        };
+        if range.trim().is_empty() || file.trim().is_empty() {
+            return Err(format!("Expected RANGE=FILE[,FORMAT], got: {}", spec));
+        }
+        Ok(Self { range: range.trim().to_string(), file: PathBuf::from(file.trim()), format })
+    }
+
+    /// Returns the format of the dump, defaulting to a hexdump on standard output.
+    fn resolved_format(&self) -> DumpFormat {
+        match self.format {
+            Some(format) => format,
+            None if self.file == Path::new("-") => DumpFormat::HexDump,
+            None => DumpFormat::from_path(&self.file),
+        }
+    }
+}
+
+/// The settings of a headless run.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// The assembly source file, or an `.arx` executable to run as is.
+    pub program: PathBuf,
+    /// The total memory size of the emulator.
+    pub memory_size: usize,
+    /// The number of instructions executed before the run is stopped.
+    pub max_steps: u64,
+    /// Whether the MMU is installed.
+    pub mmu: bool,
+    /// Whether reads of uninitialized memory are reported.
+    pub memcheck: bool,
+    /// Devices moved to another base address, by name.
+    pub devices: Vec<(String, u32)>,
+    /// Directories searched for `.include`d files.
+    pub include_paths: Vec<PathBuf>,
+    /// Constants defined on the command line.
+    pub defines: Vec<(String, u32)>,
+    /// The dumps written when the program halts.
+    pub dumps: Vec<DumpRequest>,
+}
+
+/// The result of a headless run that reached `HALT`.
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    /// The number of executed instructions.
+    pub steps: u64,
+    /// The number of executed cycles.
+    pub cycles: u64,
+    /// The memcheck reports, already symbolized.
+    pub memcheck: Vec<String>,
+}
+
+/// Assembles and runs a program until it halts, then writes the requested dumps.
+///
+/// # Arguments
+///
+/// * `options` - The program and settings of the run.
+///
+/// # Returns
+///
+/// * `Result<RunSummary, String>` - A summary of the run, or an error message if the program
+///   does not assemble, faults, exceeds the step limit or a dump cannot be written.
+pub fn run_headless(options: &RunOptions) -> Result<RunSummary, String> {
+    let executable = options.program.extension().is_some_and(|extension| extension == "arx");
+    let (mut workspace, source) = if executable {
+        (Workspace::new(options.program.parent().unwrap_or(Path::new(".")), options.memory_size), None)
+    } else {
+        let (workspace, source) = open_program(options)?;
+        (workspace, Some(source))
+    };
+    let emulator = workspace.get_emulator();
+    emulator.memory.mmu = Mmu::new(options.mmu);
+    emulator.set_memcheck(options.memcheck);
+    for (name, base) in &options.devices {
+        emulator.move_device(name, *base)?;
+    }
+    match source {
+        Some(source) => workspace.assemble_and_load_program(&source, &mut AppStatus::default())
+            .map_err(|e| format!("Assembly error: {}", e))?,
+        None => {
+            emulator.load_executable(&Executable::load(&options.program)?, false)?;
+        }
+    }
+
+    let emulator = workspace.get_emulator();
+    let steps = execute(emulator, options.max_steps)?;
+    for dump in &options.dumps {
+        write_dump(emulator, dump)?;
+    }
+    Ok(RunSummary {
+        steps,
+        cycles: emulator.cpu.cycles,
+        memcheck: emulator.memcheck_reports.iter().map(|report| emulator.describe_memcheck_report(report)).collect(),
+    })
+}
+
+/// Assembles a program without running it.
+///
+/// # Arguments
+///
+/// * `options` - The program and the assembler settings; the run settings are ignored.
+///
+/// # Returns
+///
+/// * `Result<AssembledProgram, String>` - The assembled program, or an error message.
+pub fn assemble_headless(options: &RunOptions) -> Result<AssembledProgram, String> {
+    let (mut workspace, source) = open_program(options)?;
+    workspace.try_assemble_program(&source, &mut AppStatus::default())
+        .map_err(|errors| format!("Assembly error: {}", errors.join("; ")))
+}
+
+/// Assembles a program into a relocatable object file.
+///
+/// # Arguments
+///
+/// * `options` - The program and the assembler settings; the run settings are ignored.
+///
+/// # Returns
+///
+/// * `Result<ObjectFile, String>` - The object file, named after the source file, or an error message.
+pub fn assemble_object_headless(options: &RunOptions) -> Result<ObjectFile, String> {
+    let (mut workspace, source) = open_program(options)?;
+    let name = options.program.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    workspace.try_assemble_object(&source, &name)
+        .map_err(|e| format!("Assembly error: {}", e))
+}
+
+/// Disassembles an `.arx` executable or a memory image.
+///
+/// Executables are disassembled from their text segment, with their labels. Every block of an
+/// image is disassembled as instructions; a partial word at the end of a block is padded with zeros.
+///
+/// # Arguments
+///
+/// * `path` - The executable or image file.
+/// * `format` - The image format; guessed from the file extension when `None`.
+/// * `base` - The load address of raw images.
+/// * `mark_pseudo` - Whether to name the pseudo-instructions instruction sequences may have been expanded from.
+///
+/// # Returns
+///
+/// * `Result<String, String>` - One line per instruction, or an error message if the file cannot be read.
+pub fn disassemble_file(path: &Path, format: Option<ImageFormat>, base: u32, mark_pseudo: bool) -> Result<String, String> {
+    if format.is_none() && path.extension().is_some_and(|extension| extension == "arx") {
+        let program = Executable::load(path)?.program;
+        return Ok(disassemble_range(program.actual_text_start, &program.text, &Labels::new(&program.symbols), mark_pseudo));
+    }
+    let contents = std::fs::read(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+    let image = MemoryImage::parse(format.unwrap_or_else(|| ImageFormat::from_path(path)), &contents, base)?;
+    let mut text = String::new();
+    for (start, bytes) in &image.blocks {
+        let words: Vec<u32> = bytes.chunks(4)
+            .map(|chunk| {
+                let mut word = [0u8; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u32::from_le_bytes(word)
+            })
+            .collect();
+        text.push_str(&disassemble_range(*start, &words, &Labels::default(), mark_pseudo));
+    }
+    Ok(text)
+}
+
+/// Reads the program and creates a workspace with its directory, include path and defines.
+fn open_program(options: &RunOptions) -> Result<(Workspace, String), String> {
+    let source = std::fs::read_to_string(&options.program)
+        .map_err(|e| format!("Error reading {}: {}", options.program.display(), e))?;
+    let directory = options.program.parent().unwrap_or(Path::new("."));
+    let mut workspace = Workspace::new(directory, options.memory_size);
+    workspace.active_file = Some(options.program.clone());
+    workspace.include_paths = options.include_paths.clone();
+    workspace.defines = options.defines.clone();
+    Ok((workspace, source))
+}
+
+/// Steps the emulator until it halts.
+fn execute(emulator: &mut EmulatorState, max_steps: u64) -> Result<u64, String> {
+    let mut steps = 0;
+    while !emulator.cpu.halted {
+        if steps == max_steps {
+            return Err(format!(
+                "Program did not halt within {} steps (PC at {})",
+                max_steps, emulator.symbolize(emulator.cpu.registers.pc)
+            ));
+        }
+        emulator.step().map_err(|e| emulator.fault_report(&e))?;
+        steps += 1;
+    }
+    Ok(steps)
+}
+
+/// Writes one dump to its file or to standard output.
+fn write_dump(emulator: &EmulatorState, dump: &DumpRequest) -> Result<(), String> {
+    let contents = emulator.dump_memory(&dump.range, dump.resolved_format())?;
+    if dump.file == Path::new("-") {
+        use std::io::Write;
+        return std::io::stdout().write_all(&contents).map_err(|e| format!("Error writing dump: {}", e));
+    }
+    std::fs::write(&dump.file, contents).map_err(|e| format!("Error writing {}: {}", dump.file.display(), e))
+}
+
+#[cfg(test)]
+mod runner_test {
+    use super::*;
+
+    #[test]
+    fn dump_requests_parse_ranges_files_and_formats() {
+        let dump = DumpRequest::parse("data=out/data.hex").unwrap();
+        assert_eq!((dump.range.as_str(), dump.file.as_path()), ("data", Path::new("out/data.hex")));
+        assert_eq!(dump.resolved_format(), DumpFormat::IntelHex);
+
+        let dump = DumpRequest::parse("0x8000:16=expected.bin,rust").unwrap();
+        assert_eq!(dump.range, "0x8000:16");
+        assert_eq!(dump.resolved_format(), DumpFormat::RustArray);
+        assert_eq!(DumpRequest::parse("text=-").unwrap().resolved_format(), DumpFormat::HexDump);
+        assert!(DumpRequest::parse("text").is_err());
+        assert!(DumpRequest::parse("text=out.bin,elf").is_err());
+    }
+
+    #[test]
+    fn programs_run_until_halt_and_dump_memory() {
+        let directory = std::env::temp_dir().join(format!("arcs-runner-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let program = directory.join("store.arc");
+        std::fs::write(&program, ".text\nMOVI AX, 0x2A\nPUSH AX\nHALT\n").unwrap();
+        let output = directory.join("value.bin");
+
+        let options = RunOptions {
+            program: program.clone(),
+            memory_size: 0x10000,
+            max_steps: 100,
+            mmu: false,
+            memcheck: false,
+            devices: Vec::new(),
+            include_paths: Vec::new(),
+            defines: Vec::new(),
+            dumps: vec![DumpRequest::parse(&format!("0xFFFC:4={}", output.display())).unwrap()],
+        };
+        let summary = run_headless(&options).unwrap();
+        assert_eq!(summary.steps, 3);
+        // The stack grows down from the end of its segment at 0x10000, just below the console
+        assert_eq!(std::fs::read(&output).unwrap(), vec![0x2A, 0, 0, 0]);
+
+        let moved = RunOptions { devices: vec![("console".to_string(), 0xF800)], dumps: Vec::new(), ..options.clone() };
+        assert!(run_headless(&moved).unwrap_err().contains("overlaps device 'console'"));
+
+        std::fs::write(&program, "loop: JMP loop\n").unwrap();
+        assert!(run_headless(&RunOptions { dumps: Vec::new(), ..options }).unwrap_err().contains("did not halt"));
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
@@ -5,6 +5,7 @@
 //! and rendering of the different application screens.
 
 use std::io;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use crossterm::{
@@ -69,6 +70,9 @@ use super::{
 };
 
 use crate::utils::config::config_manager::ConfigManager;
+use crate::memory::main_memory::{ImageFormat, MemoryImage};
+use crate::utils::linker::executable::Executable;
+use crate::memory::mmu::Mmu;
 
 /// The main application struct that holds the state of the TUI.
 pub struct TuiApp {
@@ -122,6 +126,93 @@ impl TuiApp {
         }
     }
 
+    /// Installs the MMU in the emulated machine.
+    ///
+    /// Translation stays disabled until a program sets bit 0 of the MMU control register.
+    pub fn enable_mmu(&mut self) {
+        self.emulator_state.memory.mmu = Mmu::new(true);
+        if let Some(emulator) = &mut self.workspace.emulator {
+            emulator.memory.mmu = Mmu::new(true);
+        }
+    }
+
+    /// Enables memcheck, which reports reads of uninitialized memory.
+    pub fn enable_memcheck(&mut self) {
+        self.emulator_state.set_memcheck(true);
+        if let Some(emulator) = &mut self.workspace.emulator {
+            emulator.set_memcheck(true);
+        }
+    }
+
+    /// Moves a device on the bus of the emulated machine to another base address.
+    pub fn move_device(&mut self, name: &str, base: u32) -> Result<(), String> {
+        self.emulator_state.move_device(name, base)?;
+        if let Some(emulator) = &mut self.workspace.emulator {
+            emulator.move_device(name, base)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the directories searched for `.include`d files.
+    pub fn set_include_paths(&mut self, include_paths: Vec<PathBuf>) {
+        self.workspace.include_paths = include_paths;
+    }
+
+    /// Sets the constants defined on the command line, visible to every assembled program.
+    pub fn set_defines(&mut self, defines: Vec<(String, u32)>) {
+        self.workspace.defines = defines;
+    }
+
+    /// Loads a memory image into the emulator and opens the emulator screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The image file.
+    /// * `format` - The image format; guessed from the file extension when `None`.
+    /// * `address` - The load address of raw images.
+    /// * `entry` - The entry point, overriding the one recorded in the image.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u32, String>` - The entry point, or an error message.
+    pub fn load_image(&mut self, path: &Path, format: Option<ImageFormat>, address: u32, entry: Option<u32>) -> Result<u32, String> {
+        let contents = std::fs::read(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        let format = format.unwrap_or_else(|| ImageFormat::from_path(path));
+        let image = MemoryImage::parse(format, &contents, address)?;
+        let entry = self.emulator_state.load_image(&image, entry)?;
+        if let Some(emulator) = &mut self.workspace.emulator {
+            emulator.load_image(&image, Some(entry))?;
+        }
+        // Skip `on_enter_emulator`, which would assemble the selected source over the image
+        self.app_state = AppState::Emulator;
+        self.prev_app_state = AppState::Emulator;
+        self.app_status.set_message(format!("Loaded {} (entry 0x{:04X})", path.display(), entry));
+        Ok(entry)
+    }
+
+    /// Loads an ARC executable into the emulator and opens the emulator screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The `.arx` file.
+    /// * `entry` - The entry point, overriding the one recorded in the executable.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<u32, String>` - The entry point, or an error message.
+    pub fn load_executable(&mut self, path: &Path, entry: Option<u32>) -> Result<u32, String> {
+        let mut executable = Executable::load(path)?;
+        executable.entry = entry.unwrap_or(executable.entry);
+        let allow_self_modifying_code = self.workspace.allow_self_modifying_code;
+        let entry = self.workspace.get_emulator().load_executable(&executable, allow_self_modifying_code)?;
+        self.emulator_state = self.workspace.get_emulator().clone();
+        // Skip `on_enter_emulator`, which would assemble the selected source over the program
+        self.app_state = AppState::Emulator;
+        self.prev_app_state = AppState::Emulator;
+        self.app_status.set_message(format!("Loaded {} (entry 0x{:04X})", path.display(), entry));
+        Ok(entry)
+    }
+
     /// Runs the main application loop.
     ///
     /// This function initializes the terminal, enters the main event loop,
@@ -168,7 +259,7 @@ impl TuiApp {
                                 AppState::Settings => handle_settings_input(key, &mut self.settings_app, &mut self.app_state, &mut handled),
                                 AppState::HelpGuide => handle_help_guide_input(key, &mut self.help_guide_state, &mut self.app_state, &mut handled),
                                 AppState::IoDevices => handle_io_devices_input(key, &mut self.workspace, &mut self.app_state, &mut handled),
-                                AppState::StartMenu => handle_start_menu_input(key, &mut self.start_menu_state, &mut self.app_state, &mut self.app_status, &mut self.workspace, &mut handled),
+                                AppState::StartMenu => handle_start_menu_input(key, &mut self.start_menu_state, &mut self.app_state, &mut self.app_status, &self.workspace, &mut handled),
                                 _ => {}
                             }
                         }
@@ -187,8 +278,7 @@ impl TuiApp {
                 self.app_status.loading_progress = 0; // Reset when not loading
             }
 
-            if update_status_message(&mut self.app_status) {
-            }
+            update_status_message(&mut self.app_status);
 
             self.terminal.draw(|frame| {
                 let size = frame.area();
@@ -204,9 +294,14 @@ impl TuiApp {
                     AppState::Emulator => {
                         if self.emulator_state.is_running {
                             match self.emulator_state.run_full_speed() {
-                                Ok(_) => {},
+                                Ok(_) => {
+                                    if let Some(report) = self.emulator_state.step_report.take() {
+                                        self.app_status.set_message(report);
+                                    }
+                                },
                                 Err(e) => {
-                                    self.app_status.set_message(format!("Emulator error: {}", e));
+                                    let report = self.emulator_state.fault_report(&format!("Emulator error: {}", e));
+                                    self.app_status.set_message(report);
                                     self.emulator_state.is_running = false;
                                 }
                             }
@@ -117,9 +117,9 @@ pub fn render_command_bar(frame: &mut Frame, area: Rect, app_state: &AppState, w
         .unwrap_or("No file open");
 
     let command_bar_hint = match app_state {
-        AppState::TextEditor => "Alt+S:Save Alt+A:SaveAs Alt+O:Open Alt+N:New Alt+Q:Back Alt+E:Run",
+        AppState::TextEditor => "Alt+S:Save Alt+A:SaveAs Alt+O:Open Alt+N:New Alt+Q:Back Alt+E:Run Alt+W:Watch",
         AppState::FileExplorer => "↓:Down ↑:Up Enter:Open/Enter Alt+N:New Del:Delete Q:Back",
-        AppState::Emulator => "P:Play/Pause S:Step R:Reset F:Flags I:I/O Q:Back",
+        AppState::Emulator => "P:Play/Pause S:Step R:Reset F:Flags I:I/O B:Predictor M:SMC W:Watch G:Goto E:Edit /:Search V:View K:Snapshot C:Compare X:Export U:Memcheck Tab:Panel Q:Back",
         AppState::Settings => "↑↓:Navigate Enter:Select Esc:Back",
         _ => "",
     };
@@ -28,6 +28,7 @@ const MESSAGE_DURATION_FRAMES: u32 = 100;
 ///
 /// Status messages are displayed for a limited duration and a maximum number
 /// can be shown at once.
+#[derive(Default)]
 pub struct AppStatus {
     /// A queue of messages, each paired with its remaining display timer.
     pub messages: VecDeque<(String, u32)>, // (message, timer)
@@ -35,16 +36,6 @@ pub struct AppStatus {
     pub loading_progress: u8, // 0-100
 }
 
-impl Default for AppStatus {
-    fn default() -> Self {
-        Self {
-            messages: VecDeque::new(),
-            is_loading: false,
-            loading_progress: 0,
-        }
-    }
-}
-
 impl AppStatus {
     /// Sets a new status message.
     ///
@@ -7,7 +7,10 @@
 
 use std::path::{PathBuf, Path};
 use std::fs;
-use crate::utils::assembler::command_processor::{AssembledProgram, parse_command, Command, Macro, assemble_program};
+use crate::utils::assembler::command_processor::{AssembledProgram, parse_command, Command, assemble_program, assemble_object};
+use crate::utils::linker::object::ObjectFile;
+use crate::utils::assembler::macros::{Macro, MacroExpander};
+use crate::utils::assembler::source::{source_lines, Includes};
 use walkdir::WalkDir;
 use crate::utils::apps::emulator::EmulatorState as AppEmulatorState;
 use crate::utils::ui::resources::AppStatus;
@@ -40,6 +43,12 @@ pub struct Workspace {
     pub io_device: IoDevice,
     /// A flag indicating if there are unsaved changes in the active file.
     pub unsaved_changes: bool,
+    /// Whether programs loaded from this workspace may write to their own text segment.
+    pub allow_self_modifying_code: bool,
+    /// Directories searched for `.include`d files that are not next to the including file.
+    pub include_paths: Vec<PathBuf>,
+    /// Constants defined on the command line (`-D NAME=VALUE`), visible to the whole program.
+    pub defines: Vec<(String, u32)>,
 }
 
 impl Workspace {
@@ -64,6 +73,9 @@ impl Workspace {
             assembler: AssemblerState::default(),
             io_device: IoDevice::new(),
             unsaved_changes: false,
+            allow_self_modifying_code: false,
+            include_paths: Vec::new(),
+            defines: Vec::new(),
         }
     }
     
@@ -203,7 +215,12 @@ impl Workspace {
         emulator.load_assembled_program(&assembled_program)?;
         emulator.program_source = Some(source.to_string());
         emulator.assembled_program = Some(assembled_program.clone());
+        emulator.apply_memory_protection(self.allow_self_modifying_code);
         emulator.last_assembly_errors.clear();
+        let missing = emulator.memory.watchpoints.resolve_labels(|label| assembled_program.label_address(label));
+        if !missing.is_empty() {
+            app_status.set_message(format!("Removed watchpoints on missing labels: {}", missing.join(", ")));
+        }
         
         self.assembler.last_assembly_result = Some(Ok(assembled_program));
         app_status.is_loading = false;
@@ -250,9 +267,27 @@ impl Workspace {
         result
     }
     
+    /// Assembles the provided source code into a relocatable object file for the linker.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The assembly source code as a string.
+    /// * `name` - The name recorded in the object, usually the source file name.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectFile, String>` - The object file, or an error message on failure.
+    pub fn try_assemble_object(&mut self, source: &str, name: &str) -> Result<ObjectFile, String> {
+        let commands = self.parse_source_to_commands(source)?;
+        assemble_object(&commands, &self.assembler.macros, name)
+    }
+
     /// Parses the given assembly source code into a vector of `Command`s.
     ///
-    /// This function handles macro definitions and expansions during the parsing process.
+    /// This function handles macro definitions and expansions during the parsing process,
+    /// and keeps the macros it defines in the assembler state. `.include` lines are resolved relative to the active file, then in the include path,
+    /// and conditional blocks are evaluated on the way, so branches that are not taken define no macros and include no files.
+    /// Every command records the file and line it was read from.
     ///
     /// # Arguments
     ///
@@ -262,39 +297,23 @@ impl Workspace {
     ///
     /// * `Result<Vec<Command>, String>` - A vector of parsed `Command`s on success,
     ///   or an error message if parsing fails (e.g., syntax error, unclosed macro).
-    fn parse_source_to_commands(&self, source: &str) -> Result<Vec<Command>, String> {
+    fn parse_source_to_commands(&mut self, source: &str) -> Result<Vec<Command>, String> {
         let mut commands = Vec::new();
-        let mut current_macro: Option<Macro> = None;
-        
-        for (line_num, line) in source.lines().enumerate() {
-            let command = parse_command(line)
-                .map_err(|e| format!("Line {}: {}", line_num + 1, e))?;
-            
-            if command.opcode == ".macro" {
-                if current_macro.is_some() {
-                    return Err(format!("Line {}: Nested macro definition not allowed", line_num + 1));
-                }
-                current_macro = Some(Macro {
-                    name: command.macro_name.clone().unwrap_or_default(),
-                    args: command.macro_args.clone().unwrap_or_default(),
-                    body: Vec::new(),
-                });
-                continue;
-            }
-            
-            if let Some(ref mut macro_def) = current_macro {
-                if command.opcode == ".endmacro" {
-                    current_macro = None;
-                } else {
-                    macro_def.body.push(command);
-                }
-            } else {
-                commands.push(command);
-            }
+        for (name, value) in &self.defines {
+            commands.push(parse_command(&format!("{}: .equ {}", name, value))?);
         }
         
-        if current_macro.is_some() {
-            return Err("Unclosed macro definition".to_string());
+        let mut expander = MacroExpander::default();
+        expander.symbols.extend(self.defines.iter().map(|(name, value)| (name.clone(), *value as i64)));
+        expander.includes = Includes::new(self.active_file.as_deref(), &self.include_paths, &self.current_path);
+        let lines = expander.expand(&source_lines(source, self.active_file.as_deref()))?;
+        self.assembler.macros = expander.macros;
+        for line in lines {
+            let mut command = parse_command(&line.text)
+                .map_err(|e| format!("{}: {}", line.location, e))?;
+            command.location = Some(line.location);
+            command.expansion_depth = line.depth;
+            commands.push(command);
         }
         
         Ok(commands)